@@ -0,0 +1,183 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Conversion of many files at once. Jobs are taken from a shared queue by a fixed number of worker threads, every
+// worker reuses its file buffer between jobs. Inputs are DDS or PNG files, outputs are DDS files that are written
+// atomically, so a cancelled batch doesn't leave truncated outputs behind.
+
+use crate::builder::max_mipmap_count;
+use crate::dds::*;
+use crate::encode::{normalized_remap, EncodeOptions};
+use crate::format::*;
+use crate::mips::MipOptions;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConvertOptions {
+    // Target format, the source format is kept if None
+    pub dxgi_format: Option<u32>,
+    // Regenerate the mip chain with this many mips, 0 is the full chain. The source mips are kept if None.
+    pub mipmap_count: Option<u32>,
+    // When `srgb` is None, mips are filtered in linear space if either the source or the target format is sRGB
+    pub mip_options: MipOptions,
+    pub encode_options: EncodeOptions,
+    // Remap colors between UNORM and SNORM formats, see `convert_normalized_with_encoder`
+    pub remap_normalized: bool,
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Mips are filtered at full precision, before the conversion to the target format
+    pub fn convert_with(&self, options: &ConvertOptions) -> Result<ScratchImage> {
+        let source_format = self.dxgi_format();
+        let target_format = options.dxgi_format.unwrap_or(source_format);
+        let remap = match options.remap_normalized {
+            true => normalized_remap(source_format, target_format),
+            false => None,
+        };
+
+        let mut image = None;
+        if let Some(mipmap_count) = options.mipmap_count {
+            let mipmap_count = match mipmap_count {
+                0 => max_mipmap_count(self.image_width(), self.image_height(), self.image_depth()),
+                mipmap_count => mipmap_count,
+            };
+            let mip_options = MipOptions {
+                srgb: Some(
+                    options
+                        .mip_options
+                        .srgb
+                        .unwrap_or_else(|| is_srgb(source_format) || is_srgb(target_format)),
+                ),
+            };
+            image = Some(
+                self.convert(DXGI_FORMAT_R32G32B32A32_FLOAT)?
+                    .generate_mips_with(mipmap_count, &mip_options)?,
+            );
+        }
+
+        match image {
+            Some(image) if image.dxgi_format() == target_format && remap.is_none() => Ok(image),
+            Some(image) => image.convert_remapped(target_format, &options.encode_options, remap),
+            None if source_format == target_format => Ok(self.to_owned_image()),
+            None => self.convert_remapped(target_format, &options.encode_options, remap),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchJob {
+    pub input: PathBuf,
+    pub options: ConvertOptions,
+    pub output: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchOptions {
+    // 0 uses the available parallelism, there are never more threads than jobs
+    pub thread_count: usize,
+    // Used for DDS inputs
+    pub parse_options: ParseOptions,
+}
+
+// Reported after every job, from the worker thread that ran it
+pub struct BatchProgress<'a> {
+    pub job_index: usize,
+    pub job: &'a BatchJob,
+    pub result: &'a Result<()>,
+    // Including this job
+    pub completed_count: usize,
+    pub job_count: usize,
+}
+
+fn has_extension(path: &std::path::Path, expected: &str) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(expected))
+}
+
+fn read_image(path: &std::path::Path, mut bytes: &[u8], parse_options: &ParseOptions) -> Result<ScratchImage> {
+    if has_extension(path, "png") {
+        return ScratchImage::from_png_reader(&mut bytes);
+    }
+
+    #[cfg(feature = "zstd")]
+    if crate::supercompression::is_zstd_path(path) {
+        return ScratchImage::from_zstd_reader_with(&mut bytes, parse_options);
+    }
+
+    ScratchImage::from_reader_with(&mut bytes, parse_options)
+}
+
+#[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+fn write_image(path: &std::path::Path, image: &ScratchImage, buffer: &mut Vec<u8>) -> Result<()> {
+    #[cfg(feature = "zstd")]
+    if crate::supercompression::is_zstd_path(path) {
+        return image.write_to_zstd(buffer, crate::supercompression::ZSTD_DEFAULT_COMPRESSION_LEVEL);
+    }
+
+    image.write_to(buffer)
+}
+
+fn run_job(job: &BatchJob, parse_options: &ParseOptions, buffer: &mut Vec<u8>) -> Result<()> {
+    buffer.clear();
+    std::fs::File::open(&job.input)?.read_to_end(buffer)?;
+    let image = read_image(&job.input, buffer, parse_options)?.convert_with(&job.options)?;
+
+    buffer.clear();
+    write_image(&job.output, &image, buffer)?;
+    write_file_atomic(&job.output, |file| Ok(file.write_all(buffer)?))
+}
+
+// Runs all jobs and returns their results in the order of `jobs`. A failed job doesn't stop the others.
+pub fn run_batch<F>(jobs: &[BatchJob], options: &BatchOptions, progress: F) -> Vec<Result<()>>
+where
+    F: Fn(&BatchProgress) + Sync,
+{
+    let thread_count = match options.thread_count {
+        0 => std::thread::available_parallelism().map_or(1, |count| count.get()),
+        thread_count => thread_count,
+    }
+    .min(jobs.len());
+
+    let next_job = AtomicUsize::new(0);
+    let completed_count = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<()>>>> = Mutex::new((0..jobs.len()).map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(|| {
+                let mut buffer = Vec::new();
+                loop {
+                    let job_index = next_job.fetch_add(1, Ordering::Relaxed);
+                    let job = match jobs.get(job_index) {
+                        Some(job) => job,
+                        None => break,
+                    };
+
+                    let result = run_job(job, &options.parse_options, &mut buffer);
+                    progress(&BatchProgress {
+                        job_index,
+                        job,
+                        result: &result,
+                        completed_count: completed_count.fetch_add(1, Ordering::Relaxed) + 1,
+                        job_count: jobs.len(),
+                    });
+                    results.lock().unwrap()[job_index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect()
+}
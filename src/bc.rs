@@ -0,0 +1,2082 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Block decompression for BC1-BC7. Every block decodes to 4x4 RGBA texels in row-major order.
+
+use crate::blocks::Bc7Fields;
+use crate::dds::*;
+use crate::decode::half_to_f32;
+use crate::encode::{f32_to_half, Bc1Alpha, EncodeOptions, EncodePreset};
+use std::convert::TryInto;
+
+pub(crate) type DecodedBlock = [[f32; 4]; 16];
+
+// None for formats that are not block compressed or don't have a defined interpretation (typeless)
+pub(crate) fn decode_block(dxgi_format: u32, block: &[u8]) -> Option<DecodedBlock> {
+    let decoded = match dxgi_format {
+        DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB => decode_bc1(block),
+        DXGI_FORMAT_BC2_UNORM | DXGI_FORMAT_BC2_UNORM_SRGB => decode_bc2(block),
+        DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB => decode_bc3(block),
+        DXGI_FORMAT_BC4_UNORM => decode_bc4(block, false),
+        DXGI_FORMAT_BC4_SNORM => decode_bc4(block, true),
+        DXGI_FORMAT_BC5_UNORM => decode_bc5(block, false),
+        DXGI_FORMAT_BC5_SNORM => decode_bc5(block, true),
+        DXGI_FORMAT_BC6H_UF16 => decode_bc6h(block, false),
+        DXGI_FORMAT_BC6H_SF16 => decode_bc6h(block, true),
+        DXGI_FORMAT_BC7_UNORM | DXGI_FORMAT_BC7_UNORM_SRGB => decode_bc7(block),
+        _ => return None,
+    };
+
+    Some(decoded)
+}
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d10/d3d10-graphics-programming-guide-resources-block-compression"]
+fn decode_bc1(block: &[u8]) -> DecodedBlock {
+    decode_color_block(block, true)
+}
+
+fn decode_bc2(block: &[u8]) -> DecodedBlock {
+    let mut texels = decode_color_block(&block[8..], false);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (texel, value) in texels.iter_mut().enumerate() {
+        value[3] = ((alpha >> (texel * 4)) & 0xf) as f32 / 15.0;
+    }
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> DecodedBlock {
+    let mut texels = decode_color_block(&block[8..], false);
+    let alpha = decode_channel_block(&block[..8], false);
+    for (value, alpha) in texels.iter_mut().zip(alpha.iter()) {
+        value[3] = *alpha;
+    }
+    texels
+}
+
+fn decode_bc4(block: &[u8], is_signed: bool) -> DecodedBlock {
+    let red = decode_channel_block(block, is_signed);
+    let mut texels = [[0.0, 0.0, 0.0, 1.0]; 16];
+    for (value, red) in texels.iter_mut().zip(red.iter()) {
+        value[0] = *red;
+    }
+    texels
+}
+
+fn decode_bc5(block: &[u8], is_signed: bool) -> DecodedBlock {
+    let red = decode_channel_block(&block[..8], is_signed);
+    let green = decode_channel_block(&block[8..], is_signed);
+    let mut texels = [[0.0, 0.0, 0.0, 1.0]; 16];
+    for (texel, value) in texels.iter_mut().enumerate() {
+        value[0] = red[texel];
+        value[1] = green[texel];
+    }
+    texels
+}
+
+// BC1 color block, also used by BC2 and BC3 where the 3-color mode is not available
+fn decode_color_block(block: &[u8], allow_transparent: bool) -> DecodedBlock {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let unpack = |color: u16| {
+        [
+            ((color >> 11) & 0x1f) as f32 / 31.0,
+            ((color >> 5) & 0x3f) as f32 / 63.0,
+            (color & 0x1f) as f32 / 31.0,
+            1.0,
+        ]
+    };
+    let mix = |a: [f32; 4], b: [f32; 4], weight_a: f32, weight_b: f32, div: f32| {
+        [
+            (a[0] * weight_a + b[0] * weight_b) / div,
+            (a[1] * weight_a + b[1] * weight_b) / div,
+            (a[2] * weight_a + b[2] * weight_b) / div,
+            1.0,
+        ]
+    };
+
+    let c0 = unpack(color0);
+    let c1 = unpack(color1);
+    let palette = if color0 > color1 || !allow_transparent {
+        [c0, c1, mix(c0, c1, 2.0, 1.0, 3.0), mix(c0, c1, 1.0, 2.0, 3.0)]
+    } else {
+        [c0, c1, mix(c0, c1, 1.0, 1.0, 2.0), [0.0; 4]]
+    };
+
+    let mut texels = [[0.0; 4]; 16];
+    for (texel, value) in texels.iter_mut().enumerate() {
+        *value = palette[((indices >> (texel * 2)) & 0x3) as usize];
+    }
+    texels
+}
+
+// BC4 block, also used for BC3 alpha and both BC5 channels
+fn decode_channel_block(block: &[u8], is_signed: bool) -> [f32; 16] {
+    let (value0, value1) = if is_signed {
+        let unpack = |value: u8| (value as i8 as f32 / 127.0).max(-1.0);
+        (unpack(block[0]), unpack(block[1]))
+    } else {
+        (block[0] as f32 / 255.0, block[1] as f32 / 255.0)
+    };
+    let (min, max) = if is_signed { (-1.0, 1.0) } else { (0.0, 1.0) };
+
+    let mut palette = [value0, value1, 0.0, 0.0, 0.0, 0.0, min, max];
+    if value0 > value1 {
+        for (index, value) in palette.iter_mut().enumerate().skip(2) {
+            let weight = (index - 1) as f32;
+            *value = ((7.0 - weight) * value0 + weight * value1) / 7.0;
+        }
+    } else {
+        for (index, value) in palette.iter_mut().enumerate().take(6).skip(2) {
+            let weight = (index - 1) as f32;
+            *value = ((5.0 - weight) * value0 + weight * value1) / 5.0;
+        }
+    }
+
+    let indices = u64::from_le_bytes(block[..8].try_into().unwrap()) >> 16;
+    let mut texels = [0.0; 16];
+    for (texel, value) in texels.iter_mut().enumerate() {
+        *value = palette[((indices >> (texel * 3)) & 0x7) as usize];
+    }
+    texels
+}
+
+// Reads 128-bit blocks LSB first
+struct BitReader(u128);
+
+impl BitReader {
+    fn new(block: &[u8]) -> Self {
+        Self(u128::from_le_bytes(block[..16].try_into().unwrap()))
+    }
+
+    fn read(&mut self, bit_count: u32) -> u32 {
+        let value = (self.0 & ((1u128 << bit_count) - 1)) as u32;
+        self.0 >>= bit_count;
+        value
+    }
+}
+
+// Subset of every texel for 2-subset partitions, one bit per texel
+const PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00,
+    0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce, 0x088c, 0x3110, 0x6666, 0x366c,
+    0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8,
+    0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660, 0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+// Subset of every texel for 3-subset partitions, two bits per texel
+const PARTITIONS3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050, 0xaa550000,
+    0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250, 0xa5945040, 0x0a425054,
+    0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500, 0x0050a4a4, 0xaaa59090, 0x14696914,
+    0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200, 0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424,
+    0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50, 0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0,
+    0x69286928, 0x44aaaa44, 0x66666600, 0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580,
+    0xaa141414, 0x96960000, 0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44,
+    0x2a4a5254,
+];
+
+// Anchor texel of the second subset of 2-subset partitions
+const ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+// Anchor texels of the second and third subsets of 3-subset partitions
+const ANCHORS3: [[u8; 2]; 64] = [
+    [3, 15],
+    [3, 8],
+    [15, 8],
+    [15, 3],
+    [8, 15],
+    [3, 15],
+    [15, 3],
+    [15, 8],
+    [8, 15],
+    [8, 15],
+    [6, 15],
+    [6, 15],
+    [6, 15],
+    [5, 15],
+    [3, 15],
+    [3, 8],
+    [3, 15],
+    [3, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [3, 8],
+    [6, 15],
+    [10, 8],
+    [5, 3],
+    [8, 15],
+    [8, 6],
+    [6, 10],
+    [8, 15],
+    [5, 15],
+    [15, 10],
+    [15, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [5, 10],
+    [6, 10],
+    [10, 8],
+    [8, 9],
+    [15, 10],
+    [15, 6],
+    [3, 15],
+    [15, 8],
+    [5, 15],
+    [15, 3],
+    [15, 6],
+    [15, 6],
+    [15, 8],
+    [3, 15],
+    [15, 3],
+    [5, 15],
+    [5, 15],
+    [5, 15],
+    [8, 15],
+    [5, 15],
+    [10, 15],
+    [5, 15],
+    [10, 15],
+    [8, 15],
+    [13, 15],
+    [15, 3],
+    [12, 15],
+    [3, 15],
+    [3, 8],
+];
+
+const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &WEIGHTS2,
+        3 => &WEIGHTS3,
+        _ => &WEIGHTS4,
+    }
+}
+
+fn subset(subset_count: u32, partition: u32, texel: usize) -> usize {
+    match subset_count {
+        2 => ((PARTITIONS2[partition as usize] >> texel) & 1) as usize,
+        3 => ((PARTITIONS3[partition as usize] >> (texel * 2)) & 3) as usize,
+        _ => 0,
+    }
+}
+
+// Anchor texels store their index with one bit less, the first texel is the anchor of the first subset
+fn anchor(subset_count: u32, partition: u32, subset: usize) -> usize {
+    match (subset_count, subset) {
+        (2, 1) => ANCHORS2[partition as usize] as usize,
+        (3, 1 | 2) => ANCHORS3[partition as usize][subset - 1] as usize,
+        _ => 0,
+    }
+}
+
+fn is_anchor(subset_count: u32, partition: u32, texel: usize) -> bool {
+    (0..subset_count as usize).any(|subset| anchor(subset_count, partition, subset) == texel)
+}
+
+struct Bc7Mode {
+    subset_count: u32,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d11/bc7-format-mode-reference"]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subset_count: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subset_count: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subset_count: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subset_count: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subset_count: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subset_count: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subset_count: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subset_count: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+// Mode is the number of zero bits before the first set bit, None for the reserved mode 8
+pub(crate) fn bc7_block_mode(block: &[u8]) -> Option<u32> {
+    let mode = block[0].trailing_zeros();
+    if mode < 8 {
+        Some(mode)
+    } else {
+        None
+    }
+}
+
+// None for modes without partitions
+pub(crate) fn bc7_block_partition(block: &[u8], mode_index: u32) -> Option<u32> {
+    let mode = &BC7_MODES[mode_index as usize];
+    if mode.partition_bits == 0 {
+        return None;
+    }
+
+    let mut bits = BitReader::new(block);
+    bits.read(mode_index + 1);
+    Some(bits.read(mode.partition_bits))
+}
+
+// Fields of a BC7 block with endpoints expanded to 8 bits, None for the reserved mode
+pub(crate) fn unpack_bc7(block: &[u8]) -> Option<Bc7Fields> {
+    let mode_index = bc7_block_mode(block)?;
+    let mode = &BC7_MODES[mode_index as usize];
+
+    let mut bits = BitReader::new(block);
+    bits.read(mode_index + 1);
+    let partition = bits.read(mode.partition_bits);
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subset_count as usize * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [0u32; 6];
+        if mode.endpoint_pbits {
+            for pbit in pbits.iter_mut().take(endpoint_count) {
+                *pbit = bits.read(1);
+            }
+        } else {
+            for subset in 0..mode.subset_count as usize {
+                let pbit = bits.read(1);
+                pbits[subset * 2] = pbit;
+                pbits[subset * 2 + 1] = pbit;
+            }
+        }
+
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits.iter()).take(endpoint_count) {
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    let expand = |value: u32, bit_count: u32| {
+        let value = value << (8 - bit_count);
+        value | (value >> bit_count)
+    };
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut().take(3) {
+            *value = expand(*value, color_bits);
+        }
+        endpoint[3] = if alpha_bits > 0 {
+            expand(endpoint[3], alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let mut indices = [0u32; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.subset_count, partition, texel) as u32;
+        *index = bits.read(mode.index_bits - anchor);
+    }
+    let mut secondary_indices = [0u32; 16];
+    if mode.secondary_index_bits > 0 {
+        for (texel, index) in secondary_indices.iter_mut().enumerate() {
+            let anchor = (texel == 0) as u32;
+            *index = bits.read(mode.secondary_index_bits - anchor);
+        }
+    }
+
+    Some(Bc7Fields {
+        mode: mode_index,
+        partition,
+        rotation,
+        index_selection,
+        subset_count: mode.subset_count,
+        endpoints: endpoints.map(|endpoint| endpoint.map(|value| value as u8)),
+        index_bits: mode.index_bits,
+        indices: indices.map(|index| index as u8),
+        secondary_index_bits: mode.secondary_index_bits,
+        secondary_indices: secondary_indices.map(|index| index as u8),
+    })
+}
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d11/bc7-format"]
+fn decode_bc7(block: &[u8]) -> DecodedBlock {
+    let fields = match unpack_bc7(block) {
+        Some(fields) => fields,
+        None => return [[0.0; 4]; 16],
+    };
+
+    let interpolate = |e0: u8, e1: u8, weight: u32| ((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6;
+    let (indices, secondary_indices) = (&fields.indices, &fields.secondary_indices);
+
+    let mut texels = [[0.0; 4]; 16];
+    for (texel, value) in texels.iter_mut().enumerate() {
+        let subset = subset(fields.subset_count, fields.partition, texel);
+        let e0 = fields.endpoints[subset * 2];
+        let e1 = fields.endpoints[subset * 2 + 1];
+
+        let (color_weight, alpha_weight) = if fields.secondary_index_bits == 0 {
+            let weight = weights(fields.index_bits)[indices[texel] as usize];
+            (weight, weight)
+        } else if fields.index_selection == 0 {
+            (
+                weights(fields.index_bits)[indices[texel] as usize],
+                weights(fields.secondary_index_bits)[secondary_indices[texel] as usize],
+            )
+        } else {
+            (
+                weights(fields.secondary_index_bits)[secondary_indices[texel] as usize],
+                weights(fields.index_bits)[indices[texel] as usize],
+            )
+        };
+
+        let mut rgba = [
+            interpolate(e0[0], e1[0], color_weight),
+            interpolate(e0[1], e1[1], color_weight),
+            interpolate(e0[2], e1[2], color_weight),
+            interpolate(e0[3], e1[3], alpha_weight),
+        ];
+        if fields.rotation > 0 {
+            rgba.swap(3, fields.rotation as usize - 1);
+        }
+
+        *value = [
+            rgba[0] as f32 / 255.0,
+            rgba[1] as f32 / 255.0,
+            rgba[2] as f32 / 255.0,
+            rgba[3] as f32 / 255.0,
+        ];
+    }
+    texels
+}
+
+// Endpoint fields of BC6H modes, endpoints are named w, x, y, z in the format reference
+const RW: u8 = 0;
+const GW: u8 = 1;
+const BW: u8 = 2;
+const RX: u8 = 3;
+const GX: u8 = 4;
+const BX: u8 = 5;
+const RY: u8 = 6;
+const GY: u8 = 7;
+const BY: u8 = 8;
+const RZ: u8 = 9;
+const GZ: u8 = 10;
+const BZ: u8 = 11;
+const D: u8 = 12;
+
+struct Bc6hMode {
+    mode_bits: u32,
+    // Bit width of the first endpoint and of the rest, per channel
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    is_transformed: bool,
+    // Runs of (field, first bit, bit count) in the order they are stored
+    layout: &'static [(u8, u8, u8)],
+}
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d11/bc6h-format"]
+const BC6H_MODES: [(u32, Bc6hMode); 14] = [
+    (
+        0b00,
+        Bc6hMode {
+            mode_bits: 2,
+            endpoint_bits: 10,
+            delta_bits: [5, 5, 5],
+            is_transformed: true,
+            layout: &[
+                (GY, 4, 1),
+                (BY, 4, 1),
+                (BZ, 4, 1),
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 5),
+                (GZ, 4, 1),
+                (GY, 0, 4),
+                (GX, 0, 5),
+                (BZ, 0, 1),
+                (GZ, 0, 4),
+                (BX, 0, 5),
+                (BZ, 1, 1),
+                (BY, 0, 4),
+                (RY, 0, 5),
+                (BZ, 2, 1),
+                (RZ, 0, 5),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b01,
+        Bc6hMode {
+            mode_bits: 2,
+            endpoint_bits: 7,
+            delta_bits: [6, 6, 6],
+            is_transformed: true,
+            layout: &[
+                (GY, 5, 1),
+                (GZ, 4, 1),
+                (GZ, 5, 1),
+                (RW, 0, 7),
+                (BZ, 0, 1),
+                (BZ, 1, 1),
+                (BY, 4, 1),
+                (GW, 0, 7),
+                (BY, 5, 1),
+                (BZ, 2, 1),
+                (GY, 4, 1),
+                (BW, 0, 7),
+                (BZ, 3, 1),
+                (BZ, 5, 1),
+                (BZ, 4, 1),
+                (RX, 0, 6),
+                (GY, 0, 4),
+                (GX, 0, 6),
+                (GZ, 0, 4),
+                (BX, 0, 6),
+                (BY, 0, 4),
+                (RY, 0, 6),
+                (RZ, 0, 6),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b00010,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 11,
+            delta_bits: [5, 4, 4],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 5),
+                (RW, 10, 1),
+                (GY, 0, 4),
+                (GX, 0, 4),
+                (GW, 10, 1),
+                (BZ, 0, 1),
+                (GZ, 0, 4),
+                (BX, 0, 4),
+                (BW, 10, 1),
+                (BZ, 1, 1),
+                (BY, 0, 4),
+                (RY, 0, 5),
+                (BZ, 2, 1),
+                (RZ, 0, 5),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b00110,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 11,
+            delta_bits: [4, 5, 4],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 4),
+                (RW, 10, 1),
+                (GZ, 4, 1),
+                (GY, 0, 4),
+                (GX, 0, 5),
+                (GW, 10, 1),
+                (GZ, 0, 4),
+                (BX, 0, 4),
+                (BW, 10, 1),
+                (BZ, 1, 1),
+                (BY, 0, 4),
+                (RY, 0, 4),
+                (BZ, 0, 1),
+                (BZ, 2, 1),
+                (RZ, 0, 4),
+                (GY, 4, 1),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b01010,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 11,
+            delta_bits: [4, 4, 5],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 4),
+                (RW, 10, 1),
+                (BY, 4, 1),
+                (GY, 0, 4),
+                (GX, 0, 4),
+                (GW, 10, 1),
+                (BZ, 0, 1),
+                (GZ, 0, 4),
+                (BX, 0, 5),
+                (BW, 10, 1),
+                (BY, 0, 4),
+                (RY, 0, 4),
+                (BZ, 1, 1),
+                (BZ, 2, 1),
+                (RZ, 0, 4),
+                (BZ, 4, 1),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b01110,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 9,
+            delta_bits: [5, 5, 5],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 9),
+                (BY, 4, 1),
+                (GW, 0, 9),
+                (GY, 4, 1),
+                (BW, 0, 9),
+                (BZ, 4, 1),
+                (RX, 0, 5),
+                (GZ, 4, 1),
+                (GY, 0, 4),
+                (GX, 0, 5),
+                (BZ, 0, 1),
+                (GZ, 0, 4),
+                (BX, 0, 5),
+                (BZ, 1, 1),
+                (BY, 0, 4),
+                (RY, 0, 5),
+                (BZ, 2, 1),
+                (RZ, 0, 5),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b10010,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 8,
+            delta_bits: [6, 5, 5],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 8),
+                (GZ, 4, 1),
+                (BY, 4, 1),
+                (GW, 0, 8),
+                (BZ, 2, 1),
+                (GY, 4, 1),
+                (BW, 0, 8),
+                (BZ, 3, 1),
+                (BZ, 4, 1),
+                (RX, 0, 6),
+                (GY, 0, 4),
+                (GX, 0, 5),
+                (BZ, 0, 1),
+                (GZ, 0, 4),
+                (BX, 0, 5),
+                (BZ, 1, 1),
+                (BY, 0, 4),
+                (RY, 0, 6),
+                (RZ, 0, 6),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b10110,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 8,
+            delta_bits: [5, 6, 5],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 8),
+                (BZ, 0, 1),
+                (BY, 4, 1),
+                (GW, 0, 8),
+                (GY, 5, 1),
+                (GY, 4, 1),
+                (BW, 0, 8),
+                (GZ, 5, 1),
+                (BZ, 4, 1),
+                (RX, 0, 5),
+                (GZ, 4, 1),
+                (GY, 0, 4),
+                (GX, 0, 6),
+                (GZ, 0, 4),
+                (BX, 0, 5),
+                (BZ, 1, 1),
+                (BY, 0, 4),
+                (RY, 0, 5),
+                (BZ, 2, 1),
+                (RZ, 0, 5),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b11010,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 8,
+            delta_bits: [5, 5, 6],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 8),
+                (BZ, 1, 1),
+                (BY, 4, 1),
+                (GW, 0, 8),
+                (BY, 5, 1),
+                (GY, 4, 1),
+                (BW, 0, 8),
+                (BZ, 5, 1),
+                (BZ, 4, 1),
+                (RX, 0, 5),
+                (GZ, 4, 1),
+                (GY, 0, 4),
+                (GX, 0, 5),
+                (BZ, 0, 1),
+                (GZ, 0, 4),
+                (BX, 0, 6),
+                (BY, 0, 4),
+                (RY, 0, 5),
+                (BZ, 2, 1),
+                (RZ, 0, 5),
+                (BZ, 3, 1),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b11110,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 6,
+            delta_bits: [6, 6, 6],
+            is_transformed: false,
+            layout: &[
+                (RW, 0, 6),
+                (GZ, 4, 1),
+                (BZ, 0, 1),
+                (BZ, 1, 1),
+                (BY, 4, 1),
+                (GW, 0, 6),
+                (GY, 5, 1),
+                (BY, 5, 1),
+                (BZ, 2, 1),
+                (GY, 4, 1),
+                (BW, 0, 6),
+                (GZ, 5, 1),
+                (BZ, 3, 1),
+                (BZ, 5, 1),
+                (BZ, 4, 1),
+                (RX, 0, 6),
+                (GY, 0, 4),
+                (GX, 0, 6),
+                (GZ, 0, 4),
+                (BX, 0, 6),
+                (BY, 0, 4),
+                (RY, 0, 6),
+                (RZ, 0, 6),
+                (D, 0, 5),
+            ],
+        },
+    ),
+    (
+        0b00011,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 10,
+            delta_bits: [10, 10, 10],
+            is_transformed: false,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 10),
+                (GX, 0, 10),
+                (BX, 0, 10),
+            ],
+        },
+    ),
+    (
+        0b00111,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 11,
+            delta_bits: [9, 9, 9],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 9),
+                (RW, 10, 1),
+                (GX, 0, 9),
+                (GW, 10, 1),
+                (BX, 0, 9),
+                (BW, 10, 1),
+            ],
+        },
+    ),
+    // The high bits of the first endpoint are stored in reverse order in the last two modes
+    (
+        0b01011,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 12,
+            delta_bits: [8, 8, 8],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 8),
+                (RW, 11, 1),
+                (RW, 10, 1),
+                (GX, 0, 8),
+                (GW, 11, 1),
+                (GW, 10, 1),
+                (BX, 0, 8),
+                (BW, 11, 1),
+                (BW, 10, 1),
+            ],
+        },
+    ),
+    (
+        0b01111,
+        Bc6hMode {
+            mode_bits: 5,
+            endpoint_bits: 16,
+            delta_bits: [4, 4, 4],
+            is_transformed: true,
+            layout: &[
+                (RW, 0, 10),
+                (GW, 0, 10),
+                (BW, 0, 10),
+                (RX, 0, 4),
+                (RW, 15, 1),
+                (RW, 14, 1),
+                (RW, 13, 1),
+                (RW, 12, 1),
+                (RW, 11, 1),
+                (RW, 10, 1),
+                (GX, 0, 4),
+                (GW, 15, 1),
+                (GW, 14, 1),
+                (GW, 13, 1),
+                (GW, 12, 1),
+                (GW, 11, 1),
+                (GW, 10, 1),
+                (BX, 0, 4),
+                (BW, 15, 1),
+                (BW, 14, 1),
+                (BW, 13, 1),
+                (BW, 12, 1),
+                (BW, 11, 1),
+                (BW, 10, 1),
+            ],
+        },
+    ),
+];
+
+// Index into BC6H_MODES, None for reserved modes
+pub(crate) fn bc6h_block_mode(block: &[u8]) -> Option<usize> {
+    let mode_bits = if block[0] & 0x2 == 0 {
+        block[0] & 0x3
+    } else {
+        block[0] & 0x1f
+    };
+    BC6H_MODES.iter().position(|(bits, _)| *bits == mode_bits as u32)
+}
+
+// None for single subset modes
+pub(crate) fn bc6h_block_partition(block: &[u8], mode_index: usize) -> Option<u32> {
+    let mode = &BC6H_MODES[mode_index].1;
+    let mut bits = BitReader::new(block);
+    bits.read(mode.mode_bits);
+
+    let mut partition = None;
+    for &(field, first_bit, bit_count) in mode.layout {
+        let value = bits.read(bit_count as u32);
+        if field == D {
+            *partition.get_or_insert(0) |= value << first_bit;
+        }
+    }
+    partition
+}
+
+fn sign_extend(value: i32, bit_count: u32) -> i32 {
+    let shift = 32 - bit_count;
+    (value << shift) >> shift
+}
+
+fn bc6h_unquantize(value: i32, bit_count: u32, is_signed: bool) -> i32 {
+    if !is_signed {
+        if bit_count >= 15 || value == 0 {
+            value
+        } else if value == (1 << bit_count) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bit_count
+        }
+    } else {
+        if bit_count >= 16 {
+            return value;
+        }
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bit_count - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bit_count - 1)
+        };
+        if value < 0 {
+            -unquantized
+        } else {
+            unquantized
+        }
+    }
+}
+
+// Scales the interpolated value to the half float range, returns half float bits
+fn bc6h_finish_unquantize(value: i32, is_signed: bool) -> u16 {
+    if !is_signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn decode_bc6h(block: &[u8], is_signed: bool) -> DecodedBlock {
+    let mode = match bc6h_block_mode(block) {
+        Some(mode) => &BC6H_MODES[mode].1,
+        None => return [[0.0, 0.0, 0.0, 1.0]; 16],
+    };
+
+    let mut bits = BitReader::new(block);
+    bits.read(mode.mode_bits);
+
+    // 4 endpoints (w, x, y, z) of 3 channels, and the partition
+    let mut fields = [0i32; 13];
+    for &(field, first_bit, bit_count) in mode.layout {
+        fields[field as usize] |= (bits.read(bit_count as u32) as i32) << first_bit;
+    }
+    let partition = fields[D as usize] as u32;
+    let subset_count = if mode.layout.iter().any(|&(field, _, _)| field == D) {
+        2
+    } else {
+        1
+    };
+    let endpoint_count = subset_count * 2;
+
+    let mut endpoints = [[0i32; 3]; 4];
+    for (endpoint, values) in endpoints.iter_mut().enumerate().take(endpoint_count) {
+        values.copy_from_slice(&fields[endpoint * 3..endpoint * 3 + 3]);
+    }
+
+    for channel in 0..3 {
+        if is_signed {
+            endpoints[0][channel] = sign_extend(endpoints[0][channel], mode.endpoint_bits);
+        }
+        for endpoint in endpoints.iter_mut().take(endpoint_count).skip(1) {
+            if mode.is_transformed || is_signed {
+                endpoint[channel] = sign_extend(endpoint[channel], mode.delta_bits[channel]);
+            }
+        }
+        if mode.is_transformed {
+            let base = endpoints[0][channel];
+            for endpoint in endpoints.iter_mut().take(endpoint_count).skip(1) {
+                let value = (endpoint[channel] + base) & ((1 << mode.endpoint_bits) - 1);
+                endpoint[channel] = if is_signed {
+                    sign_extend(value, mode.endpoint_bits)
+                } else {
+                    value
+                };
+            }
+        }
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bc6h_unquantize(endpoint[channel], mode.endpoint_bits, is_signed);
+        }
+    }
+
+    let index_bits = if subset_count == 1 { 4 } else { 3 };
+    let mut texels = [[0.0; 4]; 16];
+    for (texel, value) in texels.iter_mut().enumerate() {
+        let anchor = is_anchor(subset_count as u32, partition, texel) as u32;
+        let weight = weights(index_bits)[bits.read(index_bits - anchor) as usize] as i32;
+        let subset = subset(subset_count as u32, partition, texel);
+        let e0 = endpoints[subset * 2];
+        let e1 = endpoints[subset * 2 + 1];
+
+        for channel in 0..3 {
+            let interpolated = (e0[channel] * (64 - weight) + e1[channel] * weight + 32) >> 6;
+            value[channel] = half_to_f32(bc6h_finish_unquantize(interpolated, is_signed));
+        }
+        value[3] = 1.0;
+    }
+    texels
+}
+
+// Encoders fit a line through the texels of every subset, pick the indices against the decoded palette and refit
+// the endpoints to the chosen indices with least squares. BC1 to BC3 use the 4-color mode (BC1 can also use the
+// 3-color mode for punch-through alpha) and BC6H uses mode 11 only. BC7 searches more modes and partitions with
+// slower presets.
+
+// Parameters of the endpoint fits
+#[derive(Clone, Copy)]
+struct Fit {
+    weights: [f32; 4],
+    preset: EncodePreset,
+    // Least squares passes that refit the endpoints to the chosen indices
+    refinements: u32,
+    // Fraction of the segment length that both endpoints are moved towards the center by
+    inset: f32,
+}
+
+// Power iterations for the principal axis of a line fit
+const AXIS_ITERATIONS: u32 = 8;
+
+// Writes `block_size(dxgi_format)` bytes, false if the format doesn't have an encoder. The exhaustive preset also
+// tries endpoints inset towards the center of the block and keeps the block with the smallest weighted error.
+pub(crate) fn encode_block(dxgi_format: u32, texels: &DecodedBlock, options: &EncodeOptions, block: &mut [u8]) -> bool {
+    let fit = Fit {
+        weights: options.channel_weights,
+        preset: options.preset,
+        refinements: match options.preset {
+            EncodePreset::Fast => 0,
+            EncodePreset::Default => 1,
+            EncodePreset::Exhaustive => 4,
+        },
+        inset: 0.0,
+    };
+    if !encode_block_with(dxgi_format, texels, options.bc1_alpha, &fit, block) {
+        return false;
+    }
+
+    let has_line_fit = !matches!(
+        dxgi_format,
+        DXGI_FORMAT_BC4_UNORM | DXGI_FORMAT_BC4_SNORM | DXGI_FORMAT_BC5_UNORM | DXGI_FORMAT_BC5_SNORM
+    );
+    if options.preset == EncodePreset::Exhaustive && has_line_fit {
+        let mut best_error = block_error(dxgi_format, texels, block, &fit.weights);
+        let mut candidate = [0u8; 16];
+        let candidate = &mut candidate[..block.len()];
+        for inset in [1.0 / 64.0, 1.0 / 32.0, 1.0 / 16.0, 1.0 / 8.0] {
+            encode_block_with(dxgi_format, texels, options.bc1_alpha, &Fit { inset, ..fit }, candidate);
+            let error = block_error(dxgi_format, texels, candidate, &fit.weights);
+            if error < best_error {
+                best_error = error;
+                block.copy_from_slice(candidate);
+            }
+        }
+    }
+    true
+}
+
+fn block_error(dxgi_format: u32, texels: &DecodedBlock, block: &[u8], weights: &[f32; 4]) -> f32 {
+    let decoded = decode_block(dxgi_format, block).unwrap();
+    texels
+        .iter()
+        .zip(decoded.iter())
+        .map(|(texel, decoded)| {
+            (0..4)
+                .map(|channel| weights[channel] * (texel[channel] - decoded[channel]).powi(2))
+                .sum::<f32>()
+        })
+        .sum()
+}
+
+fn encode_block_with(
+    dxgi_format: u32,
+    texels: &DecodedBlock,
+    bc1_alpha: Bc1Alpha,
+    fit: &Fit,
+    block: &mut [u8],
+) -> bool {
+    match dxgi_format {
+        DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB => match bc1_alpha {
+            Bc1Alpha::PunchThrough { threshold } if has_transparent_texels(texels, threshold) => {
+                block[..8].copy_from_slice(&encode_punch_through_block(texels, threshold, fit))
+            }
+            _ => block[..8].copy_from_slice(&encode_color_block(texels, fit)),
+        },
+        DXGI_FORMAT_BC2_UNORM | DXGI_FORMAT_BC2_UNORM_SRGB => {
+            let mut alpha = 0u64;
+            for (texel, value) in texels.iter().enumerate() {
+                alpha |= ((value[3].clamp(0.0, 1.0) * 15.0).round() as u64) << (texel * 4);
+            }
+            block[..8].copy_from_slice(&alpha.to_le_bytes());
+            block[8..16].copy_from_slice(&encode_color_block(texels, fit));
+        }
+        DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB => {
+            block[..8].copy_from_slice(&encode_channel_block(&channel(texels, 3), false));
+            block[8..16].copy_from_slice(&encode_color_block(texels, fit));
+        }
+        DXGI_FORMAT_BC4_UNORM | DXGI_FORMAT_BC4_SNORM => {
+            let is_signed = dxgi_format == DXGI_FORMAT_BC4_SNORM;
+            block[..8].copy_from_slice(&encode_channel_block(&channel(texels, 0), is_signed));
+        }
+        DXGI_FORMAT_BC5_UNORM | DXGI_FORMAT_BC5_SNORM => {
+            let is_signed = dxgi_format == DXGI_FORMAT_BC5_SNORM;
+            block[..8].copy_from_slice(&encode_channel_block(&channel(texels, 0), is_signed));
+            block[8..16].copy_from_slice(&encode_channel_block(&channel(texels, 1), is_signed));
+        }
+        DXGI_FORMAT_BC6H_UF16 => block[..16].copy_from_slice(&encode_bc6h(texels, false, fit)),
+        DXGI_FORMAT_BC6H_SF16 => block[..16].copy_from_slice(&encode_bc6h(texels, true, fit)),
+        DXGI_FORMAT_BC7_UNORM | DXGI_FORMAT_BC7_UNORM_SRGB => block[..16].copy_from_slice(&encode_bc7(texels, fit)),
+        _ => return false,
+    }
+    true
+}
+
+fn channel(texels: &DecodedBlock, channel: usize) -> [f32; 16] {
+    let mut values = [0.0; 16];
+    for (value, texel) in values.iter_mut().zip(texels.iter()) {
+        *value = texel[channel];
+    }
+    values
+}
+
+// Writes 128-bit blocks LSB first
+struct BitWriter {
+    bits: u128,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bits: 0, bit_count: 0 }
+    }
+
+    fn write(&mut self, value: u32, bit_count: u32) {
+        self.bits |= (value as u128 & ((1u128 << bit_count) - 1)) << self.bit_count;
+        self.bit_count += bit_count;
+    }
+
+    fn finish(&self) -> [u8; 16] {
+        self.bits.to_le_bytes()
+    }
+}
+
+// Endpoints of the segment that covers the texels along their principal axis, only the first `channel_count`
+// channels are used
+fn fit_line(texels: &[[f32; 4]], channel_count: usize, inset: f32) -> ([f32; 4], [f32; 4]) {
+    let mut mean = [0.0f32; 4];
+    for texel in texels {
+        for channel in 0..channel_count {
+            mean[channel] += texel[channel] / texels.len() as f32;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for texel in texels {
+        for i in 0..channel_count {
+            for j in 0..channel_count {
+                covariance[i][j] += (texel[i] - mean[i]) * (texel[j] - mean[j]);
+            }
+        }
+    }
+
+    // Power iteration starting from the channel with the largest variance
+    let largest = (0..channel_count)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap_or(0);
+    let mut axis = covariance[largest];
+    for _ in 0..AXIS_ITERATIONS {
+        let length = axis.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if length < 1e-12 {
+            return (mean, mean);
+        }
+        let normalized = axis.map(|value| value / length);
+        axis = [0.0; 4];
+        for i in 0..channel_count {
+            for j in 0..channel_count {
+                axis[i] += covariance[i][j] * normalized[j];
+            }
+        }
+        if axis.iter().all(|value| *value == 0.0) {
+            axis = normalized;
+            break;
+        }
+    }
+    let length = axis.iter().map(|value| value * value).sum::<f32>().sqrt();
+    let axis = axis.map(|value| value / length);
+
+    let (mut min, mut max) = (f32::MAX, f32::MIN);
+    for texel in texels {
+        let projection: f32 = (0..channel_count)
+            .map(|channel| (texel[channel] - mean[channel]) * axis[channel])
+            .sum();
+        min = min.min(projection);
+        max = max.max(projection);
+    }
+
+    // Texels that are not on a line can push the endpoints out of the bounding box
+    let point = |distance: f32| {
+        let mut point = [0.0; 4];
+        for channel in 0..channel_count {
+            let (low, high) = texels.iter().fold((f32::MAX, f32::MIN), |(low, high), texel| {
+                (low.min(texel[channel]), high.max(texel[channel]))
+            });
+            point[channel] = (mean[channel] + axis[channel] * distance).clamp(low, high);
+        }
+        point
+    };
+    let inset = (max - min) * inset;
+    (point(min + inset), point(max - inset))
+}
+
+// `fit_line` for the weighted squared error, the first `channel_count` channels are scaled by the square roots of
+// their weights. Channels with a zero weight get the mean value for both endpoints.
+fn fit_weighted_line(texels: &[[f32; 4]], fit: &Fit, channel_count: usize) -> ([f32; 4], [f32; 4]) {
+    let weights = &fit.weights[..channel_count];
+    let mut scaled = texels.to_vec();
+    for texel in scaled.iter_mut() {
+        for (value, weight) in texel.iter_mut().zip(weights.iter()) {
+            *value *= weight.sqrt();
+        }
+    }
+
+    let (e0, e1) = fit_line(&scaled, channel_count, fit.inset);
+    let unscale = |mut endpoint: [f32; 4]| {
+        for (channel, weight) in weights.iter().enumerate() {
+            endpoint[channel] = match weight.sqrt() {
+                scale if scale > 0.0 => endpoint[channel] / scale,
+                _ => texels.iter().map(|texel| texel[channel]).sum::<f32>() / texels.len() as f32,
+            };
+        }
+        endpoint
+    };
+    (unscale(e0), unscale(e1))
+}
+
+// Endpoints with the smallest squared error for texels interpolated with `factors` from the first towards the second
+// endpoint, None if all factors are the same
+fn least_squares_endpoints(texels: &[[f32; 4]], factors: &[f32]) -> Option<([f32; 4], [f32; 4])> {
+    let (mut aa, mut ab, mut bb) = (0.0f32, 0.0f32, 0.0f32);
+    let (mut ax, mut bx) = ([0.0f32; 4], [0.0f32; 4]);
+    for (texel, &factor) in texels.iter().zip(factors) {
+        let (a, b) = (1.0 - factor, factor);
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        for channel in 0..4 {
+            ax[channel] += a * texel[channel];
+            bx[channel] += b * texel[channel];
+        }
+    }
+
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+    let mut endpoints = ([0.0; 4], [0.0; 4]);
+    for channel in 0..4 {
+        endpoints.0[channel] = (bb * ax[channel] - ab * bx[channel]) / determinant;
+        endpoints.1[channel] = (aa * bx[channel] - ab * ax[channel]) / determinant;
+    }
+    Some(endpoints)
+}
+
+// Entry with the smallest weighted squared error, only the first `weights.len()` channels are used
+fn closest_index(texel: &[f32; 4], palette: &[[f32; 4]], weights: &[f32]) -> u32 {
+    let error = |entry: &[f32; 4]| -> f32 {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(channel, weight)| weight * (texel[channel] - entry[channel]).powi(2))
+            .sum()
+    };
+
+    let mut best = 0;
+    for (index, entry) in palette.iter().enumerate().skip(1) {
+        if error(entry) < error(&palette[best]) {
+            best = index;
+        }
+    }
+    best as u32
+}
+
+fn pack_565(color: [f32; 4]) -> u16 {
+    let quantize = |value: f32, max: f32| (value.clamp(0.0, 1.0) * max).round() as u16;
+    (quantize(color[0], 31.0) << 11) | (quantize(color[1], 63.0) << 5) | quantize(color[2], 31.0)
+}
+
+fn encode_color_block(texels: &DecodedBlock, fit: &Fit) -> [u8; 8] {
+    let mut endpoints = fit_weighted_line(texels, fit, 3);
+    let mut best = ([0u8; 8], f32::MAX);
+    for _ in 0..=fit.refinements {
+        let (block, error, factors) = pack_color_block(texels, endpoints, &fit.weights);
+        if error >= best.1 {
+            break;
+        }
+        best = (block, error);
+        match least_squares_endpoints(texels, &factors) {
+            Some(refined) => endpoints = refined,
+            None => break,
+        }
+    }
+    best.0
+}
+
+// Block for the endpoints, its weighted squared error and the interpolation factor of every texel
+fn pack_color_block(
+    texels: &DecodedBlock,
+    (e0, e1): ([f32; 4], [f32; 4]),
+    weights: &[f32; 4],
+) -> ([u8; 8], f32, [f32; 16]) {
+    // The 4-color mode needs color0 > color1
+    let (color0, color1) = (pack_565(e0).max(pack_565(e1)), pack_565(e0).min(pack_565(e1)));
+    let mut block = [0u8; 8];
+    block[..2].copy_from_slice(&color0.to_le_bytes());
+    block[2..4].copy_from_slice(&color1.to_le_bytes());
+
+    // Indices 0, 1, 2, 3 repeated, so the first 4 decoded texels are the palette
+    block[4..8].copy_from_slice(&0xe4e4_e4e4u32.to_le_bytes());
+    let palette = decode_color_block(&block, false);
+
+    let mut indices = 0u32;
+    let mut error = 0.0;
+    let mut factors = [0.0; 16];
+    for (texel, value) in texels.iter().enumerate() {
+        let index = closest_index(value, &palette[..4], &weights[..3]);
+        indices |= index << (texel * 2);
+        error += (0..3)
+            .map(|channel| weights[channel] * (value[channel] - palette[index as usize][channel]).powi(2))
+            .sum::<f32>();
+        factors[texel] = [0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0][index as usize];
+    }
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+    (block, error, factors)
+}
+
+// Texels that the 3-color mode makes transparent black, alpha below `threshold`
+pub(crate) fn has_transparent_texels(texels: &DecodedBlock, threshold: f32) -> bool {
+    texels.iter().any(|texel| texel[3] < threshold)
+}
+
+// BC1 block in the 3-color mode, transparent texels use index 3 and don't affect the endpoints
+fn encode_punch_through_block(texels: &DecodedBlock, threshold: f32, fit: &Fit) -> [u8; 8] {
+    let opaque: Vec<[f32; 4]> = texels.iter().copied().filter(|texel| texel[3] >= threshold).collect();
+    let mut block = [0u8; 8];
+    if opaque.is_empty() {
+        block[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        return block;
+    }
+
+    // Opaque texels are repeated to fill the block, so the line fits only them
+    let mut fitted = [[0.0; 4]; 16];
+    for (texel, value) in fitted.iter_mut().zip(opaque.iter().cycle()) {
+        *texel = *value;
+    }
+    let (e0, e1) = fit_weighted_line(&fitted, fit, 3);
+
+    // The 3-color mode needs color0 <= color1
+    let (color0, color1) = (pack_565(e0).min(pack_565(e1)), pack_565(e0).max(pack_565(e1)));
+    block[..2].copy_from_slice(&color0.to_le_bytes());
+    block[2..4].copy_from_slice(&color1.to_le_bytes());
+    block[4..8].copy_from_slice(&0xe4e4_e4e4u32.to_le_bytes());
+    let palette = decode_color_block(&block, true);
+
+    let mut indices = 0u32;
+    for (texel, value) in texels.iter().enumerate() {
+        let index = match value[3] < threshold {
+            true => 3,
+            false => closest_index(value, &palette[..3], &fit.weights[..3]),
+        };
+        indices |= index << (texel * 2);
+    }
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+fn encode_channel_block(values: &[f32; 16], is_signed: bool) -> [u8; 8] {
+    let quantize = |value: f32| {
+        if is_signed {
+            (value.clamp(-1.0, 1.0) * 127.0).round() as i8 as u8
+        } else {
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+    };
+    let min = values.iter().copied().fold(f32::MAX, f32::min);
+    let max = values.iter().copied().fold(f32::MIN, f32::max);
+
+    // The 8-value mode needs value0 > value1
+    let mut block = [0u8; 8];
+    block[0] = quantize(max);
+    block[1] = quantize(min);
+
+    // Indices 0 to 7 repeated, so the first 8 decoded values are the palette
+    let mut pattern = 0u64;
+    for texel in 0..16 {
+        pattern |= ((texel % 8) as u64) << (texel * 3);
+    }
+    block[2..8].copy_from_slice(&pattern.to_le_bytes()[..6]);
+    let palette = decode_channel_block(&block, is_signed).map(|value| [value, 0.0, 0.0, 0.0]);
+
+    let mut indices = 0u64;
+    for (texel, value) in values.iter().enumerate() {
+        indices |= (closest_index(&[*value, 0.0, 0.0, 0.0], &palette[..8], &[1.0]) as u64) << (texel * 3);
+    }
+    block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+// The first texel is an anchor and its index must have the most significant bit 0
+fn swap_for_anchor<T>(endpoints: &mut [T; 2], indices: &mut [u32; 16], index_count: u32) {
+    if indices[0] >= index_count / 2 {
+        endpoints.swap(0, 1);
+        for index in indices.iter_mut() {
+            *index = index_count - 1 - *index;
+        }
+    }
+}
+
+// Mode, partition, rotation and index selection of a BC7 block
+#[derive(Clone, Copy)]
+struct Bc7Choice {
+    mode: u32,
+    partition: u32,
+    rotation: u32,
+    index_selection: u32,
+}
+
+impl Bc7Choice {
+    fn new(mode: u32) -> Self {
+        Self {
+            mode,
+            partition: 0,
+            rotation: 0,
+            index_selection: 0,
+        }
+    }
+}
+
+// Fast tries modes 6 and 5, default also tries mode 4 and the best partitions of the 2-subset modes, exhaustive tries
+// all modes, rotations and index selections and more partitions
+fn encode_bc7(texels: &DecodedBlock, fit: &Fit) -> [u8; 16] {
+    let is_opaque = texels.iter().all(|texel| texel[3] >= 254.5 / 255.0);
+    let (single_modes, partitioned_modes, partition_count): (&[u32], &[u32], usize) = match (fit.preset, is_opaque) {
+        (EncodePreset::Fast, _) => (&[6, 5], &[], 0),
+        (EncodePreset::Default, true) => (&[6, 5], &[1, 3], 4),
+        (EncodePreset::Default, false) => (&[6, 5, 4], &[7], 4),
+        (EncodePreset::Exhaustive, true) => (&[6, 5, 4], &[0, 1, 2, 3, 7], 16),
+        (EncodePreset::Exhaustive, false) => (&[6, 5, 4], &[7], 16),
+    };
+
+    let mut choices = Vec::new();
+    for &mode in single_modes {
+        let mode_info = &BC7_MODES[mode as usize];
+        let rotations = match fit.preset {
+            EncodePreset::Exhaustive => 1 << mode_info.rotation_bits,
+            _ => 1,
+        };
+        for rotation in 0..rotations {
+            for index_selection in 0..1 << mode_info.index_selection_bits {
+                choices.push(Bc7Choice {
+                    rotation,
+                    index_selection,
+                    ..Bc7Choice::new(mode)
+                });
+            }
+        }
+    }
+    // Partitions are ranked once for all modes with the same subset count
+    let channel_count = if is_opaque { 3 } else { 4 };
+    let mut rankings = [Vec::new(), Vec::new()];
+    for &mode in partitioned_modes {
+        let mode_info = &BC7_MODES[mode as usize];
+        let ranking = &mut rankings[mode_info.subset_count as usize - 2];
+        if ranking.is_empty() {
+            *ranking = bc7_partitions(texels, fit, mode_info.subset_count, channel_count);
+        }
+        let partitions = ranking
+            .iter()
+            .filter(|&&partition| partition < 1 << mode_info.partition_bits);
+        for &partition in partitions.take(partition_count) {
+            choices.push(Bc7Choice {
+                partition,
+                ..Bc7Choice::new(mode)
+            });
+        }
+    }
+
+    choices
+        .iter()
+        .map(|choice| encode_bc7_mode(texels, fit, choice))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+        .0
+}
+
+// Texels of every subset of a partition
+fn bc7_subsets(subset_count: u32, partition: u32) -> [Vec<usize>; 3] {
+    let mut subsets = [Vec::new(), Vec::new(), Vec::new()];
+    for texel in 0..16 {
+        subsets[subset(subset_count, partition, texel)].push(texel);
+    }
+    subsets
+}
+
+// Partitions ordered by the weighted squared distance of the texels from the principal axis of their subset
+fn bc7_partitions(texels: &DecodedBlock, fit: &Fit, subset_count: u32, channel_count: usize) -> Vec<u32> {
+    let mut scaled = [[0.0f32; 4]; 16];
+    for (scaled, texel) in scaled.iter_mut().zip(texels.iter()) {
+        for channel in 0..channel_count {
+            scaled[channel] = texel[channel] * fit.weights[channel].sqrt();
+        }
+    }
+
+    // Total variance minus the variance along the principal axis, from the sums of the values and their products
+    let line_error = |count: f32, sums: &[f32; 4], products: &[[f32; 4]; 4]| -> f32 {
+        let covariance: [[f32; 4]; 4] =
+            std::array::from_fn(|i| std::array::from_fn(|j| products[i][j] - sums[i] * sums[j] / count));
+        let trace: f32 = (0..4).map(|channel| covariance[channel][channel]).sum();
+
+        let largest = (0..4)
+            .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+            .unwrap();
+        let mut axis = covariance[largest];
+        let mut variance = 0.0;
+        for _ in 0..AXIS_ITERATIONS {
+            let length = axis.iter().map(|value| value * value).sum::<f32>().sqrt();
+            if length < 1e-12 {
+                break;
+            }
+            let normalized = axis.map(|value| value / length);
+            axis = std::array::from_fn(|i| (0..4).map(|j| covariance[i][j] * normalized[j]).sum());
+            variance = (0..4).map(|i| axis[i] * normalized[i]).sum();
+        }
+        trace - variance
+    };
+
+    let mut partitions: Vec<(u32, f32)> = (0..64)
+        .map(|partition| {
+            let (mut counts, mut sums, mut products) = ([0.0f32; 3], [[0.0f32; 4]; 3], [[[0.0f32; 4]; 4]; 3]);
+            for (texel, value) in scaled.iter().enumerate() {
+                let subset = subset(subset_count, partition, texel);
+                counts[subset] += 1.0;
+                for i in 0..4 {
+                    sums[subset][i] += value[i];
+                    for j in 0..4 {
+                        products[subset][i][j] += value[i] * value[j];
+                    }
+                }
+            }
+            let error = (0..subset_count as usize)
+                .map(|subset| line_error(counts[subset], &sums[subset], &products[subset]))
+                .sum();
+            (partition, error)
+        })
+        .collect();
+    partitions.sort_by(|a, b| a.1.total_cmp(&b.1));
+    partitions.into_iter().map(|(partition, _)| partition).collect()
+}
+
+// Stored endpoint fields and their values expanded to 8 bits
+#[derive(Clone, Copy, Default)]
+struct Bc7Endpoint {
+    stored: [u32; 4],
+    pbit: u32,
+    expanded: [u32; 4],
+}
+
+// Stored value with `bit_count` bits, and the `pbit` below them, whose expanded value is closest to `value`
+fn quantize_bc7(value: f32, bit_count: u32, pbit: Option<u32>) -> (u32, u32) {
+    let total_bits = bit_count + pbit.is_some() as u32;
+    let expand = |stored: u32| {
+        let value = match pbit {
+            Some(pbit) => (stored << 1) | pbit,
+            None => stored,
+        } << (8 - total_bits);
+        value | (value >> total_bits)
+    };
+    let target = value.clamp(0.0, 1.0) * 255.0;
+    let estimate = (value.clamp(0.0, 1.0) * ((1 << bit_count) - 1) as f32).round() as i32;
+    (estimate - 1..=estimate + 1)
+        .map(|stored| stored.clamp(0, (1 << bit_count) - 1) as u32)
+        .map(|stored| (stored, expand(stored)))
+        .min_by(|a, b| (a.1 as f32 - target).abs().total_cmp(&(b.1 as f32 - target).abs()))
+        .unwrap()
+}
+
+fn quantize_bc7_endpoint(
+    endpoint: &[f32; 4],
+    mode: &Bc7Mode,
+    pbit: Option<u32>,
+    weights: &[f32; 4],
+) -> (Bc7Endpoint, f32) {
+    let mut quantized = Bc7Endpoint {
+        pbit: pbit.unwrap_or(0),
+        expanded: [255; 4],
+        ..Bc7Endpoint::default()
+    };
+    let mut error = 0.0;
+    for channel in 0..4 {
+        let bit_count = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+        if bit_count > 0 {
+            let (stored, expanded) = quantize_bc7(endpoint[channel], bit_count, pbit);
+            quantized.stored[channel] = stored;
+            quantized.expanded[channel] = expanded;
+            error += weights[channel] * (expanded as f32 / 255.0 - endpoint[channel]).powi(2);
+        }
+    }
+    (quantized, error)
+}
+
+// Endpoints of a subset with the p-bits that give the smaller error
+fn quantize_bc7_endpoints(endpoints: &[[f32; 4]; 2], mode: &Bc7Mode, weights: &[f32; 4]) -> [Bc7Endpoint; 2] {
+    let quantize = |endpoint: &[f32; 4], pbit| quantize_bc7_endpoint(endpoint, mode, pbit, weights);
+    if mode.endpoint_pbits {
+        endpoints.map(|endpoint| {
+            let (zero, one) = (quantize(&endpoint, Some(0)), quantize(&endpoint, Some(1)));
+            // Opaque endpoints can only be stored with the p-bit set
+            if one.1 < zero.1 || (mode.alpha_bits > 0 && endpoint[3] >= 254.5 / 255.0) {
+                one.0
+            } else {
+                zero.0
+            }
+        })
+    } else if mode.shared_pbits {
+        let (zero, one) = (
+            endpoints.map(|e| quantize(&e, Some(0))),
+            endpoints.map(|e| quantize(&e, Some(1))),
+        );
+        match one[0].1 + one[1].1 < zero[0].1 + zero[1].1 {
+            true => one.map(|(endpoint, _)| endpoint),
+            false => zero.map(|(endpoint, _)| endpoint),
+        }
+    } else {
+        endpoints.map(|endpoint| quantize(&endpoint, None).0)
+    }
+}
+
+// Block with the smallest weighted error for a choice and the error
+fn encode_bc7_mode(texels: &DecodedBlock, fit: &Fit, choice: &Bc7Choice) -> ([u8; 16], f32) {
+    let mode = &BC7_MODES[choice.mode as usize];
+    let separate_alpha = mode.secondary_index_bits > 0;
+    let color_channels = if mode.alpha_bits > 0 && !separate_alpha { 4 } else { 3 };
+
+    // Rotation swaps alpha with a color channel after decoding, so the swapped texels are encoded
+    let mut rotated = *texels;
+    let mut weights = fit.weights;
+    if choice.rotation > 0 {
+        let channel = choice.rotation as usize - 1;
+        for texel in rotated.iter_mut() {
+            texel.swap(3, channel);
+        }
+        weights.swap(3, channel);
+    }
+    let rotated_fit = Fit { weights, ..*fit };
+
+    let subsets = bc7_subsets(mode.subset_count, choice.partition);
+    let subset_texels = |subset: &Vec<usize>| subset.iter().map(|&texel| rotated[texel]).collect::<Vec<_>>();
+    let mut endpoints = [[[0.0; 4]; 2]; 3];
+    for (subset, endpoints) in subsets
+        .iter()
+        .zip(endpoints.iter_mut())
+        .take(mode.subset_count as usize)
+    {
+        if subset.is_empty() {
+            continue;
+        }
+        let values = subset_texels(subset);
+        let (e0, e1) = fit_weighted_line(&values, &rotated_fit, color_channels);
+        *endpoints = [e0, e1];
+        if separate_alpha {
+            endpoints[0][3] = values.iter().map(|value| value[3]).fold(f32::MAX, f32::min);
+            endpoints[1][3] = values.iter().map(|value| value[3]).fold(f32::MIN, f32::max);
+        }
+    }
+
+    let mut best = ([0u8; 16], f32::MAX);
+    for _ in 0..=fit.refinements {
+        let (block, factors) = pack_bc7(&rotated, &weights, choice, &subsets, &endpoints);
+        let error = block_error(DXGI_FORMAT_BC7_UNORM, texels, &block, &fit.weights);
+        if error >= best.1 {
+            break;
+        }
+        best = (block, error);
+
+        for (subset, endpoints) in subsets
+            .iter()
+            .zip(endpoints.iter_mut())
+            .take(mode.subset_count as usize)
+        {
+            let values = subset_texels(subset);
+            let color_factors: Vec<f32> = subset.iter().map(|&texel| factors[texel][0]).collect();
+            if let Some((e0, e1)) = least_squares_endpoints(&values, &color_factors) {
+                endpoints[0][..color_channels].copy_from_slice(&e0[..color_channels]);
+                endpoints[1][..color_channels].copy_from_slice(&e1[..color_channels]);
+            }
+            let alpha_factors: Vec<f32> = subset.iter().map(|&texel| factors[texel][1]).collect();
+            if let (true, Some((e0, e1))) = (separate_alpha, least_squares_endpoints(&values, &alpha_factors)) {
+                endpoints[0][3] = e0[3];
+                endpoints[1][3] = e1[3];
+            }
+        }
+    }
+    best
+}
+
+// Quantizes the endpoints, picks the indices and writes the block. Also returns the interpolation factors of the color
+// and alpha indices of every texel.
+fn pack_bc7(
+    texels: &DecodedBlock,
+    channel_weights: &[f32; 4],
+    choice: &Bc7Choice,
+    subsets: &[Vec<usize>; 3],
+    endpoints: &[[[f32; 4]; 2]; 3],
+) -> ([u8; 16], [[f32; 2]; 16]) {
+    let mode = &BC7_MODES[choice.mode as usize];
+    let subset_count = mode.subset_count as usize;
+    let separate_alpha = mode.secondary_index_bits > 0;
+    let color_channels = if mode.alpha_bits > 0 && !separate_alpha { 4 } else { 3 };
+    let (color_index_bits, alpha_index_bits) = match (separate_alpha, choice.index_selection) {
+        (false, _) => (mode.index_bits, mode.index_bits),
+        (true, 0) => (mode.index_bits, mode.secondary_index_bits),
+        (true, _) => (mode.secondary_index_bits, mode.index_bits),
+    };
+
+    let mut quantized = [[Bc7Endpoint::default(); 2]; 3];
+    for (quantized, endpoints) in quantized.iter_mut().zip(endpoints.iter()).take(subset_count) {
+        *quantized = quantize_bc7_endpoints(endpoints, mode, channel_weights);
+    }
+
+    let interpolate = |e0: u32, e1: u32, weight: u32| ((64 - weight) * e0 + weight * e1 + 32) >> 6;
+    let palette = |[e0, e1]: [Bc7Endpoint; 2], index_bits: u32| -> Vec<[f32; 4]> {
+        weights(index_bits)
+            .iter()
+            .map(|&weight| {
+                std::array::from_fn(|channel| {
+                    interpolate(e0.expanded[channel], e1.expanded[channel], weight) as f32 / 255.0
+                })
+            })
+            .collect()
+    };
+    let (mut color_indices, mut alpha_indices) = ([0u32; 16], [0u32; 16]);
+    for (subset, quantized) in subsets.iter().zip(quantized.iter()).take(subset_count) {
+        let color_palette = palette(*quantized, color_index_bits);
+        let alpha_palette: Vec<[f32; 4]> = palette(*quantized, alpha_index_bits)
+            .iter()
+            .map(|entry| [entry[3], 0.0, 0.0, 0.0])
+            .collect();
+        for &texel in subset {
+            color_indices[texel] = closest_index(&texels[texel], &color_palette, &channel_weights[..color_channels]);
+            if separate_alpha {
+                alpha_indices[texel] = closest_index(&[texels[texel][3], 0.0, 0.0, 0.0], &alpha_palette, &[1.0]);
+            }
+        }
+    }
+
+    // Anchor indices must have the most significant bit 0, otherwise the subset endpoints are swapped
+    let color_count = 1 << color_index_bits;
+    for (subset_index, subset) in subsets.iter().enumerate().take(subset_count) {
+        if color_indices[anchor(mode.subset_count, choice.partition, subset_index)] >= color_count / 2 {
+            let [e0, e1] = &mut quantized[subset_index];
+            if separate_alpha {
+                for channel in 0..3 {
+                    std::mem::swap(&mut e0.stored[channel], &mut e1.stored[channel]);
+                    std::mem::swap(&mut e0.expanded[channel], &mut e1.expanded[channel]);
+                }
+            } else {
+                std::mem::swap(e0, e1);
+            }
+            for &texel in subset {
+                color_indices[texel] = color_count - 1 - color_indices[texel];
+            }
+        }
+    }
+    let alpha_count = 1 << alpha_index_bits;
+    if separate_alpha && alpha_indices[0] >= alpha_count / 2 {
+        let [e0, e1] = &mut quantized[0];
+        std::mem::swap(&mut e0.stored[3], &mut e1.stored[3]);
+        std::mem::swap(&mut e0.expanded[3], &mut e1.expanded[3]);
+        for index in alpha_indices.iter_mut() {
+            *index = alpha_count - 1 - *index;
+        }
+    }
+    if !separate_alpha {
+        alpha_indices = color_indices;
+    }
+
+    let mut bits = BitWriter::new();
+    bits.write(1 << choice.mode, choice.mode + 1);
+    bits.write(choice.partition, mode.partition_bits);
+    bits.write(choice.rotation, mode.rotation_bits);
+    bits.write(choice.index_selection, mode.index_selection_bits);
+    for channel in 0..4 {
+        let bit_count = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+        for endpoint in quantized.iter().take(subset_count).flatten() {
+            bits.write(endpoint.stored[channel], bit_count);
+        }
+    }
+    if mode.endpoint_pbits {
+        for endpoint in quantized.iter().take(subset_count).flatten() {
+            bits.write(endpoint.pbit, 1);
+        }
+    } else if mode.shared_pbits {
+        for [endpoint, _] in quantized.iter().take(subset_count) {
+            bits.write(endpoint.pbit, 1);
+        }
+    }
+    let (primary, secondary) = match choice.index_selection {
+        0 => (&color_indices, &alpha_indices),
+        _ => (&alpha_indices, &color_indices),
+    };
+    for (texel, index) in primary.iter().enumerate() {
+        bits.write(
+            *index,
+            mode.index_bits - is_anchor(mode.subset_count, choice.partition, texel) as u32,
+        );
+    }
+    if separate_alpha {
+        for (texel, index) in secondary.iter().enumerate() {
+            bits.write(*index, mode.secondary_index_bits - (texel == 0) as u32);
+        }
+    }
+
+    let factors = std::array::from_fn(|texel| {
+        [
+            weights(color_index_bits)[color_indices[texel] as usize] as f32 / 64.0,
+            weights(alpha_index_bits)[alpha_indices[texel] as usize] as f32 / 64.0,
+        ]
+    });
+    (bits.finish(), factors)
+}
+
+fn encode_bc6h(texels: &DecodedBlock, is_signed: bool, fit: &Fit) -> [u8; 16] {
+    // Work with the values before the final scaling in bc6h_finish_unquantize
+    let unscale = |value: f32| {
+        if value.is_nan() {
+            return 0.0;
+        }
+        let half = f32_to_half(value);
+        let magnitude = (half & 0x7fff).min(0x7bff) as f32;
+        match (is_signed, half & 0x8000 != 0) {
+            (false, true) => 0.0,
+            (false, false) => magnitude * 64.0 / 31.0,
+            (true, true) => -magnitude * 32.0 / 31.0,
+            (true, false) => magnitude * 32.0 / 31.0,
+        }
+    };
+    let mut unscaled = [[0.0; 4]; 16];
+    for (unscaled, texel) in unscaled.iter_mut().zip(texels.iter()) {
+        for channel in 0..3 {
+            unscaled[channel] = unscale(texel[channel]);
+        }
+    }
+    let mut endpoints = fit_weighted_line(&unscaled, fit, 3);
+
+    let dxgi_format = if is_signed {
+        DXGI_FORMAT_BC6H_SF16
+    } else {
+        DXGI_FORMAT_BC6H_UF16
+    };
+    let mut best = ([0u8; 16], f32::MAX);
+    for _ in 0..=fit.refinements {
+        let (block, factors) = pack_bc6h(&unscaled, is_signed, endpoints, &fit.weights);
+        let error = block_error(dxgi_format, texels, &block, &fit.weights);
+        if error >= best.1 {
+            break;
+        }
+        best = (block, error);
+        match least_squares_endpoints(&unscaled, &factors) {
+            Some(refined) => endpoints = refined,
+            None => break,
+        }
+    }
+    best.0
+}
+
+// Mode 11 block for endpoints before the final scaling and the interpolation factor of every texel
+fn pack_bc6h(
+    unscaled: &DecodedBlock,
+    is_signed: bool,
+    (e0, e1): ([f32; 4], [f32; 4]),
+    weights: &[f32; 4],
+) -> ([u8; 16], [f32; 16]) {
+    // Mode 11 has 10 bit endpoints, search around the inverse of bc6h_unquantize
+    let quantize = |value: f32| {
+        let (min, max, estimate) = if is_signed {
+            (-511, 511, (value * 512.0 / 32768.0).round() as i32)
+        } else {
+            (0, 1023, (value * 1024.0 / 65536.0).round() as i32)
+        };
+        let error = |quantized: i32| (bc6h_unquantize(quantized, 10, is_signed) as f32 - value).abs();
+        (estimate - 2..=estimate + 2)
+            .map(|quantized| quantized.clamp(min, max))
+            .min_by(|&a, &b| error(a).total_cmp(&error(b)))
+            .unwrap()
+    };
+    let mut endpoints = [e0.map(quantize), e1.map(quantize)];
+
+    let (a, b) = (
+        endpoints[0].map(|value| bc6h_unquantize(value, 10, is_signed)),
+        endpoints[1].map(|value| bc6h_unquantize(value, 10, is_signed)),
+    );
+    let mut palette = [[0.0; 4]; 16];
+    for (entry, &weight) in palette.iter_mut().zip(WEIGHTS4.iter()) {
+        let weight = weight as i32;
+        for channel in 0..3 {
+            entry[channel] = ((a[channel] * (64 - weight) + b[channel] * weight + 32) >> 6) as f32;
+        }
+    }
+
+    let mut indices = [0u32; 16];
+    for (index, texel) in indices.iter_mut().zip(unscaled.iter()) {
+        *index = closest_index(texel, &palette, &weights[..3]);
+    }
+    swap_for_anchor(&mut endpoints, &mut indices, 16);
+
+    let mut bits = BitWriter::new();
+    bits.write(0b00011, 5);
+    for endpoint in &endpoints {
+        for value in endpoint.iter().take(3) {
+            bits.write(*value as u32, 10);
+        }
+    }
+    for (texel, index) in indices.iter().enumerate() {
+        bits.write(*index, if texel == 0 { 3 } else { 4 });
+    }
+    (
+        bits.finish(),
+        indices.map(|index| WEIGHTS4[index as usize] as f32 / 64.0),
+    )
+}
@@ -0,0 +1,334 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use scratch_dds::*;
+
+const USAGE: &str = "usage:
+    ddstool info <file.dds>
+    ddstool validate <file.dds> [--level basic|strict|hardware]
+    ddstool convert <input.dds|input.png> [--format <DXGI format>] [--mips auto|<count>] <output.dds>
+    ddstool export <input.dds> [--mip <index>] [--layer <index>] <output.png|output.exr>
+    ddstool diff <a.dds|a.png> <b.dds|b.png> [--output <diff.png>] [--scale <factor>] [--mip <index>] [--layer <index>]
+                 [--min-psnr <dB>]";
+
+type CommandResult = std::result::Result<(), String>;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("info") => info(&args[1..]),
+        Some("validate") => validate(&args[1..]),
+        Some("convert") => convert(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+}
+
+fn load(path: &str) -> std::result::Result<ScratchImage, String> {
+    // Accept files that are a bit off, the problems are reported by validation
+    let options = ParseOptions {
+        allow_trailing_data: true,
+        ..Default::default()
+    };
+    ScratchImage::from_file_with(std::path::Path::new(path), &options).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn has_extension(path: &str, expected: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(expected))
+}
+
+fn load_any(path: &str) -> std::result::Result<ScratchImage, String> {
+    if has_extension(path, "png") {
+        ScratchImage::from_png_file(std::path::Path::new(path)).map_err(|err| format!("{}: {}", path, err))
+    } else {
+        load(path)
+    }
+}
+
+// Accepts names with or without the DXGI_FORMAT_ prefix, in any case
+fn parse_format(name: &str) -> Option<u32> {
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("DXGI_FORMAT_").unwrap_or(&name);
+    (0..=u8::MAX as u32).find(|&dxgi_format| dxgi_format_name(dxgi_format) == Some(name))
+}
+
+struct Arguments<'a> {
+    positional: Vec<&'a str>,
+    // `--name value` pairs
+    options: Vec<(&'a str, &'a str)>,
+}
+
+fn parse_arguments<'a>(args: &'a [String], option_names: &[&str]) -> std::result::Result<Arguments<'a>, String> {
+    let mut arguments = Arguments {
+        positional: Vec::new(),
+        options: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(name) = arg.strip_prefix("--") {
+            if !option_names.contains(&name) {
+                return Err(format!("unknown option --{}\n{}", name, USAGE));
+            }
+            let value = args.next().ok_or_else(|| format!("--{} needs a value", name))?;
+            arguments.options.push((name, value.as_str()));
+        } else {
+            arguments.positional.push(arg.as_str());
+        }
+    }
+    Ok(arguments)
+}
+
+fn format_name(dxgi_format: u32) -> String {
+    match dxgi_format_name(dxgi_format) {
+        Some(name) => format!("{} ({})", name, dxgi_format),
+        None => format!("unknown ({})", dxgi_format),
+    }
+}
+
+fn info(args: &[String]) -> CommandResult {
+    let path = match args {
+        [path] => path,
+        _ => return Err(USAGE.to_string()),
+    };
+    let image = load(path)?;
+    let header = image.header();
+    let pixel_format = &header.pixel_format;
+    let dxt10 = image.dxt10_header();
+
+    println!("{}", path);
+    println!("  format:       {}", format_name(image.dxgi_format()));
+    match image.resource_dimension() {
+        Some(dimension) => println!("  dimension:    {:?}", dimension),
+        None => println!("  dimension:    unknown"),
+    }
+    println!("  size:         {}x{}x{}", header.width, header.height, header.depth);
+    println!("  mips:         {}", header.mipmap_count);
+    println!("  array size:   {}", dxt10.array_size);
+    println!("  cubemap:      {}", image.is_cubemap());
+    match image.alpha_mode() {
+        Some(alpha_mode) => println!("  alpha mode:   {:?}", alpha_mode),
+        None => println!("  alpha mode:   invalid"),
+    }
+    println!("  data size:    {}", image.data_size());
+    if let Some(flipbook) = image.flipbook() {
+        println!(
+            "  flipbook:     {} frames at {} fps, {:?}",
+            flipbook.frame_count, flipbook.frame_rate, flipbook.loop_mode
+        );
+    }
+
+    println!("header");
+    println!("  flags:        {:#010x}", header.flags);
+    println!("  pitch/linear: {}", header.pitch_or_linear_size);
+    println!("  caps:         {:#010x} {:#010x}", header.caps, header.caps2);
+    println!("pixel format");
+    println!("  flags:        {:#010x}", pixel_format.flags);
+    println!("  fourcc:       {:?}", String::from_utf8_lossy(&pixel_format.four_cc));
+    println!("  bit count:    {}", pixel_format.rgb_bit_count);
+    println!(
+        "  masks:        {:#010x} {:#010x} {:#010x} {:#010x}",
+        pixel_format.red_bit_mask, pixel_format.green_bit_mask, pixel_format.blue_bit_mask, pixel_format.alpha_bit_mask
+    );
+    println!("dxt10");
+    println!("  dimension:    {}", dxt10.resource_dimension);
+    println!("  misc flags:   {:#010x} {:#010x}", dxt10.misc_flag, dxt10.misc_flags2);
+
+    println!("layout");
+    println!(
+        "  {:>5} {:>5} {:>5} {:>11} {:>12} {:>12}",
+        "layer", "face", "mip", "size", "offset", "bytes"
+    );
+    let face_count = if image.is_cubemap() { 6 } else { 1 };
+    for layer in 0..dxt10.array_size.max(1) {
+        for face in 0..face_count {
+            for mip in 0..header.mipmap_count.max(1) {
+                let offset = subresource_offset(header, mip, layer, face);
+                let size = subresource_size(header, mip);
+                if let (Some(offset), Some(size)) = (offset, size) {
+                    let width = header.width.checked_shr(mip).unwrap_or(0).max(1);
+                    let height = header.height.checked_shr(mip).unwrap_or(0).max(1);
+                    let extent = format!("{}x{}", width, height);
+                    println!(
+                        "  {:>5} {:>5} {:>5} {:>11} {:>12} {:>12}",
+                        layer, face, mip, extent, offset, size
+                    );
+                }
+            }
+        }
+    }
+
+    let diagnostics = image.validate(ValidationLevel::Strict);
+    if !diagnostics.is_empty() {
+        println!("validation");
+        for diagnostic in diagnostics {
+            println!("  {}", diagnostic);
+        }
+    }
+
+    Ok(())
+}
+
+// Prints the validation report as JSON, fails if any check failed
+fn validate(args: &[String]) -> CommandResult {
+    let arguments = parse_arguments(args, &["level"])?;
+    let path = match arguments.positional[..] {
+        [path] => path,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut level = ValidationLevel::Strict;
+    for (_, value) in arguments.options {
+        level = match value {
+            "basic" => ValidationLevel::Basic,
+            "strict" => ValidationLevel::Strict,
+            "hardware" => ValidationLevel::Hardware,
+            _ => return Err(format!("bad --level {}", value)),
+        };
+    }
+
+    let report = load(path)?.validation_report(level);
+    println!("{}", report.to_json());
+    match report.passed() {
+        true => Ok(()),
+        false => Err(format!("{}: validation failed", path)),
+    }
+}
+
+fn convert(args: &[String]) -> CommandResult {
+    let arguments = parse_arguments(args, &["format", "mips"])?;
+    let (input, output) = match arguments.positional[..] {
+        [input, output] => (input, output),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut options = ConvertOptions::default();
+    for (name, value) in arguments.options {
+        match name {
+            "format" => {
+                options.dxgi_format = Some(parse_format(value).ok_or_else(|| format!("unknown format {}", value))?)
+            }
+            _ if value == "auto" => options.mipmap_count = Some(0),
+            _ => match value.parse() {
+                Ok(0) | Err(_) => return Err(format!("bad mip count {}", value)),
+                Ok(mipmap_count) => options.mipmap_count = Some(mipmap_count),
+            },
+        }
+    }
+
+    let image = load_any(input)?
+        .convert_with(&options)
+        .map_err(|err| format!("{}: {}", input, err))?;
+    image
+        .write_to_file_atomic(std::path::Path::new(output))
+        .map_err(|err| format!("{}: {}", output, err))
+}
+
+fn export(args: &[String]) -> CommandResult {
+    let arguments = parse_arguments(args, &["mip", "layer"])?;
+    let (input, output) = match arguments.positional[..] {
+        [input, output] => (input, output),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let (mut mip, mut layer) = (0, 0);
+    for (name, value) in arguments.options {
+        let index = value.parse().map_err(|_| format!("bad --{} {}", name, value))?;
+        match name {
+            "mip" => mip = index,
+            _ => layer = index,
+        }
+    }
+
+    let image = load_any(input)?;
+    let mut encoded = Vec::new();
+    let result = if has_extension(output, "exr") {
+        image.write_subresource_exr(mip, layer, &mut encoded)
+    } else {
+        image.write_subresource_png(mip, layer, &mut encoded)
+    };
+    result.map_err(|err| format!("{}: {}", input, err))?;
+
+    std::fs::write(output, encoded).map_err(|err| format!("{}: {}", output, err))
+}
+
+fn diff(args: &[String]) -> CommandResult {
+    let arguments = parse_arguments(args, &["output", "scale", "mip", "layer", "min-psnr"])?;
+    let (first, second) = match arguments.positional[..] {
+        [first, second] => (first, second),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let (mut output, mut scale, mut mip, mut layer, mut min_psnr) = (None, 16.0, 0, 0, None);
+    for (name, value) in arguments.options {
+        let bad_value = || format!("bad --{} {}", name, value);
+        match name {
+            "output" => output = Some(value),
+            "scale" => scale = value.parse().map_err(|_| bad_value())?,
+            "mip" => mip = value.parse().map_err(|_| bad_value())?,
+            "layer" => layer = value.parse().map_err(|_| bad_value())?,
+            _ => min_psnr = Some(value.parse::<f64>().map_err(|_| bad_value())?),
+        }
+    }
+
+    let a = load_any(first)?;
+    let b = load_any(second)?;
+    let fail = |err: Error| format!("{} {}: {}", first, second, err);
+
+    println!(
+        "  {:>5} {:>11} {:>10} {:>10} {:>8} {:>8}",
+        "mip", "size", "rgb psnr", "a psnr", "rgb ssim", "a ssim"
+    );
+    for (mip, comparison) in a.compare_mips(&b).map_err(fail)?.iter().enumerate() {
+        let layout = a.subresource_layout(mip as u32).map_err(fail)?;
+        println!(
+            "  {:>5} {:>11} {:>10.2} {:>10.2} {:>8.5} {:>8.5}",
+            mip,
+            format!("{}x{}", layout.width, layout.height),
+            comparison.rgb_psnr(),
+            comparison.psnr[3],
+            comparison.rgb_ssim(),
+            comparison.ssim[3]
+        );
+    }
+    let comparison = a.compare(&b).map_err(fail)?;
+    println!(
+        "  {:>5} {:>11} {:>10.2} {:>10.2} {:>8.5} {:>8.5}",
+        "all",
+        "",
+        comparison.rgb_psnr(),
+        comparison.psnr[3],
+        comparison.rgb_ssim(),
+        comparison.ssim[3]
+    );
+
+    if let Some(output) = output {
+        let texels = a.difference(&b, mip, layer, scale).map_err(fail)?;
+        let layout = a.subresource_layout(mip).map_err(fail)?;
+        let mut encoded = Vec::new();
+        write_png(&texels, layout.width, layout.height, &mut encoded).map_err(fail)?;
+        std::fs::write(output, encoded).map_err(|err| format!("{}: {}", output, err))?;
+    }
+
+    // Fails the comparison for regression tests, alpha is included
+    match min_psnr {
+        Some(min_psnr) if comparison.rgb_psnr().min(comparison.psnr[3]) < min_psnr => {
+            Err(format!("PSNR is below {} dB", min_psnr))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Typed and untyped views of block compressed subresources, and codecs of single blocks. Blocks are stored as bytes, so
+// they have no alignment and any subresource can be viewed as a slice of blocks. Texels of a block are numbered in
+// row-major order.
+
+use crate::bc::{bc7_block_mode, bc7_block_partition, decode_block, encode_block, unpack_bc7, DecodedBlock};
+use crate::dds::*;
+use crate::encode::EncodeOptions;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+
+pub trait BcBlock: bytemuck::Pod {
+    // Whether subresources of the format are made of blocks of this type
+    fn is_block_of(dxgi_format: u32) -> bool;
+}
+
+// BC1 block, also the color part of BC2 and BC3 blocks
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc1Block {
+    pub bytes: [u8; 8],
+}
+
+unsafe impl bytemuck::Zeroable for Bc1Block {}
+unsafe impl bytemuck::Pod for Bc1Block {}
+
+impl Bc1Block {
+    // R5G6B5 colors
+    pub fn endpoints(&self) -> [u16; 2] {
+        [
+            u16::from_le_bytes([self.bytes[0], self.bytes[1]]),
+            u16::from_le_bytes([self.bytes[2], self.bytes[3]]),
+        ]
+    }
+
+    pub fn set_endpoints(&mut self, endpoints: [u16; 2]) {
+        self.bytes[..2].copy_from_slice(&endpoints[0].to_le_bytes());
+        self.bytes[2..4].copy_from_slice(&endpoints[1].to_le_bytes());
+    }
+
+    // BC1 blocks with the first endpoint not larger than the second have 3 colors and transparent black
+    pub fn is_punch_through(&self) -> bool {
+        let [color0, color1] = self.endpoints();
+        color0 <= color1
+    }
+
+    // 2-bit palette index
+    pub fn index(&self, texel: usize) -> u32 {
+        (u32::from_le_bytes([self.bytes[4], self.bytes[5], self.bytes[6], self.bytes[7]]) >> (texel * 2)) & 0x3
+    }
+
+    pub fn set_index(&mut self, texel: usize, index: u32) {
+        let mut indices = u32::from_le_bytes([self.bytes[4], self.bytes[5], self.bytes[6], self.bytes[7]]);
+        indices = (indices & !(0x3 << (texel * 2))) | ((index & 0x3) << (texel * 2));
+        self.bytes[4..].copy_from_slice(&indices.to_le_bytes());
+    }
+}
+
+impl BcBlock for Bc1Block {
+    fn is_block_of(dxgi_format: u32) -> bool {
+        matches!(
+            dxgi_format,
+            DXGI_FORMAT_BC1_TYPELESS | DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB
+        )
+    }
+}
+
+// BC4 block, also the alpha part of BC3 blocks and both channels of BC5 blocks
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc4Block {
+    pub bytes: [u8; 8],
+}
+
+unsafe impl bytemuck::Zeroable for Bc4Block {}
+unsafe impl bytemuck::Pod for Bc4Block {}
+
+impl Bc4Block {
+    // Stored values, signed formats interpret them as i8
+    pub fn endpoints(&self) -> [u8; 2] {
+        [self.bytes[0], self.bytes[1]]
+    }
+
+    pub fn set_endpoints(&mut self, endpoints: [u8; 2]) {
+        self.bytes[..2].copy_from_slice(&endpoints);
+    }
+
+    fn indices(&self) -> u64 {
+        u64::from_le_bytes(self.bytes) >> 16
+    }
+
+    // 3-bit palette index
+    pub fn index(&self, texel: usize) -> u32 {
+        ((self.indices() >> (texel * 3)) & 0x7) as u32
+    }
+
+    pub fn set_index(&mut self, texel: usize, index: u32) {
+        let mut indices = self.indices();
+        indices = (indices & !(0x7 << (texel * 3))) | (((index & 0x7) as u64) << (texel * 3));
+        self.bytes[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+    }
+}
+
+impl BcBlock for Bc4Block {
+    fn is_block_of(dxgi_format: u32) -> bool {
+        matches!(
+            dxgi_format,
+            DXGI_FORMAT_BC4_TYPELESS | DXGI_FORMAT_BC4_UNORM | DXGI_FORMAT_BC4_SNORM
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc3Block {
+    pub alpha: Bc4Block,
+    pub color: Bc1Block,
+}
+
+unsafe impl bytemuck::Zeroable for Bc3Block {}
+unsafe impl bytemuck::Pod for Bc3Block {}
+
+impl BcBlock for Bc3Block {
+    fn is_block_of(dxgi_format: u32) -> bool {
+        matches!(
+            dxgi_format,
+            DXGI_FORMAT_BC3_TYPELESS | DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc5Block {
+    pub red: Bc4Block,
+    pub green: Bc4Block,
+}
+
+unsafe impl bytemuck::Zeroable for Bc5Block {}
+unsafe impl bytemuck::Pod for Bc5Block {}
+
+impl BcBlock for Bc5Block {
+    fn is_block_of(dxgi_format: u32) -> bool {
+        matches!(
+            dxgi_format,
+            DXGI_FORMAT_BC5_TYPELESS | DXGI_FORMAT_BC5_UNORM | DXGI_FORMAT_BC5_SNORM
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc7Block {
+    pub bytes: [u8; 16],
+}
+
+unsafe impl bytemuck::Zeroable for Bc7Block {}
+unsafe impl bytemuck::Pod for Bc7Block {}
+
+// Decoded fields of a BC7 block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bc7Fields {
+    pub mode: u32,
+    // 0 for modes without partitions
+    pub partition: u32,
+    // Channel swapped with alpha: 0 is none, 1-3 are red, green and blue
+    pub rotation: u32,
+    // 1 if the secondary indices are used for color instead of alpha
+    pub index_selection: u32,
+    pub subset_count: u32,
+    // Two RGBA endpoints per subset expanded to 8 bits, endpoints of unused subsets are zero
+    pub endpoints: [[u8; 4]; 6],
+    pub index_bits: u32,
+    pub indices: [u8; 16],
+    // Indices of the second set in modes 4 and 5, zero in other modes
+    pub secondary_index_bits: u32,
+    pub secondary_indices: [u8; 16],
+}
+
+impl Bc7Block {
+    // None for the reserved mode
+    pub fn mode(&self) -> Option<u32> {
+        bc7_block_mode(&self.bytes)
+    }
+
+    // None for the reserved mode and modes without partitions
+    pub fn partition(&self) -> Option<u32> {
+        bc7_block_partition(&self.bytes, self.mode()?)
+    }
+
+    pub fn fields(&self) -> Option<Bc7Fields> {
+        unpack_bc7(&self.bytes)
+    }
+}
+
+impl BcBlock for Bc7Block {
+    fn is_block_of(dxgi_format: u32) -> bool {
+        matches!(
+            dxgi_format,
+            DXGI_FORMAT_BC7_TYPELESS | DXGI_FORMAT_BC7_UNORM | DXGI_FORMAT_BC7_UNORM_SRGB
+        )
+    }
+}
+
+fn to_unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn to_snorm8(value: f32) -> i8 {
+    (value.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+fn from_snorm8(value: i8) -> f32 {
+    (value as f32 / 127.0).max(-1.0)
+}
+
+fn decode_rgba8(dxgi_format: u32, block: &[u8]) -> [[u8; 4]; 16] {
+    decode_block(dxgi_format, block)
+        .unwrap()
+        .map(|texel| texel.map(to_unorm8))
+}
+
+// Blocks are encoded with the default encode options
+fn encode<const N: usize>(dxgi_format: u32, texels: &DecodedBlock) -> [u8; N] {
+    let mut block = [0u8; N];
+    encode_block(dxgi_format, texels, &EncodeOptions::default(), &mut block);
+    block
+}
+
+fn encode_rgba8<const N: usize>(dxgi_format: u32, texels: &[[u8; 4]; 16]) -> [u8; N] {
+    encode(
+        dxgi_format,
+        &texels.map(|texel| texel.map(|value| value as f32 / 255.0)),
+    )
+}
+
+pub fn decode_bc1_block(block: &[u8; 8]) -> [[u8; 4]; 16] {
+    decode_rgba8(DXGI_FORMAT_BC1_UNORM, block)
+}
+
+pub fn encode_bc1_block(texels: &[[u8; 4]; 16]) -> [u8; 8] {
+    encode_rgba8(DXGI_FORMAT_BC1_UNORM, texels)
+}
+
+pub fn decode_bc2_block(block: &[u8; 16]) -> [[u8; 4]; 16] {
+    decode_rgba8(DXGI_FORMAT_BC2_UNORM, block)
+}
+
+pub fn encode_bc2_block(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    encode_rgba8(DXGI_FORMAT_BC2_UNORM, texels)
+}
+
+pub fn decode_bc3_block(block: &[u8; 16]) -> [[u8; 4]; 16] {
+    decode_rgba8(DXGI_FORMAT_BC3_UNORM, block)
+}
+
+pub fn encode_bc3_block(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    encode_rgba8(DXGI_FORMAT_BC3_UNORM, texels)
+}
+
+pub fn decode_bc4_block(block: &[u8; 8]) -> [u8; 16] {
+    decode_rgba8(DXGI_FORMAT_BC4_UNORM, block).map(|texel| texel[0])
+}
+
+pub fn encode_bc4_block(values: &[u8; 16]) -> [u8; 8] {
+    encode_rgba8(DXGI_FORMAT_BC4_UNORM, &values.map(|value| [value, 0, 0, 255]))
+}
+
+pub fn decode_bc4_snorm_block(block: &[u8; 8]) -> [i8; 16] {
+    decode_block(DXGI_FORMAT_BC4_SNORM, block)
+        .unwrap()
+        .map(|texel| to_snorm8(texel[0]))
+}
+
+pub fn encode_bc4_snorm_block(values: &[i8; 16]) -> [u8; 8] {
+    encode(
+        DXGI_FORMAT_BC4_SNORM,
+        &values.map(|value| [from_snorm8(value), 0.0, 0.0, 1.0]),
+    )
+}
+
+pub fn decode_bc5_block(block: &[u8; 16]) -> [[u8; 2]; 16] {
+    decode_rgba8(DXGI_FORMAT_BC5_UNORM, block).map(|texel| [texel[0], texel[1]])
+}
+
+pub fn encode_bc5_block(values: &[[u8; 2]; 16]) -> [u8; 16] {
+    encode_rgba8(DXGI_FORMAT_BC5_UNORM, &values.map(|[red, green]| [red, green, 0, 255]))
+}
+
+pub fn decode_bc5_snorm_block(block: &[u8; 16]) -> [[i8; 2]; 16] {
+    decode_block(DXGI_FORMAT_BC5_SNORM, block)
+        .unwrap()
+        .map(|texel| [to_snorm8(texel[0]), to_snorm8(texel[1])])
+}
+
+pub fn encode_bc5_snorm_block(values: &[[i8; 2]; 16]) -> [u8; 16] {
+    encode(
+        DXGI_FORMAT_BC5_SNORM,
+        &values.map(|[red, green]| [from_snorm8(red), from_snorm8(green), 0.0, 1.0]),
+    )
+}
+
+// RGB values of BC6H blocks, `is_signed` selects BC6H_SF16 instead of BC6H_UF16
+pub fn decode_bc6h_block(block: &[u8; 16], is_signed: bool) -> [[f32; 3]; 16] {
+    let dxgi_format = bc6h_format(is_signed);
+    decode_block(dxgi_format, block)
+        .unwrap()
+        .map(|texel| [texel[0], texel[1], texel[2]])
+}
+
+pub fn encode_bc6h_block(texels: &[[f32; 3]; 16], is_signed: bool) -> [u8; 16] {
+    let dxgi_format = bc6h_format(is_signed);
+    encode(dxgi_format, &texels.map(|[red, green, blue]| [red, green, blue, 1.0]))
+}
+
+pub fn decode_bc7_block(block: &[u8; 16]) -> [[u8; 4]; 16] {
+    decode_rgba8(DXGI_FORMAT_BC7_UNORM, block)
+}
+
+pub fn encode_bc7_block(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    encode_rgba8(DXGI_FORMAT_BC7_UNORM, texels)
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Blocks of a subresource in row-major order
+    pub fn as_blocks<T: BcBlock>(&self, mip: u32, layer: u32) -> Result<&[T]> {
+        if !T::is_block_of(self.dxgi_format()) {
+            return Err(Error::UnsupportedFormat(self.dxgi_format()));
+        }
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        Ok(bytemuck::cast_slice(&self.as_slice()[range]))
+    }
+
+    // Blocks of a subresource of any block compressed format with their block coordinates, in row-major order
+    pub fn blocks(&self, mip: u32, layer: u32) -> Result<impl Iterator<Item = (u32, u32, &[u8])> + '_> {
+        let (range, block_size, columns) = self.block_layout(mip, layer)?;
+        Ok(self.as_slice()[range]
+            .chunks_exact(block_size)
+            .enumerate()
+            .map(move |(index, block)| (index as u32 % columns, index as u32 / columns, block)))
+    }
+
+    fn block_layout(&self, mip: u32, layer: u32) -> Result<(std::ops::Range<usize>, usize, u32)> {
+        let dxgi_format = self.dxgi_format();
+        if !is_block_compressed(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, _) = layout::mip_extent(&self.dds_header, mip);
+        let columns = width.div_ceil(block_dimensions(dxgi_format).0).max(1);
+        Ok((range, block_size(dxgi_format) as usize, columns))
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    pub fn as_blocks_mut<T: BcBlock>(&mut self, mip: u32, layer: u32) -> Result<&mut [T]> {
+        if !T::is_block_of(self.dxgi_format()) {
+            return Err(Error::UnsupportedFormat(self.dxgi_format()));
+        }
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        Ok(bytemuck::cast_slice_mut(&mut self.as_slice_mut()[range]))
+    }
+
+    pub fn blocks_mut(&mut self, mip: u32, layer: u32) -> Result<impl Iterator<Item = (u32, u32, &mut [u8])> + '_> {
+        let (range, block_size, columns) = self.block_layout(mip, layer)?;
+        Ok(self.as_slice_mut()[range]
+            .chunks_exact_mut(block_size)
+            .enumerate()
+            .map(move |(index, block)| (index as u32 % columns, index as u32 / columns, block)))
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::dds::*;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageDesc {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub mipmap_count: u32,
+    pub array_size: u32,
+    pub dxgi_format: u32,
+    pub is_cubemap: bool,
+    // Inferred from the image size if not set: 3D if depth is larger than 1, 1D for rows of texels (height of 1 and
+    // width larger than 1) that are not cubemaps, 2D otherwise
+    pub dimension: Option<ResourceDimension>,
+}
+
+impl Default for ImageDesc {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            depth: 1,
+            mipmap_count: 1,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_UNKNOWN,
+            is_cubemap: false,
+            dimension: None,
+        }
+    }
+}
+
+impl ImageDesc {
+    pub(crate) fn from_header(header: &DirectDrawHeader) -> Self {
+        Self {
+            width: header.width,
+            height: header.height,
+            depth: header.depth.max(1),
+            mipmap_count: layout::mip_count(header),
+            array_size: header.dxt10.array_size.max(1),
+            dxgi_format: header.dxt10.dxgi_format,
+            is_cubemap: layout::face_count(header) == 6,
+            dimension: ResourceDimension::from_d3d10(header.dxt10.resource_dimension),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let fail = |reason| Err(Error::BadImageDescription(reason));
+
+        if self.width == 0 || self.height == 0 || self.depth == 0 {
+            return fail("Image dimensions must be non-zero");
+        }
+        if self.dxgi_format == DXGI_FORMAT_UNKNOWN || try_bits_per_pixel(self.dxgi_format).is_none() {
+            return fail("Image format is unknown or not supported");
+        }
+        if self.mipmap_count == 0 || self.mipmap_count > max_mipmap_count(self.width, self.height, self.depth) {
+            return fail("Mipmap count must be between 1 and the length of the full mip chain");
+        }
+        if self.array_size == 0 {
+            return fail("Array size must be non-zero");
+        }
+        if self.depth > 1 && self.array_size > 1 {
+            return fail("Volume textures can't be arrays");
+        }
+        if self.is_cubemap && self.depth > 1 {
+            return fail("Cubemaps can't be volume textures");
+        }
+        if self.is_cubemap && self.width != self.height {
+            return fail("Cubemap faces must be square");
+        }
+        match self.inferred_dimension() {
+            ResourceDimension::Texture1D if self.height > 1 || self.depth > 1 => {
+                return fail("1D textures must have height and depth of 1");
+            }
+            ResourceDimension::Texture1D if self.is_cubemap => {
+                return fail("1D textures can't be cubemaps");
+            }
+            ResourceDimension::Texture2D if self.depth > 1 => {
+                return fail("2D textures must have depth of 1");
+            }
+            ResourceDimension::Texture3D if self.array_size > 1 || self.is_cubemap => {
+                return fail("3D textures can't be arrays or cubemaps");
+            }
+            _ => {}
+        }
+        if layout::checked_data_size(&self.to_header()).is_none() {
+            return Err(Error::SizeOverflow);
+        }
+
+        Ok(())
+    }
+
+    pub fn inferred_dimension(&self) -> ResourceDimension {
+        match self.dimension {
+            Some(dimension) => dimension,
+            None if self.depth > 1 => ResourceDimension::Texture3D,
+            None if self.height == 1 && self.width > 1 && !self.is_cubemap => ResourceDimension::Texture1D,
+            None => ResourceDimension::Texture2D,
+        }
+    }
+
+    pub fn to_header(&self) -> DirectDrawHeader {
+        ScratchImage::new_header(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScratchImageBuilder {
+    desc: ImageDesc,
+    // The full mip chain is resolved from the final size, whatever order the methods are called in
+    full_mips: bool,
+}
+
+impl ScratchImageBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.desc.width = width;
+        self.desc.height = height;
+        self
+    }
+
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.desc.depth = depth;
+        self
+    }
+
+    pub fn format(mut self, dxgi_format: u32) -> Self {
+        self.desc.dxgi_format = dxgi_format;
+        self
+    }
+
+    pub fn mips(mut self, mipmap_count: u32) -> Self {
+        self.desc.mipmap_count = mipmap_count;
+        self.full_mips = false;
+        self
+    }
+
+    // Full mip chain down to 1x1
+    pub fn full_mips(mut self) -> Self {
+        self.full_mips = true;
+        self
+    }
+
+    pub fn array_size(mut self, array_size: u32) -> Self {
+        self.desc.array_size = array_size;
+        self
+    }
+
+    pub fn cubemap(mut self) -> Self {
+        self.desc.is_cubemap = true;
+        self
+    }
+
+    pub fn dimension(mut self, dimension: ResourceDimension) -> Self {
+        self.desc.dimension = Some(dimension);
+        self
+    }
+
+    pub fn desc(&self) -> ImageDesc {
+        let mut desc = self.desc;
+        if self.full_mips {
+            desc.mipmap_count = max_mipmap_count(desc.width, desc.height, desc.depth);
+        }
+        desc
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.desc().validate()
+    }
+
+    pub fn build(&self) -> Result<ScratchImage> {
+        self.validate()?;
+
+        let dds_header = self.desc().to_header();
+        let dds_data = vec![0u8; layout::data_size(&dds_header)];
+        Ok(ScratchImage { dds_header, dds_data })
+    }
+
+    pub fn build_with_data(&self, dds_data: Vec<u8>) -> Result<ScratchImage> {
+        ScratchImage::with_data(&self.desc(), dds_data)
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Layout of an existing image
+    pub fn desc(&self) -> ImageDesc {
+        ImageDesc::from_header(&self.dds_header)
+    }
+}
+
+impl ScratchImage {
+    pub fn builder() -> ScratchImageBuilder {
+        ScratchImageBuilder::new()
+    }
+
+    // Zero-initialized image
+    pub fn with_desc(desc: &ImageDesc) -> Result<ScratchImage> {
+        ScratchImageBuilder {
+            desc: *desc,
+            full_mips: false,
+        }
+        .build()
+    }
+
+    // `dds_data` must contain every subresource in DDS order: all mips of the first layer, then the next layer, etc.
+    pub fn with_data(desc: &ImageDesc, dds_data: Vec<u8>) -> Result<ScratchImage> {
+        desc.validate()?;
+        Self::from_header_and_data(desc.to_header(), dds_data)
+    }
+
+    // `subresources` are in DDS order, see `with_data`
+    pub fn with_subresources(desc: &ImageDesc, subresources: &[&[u8]]) -> Result<ScratchImage> {
+        desc.validate()?;
+
+        let dds_header = desc.to_header();
+        if subresources.len() != layout::subresource_count(&dds_header) as usize {
+            return Err(Error::BadImageDescription(
+                "Subresource count does not match the image description",
+            ));
+        }
+
+        let mut dds_data = Vec::with_capacity(layout::data_size(&dds_header));
+        for (index, subresource) in subresources.iter().enumerate() {
+            let mip = index as u32 % layout::mip_count(&dds_header);
+            let mip_size = layout::mip_size(&dds_header, mip);
+            if subresource.len() != mip_size {
+                return Err(Error::BadDataSize {
+                    expected: mip_size as u64,
+                    actual: subresource.len() as u64,
+                    offset: dds_data.len() as u64,
+                });
+            }
+            dds_data.extend_from_slice(subresource);
+        }
+
+        Ok(ScratchImage { dds_header, dds_data })
+    }
+}
+
+pub fn max_mipmap_count(width: u32, height: u32, depth: u32) -> u32 {
+    32 - width.max(height).max(depth).max(1).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_mips_in_any_order() {
+        let before = ScratchImage::builder()
+            .full_mips()
+            .size(16, 4)
+            .format(DXGI_FORMAT_R8_UNORM);
+        let after = ScratchImage::builder()
+            .size(16, 4)
+            .format(DXGI_FORMAT_R8_UNORM)
+            .full_mips();
+        assert_eq!(before.desc().mipmap_count, 5);
+        assert_eq!(after.desc().mipmap_count, 5);
+        assert_eq!(before.build().unwrap().mipmap_count(), 5);
+
+        let resized = after.size(64, 64);
+        assert_eq!(resized.desc().mipmap_count, 7);
+        assert_eq!(resized.mips(2).desc().mipmap_count, 2);
+        assert_eq!(resized.mips(2).full_mips().desc().mipmap_count, 7);
+    }
+}
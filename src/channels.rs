@@ -0,0 +1,154 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Moving channels between images, e.g. packing grayscale AO, roughness and metallic maps into a single ORM texture.
+// Channels are indexed RGBA from 0 to 3, values are copied as decoded.
+
+use crate::builder::ImageDesc;
+use crate::dds::*;
+use crate::decode::{can_decode, pixel_fields};
+use crate::encode::can_encode;
+use crate::format::*;
+use crate::layout;
+use crate::sampling::resample_bilinear;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+// Indices of the channels that the format stores, None for formats that can't be decoded
+pub fn stored_channels(dxgi_format: u32) -> Option<Vec<usize>> {
+    if !can_decode(dxgi_format) {
+        return None;
+    }
+    let dxgi_format = typed_format(dxgi_format);
+    if let Some(fields) = pixel_fields(dxgi_format) {
+        return Some(
+            (0..4)
+                .filter(|&channel| fields.iter().any(|field| field.0 == channel))
+                .collect(),
+        );
+    }
+
+    Some(match dxgi_format {
+        DXGI_FORMAT_R1_UNORM | DXGI_FORMAT_BC4_UNORM | DXGI_FORMAT_BC4_SNORM => vec![0],
+        DXGI_FORMAT_BC5_UNORM | DXGI_FORMAT_BC5_SNORM => vec![0, 1],
+        _ if has_alpha(dxgi_format) => vec![0, 1, 2, 3],
+        _ => vec![0, 1, 2],
+    })
+}
+
+pub const REC709_LUMA_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+fn check_channel(channel: usize) -> Result<()> {
+    match channel {
+        0..=3 => Ok(()),
+        _ => Err(Error::BadImageDescription("Channel index must be between 0 and 3")),
+    }
+}
+
+impl ScratchImage {
+    // Channel `i` of the result is the given channel of the i-th source, channels without a source are 0 and alpha
+    // is 1. Only the first mip of every source is used, sources that are smaller than the largest one are resized
+    // with a bilinear filter. All sources must have the same layer count, the result has a single mip.
+    pub fn pack_channels<S: Storage>(sources: &[(&ScratchImage<S>, usize)], dxgi_format: u32) -> Result<ScratchImage> {
+        let (first, _) = sources
+            .first()
+            .ok_or(Error::BadImageDescription("No source channels"))?;
+        if sources.len() > 4 {
+            return Err(Error::BadImageDescription("More than 4 source channels"));
+        }
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        for (source, channel) in sources {
+            check_channel(*channel)?;
+            if source.layer_count() != first.layer_count() || source.is_cubemap() != first.is_cubemap() {
+                return Err(Error::IncompatibleImages("Sources have different layer counts"));
+            }
+        }
+
+        let width = sources.iter().map(|(source, _)| source.image_width()).max().unwrap();
+        let height = sources.iter().map(|(source, _)| source.image_height()).max().unwrap();
+        let desc = ImageDesc {
+            width,
+            height,
+            mipmap_count: 1,
+            array_size: first.desc().array_size,
+            dxgi_format,
+            is_cubemap: first.is_cubemap(),
+            ..Default::default()
+        };
+        let mut image = ScratchImage::with_desc(&desc)?;
+
+        for layer in 0..layout::layer_count(&image.dds_header) {
+            let mut texels = vec![[0.0, 0.0, 0.0, 1.0]; width as usize * height as usize];
+            for (target, (source, channel)) in sources.iter().enumerate() {
+                let decoded = source.decode_subresource(0, layer)?;
+                let resized = resample_bilinear(&decoded, source.image_width(), source.image_height(), width, height);
+                for (texel, value) in texels.iter_mut().zip(resized.iter()) {
+                    texel[target] = value[*channel];
+                }
+            }
+            image.encode_subresource(0, layer, &texels)?;
+        }
+        Ok(image)
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // R8_UNORM image with a channel of every subresource, values are clamped to [0, 1]
+    pub fn extract_channel(&self, channel: usize) -> Result<ScratchImage> {
+        check_channel(channel)?;
+        let mut desc = self.desc();
+        desc.dxgi_format = DXGI_FORMAT_R8_UNORM;
+        let mut image = ScratchImage::with_desc(&desc)?;
+
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let texels: Vec<[f32; 4]> = self
+                    .decode_subresource(mip, layer)?
+                    .iter()
+                    .map(|texel| [texel[channel], 0.0, 0.0, 1.0])
+                    .collect();
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+
+    // Channel indices and images from `extract_channel` for every channel that the format stores
+    pub fn split_channels(&self) -> Result<Vec<(usize, ScratchImage)>> {
+        let channels = stored_channels(self.dxgi_format()).ok_or(Error::UnsupportedFormat(self.dxgi_format()))?;
+        channels
+            .into_iter()
+            .map(|channel| Ok((channel, self.extract_channel(channel)?)))
+            .collect()
+    }
+
+    // Weighted sum of RGB in every subresource, usually stored as R8_UNORM or R16_UNORM. Formats with more channels
+    // get the sum in RGB and keep the alpha. Values are weighted as decoded, sRGB values are not linearized.
+    pub fn to_grayscale(&self, weights: [f32; 3], dxgi_format: u32) -> Result<ScratchImage> {
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let texels: Vec<[f32; 4]> = self
+                    .decode_subresource(mip, layer)?
+                    .iter()
+                    .map(|texel| {
+                        let gray = (0..3).map(|channel| texel[channel] * weights[channel]).sum();
+                        [gray, gray, gray, texel[3]]
+                    })
+                    .collect();
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+}
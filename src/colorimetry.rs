@@ -0,0 +1,243 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Color primaries and transfer functions of the stored values. DDS has no place for them, so they are kept in the
+// metadata chunk as the METADATA_COLOR_SPACE string, e.g. "rec2020 pq".
+// Linear values are relative to the reference white: 1.0 is 203 nits for PQ (BT.2408) and 75% of the signal for
+// HLG. The HLG system gamma is not applied, values stay scene referred.
+
+use crate::decode::{linear_to_srgb, srgb_to_linear};
+use crate::encode::can_encode;
+use crate::layout;
+use crate::metadata::*;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+// All primaries use the D65 white point
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primaries {
+    Rec709,
+    Rec2020,
+    // P3-D65, as used by HDR10 mastering displays
+    DciP3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferFunction {
+    Linear,
+    Srgb,
+    Pq,
+    Hlg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Colorimetry {
+    pub primaries: Primaries,
+    pub transfer: TransferFunction,
+}
+
+impl Colorimetry {
+    pub const SRGB: Colorimetry = Colorimetry {
+        primaries: Primaries::Rec709,
+        transfer: TransferFunction::Srgb,
+    };
+    pub const HDR10: Colorimetry = Colorimetry {
+        primaries: Primaries::Rec2020,
+        transfer: TransferFunction::Pq,
+    };
+
+    // Value of METADATA_COLOR_SPACE
+    pub fn to_metadata_string(&self) -> String {
+        let primaries = match self.primaries {
+            Primaries::Rec709 => "rec709",
+            Primaries::Rec2020 => "rec2020",
+            Primaries::DciP3 => "p3",
+        };
+        let transfer = match self.transfer {
+            TransferFunction::Linear => "linear",
+            TransferFunction::Srgb => "srgb",
+            TransferFunction::Pq => "pq",
+            TransferFunction::Hlg => "hlg",
+        };
+        format!("{} {}", primaries, transfer)
+    }
+
+    // None if the string is not in the format of `to_metadata_string`
+    pub fn from_metadata_string(value: &str) -> Option<Colorimetry> {
+        let mut words = value.split(' ');
+        let primaries = match words.next()? {
+            "rec709" => Primaries::Rec709,
+            "rec2020" => Primaries::Rec2020,
+            "p3" => Primaries::DciP3,
+            _ => return None,
+        };
+        let transfer = match words.next()? {
+            "linear" => TransferFunction::Linear,
+            "srgb" => TransferFunction::Srgb,
+            "pq" => TransferFunction::Pq,
+            "hlg" => TransferFunction::Hlg,
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(Colorimetry { primaries, transfer }),
+        }
+    }
+}
+
+impl Metadata {
+    // None if there is no color space entry or it is not a known descriptor
+    pub fn colorimetry(&self) -> Option<Colorimetry> {
+        Colorimetry::from_metadata_string(self.get_str(METADATA_COLOR_SPACE)?)
+    }
+
+    // None removes the entry
+    pub fn set_colorimetry(&mut self, colorimetry: Option<Colorimetry>) {
+        match colorimetry {
+            Some(colorimetry) => self.set_str(METADATA_COLOR_SPACE, &colorimetry.to_metadata_string()),
+            None => self.remove(METADATA_COLOR_SPACE),
+        }
+    }
+}
+
+// Luminance of linear 1.0 in PQ
+pub(crate) const REFERENCE_WHITE_NITS: f32 = 203.0;
+
+// Scene linear value of 75% HLG signal
+const HLG_REFERENCE_WHITE: f32 = 0.264_94;
+
+// PQ and HLG constants
+#[doc = "https://www.itu.int/rec/R-REC-BT.2100"]
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+pub(crate) fn pq_to_nits(value: f32) -> f32 {
+    let power = value.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    ((power - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * power)).powf(1.0 / PQ_M1) * 10000.0
+}
+
+pub(crate) fn nits_to_pq(nits: f32) -> f32 {
+    let power = (nits / 10000.0).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * power) / (1.0 + PQ_C3 * power)).powf(PQ_M2)
+}
+
+const HLG_A: f32 = 0.178_832_8;
+const HLG_B: f32 = 0.284_668_9;
+const HLG_C: f32 = 0.559_910_7;
+
+fn hlg_to_linear(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    let scene = match value <= 0.5 {
+        true => value * value / 3.0,
+        false => (((value - HLG_C) / HLG_A).exp() + HLG_B) / 12.0,
+    };
+    scene / HLG_REFERENCE_WHITE
+}
+
+fn linear_to_hlg(value: f32) -> f32 {
+    let scene = (value * HLG_REFERENCE_WHITE).clamp(0.0, 1.0);
+    match scene <= 1.0 / 12.0 {
+        true => (3.0 * scene).sqrt(),
+        false => HLG_A * (12.0 * scene - HLG_B).ln() + HLG_C,
+    }
+}
+
+impl TransferFunction {
+    pub(crate) fn decode(self, value: f32) -> f32 {
+        match self {
+            TransferFunction::Linear => value,
+            TransferFunction::Srgb => srgb_to_linear(value),
+            TransferFunction::Pq => pq_to_nits(value) / REFERENCE_WHITE_NITS,
+            TransferFunction::Hlg => hlg_to_linear(value),
+        }
+    }
+
+    fn encode(self, value: f32) -> f32 {
+        match self {
+            TransferFunction::Linear => value,
+            // Colors outside of the gamut of the primaries can't be encoded
+            TransferFunction::Srgb => linear_to_srgb(value.max(0.0)),
+            TransferFunction::Pq => nits_to_pq(value * REFERENCE_WHITE_NITS),
+            TransferFunction::Hlg => linear_to_hlg(value),
+        }
+    }
+}
+
+type Matrix = [[f32; 3]; 3];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| (0..3).map(|i| a[row][i] * b[i][column]).sum()))
+}
+
+fn transform(matrix: &Matrix, value: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * value[0] + row[1] * value[1] + row[2] * value[2])
+}
+
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |row: usize, column: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant = (0..3).map(|column| m[0][column] * cofactor(0, column)).sum::<f32>();
+
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| cofactor(column, row) / determinant))
+}
+
+impl Primaries {
+    // xy chromaticities of red, green and blue
+    pub(crate) fn chromaticities(self) -> [[f32; 2]; 3] {
+        match self {
+            Primaries::Rec709 => [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]],
+            Primaries::Rec2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]],
+            Primaries::DciP3 => [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]],
+        }
+    }
+
+    // Linear RGB to CIE XYZ
+    fn to_xyz(self) -> Matrix {
+        const WHITE: [f32; 3] = [0.3127 / 0.3290, 1.0, (1.0 - 0.3127 - 0.3290) / 0.3290];
+
+        let xyz = self.chromaticities().map(|[x, y]| [x / y, 1.0, (1.0 - x - y) / y]);
+        let primaries = [0, 1, 2].map(|row| xyz.map(|primary| primary[row]));
+        let scale = transform(&invert(&primaries), WHITE);
+        primaries.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]])
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Converts the colors of every subresource between color spaces, alpha is kept. Colors outside of the target
+    // gamut keep their negative components if the target transfer function is linear.
+    pub fn convert_colorimetry(&self, from: Colorimetry, to: Colorimetry, dxgi_format: u32) -> Result<ScratchImage> {
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+
+        let matrix = multiply(&invert(&to.primaries.to_xyz()), &from.primaries.to_xyz());
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let mut texels = self.decode_subresource(mip, layer)?;
+                for texel in texels.iter_mut() {
+                    let linear = [0, 1, 2].map(|channel| from.transfer.decode(texel[channel]));
+                    let converted = match from.primaries == to.primaries {
+                        true => linear,
+                        false => transform(&matrix, linear),
+                    };
+                    for channel in 0..3 {
+                        texel[channel] = to.transfer.encode(converted[channel]);
+                    }
+                }
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Error metrics between two images with the same layout, computed on decoded texels. The formats may differ, so
+// an image can be compared with its compressed version. PSNR uses 1.0 as the peak value.
+// SSIM is the mean over 8x8 windows placed every 4 texels, with extra windows along the right and bottom edges when
+// the stride doesn't end there, or over the whole surface if it is smaller than that.
+
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Comparison {
+    // Per channel, RGBA
+    pub rmse: [f64; 4],
+    pub psnr: [f64; 4],
+    pub ssim: [f64; 4],
+}
+
+impl Comparison {
+    // Combined over the color channels
+    pub fn rgb_rmse(&self) -> f64 {
+        ((self.rmse[0].powi(2) + self.rmse[1].powi(2) + self.rmse[2].powi(2)) / 3.0).sqrt()
+    }
+
+    pub fn rgb_psnr(&self) -> f64 {
+        psnr(self.rgb_rmse())
+    }
+
+    pub fn rgb_ssim(&self) -> f64 {
+        (self.ssim[0] + self.ssim[1] + self.ssim[2]) / 3.0
+    }
+}
+
+fn psnr(rmse: f64) -> f64 {
+    if rmse == 0.0 {
+        f64::INFINITY
+    } else {
+        -20.0 * rmse.log10()
+    }
+}
+
+const SSIM_WINDOW: usize = 8;
+const SSIM_STRIDE: usize = 4;
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+// Sums over a set of subresources
+#[derive(Clone, Copy, Default)]
+struct Metrics {
+    squared_error: [f64; 4],
+    texel_count: u64,
+    ssim: [f64; 4],
+    window_count: u64,
+}
+
+impl Metrics {
+    fn add(&mut self, a: &[[f32; 4]], b: &[[f32; 4]], width: usize, height: usize) {
+        for (a, b) in a.iter().zip(b.iter()) {
+            for channel in 0..4 {
+                self.squared_error[channel] += (a[channel] as f64 - b[channel] as f64).powi(2);
+            }
+        }
+        self.texel_count += a.len() as u64;
+
+        let window_width = width.min(SSIM_WINDOW);
+        let window_height = height.min(SSIM_WINDOW);
+        for y in window_positions(height, window_height) {
+            for x in window_positions(width, window_width) {
+                let window = (x, y, window_width, window_height);
+                for channel in 0..4 {
+                    self.ssim[channel] += window_ssim(a, b, width, window, channel);
+                }
+                self.window_count += 1;
+            }
+        }
+    }
+
+    fn comparison(&self) -> Comparison {
+        let mut rmse = [0.0; 4];
+        let mut ssim = [0.0; 4];
+        for channel in 0..4 {
+            rmse[channel] = (self.squared_error[channel] / self.texel_count.max(1) as f64).sqrt();
+            ssim[channel] = self.ssim[channel] / self.window_count.max(1) as f64;
+        }
+
+        Comparison {
+            rmse,
+            psnr: [psnr(rmse[0]), psnr(rmse[1]), psnr(rmse[2]), psnr(rmse[3])],
+            ssim,
+        }
+    }
+}
+
+// Every SSIM_STRIDE texels, and the last window ends at the edge
+fn window_positions(size: usize, window_size: usize) -> impl Iterator<Item = usize> {
+    let last = size - window_size;
+    let edge = match last % SSIM_STRIDE {
+        0 => None,
+        _ => Some(last),
+    };
+    (0..=last).step_by(SSIM_STRIDE).chain(edge)
+}
+
+fn window_ssim(
+    a: &[[f32; 4]],
+    b: &[[f32; 4]],
+    width: usize,
+    (x, y, window_width, window_height): (usize, usize, usize, usize),
+    channel: usize,
+) -> f64 {
+    let texels = || {
+        (y..y + window_height).flat_map(move |y| {
+            (x..x + window_width).map(move |x| (a[y * width + x][channel] as f64, b[y * width + x][channel] as f64))
+        })
+    };
+
+    let count = (window_width * window_height) as f64;
+    let (sum_a, sum_b) = texels().fold((0.0, 0.0), |(sum_a, sum_b), (a, b)| (sum_a + a, sum_b + b));
+    let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+
+    let (mut variance_a, mut variance_b, mut covariance) = (0.0, 0.0, 0.0);
+    for (a, b) in texels() {
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+        covariance += (a - mean_a) * (b - mean_b);
+    }
+    let (variance_a, variance_b, covariance) = (variance_a / count, variance_b / count, covariance / count);
+
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+        / ((mean_a.powi(2) + mean_b.powi(2) + SSIM_C1) * (variance_a + variance_b + SSIM_C2))
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Compares all subresources
+    pub fn compare<T: Storage>(&self, other: &ScratchImage<T>) -> Result<Comparison> {
+        self.check_comparable(other)?;
+
+        let mut metrics = Metrics::default();
+        for mip in 0..layout::mip_count(&self.dds_header) {
+            self.add_mip_metrics(other, mip, &mut metrics)?;
+        }
+
+        Ok(metrics.comparison())
+    }
+
+    // Comparison of every mip level over all layers
+    pub fn compare_mips<T: Storage>(&self, other: &ScratchImage<T>) -> Result<Vec<Comparison>> {
+        self.check_comparable(other)?;
+
+        (0..layout::mip_count(&self.dds_header))
+            .map(|mip| {
+                let mut metrics = Metrics::default();
+                self.add_mip_metrics(other, mip, &mut metrics)?;
+                Ok(metrics.comparison())
+            })
+            .collect()
+    }
+
+    pub fn compare_subresource<T: Storage>(&self, other: &ScratchImage<T>, mip: u32, layer: u32) -> Result<Comparison> {
+        self.check_comparable(other)?;
+
+        let mut metrics = Metrics::default();
+        self.add_subresource_metrics(other, mip, layer, &mut metrics)?;
+
+        Ok(metrics.comparison())
+    }
+
+    // Absolute difference of a subresource multiplied by `scale`, with opaque alpha. Differences in alpha are added
+    // to the color channels, so they are visible too.
+    pub fn difference<T: Storage>(
+        &self,
+        other: &ScratchImage<T>,
+        mip: u32,
+        layer: u32,
+        scale: f32,
+    ) -> Result<Vec<[f32; 4]>> {
+        self.check_comparable(other)?;
+
+        let a = self.decode_subresource(mip, layer)?;
+        let b = other.decode_subresource(mip, layer)?;
+        Ok(a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| {
+                let alpha = (a[3] - b[3]).abs();
+                let channel = |channel: usize| (((a[channel] - b[channel]).abs() + alpha) * scale).min(1.0);
+                [channel(0), channel(1), channel(2), 1.0]
+            })
+            .collect())
+    }
+
+    fn add_mip_metrics<T: Storage>(&self, other: &ScratchImage<T>, mip: u32, metrics: &mut Metrics) -> Result<()> {
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            self.add_subresource_metrics(other, mip, layer, metrics)?;
+        }
+        Ok(())
+    }
+
+    fn add_subresource_metrics<T: Storage>(
+        &self,
+        other: &ScratchImage<T>,
+        mip: u32,
+        layer: u32,
+        metrics: &mut Metrics,
+    ) -> Result<()> {
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        let slice_size = width as usize * height as usize;
+        let (a, b) = (
+            self.decode_subresource(mip, layer)?,
+            other.decode_subresource(mip, layer)?,
+        );
+        for (a, b) in a.chunks_exact(slice_size).zip(b.chunks_exact(slice_size)) {
+            metrics.add(a, b, width as usize, height as usize);
+        }
+        Ok(())
+    }
+
+    fn check_comparable<T: Storage>(&self, other: &ScratchImage<T>) -> Result<()> {
+        let same_layout = self.image_size() == other.image_size()
+            && self.mipmap_count() == other.mipmap_count()
+            && self.layer_count() == other.layer_count()
+            && layout::face_count(&self.dds_header) == layout::face_count(&other.dds_header);
+        if !same_layout {
+            return Err(Error::IncompatibleImages("Images have different dimensions"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageDesc;
+    use crate::dds::*;
+
+    fn image(width: u32, height: u32) -> ScratchImage {
+        let mut image = ScratchImage::with_desc(&ImageDesc {
+            width,
+            height,
+            depth: 1,
+            mipmap_count: 1,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_R8_UNORM,
+            is_cubemap: false,
+            dimension: None,
+        })
+        .unwrap();
+        for (index, byte) in image.as_slice_mut().iter_mut().enumerate() {
+            *byte = (index * 7 % 256) as u8;
+        }
+        image
+    }
+
+    #[test]
+    fn window_positions_reach_the_edges() {
+        assert_eq!(window_positions(8, 8).collect::<Vec<_>>(), [0]);
+        assert_eq!(window_positions(16, 8).collect::<Vec<_>>(), [0, 4, 8]);
+        assert_eq!(window_positions(13, 8).collect::<Vec<_>>(), [0, 4, 5]);
+        assert_eq!(window_positions(5, 5).collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn ssim_covers_the_last_column_and_row() {
+        let a = image(13, 14);
+        let mut b = a.clone();
+        let same = a.compare(&b).unwrap();
+        assert_eq!(same.ssim[0], 1.0);
+
+        // Last column
+        for y in 0..14 {
+            b.as_slice_mut()[y * 13 + 12] ^= 0x80;
+        }
+        assert!(a.compare(&b).unwrap().ssim[0] < 1.0);
+
+        // Last row
+        let mut b = a.clone();
+        for byte in &mut b.as_slice_mut()[13 * 13..] {
+            *byte ^= 0x80;
+        }
+        assert!(a.compare(&b).unwrap().ssim[0] < 1.0);
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Chunked streaming container: every subresource is compressed separately and indexed, so a streaming reader
+// can seek to and decompress only the subresources it needs.
+//
+// Layout:
+//   ContainerHeader
+//   DirectDrawHeader
+//   ContainerIndexEntry * subresource_count (DDS subresource order: layer major, then mip)
+//   zstd frames
+
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use bytemuck::Zeroable;
+
+pub const CONTAINER_MAGIC: [u8; 4] = *b"SDDC";
+pub const CONTAINER_VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ContainerHeader {
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub subresource_count: u32,
+    pub reserved: u32,
+}
+
+unsafe impl bytemuck::Zeroable for ContainerHeader {}
+unsafe impl bytemuck::Pod for ContainerHeader {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ContainerIndexEntry {
+    pub offset: u64,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+unsafe impl bytemuck::Zeroable for ContainerIndexEntry {}
+unsafe impl bytemuck::Pod for ContainerIndexEntry {}
+
+// File offsets of the validated fields
+const SUBRESOURCE_COUNT_OFFSET: u64 = 8;
+const COMPRESSED_SIZE_OFFSET: u64 = 8;
+const UNCOMPRESSED_SIZE_OFFSET: u64 = 16;
+const INDEX_OFFSET: usize = std::mem::size_of::<ContainerHeader>() + std::mem::size_of::<DirectDrawHeader>();
+
+fn read_container_header<T: std::io::Read>(file: &mut T) -> Result<(DirectDrawHeader, Vec<ContainerIndexEntry>)> {
+    let container_header = {
+        let mut header_bytes = [0u8; std::mem::size_of::<ContainerHeader>()];
+        file.read_exact(&mut header_bytes)?;
+        *bytemuck::from_bytes::<ContainerHeader>(&header_bytes)
+    };
+
+    if container_header.magic != CONTAINER_MAGIC {
+        return Err(Error::BadFileMagic {
+            actual: container_header.magic,
+        });
+    }
+    if container_header.version != CONTAINER_VERSION {
+        return Err(Error::NotImplementedYet("Unsupported container version"));
+    }
+
+    let mut dds_header = ScratchImage::read_header(file)?;
+    ScratchImage::apply_parse_options(&mut dds_header, &ParseOptions::default())?;
+    ScratchImage::validate_header(&dds_header)?;
+    let subresource_count = layout::subresource_count(&dds_header);
+    if container_header.subresource_count != subresource_count {
+        return Err(Error::BadFileHeader {
+            expected: subresource_count as u64,
+            actual: container_header.subresource_count as u64,
+            offset: SUBRESOURCE_COUNT_OFFSET,
+        });
+    }
+
+    let mut index = vec![ContainerIndexEntry::zeroed(); container_header.subresource_count as usize];
+    file.read_exact(bytemuck::cast_slice_mut(&mut index))?;
+
+    let index_end = (INDEX_OFFSET + std::mem::size_of_val(&index[..])) as u64;
+    let mut expected_offset = index_end;
+    for (subresource, entry) in index.iter().enumerate() {
+        let entry_offset = (INDEX_OFFSET + subresource * std::mem::size_of::<ContainerIndexEntry>()) as u64;
+        if entry.offset != expected_offset {
+            return Err(Error::BadFileHeader {
+                expected: expected_offset,
+                actual: entry.offset,
+                offset: entry_offset,
+            });
+        }
+
+        let mip = subresource as u32 % layout::mip_count(&dds_header);
+        let mip_size = layout::mip_size(&dds_header, mip) as u64;
+        if entry.uncompressed_size != mip_size {
+            return Err(Error::BadDataSize {
+                expected: mip_size,
+                actual: entry.uncompressed_size,
+                offset: entry_offset + UNCOMPRESSED_SIZE_OFFSET,
+            });
+        }
+
+        let max_compressed_size = zstd::compress_bound(entry.uncompressed_size as usize) as u64;
+        if entry.compressed_size > max_compressed_size {
+            return Err(Error::BadDataSize {
+                expected: max_compressed_size,
+                actual: entry.compressed_size,
+                offset: entry_offset + COMPRESSED_SIZE_OFFSET,
+            });
+        }
+
+        expected_offset += entry.compressed_size;
+    }
+
+    Ok((dds_header, index))
+}
+
+fn decompress_chunk(chunk: &[u8], entry: &ContainerIndexEntry) -> Result<Vec<u8>> {
+    let data = zstd::bulk::decompress(chunk, entry.uncompressed_size as usize)?;
+    if data.len() as u64 != entry.uncompressed_size {
+        return Err(Error::BadDataSize {
+            expected: entry.uncompressed_size,
+            actual: data.len() as u64,
+            offset: entry.offset,
+        });
+    }
+    Ok(data)
+}
+
+pub struct ContainerReader<T: std::io::Read + std::io::Seek> {
+    file: T,
+    dds_header: DirectDrawHeader,
+    index: Vec<ContainerIndexEntry>,
+}
+
+impl<T: std::io::Read + std::io::Seek> ContainerReader<T> {
+    pub fn new(mut file: T) -> Result<Self> {
+        let (dds_header, index) = read_container_header(&mut file)?;
+        Ok(Self {
+            file,
+            dds_header,
+            index,
+        })
+    }
+
+    pub fn header(&self) -> &DirectDrawHeader {
+        &self.dds_header
+    }
+
+    pub fn subresource_count(&self) -> u32 {
+        self.index.len() as u32
+    }
+
+    pub fn read_subresource(&mut self, mip: u32, layer: u32) -> Result<Vec<u8>> {
+        let subresource = layout::subresource_index(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let entry = self.index[subresource as usize];
+
+        let mut chunk = vec![0u8; entry.compressed_size as usize];
+        self.file.seek(std::io::SeekFrom::Start(entry.offset))?;
+        self.file.read_exact(&mut chunk)?;
+
+        decompress_chunk(&chunk, &entry)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.file
+    }
+}
+
+impl ScratchImage {
+    pub fn from_container_file(path: &std::path::Path) -> Result<ScratchImage> {
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        Self::from_container_reader(&mut file)
+    }
+
+    pub fn from_container_reader<T: std::io::Read>(file: &mut T) -> Result<ScratchImage> {
+        let (dds_header, index) = read_container_header(file)?;
+
+        let mut dds_data = Vec::with_capacity(layout::data_size(&dds_header));
+        for entry in &index {
+            let mut chunk = vec![0u8; entry.compressed_size as usize];
+            file.read_exact(&mut chunk)?;
+            dds_data.extend_from_slice(&decompress_chunk(&chunk, entry)?);
+        }
+
+        Self::from_header_and_data(dds_header, dds_data)
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn write_to_container_file(&self, path: &std::path::Path, compression_level: i32) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        self.write_to_container(&mut file, compression_level)
+    }
+
+    pub fn write_to_container<T: std::io::Write>(&self, file: &mut T, compression_level: i32) -> Result<()> {
+        let subresource_count = layout::subresource_count(&self.dds_header);
+
+        let mut chunks = Vec::with_capacity(subresource_count as usize);
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let range = layout::subresource_range(&self.dds_header, mip, layer).unwrap();
+                chunks.push((
+                    range.len(),
+                    zstd::bulk::compress(&self.as_slice()[range], compression_level)?,
+                ));
+            }
+        }
+
+        let mut offset =
+            (INDEX_OFFSET + subresource_count as usize * std::mem::size_of::<ContainerIndexEntry>()) as u64;
+        let index: Vec<ContainerIndexEntry> = chunks
+            .iter()
+            .map(|(uncompressed_size, chunk)| {
+                let entry = ContainerIndexEntry {
+                    offset,
+                    compressed_size: chunk.len() as u64,
+                    uncompressed_size: *uncompressed_size as u64,
+                };
+                offset += entry.compressed_size;
+                entry
+            })
+            .collect();
+
+        let container_header = ContainerHeader {
+            magic: CONTAINER_MAGIC,
+            version: CONTAINER_VERSION,
+            subresource_count,
+            reserved: 0,
+        };
+        file.write_all(bytemuck::bytes_of(&container_header))?;
+        file.write_all(bytemuck::bytes_of(&self.dds_header))?;
+        file.write_all(bytemuck::cast_slice(&index))?;
+        for (_, chunk) in &chunks {
+            file.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Faces of cubemaps, stored as six consecutive layers per array element in the order of `CubeFace`.
+// Some writers set DDSCAPS2_CUBEMAP with only some of the face bits in a DX10 header without
+// DDS_RESOURCE_MISC_TEXTURECUBE, such partial cubemaps store only those faces, still in the order of `CubeFace`.
+// Images with DDS_RESOURCE_MISC_TEXTURECUBE always store all faces, whatever the face bits are. Legacy headers without
+// the DX10 extension are not supported yet, like in the other readers.
+
+use crate::dds::*;
+use crate::layout;
+use crate::metadata::read_metadata_chunk;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+const FACE_CAPS2: u32 = DDSCAPS2_CUBEMAP_POSITIVEX
+    | DDSCAPS2_CUBEMAP_NEGATIVEX
+    | DDSCAPS2_CUBEMAP_POSITIVEY
+    | DDSCAPS2_CUBEMAP_NEGATIVEY
+    | DDSCAPS2_CUBEMAP_POSITIVEZ
+    | DDSCAPS2_CUBEMAP_NEGATIVEZ;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CubeFace {
+    PositiveX = 0,
+    NegativeX = 1,
+    PositiveY = 2,
+    NegativeY = 3,
+    PositiveZ = 4,
+    NegativeZ = 5,
+}
+
+impl CubeFace {
+    // In storage order
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    // Layer of the face in a cubemap with a single array element
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    fn caps2_bit(self) -> u32 {
+        DDSCAPS2_CUBEMAP_POSITIVEX << self.index()
+    }
+}
+
+pub(crate) fn is_partial_cubemap(header: &DirectDrawHeader) -> bool {
+    let face_caps2 = header.caps2 & FACE_CAPS2;
+    layout::face_count(header) != 6
+        && header.caps2 & DDSCAPS2_CUBEMAP != 0
+        && face_caps2 != 0
+        && face_caps2 != FACE_CAPS2
+}
+
+// Faces stored in the payload for every array element: all of them for complete cubemaps, the faces with caps2 bits
+// for partial cubemaps and none for other images
+pub fn stored_faces(header: &DirectDrawHeader) -> Vec<CubeFace> {
+    if is_partial_cubemap(header) {
+        IntoIterator::into_iter(CubeFace::ALL)
+            .filter(|face| header.caps2 & face.caps2_bit() != 0)
+            .collect()
+    } else if layout::face_count(header) == 6 {
+        CubeFace::ALL.to_vec()
+    } else {
+        Vec::new()
+    }
+}
+
+// Header of a 2D array with a layer for every stored face
+fn stored_faces_header(header: &DirectDrawHeader, face_count: u32) -> DirectDrawHeader {
+    let mut header = *header;
+    header.dxt10.array_size = header.dxt10.array_size.max(1).saturating_mul(face_count);
+    header.dxt10.misc_flag &= !DDS_RESOURCE_MISC_TEXTURECUBE;
+    header.caps2 &= !(DDSCAPS2_CUBEMAP | FACE_CAPS2);
+    header
+}
+
+#[derive(Debug)]
+pub struct PartialCubemap {
+    // Faces stored in the file, in storage order
+    pub faces: Vec<CubeFace>,
+    // 2D texture array with a layer for every stored face of every array element, in the order of `faces`
+    pub layers: ScratchImage,
+}
+
+impl PartialCubemap {
+    // Complete cubemap, the missing faces are filled with `color`
+    pub fn synthesize_missing_faces(&self, color: [f32; 4]) -> Result<ScratchImage> {
+        let face_count = self.faces.len() as u32;
+        let mut desc = self.layers.desc();
+        desc.array_size /= face_count;
+        desc.is_cubemap = true;
+        let mut image = ScratchImage::with_desc(&desc)?;
+
+        let layer_size = layout::layer_size(&image.dds_header);
+        for array_index in 0..desc.array_size {
+            for face in CubeFace::ALL.iter().copied() {
+                let layer = array_index * 6 + face.index();
+                match self.faces.iter().position(|&stored| stored == face) {
+                    Some(position) => {
+                        let offset = (array_index * face_count + position as u32) as usize * layer_size;
+                        let source = &self.layers.as_slice()[offset..offset + layer_size];
+                        let destination = layer as usize * layer_size;
+                        image.as_slice_mut()[destination..destination + layer_size].copy_from_slice(source);
+                    }
+                    None => {
+                        for mip in 0..desc.mipmap_count {
+                            image.fill_subresource(mip, layer, color)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(image)
+    }
+}
+
+impl ScratchImage {
+    // Reads complete and partial cubemaps, the faces that are stored are returned as layers of a 2D array
+    pub fn from_partial_cubemap_reader<T: std::io::Read>(
+        dds_file: &mut T,
+        options: &ParseOptions,
+    ) -> Result<PartialCubemap> {
+        let file_header = Self::read_header(dds_file)?;
+        let faces = stored_faces(&file_header);
+        if faces.is_empty() {
+            return Err(Error::BadImageDescription("Image is not a cubemap"));
+        }
+
+        let mut dds_header = stored_faces_header(&file_header, faces.len() as u32);
+        Self::apply_parse_options(&mut dds_header, options)?;
+        let dds_data = Self::read_data(dds_file, &dds_header, true)?;
+        let layers = Self::from_header_and_data(dds_header, dds_data)?;
+
+        if !options.allow_trailing_data {
+            read_metadata_chunk(dds_file, &layers.dds_header, options)?;
+        }
+        Ok(PartialCubemap { faces, layers })
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Layer of a face, `array_index` selects the cubemap of cubemap arrays
+    pub fn face_layer(&self, array_index: u32, face: CubeFace) -> Result<u32> {
+        if !self.is_cubemap() {
+            return Err(Error::BadImageDescription("Image is not a cubemap"));
+        }
+        if array_index >= self.dds_header.dxt10.array_size.max(1) {
+            return Err(Error::BadSubresource);
+        }
+        Ok(array_index * 6 + face.index())
+    }
+
+    // Payload of every face of a mip, in storage order
+    pub fn faces(&self, array_index: u32, mip: u32) -> Result<impl Iterator<Item = (CubeFace, &[u8])> + '_> {
+        let first_layer = self.face_layer(array_index, CubeFace::PositiveX)?;
+        if mip >= layout::mip_count(&self.dds_header) {
+            return Err(Error::BadSubresource);
+        }
+
+        Ok(IntoIterator::into_iter(CubeFace::ALL).map(move |face| {
+            let range = layout::subresource_range(&self.dds_header, mip, first_layer + face.index()).unwrap();
+            (face, &self.as_slice()[range])
+        }))
+    }
+}
@@ -5,7 +5,7 @@
 
 #[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dds-pixelformat"]
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct DirectDrawPixelFormat {
     pub size: u32,
     pub flags: u32,
@@ -19,7 +19,7 @@ pub struct DirectDrawPixelFormat {
 
 #[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dds-header-dxt10"]
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct DirectDrawHeader10 {
     pub dxgi_format: u32,
     pub resource_dimension: u32,
@@ -30,7 +30,7 @@ pub struct DirectDrawHeader10 {
 
 #[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dds-header"]
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct DirectDrawHeader {
     pub magic: [u8; 4],
     pub size: u32,
@@ -219,6 +219,50 @@ pub mod dxgi_format {
     pub const DXGI_FORMAT_P208: u32 = 116;
     pub const DXGI_FORMAT_V208: u32 = 117;
     pub const DXGI_FORMAT_V408: u32 = 118;
+
+    // ASTC extension formats, reserved by DXGI but not supported by D3D
+    pub const DXGI_FORMAT_ASTC_4X4_TYPELESS: u32 = 133;
+    pub const DXGI_FORMAT_ASTC_4X4_UNORM: u32 = 134;
+    pub const DXGI_FORMAT_ASTC_4X4_UNORM_SRGB: u32 = 135;
+    pub const DXGI_FORMAT_ASTC_5X4_TYPELESS: u32 = 137;
+    pub const DXGI_FORMAT_ASTC_5X4_UNORM: u32 = 138;
+    pub const DXGI_FORMAT_ASTC_5X4_UNORM_SRGB: u32 = 139;
+    pub const DXGI_FORMAT_ASTC_5X5_TYPELESS: u32 = 141;
+    pub const DXGI_FORMAT_ASTC_5X5_UNORM: u32 = 142;
+    pub const DXGI_FORMAT_ASTC_5X5_UNORM_SRGB: u32 = 143;
+    pub const DXGI_FORMAT_ASTC_6X5_TYPELESS: u32 = 145;
+    pub const DXGI_FORMAT_ASTC_6X5_UNORM: u32 = 146;
+    pub const DXGI_FORMAT_ASTC_6X5_UNORM_SRGB: u32 = 147;
+    pub const DXGI_FORMAT_ASTC_6X6_TYPELESS: u32 = 149;
+    pub const DXGI_FORMAT_ASTC_6X6_UNORM: u32 = 150;
+    pub const DXGI_FORMAT_ASTC_6X6_UNORM_SRGB: u32 = 151;
+    pub const DXGI_FORMAT_ASTC_8X5_TYPELESS: u32 = 153;
+    pub const DXGI_FORMAT_ASTC_8X5_UNORM: u32 = 154;
+    pub const DXGI_FORMAT_ASTC_8X5_UNORM_SRGB: u32 = 155;
+    pub const DXGI_FORMAT_ASTC_8X6_TYPELESS: u32 = 157;
+    pub const DXGI_FORMAT_ASTC_8X6_UNORM: u32 = 158;
+    pub const DXGI_FORMAT_ASTC_8X6_UNORM_SRGB: u32 = 159;
+    pub const DXGI_FORMAT_ASTC_8X8_TYPELESS: u32 = 161;
+    pub const DXGI_FORMAT_ASTC_8X8_UNORM: u32 = 162;
+    pub const DXGI_FORMAT_ASTC_8X8_UNORM_SRGB: u32 = 163;
+    pub const DXGI_FORMAT_ASTC_10X5_TYPELESS: u32 = 165;
+    pub const DXGI_FORMAT_ASTC_10X5_UNORM: u32 = 166;
+    pub const DXGI_FORMAT_ASTC_10X5_UNORM_SRGB: u32 = 167;
+    pub const DXGI_FORMAT_ASTC_10X6_TYPELESS: u32 = 169;
+    pub const DXGI_FORMAT_ASTC_10X6_UNORM: u32 = 170;
+    pub const DXGI_FORMAT_ASTC_10X6_UNORM_SRGB: u32 = 171;
+    pub const DXGI_FORMAT_ASTC_10X8_TYPELESS: u32 = 173;
+    pub const DXGI_FORMAT_ASTC_10X8_UNORM: u32 = 174;
+    pub const DXGI_FORMAT_ASTC_10X8_UNORM_SRGB: u32 = 175;
+    pub const DXGI_FORMAT_ASTC_10X10_TYPELESS: u32 = 177;
+    pub const DXGI_FORMAT_ASTC_10X10_UNORM: u32 = 178;
+    pub const DXGI_FORMAT_ASTC_10X10_UNORM_SRGB: u32 = 179;
+    pub const DXGI_FORMAT_ASTC_12X10_TYPELESS: u32 = 181;
+    pub const DXGI_FORMAT_ASTC_12X10_UNORM: u32 = 182;
+    pub const DXGI_FORMAT_ASTC_12X10_UNORM_SRGB: u32 = 183;
+    pub const DXGI_FORMAT_ASTC_12X12_TYPELESS: u32 = 185;
+    pub const DXGI_FORMAT_ASTC_12X12_UNORM: u32 = 186;
+    pub const DXGI_FORMAT_ASTC_12X12_UNORM_SRGB: u32 = 187;
 }
 
 // resource_dimension
@@ -231,6 +275,32 @@ mod resource_dimension {
     pub const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceDimension {
+    Texture1D,
+    Texture2D,
+    Texture3D,
+}
+
+impl ResourceDimension {
+    pub fn from_d3d10(resource_dimension: u32) -> Option<Self> {
+        match resource_dimension {
+            D3D10_RESOURCE_DIMENSION_TEXTURE1D => Some(Self::Texture1D),
+            D3D10_RESOURCE_DIMENSION_TEXTURE2D => Some(Self::Texture2D),
+            D3D10_RESOURCE_DIMENSION_TEXTURE3D => Some(Self::Texture3D),
+            _ => None,
+        }
+    }
+
+    pub fn to_d3d10(self) -> u32 {
+        match self {
+            Self::Texture1D => D3D10_RESOURCE_DIMENSION_TEXTURE1D,
+            Self::Texture2D => D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+            Self::Texture3D => D3D10_RESOURCE_DIMENSION_TEXTURE3D,
+        }
+    }
+}
+
 // misc_flag
 #[allow(unused)]
 mod misc_flag {
@@ -240,6 +310,7 @@ mod misc_flag {
 // misc_flags2
 #[allow(unused)]
 mod misc_flags2 {
+    pub const DDS_MISC_FLAGS2_ALPHA_MODE_MASK: u32 = 0x7;
     pub const DDS_ALPHA_MODE_UNKNOWN: u32 = 0x0;
     pub const DDS_ALPHA_MODE_STRAIGHT: u32 = 0x1;
     pub const DDS_ALPHA_MODE_PREMULTIPLIED: u32 = 0x2;
@@ -247,6 +318,40 @@ mod misc_flags2 {
     pub const DDS_ALPHA_MODE_CUSTOM: u32 = 0x4;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    Unknown,
+    Straight,
+    Premultiplied,
+    // Alpha is 1 everywhere, or should be ignored
+    Opaque,
+    // Alpha is not transparency, e.g. a packed mask
+    Custom,
+}
+
+impl AlphaMode {
+    pub fn from_misc_flags2(misc_flags2: u32) -> Option<Self> {
+        match misc_flags2 & DDS_MISC_FLAGS2_ALPHA_MODE_MASK {
+            DDS_ALPHA_MODE_UNKNOWN => Some(Self::Unknown),
+            DDS_ALPHA_MODE_STRAIGHT => Some(Self::Straight),
+            DDS_ALPHA_MODE_PREMULTIPLIED => Some(Self::Premultiplied),
+            DDS_ALPHA_MODE_OPAQUE => Some(Self::Opaque),
+            DDS_ALPHA_MODE_CUSTOM => Some(Self::Custom),
+            _ => None,
+        }
+    }
+
+    pub fn to_misc_flags2(self) -> u32 {
+        match self {
+            Self::Unknown => DDS_ALPHA_MODE_UNKNOWN,
+            Self::Straight => DDS_ALPHA_MODE_STRAIGHT,
+            Self::Premultiplied => DDS_ALPHA_MODE_PREMULTIPLIED,
+            Self::Opaque => DDS_ALPHA_MODE_OPAQUE,
+            Self::Custom => DDS_ALPHA_MODE_CUSTOM,
+        }
+    }
+}
+
 pub use caps::*;
 pub use caps2::*;
 pub use dxgi_format::*;
@@ -255,221 +360,3 @@ pub use misc_flag::*;
 pub use misc_flags2::*;
 pub use pixel_format_flags::*;
 pub use resource_dimension::*;
-
-pub fn is_block_compressed(dxgi_format: u32) -> bool {
-    dxgi_format == DXGI_FORMAT_BC1_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC1_UNORM
-        || dxgi_format == DXGI_FORMAT_BC1_UNORM_SRGB
-        || dxgi_format == DXGI_FORMAT_BC2_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC2_UNORM
-        || dxgi_format == DXGI_FORMAT_BC2_UNORM_SRGB
-        || dxgi_format == DXGI_FORMAT_BC3_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC3_UNORM
-        || dxgi_format == DXGI_FORMAT_BC3_UNORM_SRGB
-        || dxgi_format == DXGI_FORMAT_BC4_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC4_UNORM
-        || dxgi_format == DXGI_FORMAT_BC4_SNORM
-        || dxgi_format == DXGI_FORMAT_BC5_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC5_UNORM
-        || dxgi_format == DXGI_FORMAT_BC5_SNORM
-        || dxgi_format == DXGI_FORMAT_BC6H_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC6H_UF16
-        || dxgi_format == DXGI_FORMAT_BC6H_SF16
-        || dxgi_format == DXGI_FORMAT_BC7_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC7_UNORM
-        || dxgi_format == DXGI_FORMAT_BC7_UNORM_SRGB
-}
-
-pub fn is_legacy_yuv(dxgi_format: u32) -> bool {
-    dxgi_format == DXGI_FORMAT_AYUV
-        || dxgi_format == DXGI_FORMAT_Y410
-        || dxgi_format == DXGI_FORMAT_Y416
-        || dxgi_format == DXGI_FORMAT_NV12
-        || dxgi_format == DXGI_FORMAT_P010
-        || dxgi_format == DXGI_FORMAT_P016
-        || dxgi_format == DXGI_FORMAT_420_OPAQUE
-        || dxgi_format == DXGI_FORMAT_YUY2
-        || dxgi_format == DXGI_FORMAT_Y210
-        || dxgi_format == DXGI_FORMAT_Y216
-        || dxgi_format == DXGI_FORMAT_NV11
-        || dxgi_format == DXGI_FORMAT_AI44
-        || dxgi_format == DXGI_FORMAT_IA44
-        || dxgi_format == DXGI_FORMAT_P8
-        || dxgi_format == DXGI_FORMAT_A8P8
-        || dxgi_format == DXGI_FORMAT_B4G4R4A4_UNORM
-        || dxgi_format == DXGI_FORMAT_P208
-        || dxgi_format == DXGI_FORMAT_V208
-        || dxgi_format == DXGI_FORMAT_V408
-}
-
-pub fn bits_per_pixel(dxgi_format: u32) -> u32 {
-    match dxgi_format {
-        DXGI_FORMAT_R32G32B32A32_TYPELESS => 128,
-        DXGI_FORMAT_R32G32B32A32_FLOAT => 128,
-        DXGI_FORMAT_R32G32B32A32_UINT => 128,
-        DXGI_FORMAT_R32G32B32A32_SINT => 128,
-
-        DXGI_FORMAT_R32G32B32_TYPELESS => 96,
-        DXGI_FORMAT_R32G32B32_FLOAT => 96,
-        DXGI_FORMAT_R32G32B32_UINT => 96,
-        DXGI_FORMAT_R32G32B32_SINT => 96,
-
-        DXGI_FORMAT_R16G16B16A16_TYPELESS => 64,
-        DXGI_FORMAT_R16G16B16A16_FLOAT => 64,
-        DXGI_FORMAT_R16G16B16A16_UNORM => 64,
-        DXGI_FORMAT_R16G16B16A16_UINT => 64,
-        DXGI_FORMAT_R16G16B16A16_SNORM => 64,
-        DXGI_FORMAT_R16G16B16A16_SINT => 64,
-        DXGI_FORMAT_R32G32_TYPELESS => 64,
-        DXGI_FORMAT_R32G32_FLOAT => 64,
-        DXGI_FORMAT_R32G32_UINT => 64,
-        DXGI_FORMAT_R32G32_SINT => 64,
-        DXGI_FORMAT_R32G8X24_TYPELESS => 64,
-        DXGI_FORMAT_D32_FLOAT_S8X24_UINT => 64,
-        DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS => 64,
-        DXGI_FORMAT_X32_TYPELESS_G8X24_UINT => 64,
-        DXGI_FORMAT_Y416 => 64,
-        DXGI_FORMAT_Y210 => 64,
-        DXGI_FORMAT_Y216 => 64,
-
-        DXGI_FORMAT_R10G10B10A2_TYPELESS => 32,
-        DXGI_FORMAT_R10G10B10A2_UNORM => 32,
-        DXGI_FORMAT_R10G10B10A2_UINT => 32,
-        DXGI_FORMAT_R11G11B10_FLOAT => 32,
-        DXGI_FORMAT_R8G8B8A8_TYPELESS => 32,
-        DXGI_FORMAT_R8G8B8A8_UNORM => 32,
-        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => 32,
-        DXGI_FORMAT_R8G8B8A8_UINT => 32,
-        DXGI_FORMAT_R8G8B8A8_SNORM => 32,
-        DXGI_FORMAT_R8G8B8A8_SINT => 32,
-        DXGI_FORMAT_R16G16_TYPELESS => 32,
-        DXGI_FORMAT_R16G16_FLOAT => 32,
-        DXGI_FORMAT_R16G16_UNORM => 32,
-        DXGI_FORMAT_R16G16_UINT => 32,
-        DXGI_FORMAT_R16G16_SNORM => 32,
-        DXGI_FORMAT_R16G16_SINT => 32,
-        DXGI_FORMAT_R32_TYPELESS => 32,
-        DXGI_FORMAT_D32_FLOAT => 32,
-        DXGI_FORMAT_R32_FLOAT => 32,
-        DXGI_FORMAT_R32_UINT => 32,
-        DXGI_FORMAT_R32_SINT => 32,
-        DXGI_FORMAT_R24G8_TYPELESS => 32,
-        DXGI_FORMAT_D24_UNORM_S8_UINT => 32,
-        DXGI_FORMAT_R24_UNORM_X8_TYPELESS => 32,
-        DXGI_FORMAT_X24_TYPELESS_G8_UINT => 32,
-        DXGI_FORMAT_R9G9B9E5_SHAREDEXP => 32,
-        DXGI_FORMAT_R8G8_B8G8_UNORM => 32,
-        DXGI_FORMAT_G8R8_G8B8_UNORM => 32,
-        DXGI_FORMAT_B8G8R8A8_UNORM => 32,
-        DXGI_FORMAT_B8G8R8X8_UNORM => 32,
-        DXGI_FORMAT_R10G10B10_XR_BIAS_A2_UNORM => 32,
-        DXGI_FORMAT_B8G8R8A8_TYPELESS => 32,
-        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => 32,
-        DXGI_FORMAT_B8G8R8X8_TYPELESS => 32,
-        DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => 32,
-        DXGI_FORMAT_AYUV => 32,
-        DXGI_FORMAT_Y410 => 32,
-        DXGI_FORMAT_YUY2 => 32,
-        DXGI_FORMAT_P010 => 24,
-        DXGI_FORMAT_P016 => 24,
-        DXGI_FORMAT_R8G8_TYPELESS => 16,
-        DXGI_FORMAT_R8G8_UNORM => 16,
-        DXGI_FORMAT_R8G8_UINT => 16,
-        DXGI_FORMAT_R8G8_SNORM => 16,
-        DXGI_FORMAT_R8G8_SINT => 16,
-        DXGI_FORMAT_R16_TYPELESS => 16,
-        DXGI_FORMAT_R16_FLOAT => 16,
-        DXGI_FORMAT_D16_UNORM => 16,
-        DXGI_FORMAT_R16_UNORM => 16,
-        DXGI_FORMAT_R16_UINT => 16,
-        DXGI_FORMAT_R16_SNORM => 16,
-        DXGI_FORMAT_R16_SINT => 16,
-        DXGI_FORMAT_B5G6R5_UNORM => 16,
-        DXGI_FORMAT_B5G5R5A1_UNORM => 16,
-        DXGI_FORMAT_A8P8 => 16,
-        DXGI_FORMAT_B4G4R4A4_UNORM => 16,
-        DXGI_FORMAT_NV12 => 12,
-        DXGI_FORMAT_420_OPAQUE => 12,
-        DXGI_FORMAT_NV11 => 12,
-
-        DXGI_FORMAT_R8_TYPELESS => 8,
-        DXGI_FORMAT_R8_UNORM => 8,
-        DXGI_FORMAT_R8_UINT => 8,
-        DXGI_FORMAT_R8_SNORM => 8,
-        DXGI_FORMAT_R8_SINT => 8,
-        DXGI_FORMAT_A8_UNORM => 8,
-        DXGI_FORMAT_AI44 => 8,
-        DXGI_FORMAT_IA44 => 8,
-        DXGI_FORMAT_P8 => 8,
-        DXGI_FORMAT_R1_UNORM => 1,
-
-        DXGI_FORMAT_BC1_TYPELESS => 4,
-        DXGI_FORMAT_BC1_UNORM => 4,
-        DXGI_FORMAT_BC1_UNORM_SRGB => 4,
-        DXGI_FORMAT_BC4_TYPELESS => 4,
-        DXGI_FORMAT_BC4_UNORM => 4,
-        DXGI_FORMAT_BC4_SNORM => 4,
-
-        DXGI_FORMAT_BC2_TYPELESS => 8,
-        DXGI_FORMAT_BC2_UNORM => 8,
-        DXGI_FORMAT_BC2_UNORM_SRGB => 8,
-        DXGI_FORMAT_BC3_TYPELESS => 8,
-        DXGI_FORMAT_BC3_UNORM => 8,
-        DXGI_FORMAT_BC3_UNORM_SRGB => 8,
-        DXGI_FORMAT_BC5_TYPELESS => 8,
-        DXGI_FORMAT_BC5_UNORM => 8,
-        DXGI_FORMAT_BC5_SNORM => 8,
-        DXGI_FORMAT_BC6H_TYPELESS => 8,
-        DXGI_FORMAT_BC6H_UF16 => 8,
-        DXGI_FORMAT_BC6H_SF16 => 8,
-        DXGI_FORMAT_BC7_TYPELESS => 8,
-        DXGI_FORMAT_BC7_UNORM => 8,
-        DXGI_FORMAT_BC7_UNORM_SRGB => 8,
-
-        _ => unimplemented!("Format not implemented: {}", dxgi_format),
-    }
-}
-
-#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide"]
-pub fn block_size(dxgi_format: u32) -> u32 {
-    if dxgi_format == DXGI_FORMAT_BC1_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC1_UNORM
-        || dxgi_format == DXGI_FORMAT_BC1_UNORM_SRGB
-    {
-        return 8;
-    }
-
-    if dxgi_format == DXGI_FORMAT_BC4_TYPELESS
-        || dxgi_format == DXGI_FORMAT_BC4_UNORM
-        || dxgi_format == DXGI_FORMAT_BC4_SNORM
-    {
-        return 8;
-    }
-
-    16
-}
-
-#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide"]
-pub fn pitch_and_linear_size(width: u32, height: u32, dxgi_format: u32) -> (u32, u32) {
-    if is_block_compressed(dxgi_format) {
-        let row_pitch = 1.max((width + 3) / 4) * block_size(dxgi_format);
-        let linear_size = row_pitch * 1.max((height + 3) / 4);
-
-        return (row_pitch, linear_size);
-    }
-
-    if is_legacy_yuv(dxgi_format)
-        || dxgi_format == DXGI_FORMAT_R8G8_B8G8_UNORM
-        || dxgi_format == DXGI_FORMAT_G8R8_G8B8_UNORM
-    {
-        let row_pitch = ((width + 1) >> 1) * 4;
-        let linear_size = row_pitch * height; // TODO: is this correct?
-
-        return (row_pitch, linear_size);
-    }
-
-    let row_pitch = (width * bits_per_pixel(dxgi_format) + 7) / 8;
-    let linear_size = row_pitch * height;
-
-    (row_pitch, linear_size)
-}
@@ -0,0 +1,331 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Decoding of subresources to f32 RGBA texels. Values are returned as stored: sRGB formats are not linearized,
+// integer formats are converted to floats without normalization. Missing channels are (0, 0, 0, 1).
+
+use crate::bc;
+use crate::dds::*;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use crate::video::{self, is_packed_video};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChannelKind {
+    Unorm,
+    Snorm,
+    Uint,
+    Sint,
+    Float,
+}
+
+// Bit fields of a pixel, starting from the least significant bit: (target channel, bit count, kind).
+// Channels with index 4 are padding.
+pub(crate) type PixelField = (usize, u32, ChannelKind);
+
+const R: usize = 0;
+const G: usize = 1;
+const B: usize = 2;
+const A: usize = 3;
+pub(crate) const X: usize = 4;
+
+pub(crate) fn pixel_fields(dxgi_format: u32) -> Option<&'static [PixelField]> {
+    use ChannelKind::*;
+
+    let fields: &'static [PixelField] = match dxgi_format {
+        DXGI_FORMAT_R32G32B32A32_FLOAT => &[(R, 32, Float), (G, 32, Float), (B, 32, Float), (A, 32, Float)],
+        DXGI_FORMAT_R32G32B32A32_UINT => &[(R, 32, Uint), (G, 32, Uint), (B, 32, Uint), (A, 32, Uint)],
+        DXGI_FORMAT_R32G32B32A32_SINT => &[(R, 32, Sint), (G, 32, Sint), (B, 32, Sint), (A, 32, Sint)],
+        DXGI_FORMAT_R32G32B32_FLOAT => &[(R, 32, Float), (G, 32, Float), (B, 32, Float)],
+        DXGI_FORMAT_R32G32B32_UINT => &[(R, 32, Uint), (G, 32, Uint), (B, 32, Uint)],
+        DXGI_FORMAT_R32G32B32_SINT => &[(R, 32, Sint), (G, 32, Sint), (B, 32, Sint)],
+        DXGI_FORMAT_R16G16B16A16_FLOAT => &[(R, 16, Float), (G, 16, Float), (B, 16, Float), (A, 16, Float)],
+        DXGI_FORMAT_R16G16B16A16_UNORM => &[(R, 16, Unorm), (G, 16, Unorm), (B, 16, Unorm), (A, 16, Unorm)],
+        DXGI_FORMAT_R16G16B16A16_UINT => &[(R, 16, Uint), (G, 16, Uint), (B, 16, Uint), (A, 16, Uint)],
+        DXGI_FORMAT_R16G16B16A16_SNORM => &[(R, 16, Snorm), (G, 16, Snorm), (B, 16, Snorm), (A, 16, Snorm)],
+        DXGI_FORMAT_R16G16B16A16_SINT => &[(R, 16, Sint), (G, 16, Sint), (B, 16, Sint), (A, 16, Sint)],
+        DXGI_FORMAT_R32G32_FLOAT => &[(R, 32, Float), (G, 32, Float)],
+        DXGI_FORMAT_R32G32_UINT => &[(R, 32, Uint), (G, 32, Uint)],
+        DXGI_FORMAT_R32G32_SINT => &[(R, 32, Sint), (G, 32, Sint)],
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT => &[(R, 32, Float), (G, 8, Uint), (X, 24, Uint)],
+        // Depth and stencil views of depth-stencil formats
+        DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS => &[(R, 32, Float), (X, 32, Uint)],
+        DXGI_FORMAT_X32_TYPELESS_G8X24_UINT => &[(X, 32, Uint), (G, 8, Uint), (X, 24, Uint)],
+        DXGI_FORMAT_R10G10B10A2_UNORM => &[(R, 10, Unorm), (G, 10, Unorm), (B, 10, Unorm), (A, 2, Unorm)],
+        DXGI_FORMAT_R10G10B10A2_UINT => &[(R, 10, Uint), (G, 10, Uint), (B, 10, Uint), (A, 2, Uint)],
+        DXGI_FORMAT_R11G11B10_FLOAT => &[(R, 11, Float), (G, 11, Float), (B, 10, Float)],
+        DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => {
+            &[(R, 8, Unorm), (G, 8, Unorm), (B, 8, Unorm), (A, 8, Unorm)]
+        }
+        DXGI_FORMAT_R8G8B8A8_UINT => &[(R, 8, Uint), (G, 8, Uint), (B, 8, Uint), (A, 8, Uint)],
+        DXGI_FORMAT_R8G8B8A8_SNORM => &[(R, 8, Snorm), (G, 8, Snorm), (B, 8, Snorm), (A, 8, Snorm)],
+        DXGI_FORMAT_R8G8B8A8_SINT => &[(R, 8, Sint), (G, 8, Sint), (B, 8, Sint), (A, 8, Sint)],
+        DXGI_FORMAT_R16G16_FLOAT => &[(R, 16, Float), (G, 16, Float)],
+        DXGI_FORMAT_R16G16_UNORM => &[(R, 16, Unorm), (G, 16, Unorm)],
+        DXGI_FORMAT_R16G16_UINT => &[(R, 16, Uint), (G, 16, Uint)],
+        DXGI_FORMAT_R16G16_SNORM => &[(R, 16, Snorm), (G, 16, Snorm)],
+        DXGI_FORMAT_R16G16_SINT => &[(R, 16, Sint), (G, 16, Sint)],
+        DXGI_FORMAT_D32_FLOAT | DXGI_FORMAT_R32_FLOAT => &[(R, 32, Float)],
+        DXGI_FORMAT_R32_UINT => &[(R, 32, Uint)],
+        DXGI_FORMAT_R32_SINT => &[(R, 32, Sint)],
+        DXGI_FORMAT_D24_UNORM_S8_UINT => &[(R, 24, Unorm), (G, 8, Uint)],
+        DXGI_FORMAT_R24_UNORM_X8_TYPELESS => &[(R, 24, Unorm), (X, 8, Uint)],
+        DXGI_FORMAT_X24_TYPELESS_G8_UINT => &[(X, 24, Uint), (G, 8, Uint)],
+        DXGI_FORMAT_R8G8_UNORM => &[(R, 8, Unorm), (G, 8, Unorm)],
+        DXGI_FORMAT_R8G8_UINT => &[(R, 8, Uint), (G, 8, Uint)],
+        DXGI_FORMAT_R8G8_SNORM => &[(R, 8, Snorm), (G, 8, Snorm)],
+        DXGI_FORMAT_R8G8_SINT => &[(R, 8, Sint), (G, 8, Sint)],
+        DXGI_FORMAT_R16_FLOAT => &[(R, 16, Float)],
+        DXGI_FORMAT_D16_UNORM | DXGI_FORMAT_R16_UNORM => &[(R, 16, Unorm)],
+        DXGI_FORMAT_R16_UINT => &[(R, 16, Uint)],
+        DXGI_FORMAT_R16_SNORM => &[(R, 16, Snorm)],
+        DXGI_FORMAT_R16_SINT => &[(R, 16, Sint)],
+        DXGI_FORMAT_R8_UNORM => &[(R, 8, Unorm)],
+        DXGI_FORMAT_R8_UINT => &[(R, 8, Uint)],
+        DXGI_FORMAT_R8_SNORM => &[(R, 8, Snorm)],
+        DXGI_FORMAT_R8_SINT => &[(R, 8, Sint)],
+        DXGI_FORMAT_A8_UNORM => &[(A, 8, Unorm)],
+        DXGI_FORMAT_B5G6R5_UNORM => &[(B, 5, Unorm), (G, 6, Unorm), (R, 5, Unorm)],
+        DXGI_FORMAT_B5G5R5A1_UNORM => &[(B, 5, Unorm), (G, 5, Unorm), (R, 5, Unorm), (A, 1, Unorm)],
+        DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => {
+            &[(B, 8, Unorm), (G, 8, Unorm), (R, 8, Unorm), (A, 8, Unorm)]
+        }
+        DXGI_FORMAT_B8G8R8X8_UNORM | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+            &[(B, 8, Unorm), (G, 8, Unorm), (R, 8, Unorm), (X, 8, Unorm)]
+        }
+        DXGI_FORMAT_B4G4R4A4_UNORM => &[(B, 4, Unorm), (G, 4, Unorm), (R, 4, Unorm), (A, 4, Unorm)],
+        _ => return None,
+    };
+
+    Some(fields)
+}
+
+// Formats that `decode_surface` can decode, typeless formats are decoded as `typed_format`
+pub fn can_decode(dxgi_format: u32) -> bool {
+    let dxgi_format = typed_format(dxgi_format);
+    (is_block_compressed(dxgi_format) && !is_astc(dxgi_format))
+        || is_packed_video(dxgi_format)
+        || video::is_convertible_planar(dxgi_format)
+        || dxgi_format == DXGI_FORMAT_R9G9B9E5_SHAREDEXP
+        || dxgi_format == DXGI_FORMAT_R1_UNORM
+        || pixel_fields(dxgi_format).is_some()
+}
+
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * (2.0f32).powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * (2.0f32).powi(exponent - 15),
+    }
+}
+
+#[doc = "https://www.w3.org/Graphics/Color/srgb"]
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Unsigned 11 and 10 bit floats of R11G11B10_FLOAT, 5 bit exponent
+fn small_float_to_f32(value: u32, mantissa_bits: u32) -> f32 {
+    let exponent = (value >> mantissa_bits) as i32;
+    let mantissa = (value & ((1 << mantissa_bits) - 1)) as f32;
+    let mantissa_scale = (1u32 << mantissa_bits) as f32;
+
+    match exponent {
+        0 => mantissa / mantissa_scale * (2.0f32).powi(-14),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / mantissa_scale) * (2.0f32).powi(exponent - 15),
+    }
+}
+
+fn decode_field(value: u32, bit_count: u32, kind: ChannelKind) -> f32 {
+    let max = ((1u64 << bit_count) - 1) as f64;
+    let signed = || {
+        let shift = 32 - bit_count;
+        ((value << shift) as i32) >> shift
+    };
+
+    match kind {
+        ChannelKind::Unorm => (value as f64 / max) as f32,
+        ChannelKind::Snorm => (signed() as f32 / ((1u32 << (bit_count - 1)) - 1) as f32).max(-1.0),
+        ChannelKind::Uint => value as f32,
+        ChannelKind::Sint => signed() as f32,
+        ChannelKind::Float => match bit_count {
+            32 => f32::from_bits(value),
+            16 => half_to_f32(value as u16),
+            bit_count => small_float_to_f32(value, bit_count - 5),
+        },
+    }
+}
+
+fn decode_shared_exponent(value: u32) -> [f32; 4] {
+    let scale = (2.0f32).powi((value >> 27) as i32 - 15 - 9);
+    [
+        (value & 0x1ff) as f32 * scale,
+        ((value >> 9) & 0x1ff) as f32 * scale,
+        ((value >> 18) & 0x1ff) as f32 * scale,
+        1.0,
+    ]
+}
+
+fn decode_pixel(bytes: &[u8], dxgi_format: u32, fields: &[PixelField]) -> [f32; 4] {
+    let mut packed = [0u8; 16];
+    packed[..bytes.len()].copy_from_slice(bytes);
+    let mut bits = u128::from_le_bytes(packed);
+
+    if dxgi_format == DXGI_FORMAT_R9G9B9E5_SHAREDEXP {
+        return decode_shared_exponent(bits as u32);
+    }
+
+    let mut texel = [0.0, 0.0, 0.0, 1.0];
+    for &(channel, bit_count, kind) in fields {
+        let value = (bits & ((1u128 << bit_count) - 1)) as u32;
+        bits >>= bit_count;
+        if channel != X {
+            texel[channel] = decode_field(value, bit_count, kind);
+        }
+    }
+    texel
+}
+
+// Decodes a single 2D surface with the layout of the DDS payload, None if the format can't be decoded, the surface
+// is empty or the data size doesn't match
+pub fn decode_surface(data: &[u8], width: u32, height: u32, dxgi_format: u32) -> Option<Vec<[f32; 4]>> {
+    if !can_decode(dxgi_format) || width == 0 || height == 0 {
+        return None;
+    }
+    let dxgi_format = typed_format(dxgi_format);
+    let (row_pitch, linear_size) = checked_pitch_and_linear_size(width, height, dxgi_format)?;
+    if data.len() as u64 != linear_size {
+        return None;
+    }
+
+    let (width, height, row_pitch) = (width as usize, height as usize, row_pitch as usize);
+    let mut texels = vec![[0.0; 4]; width * height];
+
+    if is_block_compressed(dxgi_format) {
+        let block_size = block_size(dxgi_format) as usize;
+        for (block_y, block_row) in data.chunks(row_pitch).enumerate() {
+            for (block_x, block) in block_row.chunks(block_size).enumerate() {
+                let decoded = bc::decode_block(dxgi_format, block)?;
+                for (i, value) in decoded.iter().enumerate() {
+                    let x = block_x * 4 + i % 4;
+                    let y = block_y * 4 + i / 4;
+                    if x < width && y < height {
+                        texels[y * width + x] = *value;
+                    }
+                }
+            }
+        }
+    } else if video::is_convertible_planar(dxgi_format) {
+        video::decode_planar(data, width, height, dxgi_format, &mut texels);
+    } else if is_packed_video(dxgi_format) {
+        video::decode_packed(data, width, row_pitch, dxgi_format, &mut texels);
+    } else if dxgi_format == DXGI_FORMAT_R1_UNORM {
+        // 8 texels per byte, the first texel is in the most significant bit
+        for (row, texel_row) in data.chunks(row_pitch).zip(texels.chunks_mut(width)) {
+            for (x, texel) in texel_row.iter_mut().enumerate() {
+                let bit = (row[x / 8] >> (7 - x % 8)) & 1;
+                *texel = [bit as f32, 0.0, 0.0, 1.0];
+            }
+        }
+    } else {
+        let fields = pixel_fields(dxgi_format).unwrap_or(&[]);
+        let pixel_size = bits_per_pixel(dxgi_format) as usize / 8;
+        for (row, texel_row) in data.chunks(row_pitch).zip(texels.chunks_mut(width)) {
+            for (pixel, texel) in row.chunks(pixel_size).zip(texel_row.iter_mut()) {
+                *texel = decode_pixel(pixel, dxgi_format, fields);
+            }
+        }
+    }
+
+    Some(texels)
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Decoded texels of a subresource in row-major order, depth slices of volume textures follow each other
+    pub fn decode_subresource(&self, mip: u32, layer: u32) -> Result<Vec<[f32; 4]>> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        let slice_size = layout::slice_size(&self.dds_header, mip);
+
+        let mut texels = Vec::new();
+        for slice in self.as_slice()[range].chunks_exact(slice_size) {
+            let slice_texels = decode_surface(slice, width, height, self.dxgi_format())
+                .ok_or(Error::UnsupportedFormat(self.dxgi_format()))?;
+            texels.extend_from_slice(&slice_texels);
+        }
+        Ok(texels)
+    }
+
+    // Decoded texels of a single depth slice of a volume texture mip
+    pub fn decode_slice(&self, mip: u32, slice: u32) -> Result<Vec<[f32; 4]>> {
+        let data = self.slice(mip, slice)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+
+        decode_surface(data, width, height, self.dxgi_format()).ok_or(Error::UnsupportedFormat(self.dxgi_format()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_surfaces() {
+        let formats = [
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            DXGI_FORMAT_R32_FLOAT,
+            DXGI_FORMAT_R1_UNORM,
+            DXGI_FORMAT_BC1_UNORM,
+            DXGI_FORMAT_BC7_UNORM,
+            DXGI_FORMAT_YUY2,
+            DXGI_FORMAT_NV12,
+        ];
+        for dxgi_format in formats {
+            for (width, height) in [(0, 0), (0, 4), (4, 0)] {
+                assert_eq!(decode_surface(&[], width, height, dxgi_format), None);
+            }
+        }
+    }
+
+    #[test]
+    fn data_size_mismatch() {
+        assert_eq!(decode_surface(&[0; 15], 2, 2, DXGI_FORMAT_R8G8B8A8_UNORM), None);
+        assert_eq!(decode_surface(&[0; 9], 4, 4, DXGI_FORMAT_BC1_UNORM), None);
+        assert!(decode_surface(&[0; 8], 1, 1, DXGI_FORMAT_BC1_UNORM).is_some());
+    }
+
+    #[test]
+    fn fields() {
+        let texels = decode_surface(&[0, 128, 255, 64], 1, 1, DXGI_FORMAT_R8G8B8A8_UNORM).unwrap();
+        assert_eq!(texels, vec![[0.0, 128.0 / 255.0, 1.0, 64.0 / 255.0]]);
+        let texels = decode_surface(&[0x80, 0x81], 1, 1, DXGI_FORMAT_R8G8_SNORM).unwrap();
+        assert_eq!(texels, vec![[-1.0, -1.0, 0.0, 1.0]]);
+        let texels = decode_surface(&[0xff, 0xff, 0xff, 0xff], 1, 1, DXGI_FORMAT_R32_UINT).unwrap();
+        assert_eq!(texels, vec![[u32::MAX as f32, 0.0, 0.0, 1.0]]);
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+    }
+}
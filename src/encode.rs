@@ -0,0 +1,599 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Encoding of f32 RGBA texels, the inverse of `decode_surface`. Values are stored as given: nothing is converted to
+// sRGB, out of range values are clamped to the range of the format.
+
+use crate::bc;
+use crate::dds::*;
+use crate::decode::{pixel_fields, ChannelKind, PixelField, X};
+use crate::format::*;
+use crate::integer::integer_range;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+use crate::swizzle::{element_extent, element_layout};
+use crate::video::{self, is_packed_video};
+
+// Rounds to nearest even, overflows to infinity
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    let round = |value: u32, shift: u32| {
+        let truncated = value >> shift;
+        let remainder = value & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if remainder > halfway || (remainder == halfway && truncated & 1 == 1) {
+            truncated + 1
+        } else {
+            truncated
+        }
+    };
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Denormal, rounding up to 0x400 gives the smallest normal number
+        return sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16;
+    }
+
+    // Rounding up can carry into the exponent, up to infinity
+    sign | round(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+// Unsigned 11 and 10 bit floats of R11G11B10_FLOAT, negative values and NaN are stored as 0
+fn f32_to_small_float(value: f32, mantissa_bits: u32) -> u32 {
+    let half = f32_to_half(value.max(0.0)) as u32;
+    let shift = 10 - mantissa_bits;
+    (half + (1 << (shift - 1))) >> shift
+}
+
+fn encode_field(value: f32, bit_count: u32, kind: ChannelKind) -> u32 {
+    let mask = ((1u64 << bit_count) - 1) as u32;
+    let max = mask as f64;
+    let signed_max = ((1u64 << (bit_count - 1)) - 1) as f64;
+
+    match kind {
+        ChannelKind::Unorm => (value.clamp(0.0, 1.0) as f64 * max).round() as u32,
+        ChannelKind::Snorm => ((value.clamp(-1.0, 1.0) as f64 * signed_max).round() as i64 as u32) & mask,
+        ChannelKind::Uint => (value as f64).clamp(0.0, max).round() as u32,
+        ChannelKind::Sint => ((value as f64).clamp(-signed_max - 1.0, signed_max).round() as i64 as u32) & mask,
+        ChannelKind::Float => match bit_count {
+            32 => value.to_bits(),
+            16 => f32_to_half(value) as u32,
+            bit_count => f32_to_small_float(value, bit_count - 5),
+        },
+    }
+}
+
+fn encode_shared_exponent(texel: &[f32; 4]) -> u32 {
+    let max_value = 511.0 / 512.0 * 65536.0;
+    let rgb = [texel[0], texel[1], texel[2]].map(|value| value.max(0.0).min(max_value));
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+
+    let mut exponent = (max.log2().floor().max(-16.0) as i32 + 16).max(0);
+    if (max / (2.0f32).powi(exponent - 24)).round() >= 512.0 {
+        exponent += 1;
+    }
+    let scale = (2.0f32).powi(exponent - 24);
+
+    let [r, g, b] = rgb.map(|value| ((value / scale).round() as u32).min(511));
+    r | (g << 9) | (b << 18) | ((exponent as u32) << 27)
+}
+
+fn encode_pixel(texel: &[f32; 4], dxgi_format: u32, fields: &[PixelField], pixel: &mut [u8]) {
+    let bits = if dxgi_format == DXGI_FORMAT_R9G9B9E5_SHAREDEXP {
+        encode_shared_exponent(texel) as u128
+    } else {
+        let mut bits = 0u128;
+        let mut shift = 0;
+        for &(channel, bit_count, kind) in fields {
+            if channel != X {
+                bits |= (encode_field(texel[channel], bit_count, kind) as u128) << shift;
+            }
+            shift += bit_count;
+        }
+        bits
+    };
+
+    let pixel_size = pixel.len();
+    pixel.copy_from_slice(&bits.to_le_bytes()[..pixel_size]);
+}
+
+// Formats that `encode_surface` can encode, typeless formats are encoded as `typed_format`
+pub fn can_encode(dxgi_format: u32) -> bool {
+    let dxgi_format = typed_format(dxgi_format);
+    (is_block_compressed(dxgi_format) && !is_astc(dxgi_format))
+        || is_packed_video(dxgi_format)
+        || video::is_convertible_planar(dxgi_format)
+        || dxgi_format == DXGI_FORMAT_R9G9B9E5_SHAREDEXP
+        || dxgi_format == DXGI_FORMAT_R1_UNORM
+        || pixel_fields(dxgi_format).is_some()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bc1Alpha {
+    // Every block uses the 4-color mode, alpha is ignored
+    Opaque,
+    // Blocks with texels that have alpha below the threshold use the 3-color mode, and those texels become
+    // transparent black. Other blocks use the 4-color mode.
+    PunchThrough { threshold: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodePreset {
+    // Line fits only, BC7 tries modes 6 and 5
+    Fast,
+    // Refits the endpoints to the chosen indices once, BC7 also tries mode 4 and the best partitions of the 2-subset
+    // modes
+    Default,
+    // Refits the endpoints several times and tries endpoints inset towards the center, BC7 tries all modes, rotations
+    // and index selections and more partitions. Several times slower than the default, more so for BC7.
+    Exhaustive,
+}
+
+// Rec. 709 luma coefficients relative to green, alpha has the weight of green
+pub const LUMA_CHANNEL_WEIGHTS: [f32; 4] = [0.2126 / 0.7152, 1.0, 0.0722 / 0.7152, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EncodeOptions {
+    pub bc1_alpha: Bc1Alpha,
+    // RGBA weights of the squared error that the BC encoders minimize, must not be negative. Uniform weights suit
+    // normal maps and other data, LUMA_CHANNEL_WEIGHTS suit color images. BC4 and BC5 channels are encoded
+    // separately and ignore the weights, BC2 and BC3 only use the RGB weights.
+    pub channel_weights: [f32; 4],
+    // Applies to the BC encoders, other formats are always encoded the same way
+    pub preset: EncodePreset,
+    // After this time the remaining blocks of the image are encoded with the fast preset
+    pub max_time_per_image: Option<std::time::Duration>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            bc1_alpha: Bc1Alpha::Opaque,
+            channel_weights: [1.0; 4],
+            preset: EncodePreset::Default,
+            max_time_per_image: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EncodeReport {
+    // Blocks in row-major order that have transparent texels, empty for formats other than BC1
+    pub transparent_blocks: Vec<bool>,
+    // Blocks that were encoded with the fast preset because the time budget ran out
+    pub fast_block_count: usize,
+    // Texels with negative or NaN values that were encoded as 0 by BC6H_UF16
+    pub clamped_texel_count: usize,
+}
+
+impl EncodeReport {
+    pub fn transparent_block_count(&self) -> usize {
+        self.transparent_blocks
+            .iter()
+            .filter(|&&transparent| transparent)
+            .count()
+    }
+}
+
+// Encodes a single 2D surface with the layout of the DDS payload, None if the format can't be encoded, the surface
+// is empty or the texel count doesn't match
+pub fn encode_surface(texels: &[[f32; 4]], width: u32, height: u32, dxgi_format: u32) -> Option<Vec<u8>> {
+    encode_surface_with(texels, width, height, dxgi_format, &EncodeOptions::default()).map(|(data, _)| data)
+}
+
+pub fn encode_surface_with(
+    texels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    dxgi_format: u32,
+    options: &EncodeOptions,
+) -> Option<(Vec<u8>, EncodeReport)> {
+    if !can_encode(dxgi_format) || width == 0 || height == 0 || texels.len() as u64 != width as u64 * height as u64 {
+        return None;
+    }
+    let dxgi_format = typed_format(dxgi_format);
+    let (row_pitch, linear_size) = checked_pitch_and_linear_size(width, height, dxgi_format)?;
+
+    let (width, height, row_pitch) = (width as usize, height as usize, row_pitch as usize);
+    let mut data = vec![0u8; linear_size as usize];
+    let mut report = EncodeReport::default();
+    if dxgi_format == DXGI_FORMAT_BC6H_UF16 {
+        report.clamped_texel_count = texels
+            .iter()
+            .filter(|texel| texel[..3].iter().any(|value| value.is_nan() || *value < 0.0))
+            .count();
+    }
+
+    if is_block_compressed(dxgi_format) {
+        let deadline = options
+            .max_time_per_image
+            .and_then(|max_time| std::time::Instant::now().checked_add(max_time));
+        let fast_options = EncodeOptions {
+            preset: EncodePreset::Fast,
+            ..*options
+        };
+        let block_size = block_size(dxgi_format) as usize;
+        for (block_y, block_row) in data.chunks_mut(row_pitch).enumerate() {
+            for (block_x, block) in block_row.chunks_mut(block_size).enumerate() {
+                // Edge blocks repeat the last row and column
+                let mut block_texels = [[0.0; 4]; 16];
+                for (i, value) in block_texels.iter_mut().enumerate() {
+                    let x = (block_x * 4 + i % 4).min(width - 1);
+                    let y = (block_y * 4 + i / 4).min(height - 1);
+                    *value = texels[y * width + x];
+                }
+                let block_options = match deadline {
+                    Some(deadline) if options.preset != EncodePreset::Fast && std::time::Instant::now() > deadline => {
+                        report.fast_block_count += 1;
+                        &fast_options
+                    }
+                    _ => options,
+                };
+                if !bc::encode_block(dxgi_format, &block_texels, block_options, block) {
+                    return None;
+                }
+                if let (DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB, Bc1Alpha::PunchThrough { threshold }) =
+                    (dxgi_format, options.bc1_alpha)
+                {
+                    report
+                        .transparent_blocks
+                        .push(bc::has_transparent_texels(&block_texels, threshold));
+                }
+            }
+        }
+    } else if video::is_convertible_planar(dxgi_format) {
+        video::encode_planar(texels, width, height, dxgi_format, &mut data);
+    } else if is_packed_video(dxgi_format) {
+        video::encode_packed(texels, width, row_pitch, dxgi_format, &mut data);
+    } else if dxgi_format == DXGI_FORMAT_R1_UNORM {
+        // Red is rounded to the nearest bit, the first texel is in the most significant bit
+        for (row, texel_row) in data.chunks_mut(row_pitch).zip(texels.chunks(width)) {
+            for (x, texel) in texel_row.iter().enumerate() {
+                if texel[0] >= 0.5 {
+                    row[x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+    } else {
+        let fields = pixel_fields(dxgi_format).unwrap_or(&[]);
+        let pixel_size = bits_per_pixel(dxgi_format) as usize / 8;
+        for (row, texel_row) in data.chunks_mut(row_pitch).zip(texels.chunks(width)) {
+            for (pixel, texel) in row.chunks_mut(pixel_size).zip(texel_row.iter()) {
+                encode_pixel(texel, dxgi_format, fields, pixel);
+            }
+        }
+    }
+
+    Some((data, report))
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    // Replaces the texels of a subresource, `texels` are in row-major order
+    pub fn encode_subresource(&mut self, mip: u32, layer: u32, texels: &[[f32; 4]]) -> Result<()> {
+        self.encode_subresource_with(mip, layer, texels, &EncodeOptions::default())
+            .map(|_| ())
+    }
+
+    pub fn encode_subresource_with(
+        &mut self,
+        mip: u32,
+        layer: u32,
+        texels: &[[f32; 4]],
+        options: &EncodeOptions,
+    ) -> Result<EncodeReport> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        let depth = layout::mip_depth(&self.dds_header, mip);
+        if texels.len() as u64 != width as u64 * height as u64 * depth as u64 {
+            return Err(Error::BadImageDescription(
+                "Texel count does not match the subresource size",
+            ));
+        }
+
+        // Depth slices are encoded one by one, the reports are concatenated
+        let mut data = Vec::with_capacity(range.len());
+        let mut report = EncodeReport::default();
+        for slice in texels.chunks_exact(width as usize * height as usize) {
+            let (slice_data, slice_report) = encode_surface_with(slice, width, height, self.dxgi_format(), options)
+                .ok_or(Error::UnsupportedFormat(self.dxgi_format()))?;
+            data.extend_from_slice(&slice_data);
+            report.transparent_blocks.extend(slice_report.transparent_blocks);
+            report.fast_block_count += slice_report.fast_block_count;
+            report.clamped_texel_count += slice_report.clamped_texel_count;
+        }
+        self.as_slice_mut()[range].copy_from_slice(&data);
+        Ok(report)
+    }
+
+    // Replaces the texels of a single depth slice of a volume texture mip
+    pub fn encode_slice(&mut self, mip: u32, slice: u32, texels: &[[f32; 4]]) -> Result<()> {
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        if texels.len() as u64 != width as u64 * height as u64 {
+            return Err(Error::BadImageDescription("Texel count does not match the slice size"));
+        }
+
+        let data = encode_surface(texels, width, height, self.dxgi_format())
+            .ok_or(Error::UnsupportedFormat(self.dxgi_format()))?;
+        self.slice_mut(mip, slice)?.copy_from_slice(&data);
+        Ok(())
+    }
+
+    // Sets every texel of a subresource to `color`. Formats with elements of whole bytes encode a single element
+    // and repeat it.
+    pub fn fill_subresource(&mut self, mip: u32, layer: u32, color: [f32; 4]) -> Result<()> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        let dxgi_format = self.dxgi_format();
+
+        match element_layout(width, height, dxgi_format) {
+            Some((element_size, _, _)) => {
+                let (element_width, element_height) = element_extent(dxgi_format);
+                let texels = vec![color; (element_width * element_height) as usize];
+                let element = encode_surface(&texels, element_width, element_height, dxgi_format)
+                    .ok_or(Error::UnsupportedFormat(dxgi_format))?;
+                for chunk in self.as_slice_mut()[range].chunks_exact_mut(element_size) {
+                    chunk.copy_from_slice(&element);
+                }
+                Ok(())
+            }
+            None => {
+                let depth = layout::mip_depth(&self.dds_header, mip) as usize;
+                self.encode_subresource(mip, layer, &vec![color; width as usize * height as usize * depth])
+            }
+        }
+    }
+
+    // Fills every subresource, see `fill_subresource`
+    pub fn fill(&mut self, color: [f32; 4]) -> Result<()> {
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                self.fill_subresource(mip, layer, color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Remapping of the values when converting from `source_format` to `target_format`, None if the conversion is not
+// between UNORM and SNORM formats
+pub(crate) fn normalized_remap(source_format: u32, target_format: u32) -> Option<fn(f32) -> f32> {
+    let (source_format, target_format) = (typed_format(source_format), typed_format(target_format));
+    if is_unorm(source_format) && is_snorm(target_format) {
+        Some(|value| value * 2.0 - 1.0)
+    } else if is_snorm(source_format) && is_unorm(target_format) {
+        Some(|value| value * 0.5 + 0.5)
+    } else {
+        None
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // BC6H_SF16 if any texel has a negative color value, BC6H_UF16 otherwise
+    pub fn preferred_bc6h_format(&self) -> Result<u32> {
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let texels = self.decode_subresource(mip, layer)?;
+                if texels.iter().any(|texel| texel[..3].iter().any(|&value| value < 0.0)) {
+                    return Ok(DXGI_FORMAT_BC6H_SF16);
+                }
+            }
+        }
+        Ok(DXGI_FORMAT_BC6H_UF16)
+    }
+
+    // Decodes and re-encodes every subresource, the layout stays the same
+    pub fn convert(&self, dxgi_format: u32) -> Result<ScratchImage> {
+        self.convert_with_encoder(dxgi_format, &EncodeOptions::default())
+    }
+
+    pub fn convert_with_encoder(&self, dxgi_format: u32, options: &EncodeOptions) -> Result<ScratchImage> {
+        self.convert_remapped(dxgi_format, options, None)
+    }
+
+    // Like `convert_with_encoder`, colors are remapped between the ranges of UNORM and SNORM formats: x * 2 - 1 from
+    // UNORM to SNORM and x * 0.5 + 0.5 back, alpha is kept. SNORM -128 decodes to -1 like -127, so both map to 0.
+    // Other conversions don't remap.
+    pub fn convert_normalized_with_encoder(&self, dxgi_format: u32, options: &EncodeOptions) -> Result<ScratchImage> {
+        self.convert_remapped(dxgi_format, options, normalized_remap(self.dxgi_format(), dxgi_format))
+    }
+
+    pub(crate) fn convert_remapped(
+        &self,
+        dxgi_format: u32,
+        options: &EncodeOptions,
+        remap: Option<fn(f32) -> f32>,
+    ) -> Result<ScratchImage> {
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        // Integers larger than 2^24 can't be decoded exactly
+        if integer_range(self.dxgi_format()).is_some() && integer_range(dxgi_format).is_some() {
+            return self.convert_integers(dxgi_format);
+        }
+
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+        if let Some(alpha_mode) = self.alpha_mode() {
+            image.set_alpha_mode(alpha_mode);
+        }
+
+        // The time budget is shared by all subresources
+        let start = std::time::Instant::now();
+        let mut options = *options;
+        let max_time_per_image = options.max_time_per_image;
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                options.max_time_per_image =
+                    max_time_per_image.map(|max_time| max_time.saturating_sub(start.elapsed()));
+                let mut texels = self.decode_subresource(mip, layer)?;
+                if let Some(remap) = remap {
+                    for texel in texels.iter_mut() {
+                        for value in texel[..3].iter_mut() {
+                            *value = remap(*value);
+                        }
+                    }
+                }
+                image.encode_subresource_with(mip, layer, &texels, &options)?;
+            }
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode_surface;
+
+    // Smooth gradients with some detail, values are in [0, 1]
+    fn texels(width: u32, height: u32) -> Vec<[f32; 4]> {
+        (0..width * height)
+            .map(|index| {
+                let (x, y) = ((index % width) as f32, (index / width) as f32);
+                [
+                    x / width as f32,
+                    y / height as f32,
+                    0.5 + 0.4 * (x * 0.7 + y * 0.3).sin(),
+                    1.0 - 0.5 * x / width as f32,
+                ]
+            })
+            .collect()
+    }
+
+    fn max_error(a: &[[f32; 4]], b: &[[f32; 4]], channels: usize) -> f32 {
+        a.iter()
+            .zip(b)
+            .flat_map(|(a, b)| (0..channels).map(move |channel| (a[channel] - b[channel]).abs()))
+            .fold(0.0, f32::max)
+    }
+
+    fn rms_error(a: &[[f32; 4]], b: &[[f32; 4]], channels: usize) -> f32 {
+        let sum: f32 = a
+            .iter()
+            .zip(b)
+            .flat_map(|(a, b)| (0..channels).map(move |channel| (a[channel] - b[channel]).powi(2)))
+            .sum();
+        (sum / (a.len() * channels) as f32).sqrt()
+    }
+
+    // Format, channels that are stored and the largest error allowed on the test texels, the RMS error for block
+    // compressed formats
+    const ROUND_TRIPS: &[(u32, usize, f32)] = &[
+        (DXGI_FORMAT_R8G8B8A8_UNORM, 4, 0.5 / 255.0 + 1e-6),
+        (DXGI_FORMAT_B8G8R8A8_UNORM, 4, 0.5 / 255.0 + 1e-6),
+        (DXGI_FORMAT_R16G16B16A16_FLOAT, 4, 1e-3),
+        (DXGI_FORMAT_R32G32B32A32_FLOAT, 4, 0.0),
+        (DXGI_FORMAT_R10G10B10A2_UNORM, 3, 0.5 / 1023.0 + 1e-6),
+        (DXGI_FORMAT_R11G11B10_FLOAT, 3, 1e-2),
+        (DXGI_FORMAT_R9G9B9E5_SHAREDEXP, 3, 1e-2),
+        (DXGI_FORMAT_B5G6R5_UNORM, 3, 0.5 / 31.0 + 1e-6),
+        (DXGI_FORMAT_R8_UNORM, 1, 0.5 / 255.0 + 1e-6),
+        (DXGI_FORMAT_BC1_UNORM, 3, 0.15),
+        (DXGI_FORMAT_BC2_UNORM, 4, 0.15),
+        (DXGI_FORMAT_BC3_UNORM, 4, 0.15),
+        (DXGI_FORMAT_BC4_UNORM, 1, 0.03),
+        (DXGI_FORMAT_BC5_UNORM, 2, 0.03),
+        (DXGI_FORMAT_BC6H_UF16, 3, 0.2),
+        (DXGI_FORMAT_BC7_UNORM, 4, 0.15),
+    ];
+
+    #[test]
+    fn round_trips() {
+        for &(dxgi_format, channels, tolerance) in ROUND_TRIPS {
+            for (width, height) in [(1, 1), (5, 3), (8, 8), (37, 23)] {
+                let texels = texels(width, height);
+                let data = encode_surface(&texels, width, height, dxgi_format).unwrap();
+                let decoded = decode_surface(&data, width, height, dxgi_format).unwrap();
+                let error = match is_block_compressed(dxgi_format) {
+                    true => rms_error(&texels, &decoded, channels),
+                    false => max_error(&texels, &decoded, channels),
+                };
+                assert!(
+                    error <= tolerance,
+                    "format {} {}x{}: error {} > {}",
+                    dxgi_format,
+                    width,
+                    height,
+                    error,
+                    tolerance
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn presets() {
+        let texels = texels(37, 23);
+        for (dxgi_format, channels) in [
+            (DXGI_FORMAT_BC1_UNORM, 3),
+            (DXGI_FORMAT_BC3_UNORM, 4),
+            (DXGI_FORMAT_BC7_UNORM, 4),
+        ] {
+            let errors: Vec<f32> = [EncodePreset::Fast, EncodePreset::Default, EncodePreset::Exhaustive]
+                .iter()
+                .map(|&preset| {
+                    let options = EncodeOptions {
+                        preset,
+                        ..Default::default()
+                    };
+                    let (data, _) = encode_surface_with(&texels, 37, 23, dxgi_format, &options).unwrap();
+                    rms_error(&texels, &decode_surface(&data, 37, 23, dxgi_format).unwrap(), channels)
+                })
+                .collect();
+            assert!(
+                errors[0] > errors[1] && errors[1] > errors[2],
+                "format {}: {:?}",
+                dxgi_format,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn bc7_separate_alpha() {
+        let texels = texels(37, 23);
+        let error = |dxgi_format| {
+            let data = encode_surface(&texels, 37, 23, dxgi_format).unwrap();
+            rms_error(&texels, &decode_surface(&data, 37, 23, dxgi_format).unwrap(), 4)
+        };
+        assert!(error(DXGI_FORMAT_BC7_UNORM) < error(DXGI_FORMAT_BC3_UNORM));
+    }
+
+    #[test]
+    fn empty_surfaces() {
+        for &(dxgi_format, _, _) in ROUND_TRIPS {
+            for (width, height) in [(0, 0), (0, 4), (4, 0)] {
+                assert_eq!(encode_surface(&[], width, height, dxgi_format), None);
+            }
+        }
+        assert_eq!(encode_surface(&[], 0, 0, DXGI_FORMAT_R1_UNORM), None);
+        assert_eq!(encode_surface(&[], 0, 0, DXGI_FORMAT_YUY2), None);
+        assert_eq!(encode_surface(&[], 0, 0, DXGI_FORMAT_NV12), None);
+    }
+
+    #[test]
+    fn texel_count_mismatch() {
+        let texels = texels(4, 4);
+        assert_eq!(encode_surface(&texels[1..], 4, 4, DXGI_FORMAT_R8G8B8A8_UNORM), None);
+        assert_eq!(encode_surface(&texels, 4, 4, DXGI_FORMAT_UNKNOWN), None);
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// OpenEXR export for HDR subresources: uncompressed scanlines with 32-bit float RGBA channels, values are not clamped
+
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const EXR_PIXEL_TYPE_FLOAT: i32 = 2;
+
+fn attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn box2i(width: u32, height: u32) -> Vec<u8> {
+    [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+#[doc = "https://openexr.com/en/latest/OpenEXRFileLayout.html"]
+pub fn write_exr<T: std::io::Write>(texels: &[[f32; 4]], width: u32, height: u32, exr_file: &mut T) -> Result<()> {
+    if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return Err(Error::BadImageDescription("Image dimensions must be non-zero"));
+    }
+    if texels.len() as u64 != width as u64 * height as u64 {
+        return Err(Error::BadImageDescription("Texel count does not match the image size"));
+    }
+
+    // Channels are sorted by name
+    let channels = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+    let mut channel_list = Vec::new();
+    for (name, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&EXR_PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear, reserved, x and y sampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut header = EXR_MAGIC.to_vec();
+    header.extend_from_slice(&2u32.to_le_bytes());
+    attribute(&mut header, "channels", "chlist", &channel_list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &box2i(width, height));
+    attribute(&mut header, "displayWindow", "box2i", &box2i(width, height));
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // One scanline per block: y, data size and the values of every channel
+    let line_size = width as u64 * channels.len() as u64 * 4;
+    let first_line = header.len() as u64 + height as u64 * 8;
+    for y in 0..height as u64 {
+        header.extend_from_slice(&(first_line + y * (line_size + 8)).to_le_bytes());
+    }
+    exr_file.write_all(&header)?;
+
+    for (y, row) in texels.chunks(width as usize).enumerate() {
+        let mut line = Vec::with_capacity(line_size as usize + 8);
+        line.extend_from_slice(&(y as i32).to_le_bytes());
+        line.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for &(_, channel) in &channels {
+            for texel in row {
+                line.extend_from_slice(&texel[channel].to_le_bytes());
+            }
+        }
+        exr_file.write_all(&line)?;
+    }
+    Ok(())
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Depth slices of volume textures are stacked vertically
+    pub fn write_subresource_exr<T: std::io::Write>(&self, mip: u32, layer: u32, exr_file: &mut T) -> Result<()> {
+        let texels = self.decode_subresource(mip, layer)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        write_exr(
+            &texels,
+            width,
+            height * layout::mip_depth(&self.dds_header, mip),
+            exr_file,
+        )
+    }
+}
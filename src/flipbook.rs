@@ -0,0 +1,104 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Flipbook animations stored as texture arrays, one frame per array layer. The playback parameters are kept in the
+// first 4 reserved header fields, which readers ignore: a "FLIP" tag, the frame rate as f32 bits, the frame count
+// and the loop mode. Other tools use the last reserved fields (NVTT writes its tag and version to 9 and 10).
+
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+pub(crate) const FLIPBOOK_TAG: u32 = u32::from_le_bytes(*b"FLIP");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Once = 0,
+    Loop = 1,
+    PingPong = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlipbookInfo {
+    // Frames per second
+    pub frame_rate: f32,
+    // Can be smaller than the array size if the last layers are not used
+    pub frame_count: u32,
+    pub loop_mode: LoopMode,
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // None if the image has no flipbook metadata, or if it is not valid
+    pub fn flipbook(&self) -> Option<FlipbookInfo> {
+        let reserved = &self.dds_header.reserved;
+        if reserved[0] != FLIPBOOK_TAG {
+            return None;
+        }
+
+        let loop_mode = match reserved[3] {
+            0 => LoopMode::Once,
+            1 => LoopMode::Loop,
+            2 => LoopMode::PingPong,
+            _ => return None,
+        };
+        Some(FlipbookInfo {
+            frame_rate: f32::from_bits(reserved[1]),
+            frame_count: reserved[2],
+            loop_mode,
+        })
+    }
+
+    // None removes the metadata
+    pub fn set_flipbook(&mut self, info: Option<FlipbookInfo>) -> Result<()> {
+        let fields = match info {
+            Some(info) => {
+                if info.frame_count == 0 || info.frame_count > self.layer_count() {
+                    return Err(Error::BadImageDescription(
+                        "Frame count must be between 1 and the array size",
+                    ));
+                }
+                if !(info.frame_rate > 0.0 && info.frame_rate.is_finite()) {
+                    return Err(Error::BadImageDescription("Frame rate must be positive"));
+                }
+                [
+                    FLIPBOOK_TAG,
+                    info.frame_rate.to_bits(),
+                    info.frame_count,
+                    info.loop_mode as u32,
+                ]
+            }
+            None => [0; 4],
+        };
+
+        self.dds_header.reserved[..4].copy_from_slice(&fields);
+        Ok(())
+    }
+}
+
+impl ScratchImage {
+    // Texture array with a layer for every frame. Frames must be 2D images with a single layer and the same
+    // dimensions, format and mip count.
+    pub fn from_frames(frames: &[ScratchImage], frame_rate: f32, loop_mode: LoopMode) -> Result<ScratchImage> {
+        let first = frames.first().ok_or(Error::BadImageDescription("No frames"))?;
+        let mut desc = first.desc();
+        if desc.array_size != 1 || desc.is_cubemap || desc.depth != 1 {
+            return Err(Error::BadImageDescription(
+                "Frames must be 2D images with a single layer",
+            ));
+        }
+        if frames.iter().any(|frame| frame.desc() != desc) {
+            return Err(Error::IncompatibleImages("Frames have different dimensions or formats"));
+        }
+
+        desc.array_size = frames.len() as u32;
+        let dds_data = frames.iter().flat_map(|frame| frame.dds_data.iter().copied()).collect();
+        let mut image = ScratchImage::with_data(&desc, dds_data)?;
+        image.set_flipbook(Some(FlipbookInfo {
+            frame_rate,
+            frame_count: desc.array_size,
+            loop_mode,
+        }))?;
+        Ok(image)
+    }
+}
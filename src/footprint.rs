@@ -0,0 +1,89 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// GPU memory footprint of images, computed from the header alone. Drivers choose their own layouts, so apart from
+// `Tight` these are estimates meant for budgeting.
+
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use crate::swizzle::element_layout;
+use crate::tiles::{standard_tile_shape, TILE_SIZE_IN_BYTES};
+
+const TILE_SIZE: u64 = TILE_SIZE_IN_BYTES as u64;
+// Row pitch and subresource alignment that desktop drivers commonly use for optimally tiled images
+const VULKAN_ROW_ALIGNMENT: u64 = 256;
+const VULKAN_SUBRESOURCE_ALIGNMENT: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    // The DDS payload, without any padding
+    Tight,
+    // D3D12 resources with 64KB placement: mips at least as large as a standard tile take whole tiles, smaller mips
+    // of every layer share 64KB aligned mip tails. Volumes and formats without standard tiles pad every layer instead.
+    D3D12,
+    // Vulkan optimal tiling: rows aligned to 256 bytes, subresources to 4KB and the image to 64KB
+    VulkanOptimal,
+}
+
+fn align(size: u64, alignment: u64) -> u64 {
+    size.div_ceil(alignment) * alignment
+}
+
+// Size of a mip in bytes when its first mip is as large as a 64KB tile, None if it goes to the mip tail
+fn d3d12_tiled_mip_size(header: &DirectDrawHeader, mip: u32) -> Option<u64> {
+    let (tile_width, tile_height) = standard_tile_shape(header.dxt10.dxgi_format)?;
+    let (width, height) = layout::mip_extent(header, mip);
+    if header.depth > 1 || width < tile_width || height < tile_height {
+        return None;
+    }
+    Some(width.div_ceil(tile_width) as u64 * height.div_ceil(tile_height) as u64 * TILE_SIZE)
+}
+
+fn vulkan_mip_size(header: &DirectDrawHeader, mip: u32) -> Option<u64> {
+    let (width, height) = layout::mip_extent(header, mip);
+    let surface_size = match element_layout(width, height, header.dxt10.dxgi_format) {
+        Some((element_size, columns, rows)) => {
+            align((element_size * columns) as u64, VULKAN_ROW_ALIGNMENT)
+                * rows as u64
+                * layout::mip_depth(header, mip) as u64
+        }
+        None => layout::subresource_size(header, mip)?,
+    };
+    Some(align(surface_size, VULKAN_SUBRESOURCE_ALIGNMENT))
+}
+
+// None if the header doesn't describe a valid layout
+pub fn gpu_footprint(header: &DirectDrawHeader, placement: Placement) -> Option<u64> {
+    let data_size = layout::checked_data_size(header)?;
+    let layer_count = layout::layer_count(header) as u64;
+    let mips = 0..layout::mip_count(header);
+
+    match placement {
+        Placement::Tight => Some(data_size),
+        Placement::D3D12 => {
+            let mut layer_size = 0;
+            let mut mip_tail_size = 0;
+            for mip in mips {
+                match d3d12_tiled_mip_size(header, mip) {
+                    Some(size) => layer_size += size,
+                    None => mip_tail_size += layout::subresource_size(header, mip)?,
+                }
+            }
+            Some((layer_size + align(mip_tail_size, TILE_SIZE)) * layer_count)
+        }
+        Placement::VulkanOptimal => {
+            let layer_size = mips.map(|mip| vulkan_mip_size(header, mip)).sum::<Option<u64>>()?;
+            Some(align(layer_size * layer_count, TILE_SIZE))
+        }
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn gpu_footprint(&self, placement: Placement) -> u64 {
+        gpu_footprint(&self.dds_header, placement).unwrap()
+    }
+}
@@ -0,0 +1,837 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::dds::*;
+use crate::scratch_image::ScratchImage;
+use crate::storage::Storage;
+use crate::video::{packed_pair_size, plane_layouts};
+
+pub fn dxgi_format_name(dxgi_format: u32) -> Option<&'static str> {
+    let name = match dxgi_format {
+        DXGI_FORMAT_UNKNOWN => "UNKNOWN",
+        DXGI_FORMAT_R32G32B32A32_TYPELESS => "R32G32B32A32_TYPELESS",
+        DXGI_FORMAT_R32G32B32A32_FLOAT => "R32G32B32A32_FLOAT",
+        DXGI_FORMAT_R32G32B32A32_UINT => "R32G32B32A32_UINT",
+        DXGI_FORMAT_R32G32B32A32_SINT => "R32G32B32A32_SINT",
+        DXGI_FORMAT_R32G32B32_TYPELESS => "R32G32B32_TYPELESS",
+        DXGI_FORMAT_R32G32B32_FLOAT => "R32G32B32_FLOAT",
+        DXGI_FORMAT_R32G32B32_UINT => "R32G32B32_UINT",
+        DXGI_FORMAT_R32G32B32_SINT => "R32G32B32_SINT",
+        DXGI_FORMAT_R16G16B16A16_TYPELESS => "R16G16B16A16_TYPELESS",
+        DXGI_FORMAT_R16G16B16A16_FLOAT => "R16G16B16A16_FLOAT",
+        DXGI_FORMAT_R16G16B16A16_UNORM => "R16G16B16A16_UNORM",
+        DXGI_FORMAT_R16G16B16A16_UINT => "R16G16B16A16_UINT",
+        DXGI_FORMAT_R16G16B16A16_SNORM => "R16G16B16A16_SNORM",
+        DXGI_FORMAT_R16G16B16A16_SINT => "R16G16B16A16_SINT",
+        DXGI_FORMAT_R32G32_TYPELESS => "R32G32_TYPELESS",
+        DXGI_FORMAT_R32G32_FLOAT => "R32G32_FLOAT",
+        DXGI_FORMAT_R32G32_UINT => "R32G32_UINT",
+        DXGI_FORMAT_R32G32_SINT => "R32G32_SINT",
+        DXGI_FORMAT_R32G8X24_TYPELESS => "R32G8X24_TYPELESS",
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT => "D32_FLOAT_S8X24_UINT",
+        DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS => "R32_FLOAT_X8X24_TYPELESS",
+        DXGI_FORMAT_X32_TYPELESS_G8X24_UINT => "X32_TYPELESS_G8X24_UINT",
+        DXGI_FORMAT_R10G10B10A2_TYPELESS => "R10G10B10A2_TYPELESS",
+        DXGI_FORMAT_R10G10B10A2_UNORM => "R10G10B10A2_UNORM",
+        DXGI_FORMAT_R10G10B10A2_UINT => "R10G10B10A2_UINT",
+        DXGI_FORMAT_R11G11B10_FLOAT => "R11G11B10_FLOAT",
+        DXGI_FORMAT_R8G8B8A8_TYPELESS => "R8G8B8A8_TYPELESS",
+        DXGI_FORMAT_R8G8B8A8_UNORM => "R8G8B8A8_UNORM",
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => "R8G8B8A8_UNORM_SRGB",
+        DXGI_FORMAT_R8G8B8A8_UINT => "R8G8B8A8_UINT",
+        DXGI_FORMAT_R8G8B8A8_SNORM => "R8G8B8A8_SNORM",
+        DXGI_FORMAT_R8G8B8A8_SINT => "R8G8B8A8_SINT",
+        DXGI_FORMAT_R16G16_TYPELESS => "R16G16_TYPELESS",
+        DXGI_FORMAT_R16G16_FLOAT => "R16G16_FLOAT",
+        DXGI_FORMAT_R16G16_UNORM => "R16G16_UNORM",
+        DXGI_FORMAT_R16G16_UINT => "R16G16_UINT",
+        DXGI_FORMAT_R16G16_SNORM => "R16G16_SNORM",
+        DXGI_FORMAT_R16G16_SINT => "R16G16_SINT",
+        DXGI_FORMAT_R32_TYPELESS => "R32_TYPELESS",
+        DXGI_FORMAT_D32_FLOAT => "D32_FLOAT",
+        DXGI_FORMAT_R32_FLOAT => "R32_FLOAT",
+        DXGI_FORMAT_R32_UINT => "R32_UINT",
+        DXGI_FORMAT_R32_SINT => "R32_SINT",
+        DXGI_FORMAT_R24G8_TYPELESS => "R24G8_TYPELESS",
+        DXGI_FORMAT_D24_UNORM_S8_UINT => "D24_UNORM_S8_UINT",
+        DXGI_FORMAT_R24_UNORM_X8_TYPELESS => "R24_UNORM_X8_TYPELESS",
+        DXGI_FORMAT_X24_TYPELESS_G8_UINT => "X24_TYPELESS_G8_UINT",
+        DXGI_FORMAT_R8G8_TYPELESS => "R8G8_TYPELESS",
+        DXGI_FORMAT_R8G8_UNORM => "R8G8_UNORM",
+        DXGI_FORMAT_R8G8_UINT => "R8G8_UINT",
+        DXGI_FORMAT_R8G8_SNORM => "R8G8_SNORM",
+        DXGI_FORMAT_R8G8_SINT => "R8G8_SINT",
+        DXGI_FORMAT_R16_TYPELESS => "R16_TYPELESS",
+        DXGI_FORMAT_R16_FLOAT => "R16_FLOAT",
+        DXGI_FORMAT_D16_UNORM => "D16_UNORM",
+        DXGI_FORMAT_R16_UNORM => "R16_UNORM",
+        DXGI_FORMAT_R16_UINT => "R16_UINT",
+        DXGI_FORMAT_R16_SNORM => "R16_SNORM",
+        DXGI_FORMAT_R16_SINT => "R16_SINT",
+        DXGI_FORMAT_R8_TYPELESS => "R8_TYPELESS",
+        DXGI_FORMAT_R8_UNORM => "R8_UNORM",
+        DXGI_FORMAT_R8_UINT => "R8_UINT",
+        DXGI_FORMAT_R8_SNORM => "R8_SNORM",
+        DXGI_FORMAT_R8_SINT => "R8_SINT",
+        DXGI_FORMAT_A8_UNORM => "A8_UNORM",
+        DXGI_FORMAT_R1_UNORM => "R1_UNORM",
+        DXGI_FORMAT_R9G9B9E5_SHAREDEXP => "R9G9B9E5_SHAREDEXP",
+        DXGI_FORMAT_R8G8_B8G8_UNORM => "R8G8_B8G8_UNORM",
+        DXGI_FORMAT_G8R8_G8B8_UNORM => "G8R8_G8B8_UNORM",
+        DXGI_FORMAT_BC1_TYPELESS => "BC1_TYPELESS",
+        DXGI_FORMAT_BC1_UNORM => "BC1_UNORM",
+        DXGI_FORMAT_BC1_UNORM_SRGB => "BC1_UNORM_SRGB",
+        DXGI_FORMAT_BC2_TYPELESS => "BC2_TYPELESS",
+        DXGI_FORMAT_BC2_UNORM => "BC2_UNORM",
+        DXGI_FORMAT_BC2_UNORM_SRGB => "BC2_UNORM_SRGB",
+        DXGI_FORMAT_BC3_TYPELESS => "BC3_TYPELESS",
+        DXGI_FORMAT_BC3_UNORM => "BC3_UNORM",
+        DXGI_FORMAT_BC3_UNORM_SRGB => "BC3_UNORM_SRGB",
+        DXGI_FORMAT_BC4_TYPELESS => "BC4_TYPELESS",
+        DXGI_FORMAT_BC4_UNORM => "BC4_UNORM",
+        DXGI_FORMAT_BC4_SNORM => "BC4_SNORM",
+        DXGI_FORMAT_BC5_TYPELESS => "BC5_TYPELESS",
+        DXGI_FORMAT_BC5_UNORM => "BC5_UNORM",
+        DXGI_FORMAT_BC5_SNORM => "BC5_SNORM",
+        DXGI_FORMAT_B5G6R5_UNORM => "B5G6R5_UNORM",
+        DXGI_FORMAT_B5G5R5A1_UNORM => "B5G5R5A1_UNORM",
+        DXGI_FORMAT_B8G8R8A8_UNORM => "B8G8R8A8_UNORM",
+        DXGI_FORMAT_B8G8R8X8_UNORM => "B8G8R8X8_UNORM",
+        DXGI_FORMAT_R10G10B10_XR_BIAS_A2_UNORM => "R10G10B10_XR_BIAS_A2_UNORM",
+        DXGI_FORMAT_B8G8R8A8_TYPELESS => "B8G8R8A8_TYPELESS",
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => "B8G8R8A8_UNORM_SRGB",
+        DXGI_FORMAT_B8G8R8X8_TYPELESS => "B8G8R8X8_TYPELESS",
+        DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => "B8G8R8X8_UNORM_SRGB",
+        DXGI_FORMAT_BC6H_TYPELESS => "BC6H_TYPELESS",
+        DXGI_FORMAT_BC6H_UF16 => "BC6H_UF16",
+        DXGI_FORMAT_BC6H_SF16 => "BC6H_SF16",
+        DXGI_FORMAT_BC7_TYPELESS => "BC7_TYPELESS",
+        DXGI_FORMAT_BC7_UNORM => "BC7_UNORM",
+        DXGI_FORMAT_BC7_UNORM_SRGB => "BC7_UNORM_SRGB",
+        DXGI_FORMAT_AYUV => "AYUV",
+        DXGI_FORMAT_Y410 => "Y410",
+        DXGI_FORMAT_Y416 => "Y416",
+        DXGI_FORMAT_NV12 => "NV12",
+        DXGI_FORMAT_P010 => "P010",
+        DXGI_FORMAT_P016 => "P016",
+        DXGI_FORMAT_420_OPAQUE => "420_OPAQUE",
+        DXGI_FORMAT_YUY2 => "YUY2",
+        DXGI_FORMAT_Y210 => "Y210",
+        DXGI_FORMAT_Y216 => "Y216",
+        DXGI_FORMAT_NV11 => "NV11",
+        DXGI_FORMAT_AI44 => "AI44",
+        DXGI_FORMAT_IA44 => "IA44",
+        DXGI_FORMAT_P8 => "P8",
+        DXGI_FORMAT_A8P8 => "A8P8",
+        DXGI_FORMAT_B4G4R4A4_UNORM => "B4G4R4A4_UNORM",
+        DXGI_FORMAT_P208 => "P208",
+        DXGI_FORMAT_V208 => "V208",
+        DXGI_FORMAT_V408 => "V408",
+        DXGI_FORMAT_ASTC_4X4_TYPELESS => "ASTC_4X4_TYPELESS",
+        DXGI_FORMAT_ASTC_4X4_UNORM => "ASTC_4X4_UNORM",
+        DXGI_FORMAT_ASTC_4X4_UNORM_SRGB => "ASTC_4X4_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_5X4_TYPELESS => "ASTC_5X4_TYPELESS",
+        DXGI_FORMAT_ASTC_5X4_UNORM => "ASTC_5X4_UNORM",
+        DXGI_FORMAT_ASTC_5X4_UNORM_SRGB => "ASTC_5X4_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_5X5_TYPELESS => "ASTC_5X5_TYPELESS",
+        DXGI_FORMAT_ASTC_5X5_UNORM => "ASTC_5X5_UNORM",
+        DXGI_FORMAT_ASTC_5X5_UNORM_SRGB => "ASTC_5X5_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_6X5_TYPELESS => "ASTC_6X5_TYPELESS",
+        DXGI_FORMAT_ASTC_6X5_UNORM => "ASTC_6X5_UNORM",
+        DXGI_FORMAT_ASTC_6X5_UNORM_SRGB => "ASTC_6X5_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_6X6_TYPELESS => "ASTC_6X6_TYPELESS",
+        DXGI_FORMAT_ASTC_6X6_UNORM => "ASTC_6X6_UNORM",
+        DXGI_FORMAT_ASTC_6X6_UNORM_SRGB => "ASTC_6X6_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_8X5_TYPELESS => "ASTC_8X5_TYPELESS",
+        DXGI_FORMAT_ASTC_8X5_UNORM => "ASTC_8X5_UNORM",
+        DXGI_FORMAT_ASTC_8X5_UNORM_SRGB => "ASTC_8X5_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_8X6_TYPELESS => "ASTC_8X6_TYPELESS",
+        DXGI_FORMAT_ASTC_8X6_UNORM => "ASTC_8X6_UNORM",
+        DXGI_FORMAT_ASTC_8X6_UNORM_SRGB => "ASTC_8X6_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_8X8_TYPELESS => "ASTC_8X8_TYPELESS",
+        DXGI_FORMAT_ASTC_8X8_UNORM => "ASTC_8X8_UNORM",
+        DXGI_FORMAT_ASTC_8X8_UNORM_SRGB => "ASTC_8X8_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_10X5_TYPELESS => "ASTC_10X5_TYPELESS",
+        DXGI_FORMAT_ASTC_10X5_UNORM => "ASTC_10X5_UNORM",
+        DXGI_FORMAT_ASTC_10X5_UNORM_SRGB => "ASTC_10X5_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_10X6_TYPELESS => "ASTC_10X6_TYPELESS",
+        DXGI_FORMAT_ASTC_10X6_UNORM => "ASTC_10X6_UNORM",
+        DXGI_FORMAT_ASTC_10X6_UNORM_SRGB => "ASTC_10X6_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_10X8_TYPELESS => "ASTC_10X8_TYPELESS",
+        DXGI_FORMAT_ASTC_10X8_UNORM => "ASTC_10X8_UNORM",
+        DXGI_FORMAT_ASTC_10X8_UNORM_SRGB => "ASTC_10X8_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_10X10_TYPELESS => "ASTC_10X10_TYPELESS",
+        DXGI_FORMAT_ASTC_10X10_UNORM => "ASTC_10X10_UNORM",
+        DXGI_FORMAT_ASTC_10X10_UNORM_SRGB => "ASTC_10X10_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_12X10_TYPELESS => "ASTC_12X10_TYPELESS",
+        DXGI_FORMAT_ASTC_12X10_UNORM => "ASTC_12X10_UNORM",
+        DXGI_FORMAT_ASTC_12X10_UNORM_SRGB => "ASTC_12X10_UNORM_SRGB",
+        DXGI_FORMAT_ASTC_12X12_TYPELESS => "ASTC_12X12_TYPELESS",
+        DXGI_FORMAT_ASTC_12X12_UNORM => "ASTC_12X12_UNORM",
+        DXGI_FORMAT_ASTC_12X12_UNORM_SRGB => "ASTC_12X12_UNORM_SRGB",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+pub fn is_block_compressed(dxgi_format: u32) -> bool {
+    dxgi_format == DXGI_FORMAT_BC1_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC1_UNORM
+        || dxgi_format == DXGI_FORMAT_BC1_UNORM_SRGB
+        || dxgi_format == DXGI_FORMAT_BC2_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC2_UNORM
+        || dxgi_format == DXGI_FORMAT_BC2_UNORM_SRGB
+        || dxgi_format == DXGI_FORMAT_BC3_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC3_UNORM
+        || dxgi_format == DXGI_FORMAT_BC3_UNORM_SRGB
+        || dxgi_format == DXGI_FORMAT_BC4_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC4_UNORM
+        || dxgi_format == DXGI_FORMAT_BC4_SNORM
+        || dxgi_format == DXGI_FORMAT_BC5_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC5_UNORM
+        || dxgi_format == DXGI_FORMAT_BC5_SNORM
+        || dxgi_format == DXGI_FORMAT_BC6H_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC6H_UF16
+        || dxgi_format == DXGI_FORMAT_BC6H_SF16
+        || dxgi_format == DXGI_FORMAT_BC7_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC7_UNORM
+        || dxgi_format == DXGI_FORMAT_BC7_UNORM_SRGB
+        || is_astc(dxgi_format)
+}
+
+// Every ASTC block size has a TYPELESS, UNORM and UNORM_SRGB format, followed by an unused value
+pub fn is_astc(dxgi_format: u32) -> bool {
+    (DXGI_FORMAT_ASTC_4X4_TYPELESS..=DXGI_FORMAT_ASTC_12X12_UNORM_SRGB).contains(&dxgi_format)
+        && (dxgi_format - DXGI_FORMAT_ASTC_4X4_TYPELESS) % 4 != 3
+}
+
+// Block width and height in texels, 4x4 for BC formats and 1x1 for formats that are not block compressed
+pub fn block_dimensions(dxgi_format: u32) -> (u32, u32) {
+    if !is_astc(dxgi_format) {
+        return if is_block_compressed(dxgi_format) {
+            (4, 4)
+        } else {
+            (1, 1)
+        };
+    }
+
+    match (dxgi_format - DXGI_FORMAT_ASTC_4X4_TYPELESS) / 4 {
+        0 => (4, 4),
+        1 => (5, 4),
+        2 => (5, 5),
+        3 => (6, 5),
+        4 => (6, 6),
+        5 => (8, 5),
+        6 => (8, 6),
+        7 => (8, 8),
+        8 => (10, 5),
+        9 => (10, 6),
+        10 => (10, 8),
+        11 => (10, 10),
+        12 => (12, 10),
+        _ => (12, 12),
+    }
+}
+
+pub fn is_legacy_yuv(dxgi_format: u32) -> bool {
+    dxgi_format == DXGI_FORMAT_AYUV
+        || dxgi_format == DXGI_FORMAT_Y410
+        || dxgi_format == DXGI_FORMAT_Y416
+        || dxgi_format == DXGI_FORMAT_NV12
+        || dxgi_format == DXGI_FORMAT_P010
+        || dxgi_format == DXGI_FORMAT_P016
+        || dxgi_format == DXGI_FORMAT_420_OPAQUE
+        || dxgi_format == DXGI_FORMAT_YUY2
+        || dxgi_format == DXGI_FORMAT_Y210
+        || dxgi_format == DXGI_FORMAT_Y216
+        || dxgi_format == DXGI_FORMAT_NV11
+        || dxgi_format == DXGI_FORMAT_AI44
+        || dxgi_format == DXGI_FORMAT_IA44
+        || dxgi_format == DXGI_FORMAT_P8
+        || dxgi_format == DXGI_FORMAT_A8P8
+        || dxgi_format == DXGI_FORMAT_P208
+        || dxgi_format == DXGI_FORMAT_V208
+        || dxgi_format == DXGI_FORMAT_V408
+}
+
+// Formats that store luma and chroma in separate planes
+pub fn is_planar(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_NV12
+            | DXGI_FORMAT_P010
+            | DXGI_FORMAT_P016
+            | DXGI_FORMAT_420_OPAQUE
+            | DXGI_FORMAT_NV11
+            | DXGI_FORMAT_P208
+            | DXGI_FORMAT_V208
+            | DXGI_FORMAT_V408
+    )
+}
+
+// Panics on unknown formats, for formats that are known to be valid. The public version is `try_bits_per_pixel`.
+pub(crate) fn bits_per_pixel(dxgi_format: u32) -> u32 {
+    try_bits_per_pixel(dxgi_format).unwrap_or_else(|| unimplemented!("Format not implemented: {}", dxgi_format))
+}
+
+// None for unknown formats
+pub fn try_bits_per_pixel(dxgi_format: u32) -> Option<u32> {
+    let bits_per_pixel = match dxgi_format {
+        DXGI_FORMAT_R32G32B32A32_TYPELESS => 128,
+        DXGI_FORMAT_R32G32B32A32_FLOAT => 128,
+        DXGI_FORMAT_R32G32B32A32_UINT => 128,
+        DXGI_FORMAT_R32G32B32A32_SINT => 128,
+
+        DXGI_FORMAT_R32G32B32_TYPELESS => 96,
+        DXGI_FORMAT_R32G32B32_FLOAT => 96,
+        DXGI_FORMAT_R32G32B32_UINT => 96,
+        DXGI_FORMAT_R32G32B32_SINT => 96,
+
+        DXGI_FORMAT_R16G16B16A16_TYPELESS => 64,
+        DXGI_FORMAT_R16G16B16A16_FLOAT => 64,
+        DXGI_FORMAT_R16G16B16A16_UNORM => 64,
+        DXGI_FORMAT_R16G16B16A16_UINT => 64,
+        DXGI_FORMAT_R16G16B16A16_SNORM => 64,
+        DXGI_FORMAT_R16G16B16A16_SINT => 64,
+        DXGI_FORMAT_R32G32_TYPELESS => 64,
+        DXGI_FORMAT_R32G32_FLOAT => 64,
+        DXGI_FORMAT_R32G32_UINT => 64,
+        DXGI_FORMAT_R32G32_SINT => 64,
+        DXGI_FORMAT_R32G8X24_TYPELESS => 64,
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT => 64,
+        DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS => 64,
+        DXGI_FORMAT_X32_TYPELESS_G8X24_UINT => 64,
+        DXGI_FORMAT_Y416 => 64,
+
+        DXGI_FORMAT_R10G10B10A2_TYPELESS => 32,
+        DXGI_FORMAT_R10G10B10A2_UNORM => 32,
+        DXGI_FORMAT_R10G10B10A2_UINT => 32,
+        DXGI_FORMAT_R11G11B10_FLOAT => 32,
+        DXGI_FORMAT_R8G8B8A8_TYPELESS => 32,
+        DXGI_FORMAT_R8G8B8A8_UNORM => 32,
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => 32,
+        DXGI_FORMAT_R8G8B8A8_UINT => 32,
+        DXGI_FORMAT_R8G8B8A8_SNORM => 32,
+        DXGI_FORMAT_R8G8B8A8_SINT => 32,
+        DXGI_FORMAT_R16G16_TYPELESS => 32,
+        DXGI_FORMAT_R16G16_FLOAT => 32,
+        DXGI_FORMAT_R16G16_UNORM => 32,
+        DXGI_FORMAT_R16G16_UINT => 32,
+        DXGI_FORMAT_R16G16_SNORM => 32,
+        DXGI_FORMAT_R16G16_SINT => 32,
+        DXGI_FORMAT_R32_TYPELESS => 32,
+        DXGI_FORMAT_D32_FLOAT => 32,
+        DXGI_FORMAT_R32_FLOAT => 32,
+        DXGI_FORMAT_R32_UINT => 32,
+        DXGI_FORMAT_R32_SINT => 32,
+        DXGI_FORMAT_R24G8_TYPELESS => 32,
+        DXGI_FORMAT_D24_UNORM_S8_UINT => 32,
+        DXGI_FORMAT_R24_UNORM_X8_TYPELESS => 32,
+        DXGI_FORMAT_X24_TYPELESS_G8_UINT => 32,
+        DXGI_FORMAT_R9G9B9E5_SHAREDEXP => 32,
+        DXGI_FORMAT_B8G8R8A8_UNORM => 32,
+        DXGI_FORMAT_B8G8R8X8_UNORM => 32,
+        DXGI_FORMAT_R10G10B10_XR_BIAS_A2_UNORM => 32,
+        DXGI_FORMAT_B8G8R8A8_TYPELESS => 32,
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => 32,
+        DXGI_FORMAT_B8G8R8X8_TYPELESS => 32,
+        DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => 32,
+        DXGI_FORMAT_AYUV => 32,
+        DXGI_FORMAT_Y410 => 32,
+        DXGI_FORMAT_Y210 => 32,
+        DXGI_FORMAT_Y216 => 32,
+        DXGI_FORMAT_P010 => 24,
+        DXGI_FORMAT_P016 => 24,
+        DXGI_FORMAT_V408 => 24,
+        DXGI_FORMAT_R8G8_TYPELESS => 16,
+        DXGI_FORMAT_R8G8_UNORM => 16,
+        DXGI_FORMAT_R8G8_UINT => 16,
+        DXGI_FORMAT_R8G8_SNORM => 16,
+        DXGI_FORMAT_R8G8_SINT => 16,
+        DXGI_FORMAT_R16_TYPELESS => 16,
+        DXGI_FORMAT_R16_FLOAT => 16,
+        DXGI_FORMAT_D16_UNORM => 16,
+        DXGI_FORMAT_R16_UNORM => 16,
+        DXGI_FORMAT_R16_UINT => 16,
+        DXGI_FORMAT_R16_SNORM => 16,
+        DXGI_FORMAT_R16_SINT => 16,
+        DXGI_FORMAT_B5G6R5_UNORM => 16,
+        DXGI_FORMAT_B5G5R5A1_UNORM => 16,
+        DXGI_FORMAT_A8P8 => 16,
+        DXGI_FORMAT_B4G4R4A4_UNORM => 16,
+        DXGI_FORMAT_R8G8_B8G8_UNORM => 16,
+        DXGI_FORMAT_G8R8_G8B8_UNORM => 16,
+        DXGI_FORMAT_YUY2 => 16,
+        DXGI_FORMAT_P208 => 16,
+        DXGI_FORMAT_V208 => 16,
+        DXGI_FORMAT_NV12 => 12,
+        DXGI_FORMAT_420_OPAQUE => 12,
+        DXGI_FORMAT_NV11 => 12,
+
+        DXGI_FORMAT_R8_TYPELESS => 8,
+        DXGI_FORMAT_R8_UNORM => 8,
+        DXGI_FORMAT_R8_UINT => 8,
+        DXGI_FORMAT_R8_SNORM => 8,
+        DXGI_FORMAT_R8_SINT => 8,
+        DXGI_FORMAT_A8_UNORM => 8,
+        DXGI_FORMAT_AI44 => 8,
+        DXGI_FORMAT_IA44 => 8,
+        DXGI_FORMAT_P8 => 8,
+        DXGI_FORMAT_R1_UNORM => 1,
+
+        DXGI_FORMAT_BC1_TYPELESS => 4,
+        DXGI_FORMAT_BC1_UNORM => 4,
+        DXGI_FORMAT_BC1_UNORM_SRGB => 4,
+        DXGI_FORMAT_BC4_TYPELESS => 4,
+        DXGI_FORMAT_BC4_UNORM => 4,
+        DXGI_FORMAT_BC4_SNORM => 4,
+
+        DXGI_FORMAT_BC2_TYPELESS => 8,
+        DXGI_FORMAT_BC2_UNORM => 8,
+        DXGI_FORMAT_BC2_UNORM_SRGB => 8,
+        DXGI_FORMAT_BC3_TYPELESS => 8,
+        DXGI_FORMAT_BC3_UNORM => 8,
+        DXGI_FORMAT_BC3_UNORM_SRGB => 8,
+        DXGI_FORMAT_BC5_TYPELESS => 8,
+        DXGI_FORMAT_BC5_UNORM => 8,
+        DXGI_FORMAT_BC5_SNORM => 8,
+        DXGI_FORMAT_BC6H_TYPELESS => 8,
+        DXGI_FORMAT_BC6H_UF16 => 8,
+        DXGI_FORMAT_BC6H_SF16 => 8,
+        DXGI_FORMAT_BC7_TYPELESS => 8,
+        DXGI_FORMAT_BC7_UNORM => 8,
+        DXGI_FORMAT_BC7_UNORM_SRGB => 8,
+
+        // Rounded up, ASTC blocks are always 128 bits
+        dxgi_format if is_astc(dxgi_format) => {
+            let (block_width, block_height) = block_dimensions(dxgi_format);
+            128u32.div_ceil(block_width * block_height)
+        }
+
+        _ => return None,
+    };
+
+    Some(bits_per_pixel)
+}
+
+// Size of a block in bytes, only meaningful for block compressed formats
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide"]
+pub fn block_size(dxgi_format: u32) -> u32 {
+    if dxgi_format == DXGI_FORMAT_BC1_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC1_UNORM
+        || dxgi_format == DXGI_FORMAT_BC1_UNORM_SRGB
+    {
+        return 8;
+    }
+
+    if dxgi_format == DXGI_FORMAT_BC4_TYPELESS
+        || dxgi_format == DXGI_FORMAT_BC4_UNORM
+        || dxgi_format == DXGI_FORMAT_BC4_SNORM
+    {
+        return 8;
+    }
+
+    16
+}
+
+// Row pitch (a row of blocks for block compressed formats) and total size of a single 2D surface in bytes,
+// truncated to 32 bits just like the pitch_or_linear_size header field. Panics on unknown formats.
+pub fn pitch_and_linear_size(width: u32, height: u32, dxgi_format: u32) -> (u32, u32) {
+    let (row_pitch, linear_size) = checked_pitch_and_linear_size(width, height, dxgi_format).unwrap_or_else(|| {
+        panic!(
+            "Can't compute pitch for format {} and size {}x{}",
+            dxgi_format, width, height
+        )
+    });
+
+    (row_pitch as u32, linear_size as u32)
+}
+
+// 64-bit version of `pitch_and_linear_size`, None for unknown formats or if the size overflows
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide"]
+pub fn checked_pitch_and_linear_size(width: u32, height: u32, dxgi_format: u32) -> Option<(u64, u64)> {
+    let width = width as u64;
+    let height = height as u64;
+
+    if is_block_compressed(dxgi_format) {
+        let (block_width, block_height) = block_dimensions(dxgi_format);
+        let row_pitch = 1.max(width.div_ceil(block_width as u64)) * block_size(dxgi_format) as u64;
+        let linear_size = row_pitch.checked_mul(1.max(height.div_ceil(block_height as u64)))?;
+
+        return Some((row_pitch, linear_size));
+    }
+
+    // Pairs of texels that share chroma
+    if let Some(pair_size) = packed_pair_size(dxgi_format) {
+        let row_pitch = width.div_ceil(2) * pair_size as u64;
+        let linear_size = row_pitch.checked_mul(height)?;
+
+        return Some((row_pitch, linear_size));
+    }
+
+    // Row pitch of the luma plane and the size of all planes
+    if is_planar(dxgi_format) {
+        let planes = plane_layouts(width as u32, height as u32, dxgi_format)?;
+        let (luma, last) = (planes.first()?, planes.last()?);
+        let linear_size = last.offset.checked_add(last.row_pitch.checked_mul(last.row_count)?)?;
+
+        return Some((luma.row_pitch, linear_size));
+    }
+
+    // Rows are rounded up to whole bytes, so rows of R1_UNORM start on a byte boundary
+    let row_pitch = (width * try_bits_per_pixel(dxgi_format)? as u64).div_ceil(8);
+    let linear_size = row_pitch.checked_mul(height)?;
+
+    Some((row_pitch, linear_size))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatProperties {
+    pub bits_per_pixel: u32,
+    pub is_block_compressed: bool,
+    pub is_typeless: bool,
+    pub is_srgb: bool,
+    pub is_hdr: bool,
+    pub has_alpha: bool,
+    pub is_depth_stencil: bool,
+    pub is_signed: bool,
+}
+
+// None for unknown formats
+pub fn format_properties(dxgi_format: u32) -> Option<FormatProperties> {
+    Some(FormatProperties {
+        bits_per_pixel: try_bits_per_pixel(dxgi_format)?,
+        is_block_compressed: is_block_compressed(dxgi_format),
+        is_typeless: is_typeless(dxgi_format),
+        is_srgb: is_srgb(dxgi_format),
+        is_hdr: is_hdr(dxgi_format),
+        has_alpha: has_alpha(dxgi_format),
+        is_depth_stencil: is_depth_stencil(dxgi_format),
+        is_signed: is_signed(dxgi_format),
+    })
+}
+
+pub fn is_typeless(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R32G32B32A32_TYPELESS
+            | DXGI_FORMAT_R32G32B32_TYPELESS
+            | DXGI_FORMAT_R16G16B16A16_TYPELESS
+            | DXGI_FORMAT_R32G32_TYPELESS
+            | DXGI_FORMAT_R32G8X24_TYPELESS
+            | DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS
+            | DXGI_FORMAT_X32_TYPELESS_G8X24_UINT
+            | DXGI_FORMAT_R10G10B10A2_TYPELESS
+            | DXGI_FORMAT_R8G8B8A8_TYPELESS
+            | DXGI_FORMAT_R16G16_TYPELESS
+            | DXGI_FORMAT_R32_TYPELESS
+            | DXGI_FORMAT_R24G8_TYPELESS
+            | DXGI_FORMAT_R24_UNORM_X8_TYPELESS
+            | DXGI_FORMAT_X24_TYPELESS_G8_UINT
+            | DXGI_FORMAT_R8G8_TYPELESS
+            | DXGI_FORMAT_R16_TYPELESS
+            | DXGI_FORMAT_R8_TYPELESS
+            | DXGI_FORMAT_BC1_TYPELESS
+            | DXGI_FORMAT_BC2_TYPELESS
+            | DXGI_FORMAT_BC3_TYPELESS
+            | DXGI_FORMAT_BC4_TYPELESS
+            | DXGI_FORMAT_BC5_TYPELESS
+            | DXGI_FORMAT_B8G8R8A8_TYPELESS
+            | DXGI_FORMAT_B8G8R8X8_TYPELESS
+            | DXGI_FORMAT_BC6H_TYPELESS
+            | DXGI_FORMAT_BC7_TYPELESS
+    ) || (is_astc(dxgi_format) && typeless_format(dxgi_format) == Some(dxgi_format))
+}
+
+// Typeless format of the family of a format, the format itself if it is typeless, None if it has no family.
+// Depth-stencil formats belong to the family of their typeless resource format.
+pub fn typeless_format(dxgi_format: u32) -> Option<u32> {
+    let typeless = match dxgi_format {
+        DXGI_FORMAT_R32G32B32A32_TYPELESS..=DXGI_FORMAT_R32G32B32A32_SINT => DXGI_FORMAT_R32G32B32A32_TYPELESS,
+        DXGI_FORMAT_R32G32B32_TYPELESS..=DXGI_FORMAT_R32G32B32_SINT => DXGI_FORMAT_R32G32B32_TYPELESS,
+        DXGI_FORMAT_R16G16B16A16_TYPELESS..=DXGI_FORMAT_R16G16B16A16_SINT => DXGI_FORMAT_R16G16B16A16_TYPELESS,
+        DXGI_FORMAT_R32G32_TYPELESS..=DXGI_FORMAT_R32G32_SINT => DXGI_FORMAT_R32G32_TYPELESS,
+        DXGI_FORMAT_R32G8X24_TYPELESS..=DXGI_FORMAT_X32_TYPELESS_G8X24_UINT => DXGI_FORMAT_R32G8X24_TYPELESS,
+        DXGI_FORMAT_R10G10B10A2_TYPELESS..=DXGI_FORMAT_R10G10B10A2_UINT => DXGI_FORMAT_R10G10B10A2_TYPELESS,
+        DXGI_FORMAT_R8G8B8A8_TYPELESS..=DXGI_FORMAT_R8G8B8A8_SINT => DXGI_FORMAT_R8G8B8A8_TYPELESS,
+        DXGI_FORMAT_R16G16_TYPELESS..=DXGI_FORMAT_R16G16_SINT => DXGI_FORMAT_R16G16_TYPELESS,
+        DXGI_FORMAT_R32_TYPELESS..=DXGI_FORMAT_R32_SINT => DXGI_FORMAT_R32_TYPELESS,
+        DXGI_FORMAT_R24G8_TYPELESS..=DXGI_FORMAT_X24_TYPELESS_G8_UINT => DXGI_FORMAT_R24G8_TYPELESS,
+        DXGI_FORMAT_R8G8_TYPELESS..=DXGI_FORMAT_R8G8_SINT => DXGI_FORMAT_R8G8_TYPELESS,
+        DXGI_FORMAT_R16_TYPELESS..=DXGI_FORMAT_R16_SINT => DXGI_FORMAT_R16_TYPELESS,
+        DXGI_FORMAT_R8_TYPELESS..=DXGI_FORMAT_R8_SINT => DXGI_FORMAT_R8_TYPELESS,
+        DXGI_FORMAT_BC1_TYPELESS..=DXGI_FORMAT_BC1_UNORM_SRGB => DXGI_FORMAT_BC1_TYPELESS,
+        DXGI_FORMAT_BC2_TYPELESS..=DXGI_FORMAT_BC2_UNORM_SRGB => DXGI_FORMAT_BC2_TYPELESS,
+        DXGI_FORMAT_BC3_TYPELESS..=DXGI_FORMAT_BC3_UNORM_SRGB => DXGI_FORMAT_BC3_TYPELESS,
+        DXGI_FORMAT_BC4_TYPELESS..=DXGI_FORMAT_BC4_SNORM => DXGI_FORMAT_BC4_TYPELESS,
+        DXGI_FORMAT_BC5_TYPELESS..=DXGI_FORMAT_BC5_SNORM => DXGI_FORMAT_BC5_TYPELESS,
+        DXGI_FORMAT_B8G8R8A8_TYPELESS | DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => {
+            DXGI_FORMAT_B8G8R8A8_TYPELESS
+        }
+        DXGI_FORMAT_B8G8R8X8_TYPELESS | DXGI_FORMAT_B8G8R8X8_UNORM | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+            DXGI_FORMAT_B8G8R8X8_TYPELESS
+        }
+        DXGI_FORMAT_BC6H_TYPELESS..=DXGI_FORMAT_BC6H_SF16 => DXGI_FORMAT_BC6H_TYPELESS,
+        DXGI_FORMAT_BC7_TYPELESS..=DXGI_FORMAT_BC7_UNORM_SRGB => DXGI_FORMAT_BC7_TYPELESS,
+        dxgi_format if is_astc(dxgi_format) => dxgi_format - (dxgi_format - DXGI_FORMAT_ASTC_4X4_TYPELESS) % 4,
+        _ => return None,
+    };
+
+    Some(typeless)
+}
+
+// Format used to decode and encode the values of a typeless format: UNORM if the family has it, FLOAT otherwise.
+// Depth-stencil families use the depth-stencil format. Other formats are returned as is.
+pub fn typed_format(dxgi_format: u32) -> u32 {
+    match dxgi_format {
+        DXGI_FORMAT_R32G32B32A32_TYPELESS => DXGI_FORMAT_R32G32B32A32_FLOAT,
+        DXGI_FORMAT_R32G32B32_TYPELESS => DXGI_FORMAT_R32G32B32_FLOAT,
+        DXGI_FORMAT_R16G16B16A16_TYPELESS => DXGI_FORMAT_R16G16B16A16_UNORM,
+        DXGI_FORMAT_R32G32_TYPELESS => DXGI_FORMAT_R32G32_FLOAT,
+        DXGI_FORMAT_R32G8X24_TYPELESS => DXGI_FORMAT_D32_FLOAT_S8X24_UINT,
+        DXGI_FORMAT_R10G10B10A2_TYPELESS => DXGI_FORMAT_R10G10B10A2_UNORM,
+        DXGI_FORMAT_R8G8B8A8_TYPELESS => DXGI_FORMAT_R8G8B8A8_UNORM,
+        DXGI_FORMAT_R16G16_TYPELESS => DXGI_FORMAT_R16G16_UNORM,
+        DXGI_FORMAT_R32_TYPELESS => DXGI_FORMAT_R32_FLOAT,
+        DXGI_FORMAT_R24G8_TYPELESS => DXGI_FORMAT_D24_UNORM_S8_UINT,
+        DXGI_FORMAT_R8G8_TYPELESS => DXGI_FORMAT_R8G8_UNORM,
+        DXGI_FORMAT_R16_TYPELESS => DXGI_FORMAT_R16_UNORM,
+        DXGI_FORMAT_R8_TYPELESS => DXGI_FORMAT_R8_UNORM,
+        DXGI_FORMAT_BC1_TYPELESS => DXGI_FORMAT_BC1_UNORM,
+        DXGI_FORMAT_BC2_TYPELESS => DXGI_FORMAT_BC2_UNORM,
+        DXGI_FORMAT_BC3_TYPELESS => DXGI_FORMAT_BC3_UNORM,
+        DXGI_FORMAT_BC4_TYPELESS => DXGI_FORMAT_BC4_UNORM,
+        DXGI_FORMAT_BC5_TYPELESS => DXGI_FORMAT_BC5_UNORM,
+        DXGI_FORMAT_B8G8R8A8_TYPELESS => DXGI_FORMAT_B8G8R8A8_UNORM,
+        DXGI_FORMAT_B8G8R8X8_TYPELESS => DXGI_FORMAT_B8G8R8X8_UNORM,
+        DXGI_FORMAT_BC6H_TYPELESS => DXGI_FORMAT_BC6H_UF16,
+        DXGI_FORMAT_BC7_TYPELESS => DXGI_FORMAT_BC7_UNORM,
+        dxgi_format if is_astc(dxgi_format) && is_typeless(dxgi_format) => dxgi_format + 1,
+        _ => dxgi_format,
+    }
+}
+
+pub fn is_srgb(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+            | DXGI_FORMAT_BC1_UNORM_SRGB
+            | DXGI_FORMAT_BC2_UNORM_SRGB
+            | DXGI_FORMAT_BC3_UNORM_SRGB
+            | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+            | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB
+            | DXGI_FORMAT_BC7_UNORM_SRGB
+    ) || (is_astc(dxgi_format) && (dxgi_format - DXGI_FORMAT_ASTC_4X4_TYPELESS) % 4 == 2)
+}
+
+// Formats that can store values outside of [0, 1] range with floating point precision
+pub fn is_hdr(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R32G32B32A32_FLOAT
+            | DXGI_FORMAT_R32G32B32_FLOAT
+            | DXGI_FORMAT_R16G16B16A16_FLOAT
+            | DXGI_FORMAT_R32G32_FLOAT
+            | DXGI_FORMAT_R11G11B10_FLOAT
+            | DXGI_FORMAT_R16G16_FLOAT
+            | DXGI_FORMAT_R32_FLOAT
+            | DXGI_FORMAT_R16_FLOAT
+            | DXGI_FORMAT_R9G9B9E5_SHAREDEXP
+            | DXGI_FORMAT_R10G10B10_XR_BIAS_A2_UNORM
+            | DXGI_FORMAT_BC6H_TYPELESS
+            | DXGI_FORMAT_BC6H_UF16
+            | DXGI_FORMAT_BC6H_SF16
+    )
+}
+
+pub fn has_alpha(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R32G32B32A32_TYPELESS
+            | DXGI_FORMAT_R32G32B32A32_FLOAT
+            | DXGI_FORMAT_R32G32B32A32_UINT
+            | DXGI_FORMAT_R32G32B32A32_SINT
+            | DXGI_FORMAT_R16G16B16A16_TYPELESS
+            | DXGI_FORMAT_R16G16B16A16_FLOAT
+            | DXGI_FORMAT_R16G16B16A16_UNORM
+            | DXGI_FORMAT_R16G16B16A16_UINT
+            | DXGI_FORMAT_R16G16B16A16_SNORM
+            | DXGI_FORMAT_R16G16B16A16_SINT
+            | DXGI_FORMAT_R10G10B10A2_TYPELESS
+            | DXGI_FORMAT_R10G10B10A2_UNORM
+            | DXGI_FORMAT_R10G10B10A2_UINT
+            | DXGI_FORMAT_R8G8B8A8_TYPELESS
+            | DXGI_FORMAT_R8G8B8A8_UNORM
+            | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+            | DXGI_FORMAT_R8G8B8A8_UINT
+            | DXGI_FORMAT_R8G8B8A8_SNORM
+            | DXGI_FORMAT_R8G8B8A8_SINT
+            | DXGI_FORMAT_A8_UNORM
+            | DXGI_FORMAT_BC1_TYPELESS
+            | DXGI_FORMAT_BC1_UNORM
+            | DXGI_FORMAT_BC1_UNORM_SRGB
+            | DXGI_FORMAT_BC2_TYPELESS
+            | DXGI_FORMAT_BC2_UNORM
+            | DXGI_FORMAT_BC2_UNORM_SRGB
+            | DXGI_FORMAT_BC3_TYPELESS
+            | DXGI_FORMAT_BC3_UNORM
+            | DXGI_FORMAT_BC3_UNORM_SRGB
+            | DXGI_FORMAT_B5G5R5A1_UNORM
+            | DXGI_FORMAT_B8G8R8A8_UNORM
+            | DXGI_FORMAT_R10G10B10_XR_BIAS_A2_UNORM
+            | DXGI_FORMAT_B8G8R8A8_TYPELESS
+            | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+            | DXGI_FORMAT_BC7_TYPELESS
+            | DXGI_FORMAT_BC7_UNORM
+            | DXGI_FORMAT_BC7_UNORM_SRGB
+            | DXGI_FORMAT_AYUV
+            | DXGI_FORMAT_Y410
+            | DXGI_FORMAT_Y416
+            | DXGI_FORMAT_AI44
+            | DXGI_FORMAT_IA44
+            | DXGI_FORMAT_A8P8
+            | DXGI_FORMAT_B4G4R4A4_UNORM
+    ) || is_astc(dxgi_format)
+}
+
+pub fn is_depth_stencil(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+            | DXGI_FORMAT_D32_FLOAT
+            | DXGI_FORMAT_D24_UNORM_S8_UINT
+            | DXGI_FORMAT_D16_UNORM
+    )
+}
+
+pub fn is_bc6h(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_BC6H_TYPELESS | DXGI_FORMAT_BC6H_UF16 | DXGI_FORMAT_BC6H_SF16
+    )
+}
+
+// UF16 only stores non-negative values, negative values are encoded as 0. SF16 stores both signs with one bit less
+// of precision. The two formats can't be reinterpreted as each other and typeless data is decoded as UF16.
+pub fn bc6h_format(is_signed: bool) -> u32 {
+    if is_signed {
+        DXGI_FORMAT_BC6H_SF16
+    } else {
+        DXGI_FORMAT_BC6H_UF16
+    }
+}
+
+// Normalized formats with values in [0, 1], depth formats are not included
+pub fn is_unorm(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R16G16B16A16_UNORM
+            | DXGI_FORMAT_R10G10B10A2_UNORM
+            | DXGI_FORMAT_R8G8B8A8_UNORM
+            | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+            | DXGI_FORMAT_R16G16_UNORM
+            | DXGI_FORMAT_R8G8_UNORM
+            | DXGI_FORMAT_R16_UNORM
+            | DXGI_FORMAT_R8_UNORM
+            | DXGI_FORMAT_A8_UNORM
+            | DXGI_FORMAT_R1_UNORM
+            | DXGI_FORMAT_R8G8_B8G8_UNORM
+            | DXGI_FORMAT_G8R8_G8B8_UNORM
+            | DXGI_FORMAT_BC1_UNORM
+            | DXGI_FORMAT_BC1_UNORM_SRGB
+            | DXGI_FORMAT_BC2_UNORM
+            | DXGI_FORMAT_BC2_UNORM_SRGB
+            | DXGI_FORMAT_BC3_UNORM
+            | DXGI_FORMAT_BC3_UNORM_SRGB
+            | DXGI_FORMAT_BC4_UNORM
+            | DXGI_FORMAT_BC5_UNORM
+            | DXGI_FORMAT_B5G6R5_UNORM
+            | DXGI_FORMAT_B5G5R5A1_UNORM
+            | DXGI_FORMAT_B8G8R8A8_UNORM
+            | DXGI_FORMAT_B8G8R8X8_UNORM
+            | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+            | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB
+            | DXGI_FORMAT_BC7_UNORM
+            | DXGI_FORMAT_BC7_UNORM_SRGB
+            | DXGI_FORMAT_B4G4R4A4_UNORM
+    )
+}
+
+// Normalized formats with values in [-1, 1]. The smallest value (-128 for 8 bits) is also -1.
+pub fn is_snorm(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R16G16B16A16_SNORM
+            | DXGI_FORMAT_R8G8B8A8_SNORM
+            | DXGI_FORMAT_R16G16_SNORM
+            | DXGI_FORMAT_R8G8_SNORM
+            | DXGI_FORMAT_R16_SNORM
+            | DXGI_FORMAT_R8_SNORM
+            | DXGI_FORMAT_BC4_SNORM
+            | DXGI_FORMAT_BC5_SNORM
+    )
+}
+
+// Formats that can store negative values
+pub fn is_signed(dxgi_format: u32) -> bool {
+    matches!(
+        dxgi_format,
+        DXGI_FORMAT_R32G32B32A32_FLOAT
+            | DXGI_FORMAT_R32G32B32A32_SINT
+            | DXGI_FORMAT_R32G32B32_FLOAT
+            | DXGI_FORMAT_R32G32B32_SINT
+            | DXGI_FORMAT_R16G16B16A16_FLOAT
+            | DXGI_FORMAT_R16G16B16A16_SNORM
+            | DXGI_FORMAT_R16G16B16A16_SINT
+            | DXGI_FORMAT_R32G32_FLOAT
+            | DXGI_FORMAT_R32G32_SINT
+            | DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+            | DXGI_FORMAT_R8G8B8A8_SNORM
+            | DXGI_FORMAT_R8G8B8A8_SINT
+            | DXGI_FORMAT_R16G16_FLOAT
+            | DXGI_FORMAT_R16G16_SNORM
+            | DXGI_FORMAT_R16G16_SINT
+            | DXGI_FORMAT_D32_FLOAT
+            | DXGI_FORMAT_R32_FLOAT
+            | DXGI_FORMAT_R32_SINT
+            | DXGI_FORMAT_R8G8_SNORM
+            | DXGI_FORMAT_R8G8_SINT
+            | DXGI_FORMAT_R16_FLOAT
+            | DXGI_FORMAT_R16_SNORM
+            | DXGI_FORMAT_R16_SINT
+            | DXGI_FORMAT_R8_SNORM
+            | DXGI_FORMAT_R8_SINT
+            | DXGI_FORMAT_BC4_SNORM
+            | DXGI_FORMAT_BC5_SNORM
+            | DXGI_FORMAT_BC6H_SF16
+    )
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn format_properties(&self) -> Option<FormatProperties> {
+        format_properties(self.dxgi_format())
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        is_srgb(self.dxgi_format())
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        is_hdr(self.dxgi_format())
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        has_alpha(self.dxgi_format())
+    }
+
+    pub fn is_depth_stencil(&self) -> bool {
+        is_depth_stencil(self.dxgi_format())
+    }
+
+    pub fn is_signed(&self) -> bool {
+        is_signed(self.dxgi_format())
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Content digests for deduplication and change detection. The values are stable across versions and platforms.
+
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use std::convert::TryInto;
+
+const PRIME1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn round(accumulator: u64, lane: u64) -> u64 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME1)
+}
+
+fn merge_round(hash: u64, accumulator: u64) -> u64 {
+    (hash ^ round(0, accumulator)).wrapping_mul(PRIME1).wrapping_add(PRIME4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+#[doc = "https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md"]
+#[derive(Clone)]
+pub struct XxHash64 {
+    seed: u64,
+    accumulators: [u64; 4],
+    buffer: [u8; 32],
+    buffer_size: usize,
+    total_size: u64,
+}
+
+impl XxHash64 {
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            accumulators: [
+                seed.wrapping_add(PRIME1).wrapping_add(PRIME2),
+                seed.wrapping_add(PRIME2),
+                seed,
+                seed.wrapping_sub(PRIME1),
+            ],
+            buffer: [0; 32],
+            buffer_size: 0,
+            total_size: 0,
+        }
+    }
+
+    pub fn write(&mut self, mut bytes: &[u8]) {
+        self.total_size += bytes.len() as u64;
+
+        if self.buffer_size > 0 {
+            let count = bytes.len().min(32 - self.buffer_size);
+            self.buffer[self.buffer_size..self.buffer_size + count].copy_from_slice(&bytes[..count]);
+            self.buffer_size += count;
+            bytes = &bytes[count..];
+
+            if self.buffer_size < 32 {
+                return;
+            }
+            let buffer = self.buffer;
+            self.consume_stripe(&buffer);
+            self.buffer_size = 0;
+        }
+
+        let mut stripes = bytes.chunks_exact(32);
+        for stripe in &mut stripes {
+            self.consume_stripe(stripe);
+        }
+
+        let remainder = stripes.remainder();
+        self.buffer[..remainder.len()].copy_from_slice(remainder);
+        self.buffer_size = remainder.len();
+    }
+
+    fn consume_stripe(&mut self, stripe: &[u8]) {
+        for (lane, accumulator) in self.accumulators.iter_mut().enumerate() {
+            *accumulator = round(*accumulator, read_u64(&stripe[lane * 8..]));
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        let mut hash = if self.total_size >= 32 {
+            let [a, b, c, d] = self.accumulators;
+            let hash = a
+                .rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18));
+            self.accumulators
+                .iter()
+                .fold(hash, |hash, &accumulator| merge_round(hash, accumulator))
+        } else {
+            self.seed.wrapping_add(PRIME5)
+        };
+        hash = hash.wrapping_add(self.total_size);
+
+        let mut remainder = &self.buffer[..self.buffer_size];
+        while remainder.len() >= 8 {
+            hash = (hash ^ round(0, read_u64(remainder)))
+                .rotate_left(27)
+                .wrapping_mul(PRIME1)
+                .wrapping_add(PRIME4);
+            remainder = &remainder[8..];
+        }
+        if remainder.len() >= 4 {
+            hash = (hash ^ (read_u32(remainder) as u64).wrapping_mul(PRIME1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME2)
+                .wrapping_add(PRIME3);
+            remainder = &remainder[4..];
+        }
+        for &byte in remainder {
+            hash = (hash ^ (byte as u64).wrapping_mul(PRIME5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME3);
+        hash ^ (hash >> 32)
+    }
+}
+
+impl Default for XxHash64 {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+pub fn xxhash64(bytes: &[u8], seed: u64) -> u64 {
+    let mut hasher = XxHash64::with_seed(seed);
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Hash of every subresource payload, indexed by subresource (layer * mip count + mip), cubemap faces are layers.
+    // Headers are not hashed, so identical pixels in different formats have the same hash.
+    pub fn hash_subresources(&self) -> Vec<u64> {
+        let mip_count = layout::mip_count(&self.dds_header);
+        (0..layout::layer_count(&self.dds_header))
+            .flat_map(|layer| (0..mip_count).map(move |mip| (mip, layer)))
+            .map(|(mip, layer)| {
+                let range = layout::subresource_range(&self.dds_header, mip, layer).unwrap();
+                xxhash64(&self.as_slice()[range], 0)
+            })
+            .collect()
+    }
+
+    // Hash of the headers and the payload, equal to the hash of the file written by `write_to`
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = XxHash64::default();
+        hasher.write(bytemuck::bytes_of(&self.dds_header));
+        hasher.write(self.as_slice());
+        hasher.finish()
+    }
+}
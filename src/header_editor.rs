@@ -0,0 +1,119 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Editing the header of an image or of a file in place, without touching the payload. Changes are made on a copy
+// of the header and validated when they are committed, an editor that is dropped without committing changes
+// nothing. The payload size implied by the header can't change, but e.g. a format can be renamed to another format
+// with the same layout.
+
+use crate::dds::*;
+use crate::layout;
+use crate::repair::{repair_header, Repair};
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use std::io::{Seek, Write};
+
+enum Target<'a> {
+    Image(&'a mut DirectDrawHeader),
+    // File and the offset of the header in it
+    File(std::fs::File, u64),
+}
+
+pub struct HeaderEditor<'a> {
+    target: Target<'a>,
+    header: DirectDrawHeader,
+    data_size: usize,
+}
+
+impl<'a> HeaderEditor<'a> {
+    fn new(target: Target<'a>, header: DirectDrawHeader) -> Self {
+        Self {
+            target,
+            header,
+            data_size: layout::data_size(&header),
+        }
+    }
+
+    // Only the header of the file is read and written, zstd supercompressed files can be edited too
+    pub fn open_file(path: &std::path::Path) -> Result<HeaderEditor<'static>> {
+        let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let header = ScratchImage::read_header(&mut file)?;
+
+        // Files that can be read are accepted, the header is edited as it is stored
+        let mut normalized = header;
+        ScratchImage::apply_parse_options(&mut normalized, &ParseOptions::default())?;
+        ScratchImage::validate_header(&normalized)?;
+        let mut editor = HeaderEditor::new(Target::File(file, 0), header);
+        editor.data_size = layout::data_size(&normalized);
+        Ok(editor)
+    }
+
+    pub fn header(&self) -> &DirectDrawHeader {
+        &self.header
+    }
+
+    // Any field can be changed, the result is validated by `commit`
+    pub fn header_mut(&mut self) -> &mut DirectDrawHeader {
+        &mut self.header
+    }
+
+    pub fn set_flags(&mut self, flags: u32) -> &mut Self {
+        self.header.flags = flags;
+        self
+    }
+
+    pub fn set_caps(&mut self, caps: u32, caps2: u32) -> &mut Self {
+        self.header.caps = caps;
+        self.header.caps2 = caps2;
+        self
+    }
+
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) -> &mut Self {
+        let misc_flags2 = &mut self.header.dxt10.misc_flags2;
+        *misc_flags2 = (*misc_flags2 & !DDS_MISC_FLAGS2_ALPHA_MODE_MASK) | alpha_mode.to_misc_flags2();
+        self
+    }
+
+    // The new format must have the same payload size, pitch_or_linear_size is updated to match
+    pub fn set_format(&mut self, dxgi_format: u32) -> &mut Self {
+        self.header.dxt10.dxgi_format = dxgi_format;
+        self.header.pitch_or_linear_size =
+            layout::pitch_or_linear_size(self.header.width, self.header.height, dxgi_format);
+        self
+    }
+
+    // Fixes flags and caps that are inconsistent with the image description, see `repair_header`
+    pub fn repair(&mut self) -> Vec<Repair> {
+        repair_header(&mut self.header)
+    }
+
+    // Validates the header and writes it to the image or the file
+    pub fn commit(self) -> Result<()> {
+        ScratchImage::validate_base_header(&self.header)?;
+        ScratchImage::validate_header(&self.header)?;
+        if layout::checked_data_size(&self.header) != Some(self.data_size as u64) {
+            return Err(Error::BadImageDescription(
+                "Header changes must not change the payload size",
+            ));
+        }
+
+        match self.target {
+            Target::Image(header) => *header = self.header,
+            Target::File(mut file, offset) => {
+                file.seek(std::io::SeekFrom::Start(offset))?;
+                file.write_all(bytemuck::bytes_of(&self.header))?;
+                file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn edit_header(&mut self) -> HeaderEditor<'_> {
+        let header = self.dds_header;
+        HeaderEditor::new(Target::Image(&mut self.dds_header), header)
+    }
+}
@@ -0,0 +1,400 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Image based lighting: prefiltered specular cubemaps for the split-sum approximation, computed with GGX importance
+// sampling, the matching BRDF lookup table, and diffuse irradiance cubemaps. Environment maps must be cubemaps in
+// linear RGB, sRGB formats are linearized. Faces use the D3D orientation (+X, -X, +Y, -Y, +Z, -Z, v points down in
+// every face), faces are rendered in parallel.
+
+use crate::builder::{max_mipmap_count, ImageDesc};
+use crate::dds::*;
+use crate::decode::{linear_to_srgb, srgb_to_linear};
+use crate::encode::can_encode;
+use crate::format::*;
+use crate::mips::downsample;
+use crate::sampling::{bilinear, AddressMode};
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use std::f32::consts::PI;
+
+type Vec3 = [f32; 3];
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: Vec3) -> Vec3 {
+    let length = dot(v, v).sqrt();
+    v.map(|value| value / length)
+}
+
+// Direction through normalized coordinates of a face
+pub(crate) fn cube_direction(face: u32, u: f32, v: f32) -> Vec3 {
+    let (s, t) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    normalize(match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0],
+    })
+}
+
+// Face and normalized coordinates of a direction, inverse of `cube_direction`
+pub(crate) fn cube_face(direction: Vec3) -> (u32, f32, f32) {
+    let [x, y, z] = direction;
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, major, s, t) = if ax >= ay && ax >= az {
+        match x > 0.0 {
+            true => (0, ax, -z, -y),
+            false => (1, ax, z, -y),
+        }
+    } else if ay >= az {
+        match y > 0.0 {
+            true => (2, ay, x, z),
+            false => (3, ay, x, -z),
+        }
+    } else {
+        match z > 0.0 {
+            true => (4, az, x, -y),
+            false => (5, az, -x, -y),
+        }
+    };
+    (face, (s / major + 1.0) / 2.0, (t / major + 1.0) / 2.0)
+}
+
+// Source cubemap with a full mip chain, texels are indexed by mip and face
+struct Environment {
+    size: u32,
+    mips: Vec<Vec<Vec<[f32; 4]>>>,
+}
+
+impl Environment {
+    fn new<S: Storage>(image: &ScratchImage<S>) -> Result<Self> {
+        if !image.is_cubemap() {
+            return Err(Error::BadImageDescription("Environment map must be a cubemap"));
+        }
+
+        let size = image.image_width();
+        let mut mips = vec![Vec::new(); max_mipmap_count(size, size, 1) as usize];
+        for face in 0..6 {
+            let mut texels = image.decode_subresource(0, face)?;
+            if is_srgb(image.dxgi_format()) {
+                for texel in texels.iter_mut() {
+                    for value in texel.iter_mut().take(3) {
+                        *value = srgb_to_linear(*value);
+                    }
+                }
+            }
+
+            let mut mip_size = size as usize;
+            for (mip, faces) in mips.iter_mut().enumerate() {
+                if mip > 0 {
+                    texels = downsample(&texels, mip_size, mip_size);
+                    mip_size = (mip_size / 2).max(1);
+                }
+                faces.push(texels.clone());
+            }
+        }
+        Ok(Self { size, mips })
+    }
+
+    // Trilinear sample, faces are clamped at their edges
+    fn sample(&self, direction: Vec3, lod: f32) -> Vec3 {
+        let (face, u, v) = cube_face(direction);
+        let sample = |mip: usize| {
+            let size = (self.size >> mip).max(1);
+            let texels = &self.mips[mip][face as usize];
+            let texel = bilinear(u, v, size, size, AddressMode::Clamp, |x, y| {
+                Ok(texels[(y * size + x) as usize])
+            })
+            .unwrap();
+            [texel[0], texel[1], texel[2]]
+        };
+
+        let lod = lod.clamp(0.0, (self.mips.len() - 1) as f32);
+        let mip = lod.floor() as usize;
+        let fraction = lod - mip as f32;
+        if fraction == 0.0 {
+            return sample(mip);
+        }
+        let (a, b) = (sample(mip), sample(mip + 1));
+        [0, 1, 2].map(|channel| a[channel] + (b[channel] - a[channel]) * fraction)
+    }
+
+    fn texel_solid_angle(&self) -> f32 {
+        4.0 * PI / (6.0 * (self.size * self.size) as f32)
+    }
+}
+
+// Solid angle of a texel, computed from the area of its projection on the unit sphere
+fn texel_solid_angle(x: u32, y: u32, size: u32) -> f32 {
+    let area = |x: f32, y: f32| (x * y).atan2((x * x + y * y + 1.0).sqrt());
+    let coordinate = |texel: u32| 2.0 * texel as f32 / size as f32 - 1.0;
+    let (x0, y0, x1, y1) = (coordinate(x), coordinate(y), coordinate(x + 1), coordinate(y + 1));
+    area(x0, y0) - area(x0, y1) - area(x1, y0) + area(x1, y1)
+}
+
+// Largest environment mip that is integrated for irradiance, which varies slowly enough to lose nothing
+const IRRADIANCE_SOURCE_SIZE: u32 = 32;
+
+fn hammersley(index: u32, count: u32) -> (f32, f32) {
+    (index as f32 / count as f32, index.reverse_bits() as f32 / 4294967296.0)
+}
+
+// Half vector of a GGX sample around `normal`
+fn importance_sample_ggx((e1, e2): (f32, f32), alpha: f32, normal: Vec3) -> Vec3 {
+    let phi = 2.0 * PI * e1;
+    let cos_theta = ((1.0 - e2) / (1.0 + (alpha * alpha - 1.0) * e2)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let up = if normal[2].abs() < 0.999 {
+        [0.0, 0.0, 1.0]
+    } else {
+        [1.0, 0.0, 0.0]
+    };
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    let local = [sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta];
+    [0, 1, 2].map(|axis| tangent[axis] * local[0] + bitangent[axis] * local[1] + normal[axis] * local[2])
+}
+
+fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+// Radiance reflected towards the normal, with the view direction equal to the normal. Samples are taken from
+// lower mips of the environment where they are sparse, which removes most of the noise.
+fn prefilter(environment: &Environment, normal: Vec3, roughness: f32, sample_count: u32) -> Vec3 {
+    if roughness == 0.0 {
+        return environment.sample(normal, 0.0);
+    }
+
+    let alpha = roughness * roughness;
+    let mut sum = [0.0; 3];
+    let mut weight = 0.0;
+    for index in 0..sample_count {
+        let half = importance_sample_ggx(hammersley(index, sample_count), alpha, normal);
+        let n_dot_h = dot(normal, half).max(0.0);
+        let light = [0, 1, 2].map(|axis| 2.0 * n_dot_h * half[axis] - normal[axis]);
+        let n_dot_l = dot(normal, light);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        // D * NdotH / (4 * VdotH), which is D / 4 with V = N
+        let pdf = ggx_distribution(n_dot_h, alpha) / 4.0;
+        let sample_solid_angle = 1.0 / (sample_count as f32 * pdf).max(f32::MIN_POSITIVE);
+        let lod = 0.5 * (sample_solid_angle / environment.texel_solid_angle()).log2() + 1.0;
+
+        let radiance = environment.sample(light, lod);
+        (0..3).for_each(|channel| sum[channel] += radiance[channel] * n_dot_l);
+        weight += n_dot_l;
+    }
+    sum.map(|value| value / weight)
+}
+
+// Smith geometry term with the IBL remapping k = alpha / 2
+fn smith_ggx(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let k = alpha / 2.0;
+    let g1 = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    g1(n_dot_v) * g1(n_dot_l)
+}
+
+// Scale and bias of F0 in the split-sum approximation, for a normal along Z
+fn integrate_brdf(n_dot_v: f32, roughness: f32, sample_count: u32) -> (f32, f32) {
+    let alpha = roughness * roughness;
+    let normal = [0.0, 0.0, 1.0];
+    let view = [(1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v];
+
+    let (mut scale, mut bias) = (0.0, 0.0);
+    for index in 0..sample_count {
+        let half = importance_sample_ggx(hammersley(index, sample_count), alpha, normal);
+        let v_dot_h = dot(view, half).max(0.0);
+        let light = [0, 1, 2].map(|axis| 2.0 * v_dot_h * half[axis] - view[axis]);
+        let (n_dot_l, n_dot_h) = (light[2].max(0.0), half[2].max(0.0));
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let visibility = smith_ggx(n_dot_v, n_dot_l, alpha) * v_dot_h / (n_dot_h * n_dot_v);
+        let fresnel = (1.0 - v_dot_h).powi(5);
+        scale += (1.0 - fresnel) * visibility;
+        bias += fresnel * visibility;
+    }
+    (scale / sample_count as f32, bias / sample_count as f32)
+}
+
+// Cosine weighted radiance of every texel, divided by pi so a uniform environment has the same irradiance
+fn irradiance(texels: &[(Vec3, Vec3)], normal: Vec3) -> Vec3 {
+    let mut sum = [0.0; 3];
+    for &(direction, radiance) in texels {
+        let n_dot_l = dot(normal, direction);
+        if n_dot_l > 0.0 {
+            (0..3).for_each(|channel| sum[channel] += radiance[channel] * n_dot_l);
+        }
+    }
+    sum.map(|value| value / PI)
+}
+
+// Calls `radiance(direction)` for the center of every texel of every face
+fn render_faces<F>(size: u32, radiance: F) -> Vec<Vec<[f32; 4]>>
+where
+    F: Fn(Vec3) -> Vec3 + Sync,
+{
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..6)
+            .map(|face| {
+                let radiance = &radiance;
+                scope.spawn(move || {
+                    (0..size)
+                        .flat_map(|y| (0..size).map(move |x| (x, y)))
+                        .map(|(x, y)| {
+                            let (u, v) = ((x as f32 + 0.5) / size as f32, (y as f32 + 0.5) / size as f32);
+                            let [r, g, b] = radiance(cube_direction(face, u, v));
+                            [r, g, b, 1.0]
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    })
+}
+
+// Cubemap with `mipmap_count` mips of `size`, filled with `render_mip(mip, mip size)`
+fn render_cubemap<F>(size: u32, mipmap_count: u32, dxgi_format: u32, mut render_mip: F) -> Result<ScratchImage>
+where
+    F: FnMut(u32, u32) -> Vec<Vec<[f32; 4]>>,
+{
+    if !can_encode(dxgi_format) {
+        return Err(Error::UnsupportedFormat(dxgi_format));
+    }
+
+    let mut image = ScratchImage::with_desc(&ImageDesc {
+        width: size,
+        height: size,
+        mipmap_count,
+        dxgi_format,
+        is_cubemap: true,
+        ..Default::default()
+    })?;
+    for mip in 0..mipmap_count {
+        let faces = render_mip(mip, (size >> mip).max(1));
+        for (face, mut texels) in faces.into_iter().enumerate() {
+            if is_srgb(dxgi_format) {
+                for texel in texels.iter_mut() {
+                    for value in texel.iter_mut().take(3) {
+                        *value = linear_to_srgb(*value);
+                    }
+                }
+            }
+            image.encode_subresource(mip, face as u32, &texels)?;
+        }
+    }
+    Ok(image)
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Specular cubemap of this environment map with roughness increasing from 0 in the first mip to 1 in the last
+    // one, roughness is perceptual (GGX alpha is roughness squared). `sample_count` samples are taken per texel.
+    pub fn prefilter_specular(
+        &self,
+        size: u32,
+        mipmap_count: u32,
+        sample_count: u32,
+        dxgi_format: u32,
+    ) -> Result<ScratchImage> {
+        if mipmap_count == 0 || mipmap_count > max_mipmap_count(size, size, 1) {
+            return Err(Error::BadImageDescription(
+                "Mipmap count must be between 1 and the full mip chain of the cubemap size",
+            ));
+        }
+        if sample_count == 0 {
+            return Err(Error::BadImageDescription("Sample count must not be zero"));
+        }
+
+        let environment = Environment::new(self)?;
+        render_cubemap(size, mipmap_count, dxgi_format, |mip, mip_size| {
+            let roughness = match mipmap_count {
+                1 => 0.0,
+                mipmap_count => mip as f32 / (mipmap_count - 1) as f32,
+            };
+            render_faces(mip_size, |normal| {
+                prefilter(&environment, normal, roughness, sample_count)
+            })
+        })
+    }
+
+    // Diffuse irradiance cubemap with a single mip, the environment is integrated exactly at a low resolution
+    pub fn irradiance_map(&self, size: u32, dxgi_format: u32) -> Result<ScratchImage> {
+        let environment = Environment::new(self)?;
+        let mip = (0..environment.mips.len())
+            .find(|&mip| environment.size >> mip <= IRRADIANCE_SOURCE_SIZE)
+            .unwrap();
+        let source_size = (environment.size >> mip).max(1);
+
+        // Direction and radiance times solid angle of every source texel
+        let mut texels = Vec::new();
+        for (face, face_texels) in environment.mips[mip].iter().enumerate() {
+            for y in 0..source_size {
+                for x in 0..source_size {
+                    let (u, v) = (
+                        (x as f32 + 0.5) / source_size as f32,
+                        (y as f32 + 0.5) / source_size as f32,
+                    );
+                    let texel = face_texels[(y * source_size + x) as usize];
+                    let solid_angle = texel_solid_angle(x, y, source_size);
+                    texels.push((
+                        cube_direction(face as u32, u, v),
+                        [texel[0] * solid_angle, texel[1] * solid_angle, texel[2] * solid_angle],
+                    ));
+                }
+            }
+        }
+
+        render_cubemap(size, 1, dxgi_format, |_, size| {
+            render_faces(size, |normal| irradiance(&texels, normal))
+        })
+    }
+}
+
+impl ScratchImage {
+    // Split-sum BRDF lookup table in R16G16_FLOAT: F0 scale in red and bias in green. NdotV increases from left to
+    // right and roughness from top to bottom, both are sampled at texel centers.
+    pub fn brdf_lut(size: u32, sample_count: u32) -> Result<ScratchImage> {
+        if sample_count == 0 {
+            return Err(Error::BadImageDescription("Sample count must not be zero"));
+        }
+
+        let mut image = ScratchImage::with_desc(&ImageDesc {
+            width: size,
+            height: size,
+            dxgi_format: DXGI_FORMAT_R16G16_FLOAT,
+            ..Default::default()
+        })?;
+        let texels: Vec<[f32; 4]> = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (n_dot_v, roughness) = ((x as f32 + 0.5) / size as f32, (y as f32 + 0.5) / size as f32);
+                let (scale, bias) = integrate_brdf(n_dot_v, roughness, sample_count);
+                [scale, bias, 0.0, 1.0]
+            })
+            .collect();
+        image.encode_subresource(0, 0, &texels)?;
+        Ok(image)
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Typed views and exact conversions of single channel integer formats (R8, R16 and R32 UINT and SINT), for data
+// textures such as ID maps and lookup tables. Decoding goes through f32, so values above 2^24 lose precision, but
+// conversions between these formats don't: values are copied as integers and clamped to the target range.
+// Conversions from other formats round and clamp, conversions to them convert the values without normalization.
+
+use crate::dds::*;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+
+pub trait IntegerTexel: bytemuck::Pod {
+    const DXGI_FORMAT: u32;
+}
+
+impl IntegerTexel for u8 {
+    const DXGI_FORMAT: u32 = DXGI_FORMAT_R8_UINT;
+}
+
+impl IntegerTexel for i8 {
+    const DXGI_FORMAT: u32 = DXGI_FORMAT_R8_SINT;
+}
+
+impl IntegerTexel for u16 {
+    const DXGI_FORMAT: u32 = DXGI_FORMAT_R16_UINT;
+}
+
+impl IntegerTexel for i16 {
+    const DXGI_FORMAT: u32 = DXGI_FORMAT_R16_SINT;
+}
+
+impl IntegerTexel for u32 {
+    const DXGI_FORMAT: u32 = DXGI_FORMAT_R32_UINT;
+}
+
+impl IntegerTexel for i32 {
+    const DXGI_FORMAT: u32 = DXGI_FORMAT_R32_SINT;
+}
+
+// Smallest and largest value of single channel integer formats
+pub fn integer_range(dxgi_format: u32) -> Option<(i64, i64)> {
+    match dxgi_format {
+        DXGI_FORMAT_R8_UINT => Some((0, u8::MAX as i64)),
+        DXGI_FORMAT_R8_SINT => Some((i8::MIN as i64, i8::MAX as i64)),
+        DXGI_FORMAT_R16_UINT => Some((0, u16::MAX as i64)),
+        DXGI_FORMAT_R16_SINT => Some((i16::MIN as i64, i16::MAX as i64)),
+        DXGI_FORMAT_R32_UINT => Some((0, u32::MAX as i64)),
+        DXGI_FORMAT_R32_SINT => Some((i32::MIN as i64, i32::MAX as i64)),
+        _ => None,
+    }
+}
+
+fn read_integer(bytes: &[u8], dxgi_format: u32) -> i64 {
+    match dxgi_format {
+        DXGI_FORMAT_R8_UINT => bytes[0] as i64,
+        DXGI_FORMAT_R8_SINT => bytes[0] as i8 as i64,
+        DXGI_FORMAT_R16_UINT => u16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+        DXGI_FORMAT_R16_SINT => i16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+        DXGI_FORMAT_R32_UINT => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+        _ => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+    }
+}
+
+// Values are stored as two's complement, so the low bytes are the same for signed and unsigned formats
+fn write_integer(value: i64, bytes: &mut [u8]) {
+    let size = bytes.len();
+    bytes.copy_from_slice(&value.to_le_bytes()[..size]);
+}
+
+fn check_texel_format<T: IntegerTexel>(dxgi_format: u32) -> Result<()> {
+    match dxgi_format == T::DXGI_FORMAT {
+        true => Ok(()),
+        false => Err(Error::UnsupportedFormat(dxgi_format)),
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Texels of a subresource in row-major order, slice after slice for volumes. Fails if the data is not aligned
+    // for `T` in memory.
+    pub fn as_texels<T: IntegerTexel>(&self, mip: u32, layer: u32) -> Result<&[T]> {
+        check_texel_format::<T>(self.dxgi_format())?;
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        bytemuck::try_cast_slice(&self.as_slice()[range])
+            .map_err(|_| Error::BadImageDescription("Texel data is not aligned"))
+    }
+
+    // Exact conversion between single channel integer formats
+    pub(crate) fn convert_integers(&self, dxgi_format: u32) -> Result<ScratchImage> {
+        let source_format = self.dxgi_format();
+        let (min, max) = integer_range(dxgi_format).ok_or(Error::UnsupportedFormat(dxgi_format))?;
+        if integer_range(source_format).is_none() {
+            return Err(Error::UnsupportedFormat(source_format));
+        }
+
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+
+        // Both images have the same texels in the same order, without padding
+        let source_size = bits_per_pixel(source_format) as usize / 8;
+        let target_size = bits_per_pixel(dxgi_format) as usize / 8;
+        let texels = self.as_slice().chunks_exact(source_size);
+        for (source, target) in texels.zip(image.as_slice_mut().chunks_exact_mut(target_size)) {
+            write_integer(read_integer(source, source_format).clamp(min, max), target);
+        }
+        Ok(image)
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    pub fn as_texels_mut<T: IntegerTexel>(&mut self, mip: u32, layer: u32) -> Result<&mut [T]> {
+        check_texel_format::<T>(self.dxgi_format())?;
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        bytemuck::try_cast_slice_mut(&mut self.as_slice_mut()[range])
+            .map_err(|_| Error::BadImageDescription("Texel data is not aligned"))
+    }
+}
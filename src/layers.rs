@@ -0,0 +1,56 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Array layers as images of their own. Every layer stores its complete mip chain contiguously, so a layer is an
+// image that borrows part of the payload, with a header for a single layer. Cubemap faces are separate layers.
+
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+
+fn layer_header(header: &DirectDrawHeader) -> DirectDrawHeader {
+    let mut header = *header;
+    header.dxt10.array_size = 1;
+    header.dxt10.misc_flag &= !DDS_RESOURCE_MISC_TEXTURECUBE;
+    header.caps2 &= !(DDSCAPS2_CUBEMAP
+        | DDSCAPS2_CUBEMAP_POSITIVEX
+        | DDSCAPS2_CUBEMAP_NEGATIVEX
+        | DDSCAPS2_CUBEMAP_POSITIVEY
+        | DDSCAPS2_CUBEMAP_NEGATIVEY
+        | DDSCAPS2_CUBEMAP_POSITIVEZ
+        | DDSCAPS2_CUBEMAP_NEGATIVEZ);
+    header
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn layer(&self, layer: u32) -> Result<ScratchImage<&[u8]>> {
+        self.layers().nth(layer as usize).ok_or(Error::BadSubresource)
+    }
+
+    // Layers in storage order, as images with a single layer and all mips
+    pub fn layers(&self) -> impl Iterator<Item = ScratchImage<&[u8]>> + '_ {
+        let header = layer_header(&self.dds_header);
+        self.as_slice()
+            .chunks_exact(layout::layer_size(&self.dds_header))
+            .map(move |data| ScratchImage {
+                dds_header: header,
+                dds_data: data,
+            })
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    pub fn layers_mut(&mut self) -> impl Iterator<Item = ScratchImage<&mut [u8]>> + '_ {
+        let header = layer_header(&self.dds_header);
+        let layer_size = layout::layer_size(&self.dds_header);
+        self.as_slice_mut()
+            .chunks_exact_mut(layer_size)
+            .map(move |data| ScratchImage {
+                dds_header: header,
+                dds_data: data,
+            })
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Subresource layout of the DDS payload: every array layer (cubemap faces count as separate layers)
+// stores its complete mip chain, largest mip first. Mips of volume textures store their depth slices one after
+// another, the depth is halved with every mip like the width and height.
+
+use crate::dds::*;
+use crate::format::*;
+
+pub(crate) fn face_count(header: &DirectDrawHeader) -> u32 {
+    if header.dxt10.misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE == DDS_RESOURCE_MISC_TEXTURECUBE {
+        6
+    } else {
+        1
+    }
+}
+
+pub(crate) fn mip_count(header: &DirectDrawHeader) -> u32 {
+    header.mipmap_count.max(1)
+}
+
+pub(crate) fn layer_count(header: &DirectDrawHeader) -> u32 {
+    header.dxt10.array_size.max(1).saturating_mul(face_count(header))
+}
+
+pub(crate) fn subresource_count(header: &DirectDrawHeader) -> u32 {
+    layer_count(header).saturating_mul(mip_count(header))
+}
+
+// Size of a dimension at a mip, mips past the end of the full chain are 1. Files can have more mips than that.
+fn mip_dimension(size: u32, mip: u32) -> u32 {
+    size.checked_shr(mip).unwrap_or(0).max(1)
+}
+
+pub(crate) fn mip_extent(header: &DirectDrawHeader, mip: u32) -> (u32, u32) {
+    if header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D {
+        return (mip_dimension(header.width, mip), 1);
+    }
+    (mip_dimension(header.width, mip), mip_dimension(header.height, mip))
+}
+
+// Number of depth slices of a mip, 1 for everything but volume textures
+pub(crate) fn mip_depth(header: &DirectDrawHeader, mip: u32) -> u32 {
+    if header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D {
+        mip_dimension(header.depth, mip)
+    } else {
+        1
+    }
+}
+
+fn checked_mip_size(header: &DirectDrawHeader, mip: u32) -> Option<u64> {
+    let (width, height) = mip_extent(header, mip);
+    let (_, slice_pitch) = checked_pitch_and_linear_size(width, height, header.dxt10.dxgi_format)?;
+    slice_pitch.checked_mul(mip_depth(header, mip) as u64)
+}
+
+fn checked_layer_size(header: &DirectDrawHeader) -> Option<u64> {
+    (0..mip_count(header)).try_fold(0u64, |size, mip| size.checked_add(checked_mip_size(header, mip)?))
+}
+
+// None if the layout can't be computed: unknown format, or any size or count doesn't fit the types used for it.
+// Headers that pass this check can be used with the rest of the layout functions.
+pub(crate) fn checked_data_size(header: &DirectDrawHeader) -> Option<u64> {
+    let layer_count = (header.dxt10.array_size.max(1) as u64).checked_mul(face_count(header) as u64)?;
+    let subresource_count = layer_count.checked_mul(mip_count(header) as u64)?;
+    if subresource_count > u32::MAX as u64 {
+        return None;
+    }
+
+    let data_size = checked_layer_size(header)?.checked_mul(layer_count)?;
+    if data_size > usize::MAX as u64 {
+        return None;
+    }
+
+    Some(data_size)
+}
+
+// Size of a single depth slice of a mip
+pub(crate) fn slice_size(header: &DirectDrawHeader, mip: u32) -> usize {
+    mip_size(header, mip) / mip_depth(header, mip) as usize
+}
+
+pub(crate) fn mip_size(header: &DirectDrawHeader, mip: u32) -> usize {
+    checked_mip_size(header, mip).expect("Image layout is not validated") as usize
+}
+
+pub(crate) fn layer_size(header: &DirectDrawHeader) -> usize {
+    checked_layer_size(header).expect("Image layout is not validated") as usize
+}
+
+pub(crate) fn data_size(header: &DirectDrawHeader) -> usize {
+    checked_data_size(header).expect("Image layout is not validated") as usize
+}
+
+pub(crate) fn subresource_index(header: &DirectDrawHeader, mip: u32, layer: u32) -> Option<u32> {
+    if mip < mip_count(header) && layer < layer_count(header) {
+        Some(layer * mip_count(header) + mip)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn subresource_range(header: &DirectDrawHeader, mip: u32, layer: u32) -> Option<std::ops::Range<usize>> {
+    subresource_index(header, mip, layer)?;
+
+    let layer_offset = layer_size(header) * layer as usize;
+    let mip_offset: usize = (0..mip).map(|mip| mip_size(header, mip)).sum();
+    let offset = layer_offset + mip_offset;
+
+    Some(offset..offset + mip_size(header, mip))
+}
+
+pub(crate) fn pitch_or_linear_size(width: u32, height: u32, dxgi_format: u32) -> u32 {
+    match checked_pitch_and_linear_size(width, height, dxgi_format) {
+        Some((_, linear_size)) if is_block_compressed(dxgi_format) => linear_size as u32,
+        Some((row_pitch, _)) => row_pitch as u32,
+        None => 0,
+    }
+}
+
+// Header describing `mip_count` mips of `header` starting at `first_mip`
+pub(crate) fn mip_chain_header(header: &DirectDrawHeader, first_mip: u32, mip_count: u32) -> DirectDrawHeader {
+    let (width, height) = mip_extent(header, first_mip);
+
+    let mut result = *header;
+    result.width = width;
+    result.height = height;
+    if header.depth > 1 {
+        result.depth = 1.max(header.depth >> first_mip);
+    }
+    result.mipmap_count = mip_count;
+    result.pitch_or_linear_size = pitch_or_linear_size(width, height, header.dxt10.dxgi_format);
+
+    if mip_count > 1 {
+        result.flags |= DDSD_MIPMAPCOUNT;
+        result.caps |= DDSCAPS_MIPMAP | DDSCAPS_COMPLEX;
+    } else {
+        result.flags &= !DDSD_MIPMAPCOUNT;
+        result.caps &= !DDSCAPS_MIPMAP;
+    }
+
+    result
+}
+
+// Offset of a subresource from the start of the DDS payload, the analogue of D3D CalcSubresource.
+// `face` must be 0 for non-cubemap images; returns None if any index is out of range.
+pub fn subresource_offset(header: &DirectDrawHeader, mip: u32, layer: u32, face: u32) -> Option<u64> {
+    checked_data_size(header)?;
+    if face >= face_count(header) {
+        return None;
+    }
+
+    let range = subresource_range(header, mip, layer.checked_mul(face_count(header))?.checked_add(face)?)?;
+    Some(range.start as u64)
+}
+
+pub fn subresource_size(header: &DirectDrawHeader, mip: u32) -> Option<u64> {
+    checked_data_size(header)?;
+    if mip < mip_count(header) {
+        checked_mip_size(header, mip)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubresourceLayout {
+    pub width: u32,
+    pub height: u32,
+    // Depth slices, 1 for everything but volume textures
+    pub depth: u32,
+    // Bytes between rows of texels, or rows of blocks for block compressed formats
+    pub row_pitch: u64,
+    // Bytes between depth slices
+    pub slice_pitch: u64,
+    // slice_pitch * depth
+    pub size: u64,
+}
+
+// Layout of every subresource of a mip, None if the mip is out of range
+pub fn subresource_layout(header: &DirectDrawHeader, mip: u32) -> Option<SubresourceLayout> {
+    checked_data_size(header)?;
+    if mip >= mip_count(header) {
+        return None;
+    }
+
+    let (width, height) = mip_extent(header, mip);
+    let depth = mip_depth(header, mip);
+    let (row_pitch, slice_pitch) = checked_pitch_and_linear_size(width, height, header.dxt10.dxgi_format)?;
+    Some(SubresourceLayout {
+        width,
+        height,
+        depth,
+        row_pitch,
+        slice_pitch,
+        size: slice_pitch * depth as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageDesc;
+    use crate::scratch_image::*;
+
+    fn header(width: u32, height: u32, depth: u32, resource_dimension: u32) -> DirectDrawHeader {
+        let mut header: DirectDrawHeader = bytemuck::Zeroable::zeroed();
+        header.width = width;
+        header.height = height;
+        header.depth = depth;
+        header.dxt10.dxgi_format = DXGI_FORMAT_R8_UNORM;
+        header.dxt10.resource_dimension = resource_dimension;
+        header
+    }
+
+    #[test]
+    fn mips_past_the_full_chain_are_1x1() {
+        let header = header(4, 8, 16, D3D10_RESOURCE_DIMENSION_TEXTURE3D);
+        assert_eq!(mip_extent(&header, 2), (1, 2));
+        assert_eq!(mip_depth(&header, 2), 4);
+        for mip in [31, 32, 40, u32::MAX] {
+            assert_eq!(mip_extent(&header, mip), (1, 1));
+            assert_eq!(mip_depth(&header, mip), 1);
+        }
+    }
+
+    #[test]
+    fn long_mip_chain_size() {
+        let mut header = header(4, 4, 1, D3D10_RESOURCE_DIMENSION_TEXTURE2D);
+        header.mipmap_count = 40;
+        assert_eq!(checked_data_size(&header), Some(16 + 4 + 38));
+    }
+
+    #[test]
+    fn read_long_mip_chain() {
+        let desc = ImageDesc {
+            width: 4,
+            height: 4,
+            depth: 1,
+            mipmap_count: 1,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_R8_UNORM,
+            is_cubemap: false,
+            dimension: None,
+        };
+        let mut file = Vec::new();
+        ScratchImage::with_desc(&desc).unwrap().write_to(&mut file).unwrap();
+        let mipmap_count_offset = std::mem::offset_of!(DirectDrawHeader, mipmap_count);
+        file[mipmap_count_offset..mipmap_count_offset + 4].copy_from_slice(&40u32.to_le_bytes());
+        file.resize(file.len() + 4 + 38, 0);
+
+        let mut options = ParseOptions::default();
+        options.limits.max_mips = 64;
+        let image = ScratchImage::from_reader_with(&mut file.as_slice(), &options).unwrap();
+        assert_eq!(image.mipmap_count(), 40);
+        assert_eq!(image.subresource_layout(39).unwrap().width, 1);
+    }
+
+    fn volume(width: u32, height: u32, depth: u32, mipmap_count: u32, dxgi_format: u32) -> ScratchImage {
+        let desc = ImageDesc {
+            width,
+            height,
+            depth,
+            mipmap_count,
+            array_size: 1,
+            dxgi_format,
+            is_cubemap: false,
+            dimension: None,
+        };
+        let mut image = ScratchImage::with_desc(&desc).unwrap();
+        for (index, byte) in image.as_slice_mut().iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        image
+    }
+
+    #[test]
+    fn volume_layout() {
+        let image = volume(8, 4, 4, 3, DXGI_FORMAT_R8_UNORM);
+        assert_eq!(image.data_size(), 8 * 4 * 4 + 4 * 2 * 2 + 2);
+
+        let layout = image.subresource_layout(1).unwrap();
+        assert_eq!((layout.width, layout.height, layout.depth), (4, 2, 2));
+        assert_eq!((layout.row_pitch, layout.slice_pitch, layout.size), (4, 8, 16));
+        let layout = image.subresource_layout(2).unwrap();
+        assert_eq!((layout.width, layout.height, layout.depth, layout.size), (2, 1, 1, 2));
+        assert!(image.subresource_layout(3).is_err());
+
+        assert_eq!(image.slice(0, 3).unwrap(), &image.as_slice()[96..128]);
+        assert_eq!(image.slice(1, 1).unwrap(), &image.as_slice()[136..144]);
+        assert!(image.slice(1, 2).is_err());
+        assert!(image.slice(2, 1).is_err());
+    }
+
+    #[test]
+    fn block_compressed_volume_layout() {
+        let image = volume(8, 8, 2, 2, DXGI_FORMAT_BC1_UNORM);
+        let layout = image.subresource_layout(0).unwrap();
+        assert_eq!((layout.row_pitch, layout.slice_pitch, layout.size), (16, 32, 64));
+        let layout = image.subresource_layout(1).unwrap();
+        assert_eq!((layout.depth, layout.size), (1, 8));
+        assert_eq!(image.data_size(), 72);
+    }
+
+    #[test]
+    fn read_volume() {
+        let image = volume(8, 4, 4, 3, DXGI_FORMAT_R8_UNORM);
+        let mut file = Vec::new();
+        image.write_to(&mut file).unwrap();
+        assert_eq!(ScratchImage::from_reader(&mut file.as_slice()).unwrap(), image);
+
+        // Sized as if the depth didn't shrink with every mip
+        file.resize(file.len() + (4 * 2 * 4 - 4 * 2 * 2) + (2 * 4 - 2), 0);
+        assert!(matches!(
+            ScratchImage::from_reader(&mut file.as_slice()),
+            Err(Error::BadDataSize { .. })
+        ));
+    }
+}
@@ -1,5 +1,92 @@
+mod batch;
+mod bc;
+mod blocks;
+mod builder;
+mod channels;
+mod colorimetry;
+mod compare;
+#[cfg(feature = "zstd")]
+mod container;
+mod cubemap;
 mod dds;
+mod decode;
+mod encode;
+mod exr;
+mod flipbook;
+mod footprint;
+pub mod format;
+mod hash;
+mod header_editor;
+mod ibl;
+mod integer;
+mod layers;
+mod layout;
+mod loader;
+mod mastering;
+mod metadata;
+mod mips;
+mod noise;
+mod normal_map;
+mod patterns;
+mod png;
+mod probe;
+mod profile;
+mod repair;
+mod sampling;
 mod scratch_image;
+mod signature;
+mod stats;
+mod storage;
+mod streaming;
+#[cfg(feature = "zstd")]
+mod supercompression;
+mod swizzle;
+mod tiles;
+mod transparency;
+mod upload;
+mod validation;
+mod video;
 
+pub use batch::*;
+pub use blocks::*;
+pub use builder::*;
+pub use channels::*;
+pub use colorimetry::*;
+pub use compare::*;
+#[cfg(feature = "zstd")]
+pub use container::*;
+pub use cubemap::*;
 pub use dds::*;
+pub use decode::*;
+pub use encode::*;
+pub use exr::*;
+pub use flipbook::*;
+pub use footprint::*;
+pub use format::*;
+pub use hash::*;
+pub use header_editor::*;
+pub use integer::*;
+pub use layout::*;
+pub use loader::*;
+pub use mastering::*;
+pub use metadata::*;
+pub use mips::*;
+pub use noise::*;
+pub use patterns::*;
+pub use png::*;
+pub use probe::*;
+pub use profile::*;
+pub use repair::*;
+pub use sampling::*;
 pub use scratch_image::*;
+pub use signature::*;
+pub use stats::*;
+pub use storage::*;
+pub use streaming::*;
+#[cfg(feature = "zstd")]
+pub use supercompression::*;
+pub use swizzle::*;
+pub use tiles::*;
+pub use upload::*;
+pub use validation::*;
+pub use video::*;
@@ -0,0 +1,111 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Concurrent loading of many DDS files. Worker threads take paths from a shared queue and deliver images in the
+// order they complete. At most `max_pending` loaded images wait to be received, which bounds the memory used by a
+// load together with the thread count. Payload buffers of images returned with `recycle` are reused by later loads.
+
+use crate::scratch_image::*;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoaderOptions {
+    // 0 uses the available parallelism
+    pub thread_count: usize,
+    // Loaded images that are not received yet, 0 is one per thread
+    pub max_pending: usize,
+    pub parse_options: ParseOptions,
+}
+
+pub struct LoadedImage {
+    // Index of the path in the load
+    pub index: usize,
+    pub path: PathBuf,
+    pub result: Result<ScratchImage>,
+}
+
+pub struct Loader {
+    options: LoaderOptions,
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl Loader {
+    pub fn new(options: &LoaderOptions) -> Self {
+        let thread_count = match options.thread_count {
+            0 => std::thread::available_parallelism().map_or(1, |count| count.get()),
+            thread_count => thread_count,
+        };
+        let max_pending = match options.max_pending {
+            0 => thread_count,
+            max_pending => max_pending,
+        };
+
+        Self {
+            options: LoaderOptions {
+                thread_count,
+                max_pending,
+                parse_options: options.parse_options,
+            },
+            buffers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // Loads in background threads, the load stops early when the receiver is dropped
+    pub fn load(&self, paths: Vec<PathBuf>) -> Receiver<LoadedImage> {
+        let (sender, receiver) = sync_channel(self.options.max_pending);
+        let paths = Arc::new(paths);
+        let next_path = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..self.options.thread_count.min(paths.len()) {
+            let (sender, paths, next_path) = (sender.clone(), paths.clone(), next_path.clone());
+            let (buffers, parse_options) = (self.buffers.clone(), self.options.parse_options);
+            std::thread::spawn(move || loop {
+                let index = next_path.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(index) {
+                    Some(path) => path,
+                    None => break,
+                };
+
+                let buffer = buffers.lock().unwrap().pop().unwrap_or_default();
+                let result = load_file(path, buffer, &parse_options);
+                let loaded = LoadedImage {
+                    index,
+                    path: path.clone(),
+                    result,
+                };
+                if sender.send(loaded).is_err() {
+                    break;
+                }
+            });
+        }
+        receiver
+    }
+
+    // Blocks until every path is loaded, `deliver` is called on this thread in the order images complete
+    pub fn load_with<F>(&self, paths: Vec<PathBuf>, deliver: F)
+    where
+        F: FnMut(LoadedImage),
+    {
+        self.load(paths).into_iter().for_each(deliver);
+    }
+
+    // Returns the payload buffer of an image to the pool, the pool keeps one buffer per image that can be in flight
+    pub fn recycle(&self, image: ScratchImage) {
+        let (_, buffer) = image.into_raw();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.options.thread_count + self.options.max_pending {
+            buffers.push(buffer);
+        }
+    }
+}
+
+fn load_file(path: &std::path::Path, mut buffer: Vec<u8>, parse_options: &ParseOptions) -> Result<ScratchImage> {
+    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let dds_header = *ScratchImage::from_reader_with_buffer(&mut file, &mut buffer, parse_options)?.header();
+    ScratchImage::from_parts(dds_header, buffer)
+}
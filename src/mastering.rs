@@ -0,0 +1,211 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// HDR10 static metadata: the mastering display color volume (SMPTE ST 2086) and the content light levels
+// (CTA-861.3). They are kept in the metadata chunk as strings of numbers separated by spaces, luminances are in nits.
+
+use crate::colorimetry::*;
+use crate::encode::can_encode;
+use crate::layout;
+use crate::metadata::*;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MasteringDisplay {
+    // xy chromaticities of red, green and blue
+    pub primaries: [[f32; 2]; 3],
+    pub white_point: [f32; 2],
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+}
+
+impl MasteringDisplay {
+    // Display with standard primaries and the D65 white point
+    pub fn with_primaries(primaries: Primaries, max_luminance: f32, min_luminance: f32) -> Self {
+        Self {
+            primaries: primaries.chromaticities(),
+            white_point: [0.3127, 0.3290],
+            max_luminance,
+            min_luminance,
+        }
+    }
+
+    // Value of METADATA_MASTERING_DISPLAY: red, green, blue and white xy, then the maximum and minimum luminance
+    pub fn to_metadata_string(&self) -> String {
+        let [[red_x, red_y], [green_x, green_y], [blue_x, blue_y]] = self.primaries;
+        let [white_x, white_y] = self.white_point;
+        format!(
+            "{} {} {} {} {} {} {} {} {} {}",
+            red_x, red_y, green_x, green_y, blue_x, blue_y, white_x, white_y, self.max_luminance, self.min_luminance
+        )
+    }
+
+    pub fn from_metadata_string(value: &str) -> Option<Self> {
+        let [red_x, red_y, green_x, green_y, blue_x, blue_y, white_x, white_y, max_luminance, min_luminance] =
+            parse_numbers(value)?;
+        Some(Self {
+            primaries: [[red_x, red_y], [green_x, green_y], [blue_x, blue_y]],
+            white_point: [white_x, white_y],
+            max_luminance,
+            min_luminance,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ContentLightLevel {
+    // Brightest texel (MaxCLL) and brightest frame average (MaxFALL), texels are measured by their largest channel
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+impl ContentLightLevel {
+    // Value of METADATA_CONTENT_LIGHT_LEVEL: MaxCLL and MaxFALL
+    pub fn to_metadata_string(&self) -> String {
+        format!(
+            "{} {}",
+            self.max_content_light_level, self.max_frame_average_light_level
+        )
+    }
+
+    pub fn from_metadata_string(value: &str) -> Option<Self> {
+        let [max_content_light_level, max_frame_average_light_level] = parse_numbers(value)?;
+        Some(Self {
+            max_content_light_level,
+            max_frame_average_light_level,
+        })
+    }
+}
+
+// Exactly N finite numbers
+fn parse_numbers<const N: usize>(value: &str) -> Option<[f32; N]> {
+    let mut numbers = [0.0; N];
+    let mut words = value.split_whitespace();
+    for number in numbers.iter_mut() {
+        *number = words.next()?.parse().ok().filter(|number: &f32| number.is_finite())?;
+    }
+    match words.next() {
+        Some(_) => None,
+        None => Some(numbers),
+    }
+}
+
+impl Metadata {
+    pub fn mastering_display(&self) -> Option<MasteringDisplay> {
+        MasteringDisplay::from_metadata_string(self.get_str(METADATA_MASTERING_DISPLAY)?)
+    }
+
+    // None removes the entry
+    pub fn set_mastering_display(&mut self, display: Option<MasteringDisplay>) {
+        match display {
+            Some(display) => self.set_str(METADATA_MASTERING_DISPLAY, &display.to_metadata_string()),
+            None => self.remove(METADATA_MASTERING_DISPLAY),
+        }
+    }
+
+    pub fn content_light_level(&self) -> Option<ContentLightLevel> {
+        ContentLightLevel::from_metadata_string(self.get_str(METADATA_CONTENT_LIGHT_LEVEL)?)
+    }
+
+    pub fn set_content_light_level(&mut self, level: Option<ContentLightLevel>) {
+        match level {
+            Some(level) => self.set_str(METADATA_CONTENT_LIGHT_LEVEL, &level.to_metadata_string()),
+            None => self.remove(METADATA_CONTENT_LIGHT_LEVEL),
+        }
+    }
+}
+
+// BT.2390 EETF: compresses PQ values above a knee so `source_max` nits map to `target_max` nits
+#[doc = "https://www.itu.int/pub/R-REP-BT.2390"]
+fn eetf(value: f32, source_min: f32, source_max: f32, target_max: f32) -> f32 {
+    let (black, white) = (nits_to_pq(source_min), nits_to_pq(source_max));
+    let range = white - black;
+    if target_max >= source_max || range <= 0.0 {
+        return value;
+    }
+
+    let normalized = ((value - black) / range).clamp(0.0, 1.0);
+    let max_luminance = (nits_to_pq(target_max) - black) / range;
+    let knee = 1.5 * max_luminance - 0.5;
+    let compressed = match normalized < knee {
+        true => normalized,
+        false => {
+            let t = (normalized - knee) / (1.0 - knee);
+            let (t2, t3) = (t * t, t * t * t);
+            (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+                + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+                + (-2.0 * t3 + 3.0 * t2) * max_luminance
+        }
+    };
+    compressed * range + black
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // MaxCLL and MaxFALL of the top mip, every layer is a frame. `transfer` decodes the stored values, linear 1.0 is
+    // the reference white of 203 nits.
+    pub fn measure_content_light_level(&self, transfer: TransferFunction) -> Result<ContentLightLevel> {
+        let mut level = ContentLightLevel::default();
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            let texels = self.decode_subresource(0, layer)?;
+            let mut sum = 0.0f64;
+            for texel in texels.iter() {
+                let nits = (0..3)
+                    .map(|channel| transfer.decode(texel[channel]) * REFERENCE_WHITE_NITS)
+                    .fold(0.0f32, f32::max);
+                level.max_content_light_level = level.max_content_light_level.max(nits);
+                sum += nits as f64;
+            }
+            let average = (sum / texels.len().max(1) as f64) as f32;
+            level.max_frame_average_light_level = level.max_frame_average_light_level.max(average);
+        }
+        Ok(level)
+    }
+
+    // Tone maps PQ content mastered on `source` for a display with a lower peak luminance, with the BT.2390 EETF on
+    // the largest channel of every texel, so hues are kept. The result is PQ encoded in `dxgi_format`, and should be
+    // tagged with a mastering display of `target_max_luminance`.
+    pub fn tone_map_pq(
+        &self,
+        source: &MasteringDisplay,
+        target_max_luminance: f32,
+        dxgi_format: u32,
+    ) -> Result<ScratchImage> {
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        if !(target_max_luminance > 0.0 && source.max_luminance > source.min_luminance) {
+            return Err(Error::BadImageDescription("Display luminance range is empty"));
+        }
+
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let mut texels = self.decode_subresource(mip, layer)?;
+                for texel in texels.iter_mut() {
+                    let nits = [0, 1, 2].map(|channel| pq_to_nits(texel[channel]));
+                    let max_nits = nits[0].max(nits[1]).max(nits[2]);
+                    if max_nits <= 0.0 {
+                        continue;
+                    }
+
+                    let mapped = pq_to_nits(eetf(
+                        nits_to_pq(max_nits),
+                        source.min_luminance,
+                        source.max_luminance,
+                        target_max_luminance,
+                    ));
+                    for channel in 0..3 {
+                        texel[channel] = nits_to_pq(nits[channel] * mapped / max_nits);
+                    }
+                }
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Optional user metadata chunk after the last subresource. Other readers see it as trailing data, which they ignore.
+// The chunk is the "SDDM" magic and the payload size as u32, the payload is a sequence of entries, every entry is
+// a UTF-8 key and a value of raw bytes, both prefixed with their size as u32. All integers are little endian.
+
+use crate::dds::DirectDrawHeader;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use std::convert::{TryFrom, TryInto};
+use std::io::Read;
+
+pub const METADATA_MAGIC: [u8; 4] = *b"SDDM";
+
+// Keys with a common meaning, values are UTF-8 strings
+pub const METADATA_SOURCE_PATH: &str = "source_path";
+pub const METADATA_CONTENT_HASH: &str = "content_hash";
+pub const METADATA_COLOR_SPACE: &str = "color_space";
+pub const METADATA_MASTERING_DISPLAY: &str = "mastering_display";
+pub const METADATA_CONTENT_LIGHT_LEVEL: &str = "content_light_level";
+
+const CHUNK_HEADER_SIZE: usize = 8;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    // In the order they are stored, keys are unique
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Metadata {
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries()
+            .find(|&(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    // None if there is no such key or the value is not UTF-8
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        std::str::from_utf8(self.get(key)?).ok()
+    }
+
+    // Replaces the value of an existing key
+    pub fn set(&mut self, key: &str, value: &[u8]) {
+        match self.entries.iter_mut().find(|(entry_key, _)| entry_key == key) {
+            Some((_, entry_value)) => *entry_value = value.to_vec(),
+            None => self.entries.push((key.to_owned(), value.to_vec())),
+        }
+    }
+
+    pub fn set_str(&mut self, key: &str, value: &str) {
+        self.set(key, value.as_bytes());
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(entry_key, _)| entry_key != key);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The whole chunk, including the magic and the payload size
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut payload = Vec::new();
+        for (key, value) in self.entries.iter() {
+            for field in [key.as_bytes(), value.as_slice()] {
+                let size = u32::try_from(field.len()).map_err(|_| Error::SizeOverflow)?;
+                payload.extend_from_slice(&size.to_le_bytes());
+                payload.extend_from_slice(field);
+            }
+        }
+
+        let payload_size = u32::try_from(payload.len()).map_err(|_| Error::SizeOverflow)?;
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + payload.len());
+        chunk.extend_from_slice(&METADATA_MAGIC);
+        chunk.extend_from_slice(&payload_size.to_le_bytes());
+        chunk.extend_from_slice(&payload);
+        Ok(chunk)
+    }
+
+    fn from_payload(mut payload: &[u8]) -> Option<Self> {
+        let mut read_field = || {
+            let size = u32::from_le_bytes(payload.get(..4)?.try_into().unwrap()) as usize;
+            let field = payload.get(4..4 + size)?;
+            payload = &payload[4 + size..];
+            Some(field)
+        };
+
+        let mut metadata = Metadata::default();
+        while let Some(key) = read_field() {
+            let key = std::str::from_utf8(key).ok()?;
+            let value = read_field()?;
+            metadata.set(key, value);
+        }
+        match payload.is_empty() {
+            true => Some(metadata),
+            false => None,
+        }
+    }
+}
+
+// Reads the rest of the file after the payload. None if there is no trailing data, or if it is not a metadata
+// chunk and trailing data is allowed. The chunk counts towards `limits.max_data_size` like the payload.
+pub(crate) fn read_metadata_chunk<T: Read>(
+    dds_file: &mut T,
+    dds_header: &DirectDrawHeader,
+    options: &ParseOptions,
+) -> Result<Option<Metadata>> {
+    let mut chunk_header = Vec::new();
+    dds_file.take(CHUNK_HEADER_SIZE as u64).read_to_end(&mut chunk_header)?;
+    if chunk_header.is_empty() {
+        return Ok(None);
+    }
+
+    let mut trailing_size = chunk_header.len() as u64;
+    if chunk_header.len() == CHUNK_HEADER_SIZE && chunk_header[..4] == METADATA_MAGIC {
+        let payload_size = u32::from_le_bytes(chunk_header[4..].try_into().unwrap()) as u64;
+        let max_size = options.limits.max_data_size;
+        let data_size = layout::data_size(dds_header) as u64;
+        if data_size.saturating_add(payload_size) > max_size {
+            return Err(Error::LimitExceeded {
+                limit: "data size with metadata",
+                max: max_size,
+                actual: data_size + payload_size,
+            });
+        }
+        let mut payload = Vec::new();
+        dds_file.take(payload_size + 1).read_to_end(&mut payload)?;
+        if payload.len() as u64 == payload_size {
+            if let Some(metadata) = Metadata::from_payload(&payload) {
+                return Ok(Some(metadata));
+            }
+        }
+        trailing_size += payload.len() as u64;
+    }
+
+    if options.allow_trailing_data {
+        return Ok(None);
+    }
+    let data_size = layout::data_size(dds_header) as u64;
+    Err(Error::BadDataSize {
+        expected: data_size,
+        actual: data_size + trailing_size,
+        offset: DATA_OFFSET,
+    })
+}
+
+impl ScratchImage {
+    // Like `from_reader_with`, with the metadata chunk if the file has one
+    pub fn from_reader_with_metadata<T: Read>(
+        dds_file: &mut T,
+        options: &ParseOptions,
+    ) -> Result<(ScratchImage, Option<Metadata>)> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, options)?;
+        let dds_data = Self::read_data(dds_file, &dds_header, true)?;
+        let image = Self::from_header_and_data(dds_header, dds_data)?;
+
+        let metadata = read_metadata_chunk(dds_file, &image.dds_header, options)?;
+        Ok((image, metadata))
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn write_to_with_metadata<T: std::io::Write>(&self, file: &mut T, metadata: &Metadata) -> Result<()> {
+        let chunk = metadata.to_bytes()?;
+        self.write_to(file)?;
+        file.write_all(&chunk)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageDesc;
+    use crate::dds::*;
+
+    fn image(is_cubemap: bool) -> ScratchImage {
+        let desc = ImageDesc {
+            width: 4,
+            height: 4,
+            depth: 1,
+            mipmap_count: 3,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            is_cubemap,
+            dimension: None,
+        };
+        let mut image = ScratchImage::with_desc(&desc).unwrap();
+        for (index, byte) in image.as_slice_mut().iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        image
+    }
+
+    fn metadata() -> Metadata {
+        let mut metadata = Metadata::default();
+        metadata.set_str(METADATA_SOURCE_PATH, "textures/albedo.png");
+        metadata.set(METADATA_CONTENT_HASH, &[0, 1, 2, 255]);
+        metadata
+    }
+
+    fn file_with_metadata(image: &ScratchImage) -> Vec<u8> {
+        let mut file = Vec::new();
+        image.write_to_with_metadata(&mut file, &metadata()).unwrap();
+        file
+    }
+
+    #[test]
+    fn payload_round_trip() {
+        let metadata = metadata();
+        let bytes = metadata.to_bytes().unwrap();
+        assert_eq!(bytes[..4], METADATA_MAGIC);
+        assert_eq!(Metadata::from_payload(&bytes[CHUNK_HEADER_SIZE..]), Some(metadata));
+    }
+
+    #[test]
+    fn read_with_metadata() {
+        let image = image(false);
+        let file = file_with_metadata(&image);
+        let (read, metadata) =
+            ScratchImage::from_reader_with_metadata(&mut file.as_slice(), &Default::default()).unwrap();
+        assert_eq!(read, image);
+        assert_eq!(metadata, Some(self::metadata()));
+
+        let mut plain = Vec::new();
+        image.write_to(&mut plain).unwrap();
+        let (_, metadata) =
+            ScratchImage::from_reader_with_metadata(&mut plain.as_slice(), &Default::default()).unwrap();
+        assert_eq!(metadata, None);
+    }
+
+    #[test]
+    fn metadata_size_is_limited() {
+        let image = image(false);
+        let mut file = Vec::new();
+        image.write_to(&mut file).unwrap();
+        file.extend_from_slice(&METADATA_MAGIC);
+        file.extend_from_slice(&4096u32.to_le_bytes());
+
+        let mut options = ParseOptions::default();
+        options.limits.max_data_size = 1024;
+        let limit_exceeded = |result: Result<ScratchImage>| matches!(result, Err(Error::LimitExceeded { .. }));
+        assert!(limit_exceeded(ScratchImage::from_reader_with(
+            &mut file.as_slice(),
+            &options
+        )));
+        let result = ScratchImage::from_reader_with_metadata(&mut file.as_slice(), &options);
+        assert!(limit_exceeded(result.map(|(image, _)| image)));
+
+        // The chunk is checked before trailing data is skipped
+        options.allow_trailing_data = true;
+        let result = ScratchImage::from_reader_with_metadata(&mut file.as_slice(), &options);
+        assert!(limit_exceeded(result.map(|(image, _)| image)));
+    }
+
+    #[test]
+    fn every_reader_accepts_metadata() {
+        let image = image(false);
+        let file = file_with_metadata(&image);
+        let options = ParseOptions::default();
+
+        assert_eq!(ScratchImage::from_reader(&mut file.as_slice()).unwrap(), image);
+        let (read, _) = ScratchImage::from_reader_with_warnings(&mut file.as_slice(), &options).unwrap();
+        assert_eq!(read, image);
+        let mut buffer = Vec::new();
+        let read = ScratchImage::from_reader_with_buffer(&mut file.as_slice(), &mut buffer, &options).unwrap();
+        assert_eq!(read, image);
+        let (read, _) = ScratchImage::from_reader_lenient(&mut file.as_slice()).unwrap();
+        assert_eq!(read, image);
+        assert!(ScratchImage::probe(&mut file.as_slice()).is_ok());
+
+        let read = ScratchImage::from_reader_mip_range(&mut std::io::Cursor::new(&file), 1..=2).unwrap();
+        assert_eq!(read.mipmap_count(), 2);
+    }
+
+    #[test]
+    fn partial_cubemap_reader_accepts_metadata() {
+        let image = image(true);
+        let file = file_with_metadata(&image);
+        let cubemap = ScratchImage::from_partial_cubemap_reader(&mut file.as_slice(), &Default::default()).unwrap();
+        assert_eq!(cubemap.faces.len(), 6);
+        assert_eq!(cubemap.layers.as_slice(), image.as_slice());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_reader_accepts_metadata() {
+        let image = image(false);
+        let file = file_with_metadata(&image);
+        let header_size = std::mem::size_of::<DirectDrawHeader>();
+        let mut compressed = file[..header_size].to_vec();
+        compressed.extend(zstd::stream::encode_all(&file[header_size..], 0).unwrap());
+        assert_eq!(
+            ScratchImage::from_zstd_reader(&mut compressed.as_slice()).unwrap(),
+            image
+        );
+    }
+
+    #[test]
+    fn other_trailing_data_is_rejected() {
+        let image = image(false);
+        let mut file = file_with_metadata(&image);
+        file.push(0);
+        assert!(matches!(
+            ScratchImage::from_reader(&mut file.as_slice()),
+            Err(Error::BadDataSize { .. })
+        ));
+        assert!(ScratchImage::from_reader_lenient(&mut file.as_slice()).is_err());
+
+        let options = ParseOptions {
+            allow_trailing_data: true,
+            ..Default::default()
+        };
+        assert!(ScratchImage::from_reader_with(&mut file.as_slice(), &options).is_ok());
+    }
+}
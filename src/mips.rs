@@ -0,0 +1,120 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Mip chain generation with a 2x2 box filter, every level is filtered from the previous one
+
+use crate::decode::{linear_to_srgb, srgb_to_linear};
+use crate::encode::can_encode;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MipOptions {
+    // Filter in linear space and store sRGB values. The default is whether the image format is sRGB: set it for
+    // images that hold sRGB values in a float or UNORM format that will be converted to sRGB later.
+    pub srgb: Option<bool>,
+}
+
+// Halves the size, the last row and column of odd sizes are repeated
+pub(crate) fn downsample(texels: &[[f32; 4]], width: usize, height: usize) -> Vec<[f32; 4]> {
+    let (mip_width, mip_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut mip = vec![[0.0; 4]; mip_width * mip_height];
+
+    for y in 0..mip_height {
+        for x in 0..mip_width {
+            let (x0, x1) = ((x * 2).min(width - 1), (x * 2 + 1).min(width - 1));
+            let (y0, y1) = ((y * 2).min(height - 1), (y * 2 + 1).min(height - 1));
+            let value = &mut mip[y * mip_width + x];
+            for &(sx, sy) in &[(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+                for channel in 0..4 {
+                    value[channel] += texels[sy * width + sx][channel] / 4.0;
+                }
+            }
+        }
+    }
+    mip
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // New image with `mipmap_count` mips filtered from the first mip of every layer
+    pub fn generate_mips(&self, mipmap_count: u32) -> Result<ScratchImage> {
+        self.generate_mips_with(mipmap_count, &MipOptions::default())
+    }
+
+    pub fn generate_mips_with(&self, mipmap_count: u32, options: &MipOptions) -> Result<ScratchImage> {
+        if self.image_depth() > 1 {
+            return Err(Error::NotImplementedYet("Mip generation for volume textures"));
+        }
+        if !can_encode(self.dxgi_format()) {
+            return Err(Error::UnsupportedFormat(self.dxgi_format()));
+        }
+
+        let mut desc = self.desc();
+        desc.mipmap_count = mipmap_count;
+        let mut image = ScratchImage::with_desc(&desc)?;
+        if let Some(alpha_mode) = self.alpha_mode() {
+            image.set_alpha_mode(alpha_mode);
+        }
+        let is_srgb = options.srgb.unwrap_or_else(|| is_srgb(self.dxgi_format()));
+
+        let convert = |texels: &mut Vec<[f32; 4]>, transfer: fn(f32) -> f32| {
+            if is_srgb {
+                for texel in texels.iter_mut() {
+                    for value in texel.iter_mut().take(3) {
+                        *value = transfer(*value);
+                    }
+                }
+            }
+        };
+
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            // The first mip is copied as is, so compressed images don't lose quality
+            let source = layout::subresource_range(&self.dds_header, 0, layer).ok_or(Error::BadSubresource)?;
+            let target = layout::subresource_range(&image.dds_header, 0, layer).ok_or(Error::BadSubresource)?;
+            image.dds_data[target].copy_from_slice(&self.as_slice()[source]);
+
+            let mut texels = self.decode_subresource(0, layer)?;
+            convert(&mut texels, srgb_to_linear);
+
+            let (mut width, mut height) = (self.image_width() as usize, self.image_height() as usize);
+            for mip in 1..mipmap_count {
+                texels = downsample(&texels, width, height);
+                width = (width / 2).max(1);
+                height = (height / 2).max(1);
+
+                let mut stored = texels.clone();
+                convert(&mut stored, linear_to_srgb);
+                image.encode_subresource(mip, layer, &stored)?;
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+impl ScratchImage {
+    // Keeps the first `mipmap_count` mips of every layer, the header is updated to match
+    pub fn set_mipmap_count(&mut self, mipmap_count: u32) -> Result<()> {
+        if mipmap_count == 0 || mipmap_count > layout::mip_count(&self.dds_header) {
+            return Err(Error::BadImageDescription(
+                "Mipmap count must be between 1 and the current mipmap count",
+            ));
+        }
+
+        let header = layout::mip_chain_header(&self.dds_header, 0, mipmap_count);
+        let layer_size = layout::layer_size(&header);
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            let source = layout::subresource_range(&self.dds_header, 0, layer).ok_or(Error::BadSubresource)?;
+            self.dds_data
+                .copy_within(source.start..source.start + layer_size, layer as usize * layer_size);
+        }
+
+        self.dds_data.truncate(layout::data_size(&header));
+        self.dds_header = header;
+        Ok(())
+    }
+}
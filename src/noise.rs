@@ -0,0 +1,221 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Noise textures. Every channel is an independent noise in [0, 1] derived from the seed, so a single channel format
+// gets a single noise and RGBA formats get four. Every mip is generated at its own size, noise is not filtered.
+
+use crate::builder::ImageDesc;
+use crate::scratch_image::*;
+
+// SplitMix64 finalizer
+fn hash(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+fn hash_all(seed: u64, values: &[u64]) -> u64 {
+    values.iter().fold(hash(seed), |state, &value| hash(state ^ value))
+}
+
+// Uniform in [0, 1)
+fn unit_float(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn check_noise_desc(desc: &ImageDesc) -> Result<()> {
+    if desc.depth > 1 {
+        return Err(Error::NotImplementedYet("Noise volumes"));
+    }
+    Ok(())
+}
+
+// Ranks of the texels of a toroidal blue noise pattern, computed with the void-and-cluster method.
+// The cost is quadratic in the texel count, sizes above 256x256 are slow.
+#[doc = "https://doi.org/10.1117/12.152707"]
+pub fn void_and_cluster(width: u32, height: u32, seed: u64) -> Vec<u32> {
+    let (width, height) = (width as usize, height as usize);
+    let texel_count = width * height;
+    if texel_count == 0 {
+        return Vec::new();
+    }
+    const SIGMA: f32 = 1.5;
+    let radius = 4.min((width - 1) / 2).min((height - 1) / 2) as isize;
+
+    let mut kernel = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let weight = (-((dx * dx + dy * dy) as f32) / (2.0 * SIGMA * SIGMA)).exp();
+            kernel.push((dx, dy, weight));
+        }
+    }
+
+    // Energy of every texel, the sum of the kernel over the set texels
+    let update = |energy: &mut [f32], index: usize, sign: f32| {
+        let (x, y) = ((index % width) as isize, (index / width) as isize);
+        for &(dx, dy, weight) in &kernel {
+            let nx = (x + dx).rem_euclid(width as isize) as usize;
+            let ny = (y + dy).rem_euclid(height as isize) as usize;
+            energy[ny * width + nx] += sign * weight;
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f32]| {
+        (0..texel_count)
+            .filter(|&index| pattern[index])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+    let largest_void = |pattern: &[bool], energy: &[f32]| {
+        (0..texel_count)
+            .filter(|&index| !pattern[index])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+
+    // Random initial pattern with a tenth of the texels set
+    let initial_count = (texel_count / 10).max(1);
+    let mut pattern = vec![false; texel_count];
+    let mut energy = vec![0.0f32; texel_count];
+    let mut set_count = 0;
+    let mut counter = 0;
+    while set_count < initial_count {
+        let index = (hash_all(seed, &[counter]) % texel_count as u64) as usize;
+        counter += 1;
+        if !pattern[index] {
+            pattern[index] = true;
+            update(&mut energy, index, 1.0);
+            set_count += 1;
+        }
+    }
+
+    // Move texels from the tightest cluster to the largest void until that doesn't change anything
+    for _ in 0..texel_count {
+        let cluster = tightest_cluster(&pattern, &energy).unwrap();
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+
+        let void = largest_void(&pattern, &energy).unwrap();
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0u32; texel_count];
+
+    // Texels of the initial pattern are ranked by removing the tightest clusters
+    let (mut removed, mut removed_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&removed, &removed_energy).unwrap();
+        removed[cluster] = false;
+        update(&mut removed_energy, cluster, -1.0);
+        ranks[cluster] = rank as u32;
+    }
+
+    // The remaining texels are ranked by filling the largest voids
+    for rank in initial_count..texel_count {
+        let void = largest_void(&pattern, &energy).unwrap();
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        ranks[void] = rank as u32;
+    }
+
+    ranks
+}
+
+// Gradient noise with a lattice that repeats every `period` cells in every dimension, in about [-1, 1]
+fn perlin(x: f32, y: f32, z: f32, period: u32, seed: u64) -> f32 {
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let cell = [x.floor(), y.floor(), z.floor()];
+    let offset = [x - cell[0], y - cell[1], z - cell[2]];
+
+    let gradient = |corner: [i64; 3]| {
+        let wrapped = corner.map(|value| value.rem_euclid(period as i64) as u64);
+        // One of the 12 edge directions of a cube
+        match hash_all(seed, &wrapped) % 12 {
+            0 => [1.0, 1.0, 0.0],
+            1 => [-1.0, 1.0, 0.0],
+            2 => [1.0, -1.0, 0.0],
+            3 => [-1.0, -1.0, 0.0],
+            4 => [1.0, 0.0, 1.0],
+            5 => [-1.0, 0.0, 1.0],
+            6 => [1.0, 0.0, -1.0],
+            7 => [-1.0, 0.0, -1.0],
+            8 => [0.0, 1.0, 1.0],
+            9 => [0.0, -1.0, 1.0],
+            10 => [0.0, 1.0, -1.0],
+            _ => [0.0, -1.0, -1.0],
+        }
+    };
+
+    let mut corners = [0.0f32; 8];
+    for (index, value) in corners.iter_mut().enumerate() {
+        let bits = [index & 1, (index >> 1) & 1, (index >> 2) & 1];
+        let corner = [0, 1, 2].map(|axis| cell[axis] as i64 + bits[axis] as i64);
+        let gradient = gradient(corner);
+        *value = (0..3)
+            .map(|axis| gradient[axis] * (offset[axis] - bits[axis] as f32))
+            .sum();
+    }
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let (u, v, w) = (fade(offset[0]), fade(offset[1]), fade(offset[2]));
+    let x0 = [lerp(corners[0], corners[1], u), lerp(corners[2], corners[3], u)];
+    let x1 = [lerp(corners[4], corners[5], u), lerp(corners[6], corners[7], u)];
+    lerp(lerp(x0[0], x0[1], v), lerp(x1[0], x1[1], v), w)
+}
+
+impl ScratchImage {
+    pub fn white_noise(desc: &ImageDesc, seed: u64) -> Result<ScratchImage> {
+        check_noise_desc(desc)?;
+        Self::from_pattern(desc, |mip, x, y, _, _| {
+            [0u64, 1, 2, 3].map(|channel| unit_float(hash_all(seed, &[mip as u64, x as u64, y as u64, channel])))
+        })
+    }
+
+    // Tileable blue noise, see `void_and_cluster`. Every value occurs about the same number of times.
+    pub fn blue_noise(desc: &ImageDesc, seed: u64) -> Result<ScratchImage> {
+        check_noise_desc(desc)?;
+        let mut ranks: Option<(u32, Vec<Vec<u32>>)> = None;
+        Self::from_pattern(desc, |mip, x, y, width, height| {
+            if ranks.as_ref().map(|(ranks_mip, _)| *ranks_mip) != Some(mip) {
+                let channels = (0..4)
+                    .map(|channel| void_and_cluster(width, height, hash_all(seed, &[mip as u64, channel])))
+                    .collect();
+                ranks = Some((mip, channels));
+            }
+            let channels = &ranks.as_ref().unwrap().1;
+            let index = (y * width + x) as usize;
+            let texel_count = (width * height) as f32;
+            [0, 1, 2, 3].map(|channel| (channels[channel][index] as f32 + 0.5) / texel_count)
+        })
+    }
+
+    // Tileable gradient noise with `period` lattice cells across the image and `octaves` layers of detail, every
+    // octave has twice the frequency and half the amplitude of the previous one
+    pub fn perlin_noise(desc: &ImageDesc, period: u32, octaves: u32, seed: u64) -> Result<ScratchImage> {
+        check_noise_desc(desc)?;
+        if period == 0 || !(1..=16).contains(&octaves) {
+            return Err(Error::BadImageDescription(
+                "Noise period must not be zero, octave count must be between 1 and 16",
+            ));
+        }
+
+        Self::from_pattern(desc, |_, x, y, width, height| {
+            let (u, v) = ((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32);
+            [0u64, 1, 2, 3].map(|channel| {
+                let (mut value, mut amplitude, mut total) = (0.0, 1.0, 0.0);
+                for octave in 0..octaves {
+                    let period = period << octave;
+                    let (px, py) = (u * period as f32, v * period as f32);
+                    value += amplitude * perlin(px, py, 0.0, period, hash_all(seed, &[channel, octave as u64]));
+                    total += amplitude;
+                    amplitude *= 0.5;
+                }
+                (0.5 + 0.5 * value / total).clamp(0.0, 1.0)
+            })
+        })
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Tangent space normal maps. Normals have X to the right and Y down the image, as in the DirectX convention.
+// Formats that can store negative values hold the normal as is, other formats hold it biased to n * 0.5 + 0.5.
+
+use crate::blocks::{Bc4Block, Bc5Block};
+use crate::channels::stored_channels;
+use crate::dds::*;
+use crate::encode::can_encode;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+
+fn encode_normal(normal: [f32; 3], dxgi_format: u32) -> [f32; 3] {
+    match is_signed(dxgi_format) {
+        true => normal,
+        false => normal.map(|value| value * 0.5 + 0.5),
+    }
+}
+
+// Two channel formats (e.g. BC5, R8G8) store X and Y, Z is reconstructed
+fn stores_z(dxgi_format: u32) -> bool {
+    stored_channels(dxgi_format).is_some_and(|channels| channels.contains(&2))
+}
+
+// Unit length normal
+fn decode_normal(texel: &[f32; 4], dxgi_format: u32) -> [f32; 3] {
+    let [x, y, z] = match is_signed(dxgi_format) {
+        true => [texel[0], texel[1], texel[2]],
+        false => [texel[0], texel[1], texel[2]].map(|value| value * 2.0 - 1.0),
+    };
+    let z = match stores_z(dxgi_format) {
+        true => z,
+        false => (1.0 - x * x - y * y).max(0.0).sqrt(),
+    };
+
+    let length = (x * x + y * y + z * z).sqrt();
+    match length > 0.0 {
+        true => [x / length, y / length, z / length],
+        false => [0.0, 0.0, 1.0],
+    }
+}
+
+// Negates the values of a BC4 block. The endpoints are swapped to keep the interpolation mode, so the indices of the
+// interpolated values are reversed, and the 0 and 1 (or -1 and 1) values of the 6 value mode are swapped.
+// Signed endpoints of -128 are clamped to -127 first, the decoder compares them after clamping to choose the mode.
+fn negate_bc4(block: &mut Bc4Block, is_signed: bool) {
+    let clamp = |value: u8| match is_signed {
+        true => (value as i8).max(-127) as u8,
+        false => value,
+    };
+    let negate = |value: u8| match is_signed {
+        true => (value as i8).wrapping_neg() as u8,
+        false => 255 - value,
+    };
+    let [e0, e1] = block.endpoints().map(clamp);
+    block.set_endpoints([negate(e1), negate(e0)]);
+
+    let [e0, e1] = block.endpoints();
+    let is_8_value = match is_signed {
+        true => e0 as i8 > e1 as i8,
+        false => e0 > e1,
+    };
+    for texel in 0..16 {
+        let index = match (block.index(texel), is_8_value) {
+            // Equal endpoints, all interpolated values are the endpoint
+            (0..=5, false) if e0 == e1 => 0,
+            (0, _) => 1,
+            (1, _) => 0,
+            (index, true) => 9 - index,
+            (index @ 2..=5, false) => 7 - index,
+            (index, false) => 13 - index,
+        };
+        block.set_index(texel, index);
+    }
+}
+
+// Normals of a height field with the Sobel operator, slopes are in height units per texel
+fn sobel_normals(heights: &[f32], width: usize, height: usize, strength: f32, wrap: bool) -> Vec<[f32; 3]> {
+    let at = |x: isize, y: isize| {
+        let (x, y) = match wrap {
+            true => (x.rem_euclid(width as isize), y.rem_euclid(height as isize)),
+            false => (x.clamp(0, width as isize - 1), y.clamp(0, height as isize - 1)),
+        };
+        heights[y as usize * width + x as usize]
+    };
+
+    let mut normals = Vec::with_capacity(width * height);
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let dx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            let dy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+            let normal = [-dx * strength / 8.0, -dy * strength / 8.0, 1.0];
+            let length = normal.iter().map(|value| value * value).sum::<f32>().sqrt();
+            normals.push(normal.map(|value| value / length));
+        }
+    }
+    normals
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Normal map of the heights in the red channel, for every mip and layer. `strength` scales the slopes, `wrap`
+    // wraps the filter around the edges for tiling textures. The heights are kept in alpha.
+    pub fn height_to_normal(&self, strength: f32, wrap: bool, dxgi_format: u32) -> Result<ScratchImage> {
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let (width, height) = layout::mip_extent(&self.dds_header, mip);
+                let heights: Vec<f32> = self
+                    .decode_subresource(mip, layer)?
+                    .iter()
+                    .map(|texel| texel[0])
+                    .collect();
+                let normals: Vec<[f32; 3]> = heights
+                    .chunks_exact(width as usize * height as usize)
+                    .flat_map(|slice| sobel_normals(slice, width as usize, height as usize, strength, wrap))
+                    .collect();
+
+                let texels: Vec<[f32; 4]> = normals
+                    .iter()
+                    .zip(heights.iter())
+                    .map(|(normal, &height)| {
+                        let [x, y, z] = encode_normal(*normal, dxgi_format);
+                        [x, y, z, height]
+                    })
+                    .collect();
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    // Converts between the DirectX (Y down) and OpenGL (Y up) conventions by negating Y. BC5 blocks are patched in
+    // place, other block compressed formats are not supported. Uncompressed formats store values that can be
+    // negated exactly.
+    pub fn flip_normal_green(&mut self) -> Result<()> {
+        let dxgi_format = self.dxgi_format();
+        let is_signed = is_signed(typed_format(dxgi_format));
+        if is_block_compressed(dxgi_format) {
+            if !matches!(
+                dxgi_format,
+                DXGI_FORMAT_BC5_TYPELESS | DXGI_FORMAT_BC5_UNORM | DXGI_FORMAT_BC5_SNORM
+            ) {
+                return Err(Error::UnsupportedFormat(dxgi_format));
+            }
+            for layer in 0..layout::layer_count(&self.dds_header) {
+                for mip in 0..layout::mip_count(&self.dds_header) {
+                    for block in self.as_blocks_mut::<Bc5Block>(mip, layer)? {
+                        negate_bc4(&mut block.green, is_signed);
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        if !stored_channels(dxgi_format).is_some_and(|channels| channels.contains(&1)) || !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let mut texels = self.decode_subresource(mip, layer)?;
+                for texel in texels.iter_mut() {
+                    texel[1] = match is_signed {
+                        true => -texel[1],
+                        false => 1.0 - texel[1],
+                    };
+                }
+                self.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Toksvig style specular antialiasing: the roughness in `roughness_channel` of every mip and layer is widened by
+    // the variance of the normals of `normal_map` that the texel covers. Roughness is perceptual (alpha is roughness
+    // squared), the variance comes from a von Mises-Fisher fit of the average normal length, as in "Crafting a
+    // Next-Gen Material Pipeline for The Order: 1886". Only the first mip of the normal map is used, its size can be
+    // different from the roughness size.
+    pub fn bake_normal_variance<T: Storage>(
+        &mut self,
+        normal_map: &ScratchImage<T>,
+        roughness_channel: usize,
+    ) -> Result<()> {
+        if roughness_channel > 3 {
+            return Err(Error::BadImageDescription("Channel index must be between 0 and 3"));
+        }
+        if normal_map.layer_count() != self.layer_count() {
+            return Err(Error::IncompatibleImages(
+                "Normal and roughness maps have different layer counts",
+            ));
+        }
+
+        let (normal_width, normal_height) = (normal_map.image_width() as usize, normal_map.image_height() as usize);
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            let normals: Vec<[f32; 3]> = normal_map
+                .decode_subresource(0, layer)?
+                .iter()
+                .map(|texel| decode_normal(texel, normal_map.dxgi_format()))
+                .collect();
+
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let (width, height) = layout::mip_extent(&self.dds_header, mip);
+                let (width, height) = (width as usize, height as usize);
+                let mut texels = self.decode_subresource(mip, layer)?;
+
+                for y in 0..height {
+                    // Normal texels covered by the roughness texel, at least one
+                    let y0 = y * normal_height / height;
+                    let y1 = ((y + 1) * normal_height / height).max(y0 + 1);
+                    for x in 0..width {
+                        let x0 = x * normal_width / width;
+                        let x1 = ((x + 1) * normal_width / width).max(x0 + 1);
+
+                        let mut sum = [0.0f32; 3];
+                        for normal in
+                            (y0..y1).flat_map(|ny| normals[ny * normal_width + x0..ny * normal_width + x1].iter())
+                        {
+                            for axis in 0..3 {
+                                sum[axis] += normal[axis];
+                            }
+                        }
+                        let count = ((y1 - y0) * (x1 - x0)) as f32;
+                        let average_length = sum.iter().map(|value| value * value).sum::<f32>().sqrt() / count;
+                        if average_length >= 1.0 - 1e-6 {
+                            continue;
+                        }
+
+                        let r = average_length.max(1e-6);
+                        let kappa = (3.0 * r - r * r * r) / (1.0 - r * r);
+                        let roughness = &mut texels[y * width + x][roughness_channel];
+                        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+                        let alpha = (alpha * alpha + 2.0 / kappa).sqrt().min(1.0);
+                        *roughness = alpha.sqrt();
+                    }
+                }
+                self.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(())
+    }
+}
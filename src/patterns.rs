@@ -0,0 +1,122 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Procedural test images. Patterns are generated in f32 RGBA for every mip and layer and encoded to the format of
+// the image description, which can be any format that `encode_surface` supports.
+
+use crate::builder::ImageDesc;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientDirection {
+    // Left to right
+    Horizontal,
+    // Top to bottom
+    Vertical,
+    // Top left to bottom right
+    Diagonal,
+}
+
+// Colors of the mips in `mip_debug_colors` and `tint_mips`, repeated for longer mip chains
+pub const MIP_DEBUG_COLORS: [[f32; 4]; 8] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 1.0],
+    [1.0, 1.0, 0.0, 1.0],
+    [1.0, 0.0, 1.0, 1.0],
+    [0.0, 1.0, 1.0, 1.0],
+    [1.0, 0.5, 0.0, 1.0],
+    [1.0, 1.0, 1.0, 1.0],
+];
+
+impl ScratchImage {
+    // `texel(mip, x, y, width, height)` is called for every texel of every mip, all layers and depth slices are the
+    // same
+    pub(crate) fn from_pattern<F>(desc: &ImageDesc, mut texel: F) -> Result<ScratchImage>
+    where
+        F: FnMut(u32, u32, u32, u32, u32) -> [f32; 4],
+    {
+        let mut image = ScratchImage::with_desc(desc)?;
+        for mip in 0..layout::mip_count(&image.dds_header) {
+            let (width, height) = layout::mip_extent(&image.dds_header, mip);
+            let texels: Vec<[f32; 4]> = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| texel(mip, x, y, width, height))
+                .collect::<Vec<_>>()
+                .repeat(layout::mip_depth(&image.dds_header, mip) as usize);
+            for layer in 0..layout::layer_count(&image.dds_header) {
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+
+    // Squares of `cell_size` texels in the top mip, cells are halved in every smaller mip down to a single texel
+    pub fn checkerboard(desc: &ImageDesc, cell_size: u32, colors: [[f32; 4]; 2]) -> Result<ScratchImage> {
+        if cell_size == 0 {
+            return Err(Error::BadImageDescription("Checkerboard cell size must not be zero"));
+        }
+        Self::from_pattern(desc, |mip, x, y, _, _| {
+            let cell_size = (cell_size >> mip).max(1);
+            colors[((x / cell_size + y / cell_size) % 2) as usize]
+        })
+    }
+
+    // Linear interpolation from `start` at the first texel center to `end` at the last one
+    pub fn gradient(
+        desc: &ImageDesc,
+        start: [f32; 4],
+        end: [f32; 4],
+        direction: GradientDirection,
+    ) -> Result<ScratchImage> {
+        Self::from_pattern(desc, |_, x, y, width, height| {
+            let fraction = |position: u32, size: u32| match size {
+                1 => 0.0,
+                size => position as f32 / (size - 1) as f32,
+            };
+            let t = match direction {
+                GradientDirection::Horizontal => fraction(x, width),
+                GradientDirection::Vertical => fraction(y, height),
+                GradientDirection::Diagonal => (fraction(x, width) + fraction(y, height)) / 2.0,
+            };
+
+            let mut texel = [0.0; 4];
+            for channel in 0..4 {
+                texel[channel] = start[channel] + (end[channel] - start[channel]) * t;
+            }
+            texel
+        })
+    }
+
+    // Every mip is filled with a color from MIP_DEBUG_COLORS, so the mip selected by a renderer is visible
+    pub fn mip_debug_colors(desc: &ImageDesc) -> Result<ScratchImage> {
+        Self::from_pattern(desc, |mip, _, _, _, _| {
+            MIP_DEBUG_COLORS[mip as usize % MIP_DEBUG_COLORS.len()]
+        })
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Copy of the image with the color of every mip blended towards its MIP_DEBUG_COLORS entry, alpha is kept.
+    // `strength` 0 keeps the image, 1 replaces the colors.
+    pub fn tint_mips(&self, strength: f32) -> Result<ScratchImage> {
+        let mut image = ScratchImage::with_desc(&self.desc())?;
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let tint = MIP_DEBUG_COLORS[mip as usize % MIP_DEBUG_COLORS.len()];
+                let mut texels = self.decode_subresource(mip, layer)?;
+                for texel in texels.iter_mut() {
+                    for channel in 0..3 {
+                        texel[channel] += (tint[channel] - texel[channel]) * strength;
+                    }
+                }
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+}
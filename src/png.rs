@@ -0,0 +1,738 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// PNG import and export for the conversion tools. All color types, bit depths and interlacing are supported, images
+// are read as R8G8B8A8_UNORM, or R16G16B16A16_UNORM for 16-bit images. Ancillary chunks other than tRNS are ignored,
+// so values are stored as in the file, without gamma or color space conversions.
+// Exported subresources are 8-bit RGBA, decoded values are clamped to [0, 1] and written as is.
+
+use crate::builder::ImageDesc;
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Reads deflate streams LSB first
+struct InflateInput<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u64,
+    bit_count: u32,
+}
+
+impl<'a> InflateInput<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(Error::BadPng("Compressed data is truncated"))?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u64) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = (self.bit_buffer & ((1 << count) - 1)) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+// Canonical Huffman code: number of codes of every length and the symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(Error::BadPng("Huffman code is over-subscribed"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut InflateInput) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= input.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::BadPng("Bad Huffman code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[doc = "https://www.rfc-editor.org/rfc/rfc1951"]
+fn inflate_block(
+    input: &mut InflateInput,
+    lengths: &Huffman,
+    distances: &Huffman,
+    output: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<()> {
+    loop {
+        let symbol = lengths.decode(input)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= 29 {
+                    return Err(Error::BadPng("Bad length code"));
+                }
+                let length = LENGTH_BASE[symbol] as usize + input.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = distances.decode(input)? as usize;
+                if symbol >= 30 {
+                    return Err(Error::BadPng("Bad distance code"));
+                }
+                let distance = DISTANCE_BASE[symbol] as usize + input.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(Error::BadPng("Distance is too far back"));
+                }
+
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+        if output.len() > max_size {
+            return Err(Error::BadPng("Decompressed data is larger than the image"));
+        }
+    }
+}
+
+fn dynamic_codes(input: &mut InflateInput) -> Result<(Huffman, Huffman)> {
+    let length_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = input.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; length_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(input)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 if index > 0 => (lengths[index - 1], 3 + input.bits(2)? as usize),
+            17 => (0, 3 + input.bits(3)? as usize),
+            18 => (0, 11 + input.bits(7)? as usize),
+            _ => return Err(Error::BadPng("Bad code length repeat")),
+        };
+        if index + repeat > lengths.len() {
+            return Err(Error::BadPng("Too many code lengths"));
+        }
+        lengths[index..index + repeat]
+            .iter_mut()
+            .for_each(|length| *length = value);
+        index += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..length_count])?,
+        Huffman::new(&lengths[length_count..])?,
+    ))
+}
+
+#[doc = "https://www.rfc-editor.org/rfc/rfc1950"]
+fn zlib_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    if data.len() < 6
+        || data[0] & 0x0f != 8
+        || !(data[0] as u32 * 256 + data[1] as u32).is_multiple_of(31)
+        || data[1] & 0x20 != 0
+    {
+        return Err(Error::BadPng("Bad zlib header"));
+    }
+
+    let mut input = InflateInput {
+        data: &data[2..],
+        position: 0,
+        bit_buffer: 0,
+        bit_count: 0,
+    };
+    let mut output = Vec::new();
+
+    loop {
+        let is_last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => {
+                input.align_to_byte();
+                let header = input
+                    .data
+                    .get(input.position..input.position + 4)
+                    .ok_or(Error::BadPng("Compressed data is truncated"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if length != !u16::from_le_bytes([header[2], header[3]]) as usize {
+                    return Err(Error::BadPng("Bad stored block length"));
+                }
+                input.position += 4;
+                let stored = input
+                    .data
+                    .get(input.position..input.position + length)
+                    .ok_or(Error::BadPng("Compressed data is truncated"))?;
+                output.extend_from_slice(stored);
+                input.position += length;
+                if output.len() > max_size {
+                    return Err(Error::BadPng("Decompressed data is larger than the image"));
+                }
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].iter_mut().for_each(|length| *length = 8);
+                lengths[144..256].iter_mut().for_each(|length| *length = 9);
+                lengths[256..280].iter_mut().for_each(|length| *length = 7);
+                lengths[280..].iter_mut().for_each(|length| *length = 8);
+                let lengths = Huffman::new(&lengths)?;
+                let distances = Huffman::new(&[5; 30])?;
+                inflate_block(&mut input, &lengths, &distances, &mut output, max_size)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut input)?;
+                inflate_block(&mut input, &lengths, &distances, &mut output, max_size)?;
+            }
+            _ => return Err(Error::BadPng("Bad block type")),
+        }
+        if is_last {
+            break;
+        }
+    }
+
+    input.align_to_byte();
+    let checksum = input
+        .data
+        .get(input.position..input.position + 4)
+        .ok_or(Error::BadPng("Compressed data is truncated"))?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&output) {
+        return Err(Error::BadPng("Bad zlib checksum"));
+    }
+
+    Ok(output)
+}
+
+// Writes deflate streams LSB first
+struct DeflateOutput {
+    data: Vec<u8>,
+    bit_buffer: u64,
+    bit_count: u32,
+}
+
+impl DeflateOutput {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.data.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are stored starting from the most significant bit
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn fixed_symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.data.push(self.bit_buffer as u8);
+        }
+        self.data
+    }
+}
+
+// Index of the last base that is not larger than the value
+fn base_index(bases: &[u16], value: usize) -> usize {
+    bases.iter().rposition(|&base| base as usize <= value).unwrap_or(0)
+}
+
+// A single block with the fixed Huffman codes, matches are found with a hash of the next 3 bytes
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    const WINDOW_SIZE: usize = 32768;
+    const MAX_MATCH: usize = 258;
+
+    let mut output = DeflateOutput {
+        data: vec![0x78, 0x01],
+        bit_buffer: 0,
+        bit_count: 0,
+    };
+    output.bits(1, 1);
+    output.bits(1, 2);
+
+    let hash = |position: usize| {
+        let value = u32::from_le_bytes([data[position], data[position + 1], data[position + 2], 0]);
+        (value.wrapping_mul(0x9e37_79b1) >> 17) as usize
+    };
+    let mut last_positions = vec![usize::MAX; 1 << 15];
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut match_length = 0;
+        let mut distance = 0;
+        if position + 3 <= data.len() {
+            let hash = hash(position);
+            let candidate = last_positions[hash];
+            last_positions[hash] = position;
+            if candidate != usize::MAX && position - candidate <= WINDOW_SIZE {
+                let max_length = MAX_MATCH.min(data.len() - position);
+                match_length = (0..max_length)
+                    .find(|&i| data[candidate + i] != data[position + i])
+                    .unwrap_or(max_length);
+                distance = position - candidate;
+            }
+        }
+
+        if match_length >= 3 {
+            let index = base_index(&LENGTH_BASE, match_length);
+            output.fixed_symbol(257 + index as u32);
+            output.bits(
+                (match_length - LENGTH_BASE[index] as usize) as u32,
+                LENGTH_EXTRA[index] as u32,
+            );
+
+            let index = base_index(&DISTANCE_BASE, distance);
+            output.code(index as u32, 5);
+            output.bits(
+                (distance - DISTANCE_BASE[index] as usize) as u32,
+                DISTANCE_EXTRA[index] as u32,
+            );
+
+            for skipped in position + 1..(position + match_length).min(data.len().saturating_sub(2)) {
+                last_positions[hash(skipped)] = skipped;
+            }
+            position += match_length;
+        } else {
+            output.fixed_symbol(data[position] as u32);
+            position += 1;
+        }
+    }
+    output.fixed_symbol(256);
+
+    let mut compressed = output.finish();
+    compressed.extend_from_slice(&adler32(data).to_be_bytes());
+    compressed
+}
+
+// Start and step of the Adam7 passes, (x, y, dx, dy)
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reverses the filter of every row in place, rows are preceded by the filter type
+fn unfilter(data: &mut [u8], row_size: usize, pixel_size: usize) -> Result<()> {
+    let mut previous = vec![0u8; row_size];
+    for row in data.chunks_mut(row_size + 1) {
+        let (filter, row) = row.split_at_mut(1);
+        for i in 0..row_size {
+            let left = if i >= pixel_size { row[i - pixel_size] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= pixel_size { previous[i - pixel_size] } else { 0 };
+            row[i] = row[i].wrapping_add(match filter[0] {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(Error::BadPng("Bad filter type")),
+            });
+        }
+        previous.copy_from_slice(row);
+    }
+    Ok(())
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u32,
+    color_type: u8,
+    is_interlaced: bool,
+}
+
+impl PngHeader {
+    fn channel_count(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn row_size(&self, width: usize) -> usize {
+        (width * self.channel_count() * self.bit_depth as usize).div_ceil(8)
+    }
+}
+
+impl ScratchImage {
+    pub fn from_png_file(path: &std::path::Path) -> Result<ScratchImage> {
+        let mut file = std::fs::File::open(path)?;
+        Self::from_png_reader(&mut std::io::BufReader::new(&mut file))
+    }
+
+    #[doc = "https://www.w3.org/TR/png/"]
+    pub fn from_png_reader<T: std::io::Read>(png_file: &mut T) -> Result<ScratchImage> {
+        let mut signature = [0u8; 8];
+        png_file.read_exact(&mut signature)?;
+        if signature != PNG_SIGNATURE {
+            return Err(Error::BadPng("Bad signature"));
+        }
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut transparency = Vec::new();
+        let mut compressed = Vec::new();
+
+        loop {
+            let mut chunk_header = [0u8; 8];
+            png_file.read_exact(&mut chunk_header)?;
+            let length = u32::from_be_bytes([chunk_header[0], chunk_header[1], chunk_header[2], chunk_header[3]]);
+            if length > i32::MAX as u32 {
+                return Err(Error::BadPng("Bad chunk length"));
+            }
+
+            // Chunk type, data and CRC
+            let mut chunk = chunk_header[4..].to_vec();
+            std::io::Read::read_to_end(&mut std::io::Read::take(&mut *png_file, length as u64 + 4), &mut chunk)?;
+            if chunk.len() != length as usize + 8 {
+                return Err(Error::BadPng("Chunk is truncated"));
+            }
+            let (chunk, crc) = chunk.split_at(length as usize + 4);
+            if crc32(chunk) != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+                return Err(Error::BadPng("Bad chunk CRC"));
+            }
+            let (chunk_type, data) = chunk.split_at(4);
+
+            match chunk_type {
+                b"IHDR" => {
+                    if data.len() != 13 {
+                        return Err(Error::BadPng("Bad IHDR chunk"));
+                    }
+                    let read_u32 = |offset: usize| {
+                        u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+                    };
+                    let (bit_depth, color_type) = (data[8] as u32, data[9]);
+                    let is_valid = match color_type {
+                        0 => [1, 2, 4, 8, 16].contains(&bit_depth),
+                        3 => [1, 2, 4, 8].contains(&bit_depth),
+                        2 | 4 | 6 => [8, 16].contains(&bit_depth),
+                        _ => false,
+                    };
+                    if !is_valid || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+                        return Err(Error::BadPng(
+                            "Unsupported bit depth, color type, compression or filter",
+                        ));
+                    }
+                    header = Some(PngHeader {
+                        width: read_u32(0) as usize,
+                        height: read_u32(4) as usize,
+                        bit_depth,
+                        color_type,
+                        is_interlaced: data[12] == 1,
+                    });
+                }
+                b"PLTE" => palette = data.to_vec(),
+                b"tRNS" => transparency = data.to_vec(),
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ if chunk_type[0] & 0x20 == 0 => return Err(Error::BadPng("Unknown critical chunk")),
+                _ => {}
+            }
+        }
+
+        let header = header.ok_or(Error::BadPng("IHDR chunk is missing"))?;
+        if header.color_type == 3 && palette.is_empty() {
+            return Err(Error::BadPng("PLTE chunk is missing"));
+        }
+
+        let is_16bit = header.bit_depth == 16;
+        let desc = ImageDesc {
+            width: header.width as u32,
+            height: header.height as u32,
+            dxgi_format: if is_16bit {
+                DXGI_FORMAT_R16G16B16A16_UNORM
+            } else {
+                DXGI_FORMAT_R8G8B8A8_UNORM
+            },
+            ..Default::default()
+        };
+        if header.width == 0
+            || header.height == 0
+            || header.width > u32::MAX as usize
+            || header.height > u32::MAX as usize
+        {
+            return Err(Error::BadPng("Bad image size"));
+        }
+        Limits::default().check_header(&desc.to_header())?;
+        let mut image = ScratchImage::with_desc(&desc)?;
+
+        let passes: Vec<_> = if header.is_interlaced {
+            ADAM7_PASSES
+                .iter()
+                .map(|&(x, y, dx, dy)| {
+                    let width = (header.width + dx - 1 - x) / dx;
+                    let height = (header.height + dy - 1 - y) / dy;
+                    (x, y, dx, dy, width, height)
+                })
+                .filter(|&(.., width, height)| width > 0 && height > 0)
+                .collect()
+        } else {
+            vec![(0, 0, 1, 1, header.width, header.height)]
+        };
+        let filtered_size = passes
+            .iter()
+            .map(|&(.., width, height)| (header.row_size(width) + 1) * height)
+            .sum();
+        let mut filtered = zlib_decompress(&compressed, filtered_size)?;
+        if filtered.len() != filtered_size {
+            return Err(Error::BadPng("Decompressed data size does not match the image"));
+        }
+
+        let channel_count = header.channel_count();
+        let pixel_size = (channel_count * header.bit_depth as usize / 8).max(1);
+        let max_value = (1u32 << header.bit_depth) - 1;
+        let texel_size = if is_16bit { 8 } else { 4 };
+        let image_width = header.width;
+        let data = image.as_slice_mut();
+
+        let mut offset = 0;
+        for &(x0, y0, dx, dy, width, height) in &passes {
+            let row_size = header.row_size(width);
+            let pass = &mut filtered[offset..offset + (row_size + 1) * height];
+            offset += pass.len();
+            unfilter(pass, row_size, pixel_size)?;
+
+            for (y, row) in pass.chunks(row_size + 1).enumerate() {
+                let row = &row[1..];
+                let sample = |index: usize| -> u32 {
+                    match header.bit_depth {
+                        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32,
+                        8 => row[index] as u32,
+                        bit_depth => {
+                            let bit = index * bit_depth as usize;
+                            ((row[bit / 8] >> (8 - bit_depth as usize - bit % 8)) as u32) & max_value
+                        }
+                    }
+                };
+
+                for x in 0..width {
+                    let mut samples = [0u32; 4];
+                    for (channel, value) in samples.iter_mut().enumerate().take(channel_count) {
+                        *value = sample(x * channel_count + channel);
+                    }
+                    let key = |channel: usize| {
+                        transparency
+                            .get(channel * 2..channel * 2 + 2)
+                            .map(|key| u16::from_be_bytes([key[0], key[1]]) as u32)
+                    };
+
+                    // RGBA with the bit depth of the image, palette entries are 8-bit
+                    let (rgba, depth_max) = match header.color_type {
+                        0 => {
+                            let alpha = if key(0) == Some(samples[0]) { 0 } else { max_value };
+                            ([samples[0], samples[0], samples[0], alpha], max_value)
+                        }
+                        2 => {
+                            let is_key = (0..3).all(|channel| key(channel) == Some(samples[channel]));
+                            let alpha = if is_key { 0 } else { max_value };
+                            ([samples[0], samples[1], samples[2], alpha], max_value)
+                        }
+                        3 => {
+                            let index = samples[0] as usize;
+                            let color = palette
+                                .get(index * 3..index * 3 + 3)
+                                .ok_or(Error::BadPng("Palette index is out of range"))?;
+                            let alpha = transparency.get(index).copied().unwrap_or(255);
+                            ([color[0] as u32, color[1] as u32, color[2] as u32, alpha as u32], 255)
+                        }
+                        4 => ([samples[0], samples[0], samples[0], samples[1]], max_value),
+                        _ => ([samples[0], samples[1], samples[2], samples[3]], max_value),
+                    };
+
+                    let texel_offset = ((y0 + y * dy) * image_width + x0 + x * dx) * texel_size;
+                    let texel = &mut data[texel_offset..texel_offset + texel_size];
+                    for (channel, value) in rgba.iter().enumerate() {
+                        if is_16bit {
+                            texel[channel * 2..channel * 2 + 2].copy_from_slice(&(*value as u16).to_le_bytes());
+                        } else {
+                            texel[channel] = ((value * 255 + depth_max / 2) / depth_max) as u8;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Writes a subresource as an 8-bit RGBA PNG, depth slices of volume textures are stacked vertically
+    pub fn write_subresource_png<T: std::io::Write>(&self, mip: u32, layer: u32, png_file: &mut T) -> Result<()> {
+        let texels = self.decode_subresource(mip, layer)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        write_png(
+            &texels,
+            width,
+            height * layout::mip_depth(&self.dds_header, mip),
+            png_file,
+        )
+    }
+}
+
+// Writes 8-bit RGBA texels in row-major order, values are clamped to [0, 1]
+pub fn write_png<T: std::io::Write>(texels: &[[f32; 4]], width: u32, height: u32, png_file: &mut T) -> Result<()> {
+    if width == 0 || height == 0 || texels.len() as u64 != width as u64 * height as u64 {
+        return Err(Error::BadImageDescription("Texel count does not match the image size"));
+    }
+
+    // Every row uses the Paeth filter
+    let row_size = width as usize * 4;
+    let mut filtered = Vec::with_capacity((row_size + 1) * height as usize);
+    let mut previous = vec![0u8; row_size];
+    for row in texels.chunks(width as usize) {
+        let row: Vec<u8> = row
+            .iter()
+            .flat_map(|texel| texel.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+            .collect();
+        filtered.push(4);
+        for i in 0..row_size {
+            let left = if i >= 4 { row[i - 4] } else { 0 };
+            let up_left = if i >= 4 { previous[i - 4] } else { 0 };
+            filtered.push(row[i].wrapping_sub(paeth(left, previous[i], up_left)));
+        }
+        previous = row;
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    png_file.write_all(&PNG_SIGNATURE)?;
+    for (chunk_type, data) in [
+        (b"IHDR", header),
+        (b"IDAT", zlib_compress(&filtered)),
+        (b"IEND", Vec::new()),
+    ] {
+        let mut chunk = chunk_type.to_vec();
+        chunk.extend_from_slice(&data);
+        png_file.write_all(&(data.len() as u32).to_be_bytes())?;
+        png_file.write_all(&chunk)?;
+        png_file.write_all(&crc32(&chunk).to_be_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Reading only the header of a file, for detecting, scanning and indexing many files without loading their payloads.
+// Zstd supercompressed files store the header as is, so they can be probed like regular DDS files.
+
+use crate::builder::ImageDesc;
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+
+// Cheap check that `bytes` start with a DDS header, before committing to a full parse. Needs at least the first
+// 128 bytes of the file, only the magic and the structure sizes are checked.
+pub fn sniff(bytes: &[u8]) -> bool {
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+    bytes.len() >= 128 && bytes.starts_with(b"DDS ") && read_u32(4) == 124 && read_u32(76) == 32
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImageInfo {
+    pub desc: ImageDesc,
+    // None if the header has an invalid alpha mode
+    pub alpha_mode: Option<AlphaMode>,
+    // Payload size in bytes, without the header
+    pub data_size: u64,
+    pub header: DirectDrawHeader,
+}
+
+impl ScratchImage {
+    pub fn probe<T: std::io::Read>(dds_file: &mut T) -> Result<ImageInfo> {
+        Self::probe_with(dds_file, &ParseOptions::default())
+    }
+
+    // The header is validated like `from_reader_with` does, the payload is not read
+    pub fn probe_with<T: std::io::Read>(dds_file: &mut T, options: &ParseOptions) -> Result<ImageInfo> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, options)?;
+        Self::validate_header(&dds_header)?;
+
+        Ok(ImageInfo {
+            desc: ImageDesc::from_header(&dds_header),
+            alpha_mode: AlphaMode::from_misc_flags2(dds_header.dxt10.misc_flags2),
+            data_size: layout::data_size(&dds_header) as u64,
+            header: dds_header,
+        })
+    }
+
+    pub fn probe_file(path: &std::path::Path) -> Result<ImageInfo> {
+        Self::probe(&mut std::fs::File::open(path)?)
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Header conventions of other DDS writers, so outputs can be compared byte for byte with theirs. Only the header
+// differs between profiles, the payload is always written as is.
+
+use crate::dds::*;
+use crate::format::*;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriterProfile {
+    // The header as stored in the image
+    Native,
+    // DirectXTex SaveToDDSFile with DDS_FLAGS_FORCE_DX10_EXT (texconv -dx10): DDSD_MIPMAPCOUNT is always set,
+    // volumes don't have DDSCAPS_COMPLEX, caps2 and depth are derived from the resource dimension, only the cubemap
+    // bit is kept in the misc flags and the reserved fields are zero, which drops flipbook and tool metadata
+    DirectXTex,
+}
+
+#[doc = "https://github.com/microsoft/DirectXTex/blob/main/DirectXTex/DirectXTexDDS.cpp"]
+fn directxtex_header(header: &DirectDrawHeader) -> DirectDrawHeader {
+    let mut header = *header;
+    let is_volume = header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D;
+    let is_cubemap = header.dxt10.misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+
+    header.flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT;
+    header.flags |= match is_block_compressed(header.dxt10.dxgi_format) {
+        true => DDSD_LINEARSIZE,
+        false => DDSD_PITCH,
+    };
+    header.caps = DDSCAPS_TEXTURE;
+    if header.mipmap_count > 1 {
+        header.caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+    if is_cubemap {
+        header.caps |= DDSCAPS_COMPLEX;
+    }
+    if is_volume {
+        header.flags |= DDSD_DEPTH;
+    }
+
+    // Derived from the DX10 header, not kept from the source
+    header.caps2 = if is_cubemap {
+        DDSCAPS2_CUBEMAP
+            | DDSCAPS2_CUBEMAP_POSITIVEX
+            | DDSCAPS2_CUBEMAP_NEGATIVEX
+            | DDSCAPS2_CUBEMAP_POSITIVEY
+            | DDSCAPS2_CUBEMAP_NEGATIVEY
+            | DDSCAPS2_CUBEMAP_POSITIVEZ
+            | DDSCAPS2_CUBEMAP_NEGATIVEZ
+    } else if is_volume {
+        DDSCAPS2_VOLUME
+    } else {
+        0
+    };
+    if !is_volume {
+        header.depth = 1;
+    }
+    if header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D {
+        header.height = 1;
+    }
+
+    header.reserved = [0; 11];
+    header.caps3 = 0;
+    header.caps4 = 0;
+    header.reserved2 = 0;
+    header.dxt10.misc_flag &= DDS_RESOURCE_MISC_TEXTURECUBE;
+    header
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn profile_header(&self, profile: WriterProfile) -> DirectDrawHeader {
+        match profile {
+            WriterProfile::Native => self.dds_header,
+            WriterProfile::DirectXTex => directxtex_header(&self.dds_header),
+        }
+    }
+
+    pub fn write_to_with<T: std::io::Write>(&self, file: &mut T, profile: WriterProfile) -> Result<()> {
+        write_header_and_data(file, &self.profile_header(profile), self.as_slice(), |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageDesc;
+    use crate::ResourceDimension;
+
+    fn image(width: u32, height: u32, depth: u32, is_cubemap: bool) -> ScratchImage {
+        ScratchImage::with_desc(&ImageDesc {
+            width,
+            height,
+            depth,
+            mipmap_count: 1,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            is_cubemap,
+            dimension: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn directxtex_caps2_and_depth() {
+        let mut cubemap = image(4, 4, 1, true);
+        cubemap.dds_header.caps2 = 0;
+        cubemap.dds_header.depth = 0;
+        let header = cubemap.profile_header(WriterProfile::DirectXTex);
+        assert_eq!(header.caps2, 0xfe00);
+        assert_eq!(header.depth, 1);
+        assert_ne!(header.caps & DDSCAPS_COMPLEX, 0);
+
+        let mut texture = image(4, 4, 1, false);
+        texture.dds_header.caps2 = DDSCAPS2_VOLUME;
+        texture.dds_header.depth = 7;
+        let header = texture.profile_header(WriterProfile::DirectXTex);
+        assert_eq!((header.caps2, header.depth), (0, 1));
+
+        let volume = image(4, 4, 4, false);
+        let header = volume.profile_header(WriterProfile::DirectXTex);
+        assert_eq!((header.caps2, header.depth), (DDSCAPS2_VOLUME, 4));
+        assert_ne!(header.flags & DDSD_DEPTH, 0);
+        assert_eq!(header.caps & DDSCAPS_COMPLEX, 0);
+    }
+
+    #[test]
+    fn directxtex_texture1d_height() {
+        let mut texture = image(8, 1, 1, false);
+        assert_eq!(texture.resource_dimension(), Some(ResourceDimension::Texture1D));
+        texture.dds_header.height = 0;
+        let header = texture.profile_header(WriterProfile::DirectXTex);
+        assert_eq!((header.height, header.depth), (1, 1));
+    }
+}
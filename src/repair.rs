@@ -0,0 +1,166 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Fixes for header bugs commonly found in files written by real-world exporters
+
+use crate::dds::*;
+use crate::format::*;
+use crate::layout;
+use crate::metadata::read_metadata_chunk;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HeaderField {
+    Flags,
+    PitchOrLinearSize,
+    MipmapCount,
+    Caps,
+    Caps2,
+    ResourceDimension,
+    ArraySize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Repair {
+    pub field: HeaderField,
+    pub old_value: u32,
+    pub new_value: u32,
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} changed from {:#x} to {:#x}",
+            self.field, self.old_value, self.new_value
+        )
+    }
+}
+
+fn repair_field(repairs: &mut Vec<Repair>, field: HeaderField, value: &mut u32, new_value: u32) {
+    if *value != new_value {
+        repairs.push(Repair {
+            field,
+            old_value: *value,
+            new_value,
+        });
+        *value = new_value;
+    }
+}
+
+// Rewrites header fields that are inconsistent with the image description, returns what was changed.
+// Headers with unknown formats are left as is.
+pub fn repair_header(header: &mut DirectDrawHeader) -> Vec<Repair> {
+    let mut repairs = Vec::new();
+
+    let dxgi_format = header.dxt10.dxgi_format;
+    if try_bits_per_pixel(dxgi_format).is_none() {
+        return repairs;
+    }
+
+    let mipmap_count = header.mipmap_count.max(1);
+    repair_field(
+        &mut repairs,
+        HeaderField::MipmapCount,
+        &mut header.mipmap_count,
+        mipmap_count,
+    );
+
+    let array_size = header.dxt10.array_size.max(1);
+    repair_field(
+        &mut repairs,
+        HeaderField::ArraySize,
+        &mut header.dxt10.array_size,
+        array_size,
+    );
+
+    let resource_dimension = match header.dxt10.resource_dimension {
+        D3D10_RESOURCE_DIMENSION_TEXTURE1D
+        | D3D10_RESOURCE_DIMENSION_TEXTURE2D
+        | D3D10_RESOURCE_DIMENSION_TEXTURE3D => header.dxt10.resource_dimension,
+        _ if header.depth > 1 => D3D10_RESOURCE_DIMENSION_TEXTURE3D,
+        _ => D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+    };
+    repair_field(
+        &mut repairs,
+        HeaderField::ResourceDimension,
+        &mut header.dxt10.resource_dimension,
+        resource_dimension,
+    );
+
+    let pitch_or_linear_size = layout::pitch_or_linear_size(header.width, header.height, dxgi_format);
+    repair_field(
+        &mut repairs,
+        HeaderField::PitchOrLinearSize,
+        &mut header.pitch_or_linear_size,
+        pitch_or_linear_size,
+    );
+
+    let is_cubemap = layout::face_count(header) == 6;
+    let is_volume = resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D;
+    let has_mips = mipmap_count > 1;
+
+    let mut flags = header.flags | DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+    if is_block_compressed(dxgi_format) {
+        flags = (flags | DDSD_LINEARSIZE) & !DDSD_PITCH;
+    } else {
+        flags = (flags | DDSD_PITCH) & !DDSD_LINEARSIZE;
+    }
+    if has_mips {
+        flags |= DDSD_MIPMAPCOUNT;
+    }
+    if is_volume {
+        flags |= DDSD_DEPTH;
+    }
+    repair_field(&mut repairs, HeaderField::Flags, &mut header.flags, flags);
+
+    let mut caps = header.caps | DDSCAPS_TEXTURE;
+    if has_mips {
+        caps |= DDSCAPS_MIPMAP;
+    }
+    if has_mips || is_cubemap || is_volume {
+        caps |= DDSCAPS_COMPLEX;
+    }
+    repair_field(&mut repairs, HeaderField::Caps, &mut header.caps, caps);
+
+    let mut caps2 = header.caps2;
+    if is_cubemap {
+        caps2 |= DDSCAPS2_CUBEMAP
+            | DDSCAPS2_CUBEMAP_POSITIVEX
+            | DDSCAPS2_CUBEMAP_NEGATIVEX
+            | DDSCAPS2_CUBEMAP_POSITIVEY
+            | DDSCAPS2_CUBEMAP_NEGATIVEY
+            | DDSCAPS2_CUBEMAP_POSITIVEZ
+            | DDSCAPS2_CUBEMAP_NEGATIVEZ;
+    }
+    if is_volume {
+        caps2 |= DDSCAPS2_VOLUME;
+    }
+    repair_field(&mut repairs, HeaderField::Caps2, &mut header.caps2, caps2);
+
+    repairs
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn repair(&mut self) -> Vec<Repair> {
+        repair_header(&mut self.dds_header)
+    }
+}
+
+impl ScratchImage {
+    // Like `from_reader`, but repairs the header before validating it
+    pub fn from_reader_lenient<T: std::io::Read>(dds_file: &mut T) -> Result<(ScratchImage, Vec<Repair>)> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        let repairs = repair_header(&mut dds_header);
+        Self::apply_parse_options(&mut dds_header, &ParseOptions::default())?;
+
+        let dds_data = Self::read_data(dds_file, &dds_header, true)?;
+        let image = Self::from_header_and_data(dds_header, dds_data)?;
+        read_metadata_chunk(dds_file, &image.dds_header, &ParseOptions::default())?;
+
+        Ok((image, repairs))
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Sampling of single texels in any format that can be decoded, texels are returned as by `decode_subresource`.
+// Filtered sampling uses normalized coordinates with texel centers at (x + 0.5) / width and interpolates the
+// values as decoded, sRGB values are not linearized.
+
+use crate::decode::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use crate::swizzle::{element_extent, element_layout};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressMode {
+    // Coordinates outside of [0, 1] repeat the image
+    Wrap,
+    // Coordinates outside of [0, 1] use the edge texels
+    Clamp,
+}
+
+impl AddressMode {
+    fn apply(self, texel: i64, size: u32) -> u32 {
+        match self {
+            AddressMode::Wrap => texel.rem_euclid(size as i64) as u32,
+            AddressMode::Clamp => texel.clamp(0, size as i64 - 1) as u32,
+        }
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut value = [0.0; 4];
+    for channel in 0..4 {
+        value[channel] = a[channel] + (b[channel] - a[channel]) * t;
+    }
+    value
+}
+
+// Bilinear filter of `texel(x, y)` at normalized coordinates
+pub(crate) fn bilinear<F>(
+    u: f32,
+    v: f32,
+    width: u32,
+    height: u32,
+    address_mode: AddressMode,
+    mut texel: F,
+) -> Result<[f32; 4]>
+where
+    F: FnMut(u32, u32) -> Result<[f32; 4]>,
+{
+    let (x, y) = (u * width as f32 - 0.5, v * height as f32 - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let mut tap = |dx: i64, dy: i64| {
+        let x = address_mode.apply(x0 as i64 + dx, width);
+        let y = address_mode.apply(y0 as i64 + dy, height);
+        texel(x, y)
+    };
+
+    let top = lerp(tap(0, 0)?, tap(1, 0)?, fx);
+    let bottom = lerp(tap(0, 1)?, tap(1, 1)?, fx);
+    Ok(lerp(top, bottom, fy))
+}
+
+// Bilinear resize of a surface with clamped edges, texels are in row-major order
+pub(crate) fn resample_bilinear(
+    texels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+) -> Vec<[f32; 4]> {
+    if (width, height) == (new_width, new_height) {
+        return texels.to_vec();
+    }
+
+    let texel = |x: u32, y: u32| Ok(texels[(y * width + x) as usize]);
+    (0..new_height)
+        .flat_map(|y| (0..new_width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (u, v) = (
+                (x as f32 + 0.5) / new_width as f32,
+                (y as f32 + 0.5) / new_height as f32,
+            );
+            bilinear(u, v, width, height, AddressMode::Clamp, texel).unwrap()
+        })
+        .collect()
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Only the element that contains the texel is decoded, e.g. a single block of block compressed formats. Planar
+    // formats and formats with texels smaller than a byte decode the whole subresource.
+    pub fn sample_texel(&self, x: u32, y: u32, mip: u32, layer: u32) -> Result<[f32; 4]> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        if x >= width || y >= height {
+            return Err(Error::BadImageDescription(
+                "Texel coordinates are outside of the subresource",
+            ));
+        }
+
+        let dxgi_format = self.dxgi_format();
+        if !can_decode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+
+        let (element_width, element_height) = element_extent(dxgi_format);
+        match element_layout(width, height, dxgi_format) {
+            Some((element_size, columns, _)) => {
+                let element = (y / element_height) as usize * columns + (x / element_width) as usize;
+                let offset = range.start + element * element_size;
+                let texels = decode_surface(
+                    &self.as_slice()[offset..offset + element_size],
+                    element_width,
+                    element_height,
+                    dxgi_format,
+                )
+                .unwrap();
+                Ok(texels[((y % element_height) * element_width + x % element_width) as usize])
+            }
+            None => Ok(self.decode_subresource(mip, layer)?[(y * width + x) as usize]),
+        }
+    }
+
+    pub fn sample_bilinear(&self, u: f32, v: f32, mip: u32, layer: u32, address_mode: AddressMode) -> Result<[f32; 4]> {
+        layout::subresource_index(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        bilinear(u, v, width, height, address_mode, |x, y| {
+            self.sample_texel(x, y, mip, layer)
+        })
+    }
+
+    // Bilinear samples of the two closest mips, `lod` is clamped to the mip chain
+    pub fn sample_trilinear(
+        &self,
+        u: f32,
+        v: f32,
+        lod: f32,
+        layer: u32,
+        address_mode: AddressMode,
+    ) -> Result<[f32; 4]> {
+        let last_mip = layout::mip_count(&self.dds_header) - 1;
+        let lod = lod.clamp(0.0, last_mip as f32);
+        let mip = (lod.floor() as u32).min(last_mip);
+        let t = lod - mip as f32;
+
+        let value = self.sample_bilinear(u, v, mip, layer, address_mode)?;
+        if t == 0.0 || mip == last_mip {
+            return Ok(value);
+        }
+        Ok(lerp(
+            value,
+            self.sample_bilinear(u, v, mip + 1, layer, address_mode)?,
+            t,
+        ))
+    }
+}
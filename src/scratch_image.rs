@@ -3,20 +3,108 @@
 // This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
 // If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::builder::ImageDesc;
+use crate::cubemap::is_partial_cubemap;
 use crate::dds::*;
+use crate::format::*;
+use crate::layout::{self, SubresourceLayout};
+use crate::metadata::read_metadata_chunk;
+use crate::storage::*;
+use crate::validation::{self, Diagnostic};
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    BadFileMagic,
-    BadFileHeader,
-    BadPixelFormat,
-    BadLinearSize,
-    BadPitch,
-    BadDataSize,
+    BadFileMagic { actual: [u8; 4] },
+    BadFileHeader { expected: u64, actual: u64, offset: u64 },
+    BadPixelFormat { expected: u64, actual: u64, offset: u64 },
+    BadLinearSize { expected: u64, actual: u64, offset: u64 },
+    BadPitch { expected: u64, actual: u64, offset: u64 },
+    BadDataSize { expected: u64, actual: u64, offset: u64 },
+    BadSubresource,
+    BadImageDescription(&'static str),
+    UnsupportedFormat(u32),
+    BadHeaderFlags(String),
+    IncompatibleImages(&'static str),
+    SizeOverflow,
+    LimitExceeded { limit: &'static str, max: u64, actual: u64 },
     NotImplementedYet(&'static str),
+    BadPng(&'static str),
     IO(std::io::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadFileMagic { actual } => write!(f, "bad file magic {:?}", actual),
+            Self::BadFileHeader {
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "bad file header: expected {}, got {} at offset {}",
+                expected, actual, offset
+            ),
+            Self::BadPixelFormat {
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "bad pixel format: expected size {}, got {} at offset {}",
+                expected, actual, offset
+            ),
+            Self::BadLinearSize {
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "bad linear size: expected {}, got {} at offset {}",
+                expected, actual, offset
+            ),
+            Self::BadPitch {
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "bad pitch: expected {}, got {} at offset {}",
+                expected, actual, offset
+            ),
+            Self::BadDataSize {
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "bad data size: expected {} bytes, got {} at offset {}",
+                expected, actual, offset
+            ),
+            Self::BadSubresource => write!(f, "subresource is out of range"),
+            Self::BadImageDescription(reason) => write!(f, "bad image description: {}", reason),
+            Self::UnsupportedFormat(dxgi_format) => write!(f, "unsupported DXGI format {}", dxgi_format),
+            Self::BadHeaderFlags(reason) => write!(f, "bad header flags: {}", reason),
+            Self::IncompatibleImages(reason) => write!(f, "incompatible images: {}", reason),
+            Self::SizeOverflow => write!(f, "image size overflows"),
+            Self::LimitExceeded { limit, max, actual } => write!(f, "{} is {}, the limit is {}", limit, actual, max),
+            Self::NotImplementedYet(what) => write!(f, "not implemented yet: {}", what),
+            Self::BadPng(reason) => write!(f, "bad PNG file: {}", reason),
+            Self::IO(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IO(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Self::IO(err)
@@ -25,6 +113,16 @@ impl From<std::io::Error> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+// File offsets of the validated header fields
+const HEADER_SIZE_OFFSET: u64 = 4;
+const PITCH_OR_LINEAR_SIZE_OFFSET: u64 = 20;
+const PIXEL_FORMAT_SIZE_OFFSET: u64 = 76;
+// Magic and DDS_HEADER, without the DXT10 header
+const BASE_HEADER_SIZE: usize = 128;
+// Large payloads are written in chunks of this size, so writers don't see a single multi-gigabyte buffer
+const WRITE_CHUNK_SIZE: usize = 64 << 20;
+pub(crate) const DATA_OFFSET: u64 = std::mem::size_of::<DirectDrawHeader>() as u64;
+
 macro_rules! validate_eq {
     ($ex0: expr, $ex1: expr, $er: expr) => {
         if $ex0 != $ex1 {
@@ -33,66 +131,415 @@ macro_rules! validate_eq {
     };
 }
 
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    // Reject files with a wrong pitch_or_linear_size, otherwise it is recomputed from the image description.
+    // Readers are allowed to ignore this field, so writers can leave it zero or write the row pitch where the linear
+    // size is expected and the other way around. It is not strict by default.
+    pub strict_pitch: bool,
+    // Reject files with header flags and caps that are inconsistent with the image description
+    pub strict_caps: bool,
+    // Accept and discard any data after the last subresource, metadata chunks are always accepted
+    pub allow_trailing_data: bool,
+    pub limits: Limits,
+}
+
+// Upper bounds for the image description read from a file header, so a crafted header can't make the reader
+// allocate an arbitrary amount of memory
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    // Maximum size of the payload in bytes
+    pub max_data_size: u64,
+    // Maximum width, height and depth of the top mip level
+    pub max_dimension: u32,
+    pub max_mips: u32,
+    // Maximum number of array elements, cubemaps count as 6 each
+    pub max_array_size: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_data_size: 1 << 32,
+            max_dimension: 65536,
+            max_mips: 17,
+            max_array_size: 2048 * 6,
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_data_size: u64::MAX,
+            max_dimension: u32::MAX,
+            max_mips: u32::MAX,
+            max_array_size: u32::MAX,
+        }
+    }
+
+    pub fn check_header(&self, header: &DirectDrawHeader) -> Result<()> {
+        let check = |limit, max: u64, actual: u64| {
+            if actual > max {
+                Err(Error::LimitExceeded { limit, max, actual })
+            } else {
+                Ok(())
+            }
+        };
+
+        let max_dimension = self.max_dimension as u64;
+        check("width", max_dimension, header.width as u64)?;
+        check("height", max_dimension, header.height as u64)?;
+        check("depth", max_dimension, header.depth as u64)?;
+        check("mipmap count", self.max_mips as u64, header.mipmap_count as u64)?;
+        check(
+            "array size",
+            self.max_array_size as u64,
+            layout::layer_count(header) as u64,
+        )?;
+
+        let data_size = layout::checked_data_size(header).ok_or(Error::SizeOverflow)?;
+        check("data size", self.max_data_size, data_size)
+    }
+}
+
+// The temporary file is removed if `write` fails. Temporary names are unique within the process, so concurrent
+// writes of the same path don't write to the same temporary file.
+pub(crate) fn write_file_atomic(
+    path: &std::path::Path,
+    write: impl FnOnce(&mut std::fs::File) -> Result<()>,
+) -> Result<()> {
+    static TEMP_FILE_INDEX: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_FILE_INDEX.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
+
+    let result = std::fs::File::create(&temp_path)
+        .map_err(Error::from)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()?;
+            drop(file);
+            Ok(std::fs::rename(&temp_path, path)?)
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// `S` is the payload storage, see `Storage`
 #[derive(Clone)]
-pub struct ScratchImage {
-    dds_header: DirectDrawHeader,
-    dds_data: Vec<u8>,
+pub struct ScratchImage<S = Vec<u8>> {
+    pub(crate) dds_header: DirectDrawHeader,
+    pub(crate) dds_data: S,
+}
+
+impl<S: Storage> std::fmt::Debug for ScratchImage<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScratchImage")
+            .field("width", &self.dds_header.width)
+            .field("height", &self.dds_header.height)
+            .field("depth", &self.dds_header.depth)
+            .field("mipmap_count", &self.dds_header.mipmap_count)
+            .field("array_size", &self.dds_header.dxt10.array_size)
+            .field("is_cubemap", &self.is_cubemap())
+            .field("dxgi_format", &FormatName(self.dds_header.dxt10.dxgi_format))
+            .field("data_size", &self.data_size())
+            .finish()
+    }
+}
+
+impl<S: Storage> std::fmt::Display for ScratchImage<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.dds_header.width, self.dds_header.height)?;
+        if self.dds_header.depth > 1 {
+            write!(f, "x{}", self.dds_header.depth)?;
+        }
+        write!(f, " {}", FormatName(self.dds_header.dxt10.dxgi_format))?;
+        if self.is_cubemap() {
+            write!(f, " cubemap")?;
+        }
+        write!(
+            f,
+            ", {} mips, array size {}, {} bytes",
+            self.dds_header.mipmap_count,
+            self.dds_header.dxt10.array_size,
+            self.data_size()
+        )
+    }
+}
+
+impl<S: Storage, T: Storage> PartialEq<ScratchImage<T>> for ScratchImage<S> {
+    fn eq(&self, other: &ScratchImage<T>) -> bool {
+        self.same_metadata(other) && self.as_slice() == other.as_slice()
+    }
+}
+
+impl<S: Storage> Eq for ScratchImage<S> {}
+
+struct FormatName(u32);
+
+impl std::fmt::Debug for FormatName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::fmt::Display for FormatName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match dxgi_format_name(self.0) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "<unknown format {}>", self.0),
+        }
+    }
 }
 
 impl ScratchImage {
     pub fn from_file(path: &std::path::Path) -> Result<ScratchImage> {
         let mut dds_file = std::fs::File::open(path)?;
+
+        #[cfg(feature = "zstd")]
+        if crate::supercompression::is_zstd_path(path) {
+            return Self::from_zstd_reader(&mut std::io::BufReader::new(dds_file));
+        }
+
         Self::from_reader(&mut dds_file)
     }
 
+    pub fn from_file_with(path: &std::path::Path, options: &ParseOptions) -> Result<ScratchImage> {
+        let mut dds_file = std::io::BufReader::new(std::fs::File::open(path)?);
+
+        #[cfg(feature = "zstd")]
+        if crate::supercompression::is_zstd_path(path) {
+            return Self::from_zstd_reader_with(&mut dds_file, options);
+        }
+
+        Self::from_reader_with(&mut dds_file, options)
+    }
+
     pub fn from_reader<T: std::io::Read>(dds_file: &mut T) -> Result<ScratchImage> {
-        let dds_header = {
-            let mut header_bytes = [0u8; 148];
-            dds_file.read_exact(&mut header_bytes)?;
+        Self::from_reader_with(dds_file, &ParseOptions::default())
+    }
 
-            let header: &DirectDrawHeader = bytemuck::from_bytes(&header_bytes);
+    pub fn from_reader_with<T: std::io::Read>(dds_file: &mut T, options: &ParseOptions) -> Result<ScratchImage> {
+        Self::from_reader_with_warnings(dds_file, options).map(|(image, _)| image)
+    }
 
-            validate_eq!(&header.magic, b"DDS ", Error::BadFileMagic);
-            validate_eq!(header.size, 124, Error::BadFileHeader);
-            validate_eq!(header.pixel_format.size, 32, Error::BadPixelFormat);
-            validate_eq!(
-                &header.pixel_format.four_cc,
-                b"DX10",
-                Error::NotImplementedYet("File does not have DX10 headers, DX9 files are not implemented yet")
-            );
+    // Also returns problems that were tolerated by `options`, e.g. a wrong pitch, inconsistent caps or unknown data
+    // in the reserved header fields
+    pub fn from_reader_with_warnings<T: std::io::Read>(
+        dds_file: &mut T,
+        options: &ParseOptions,
+    ) -> Result<(ScratchImage, Vec<Diagnostic>)> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        let file_header = dds_header;
+        Self::apply_parse_options(&mut dds_header, options)?;
+        let dds_data = Self::read_data(dds_file, &dds_header, true)?;
+        let image = Self::from_header_and_data(dds_header, dds_data)?;
+
+        // Metadata chunks are the only trailing data that is accepted by default
+        if !options.allow_trailing_data {
+            read_metadata_chunk(dds_file, &image.dds_header, options)?;
+        }
+        Ok((image, validation::parse_warnings(&file_header)))
+    }
 
-            *header
-        };
-        let dds_data = {
-            let mut buffer = Vec::new();
-            dds_file.read_to_end(&mut buffer)?;
-            buffer
-        };
+    // Reads the payload into `buffer`, which keeps its capacity, so loading many files doesn't allocate once the
+    // buffer is large enough. The image borrows the buffer until the next load.
+    pub fn from_reader_with_buffer<'a, T: std::io::Read>(
+        dds_file: &mut T,
+        buffer: &'a mut Vec<u8>,
+        options: &ParseOptions,
+    ) -> Result<ScratchImage<&'a [u8]>> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, options)?;
+        Self::validate_header(&dds_header)?;
+        buffer.clear();
+        Self::read_data_into(dds_file, &dds_header, true, buffer)?;
+
+        if !options.allow_trailing_data {
+            read_metadata_chunk(dds_file, &dds_header, options)?;
+        }
+        ScratchImage::from_storage(dds_header, buffer.as_slice())
+    }
+
+    pub(crate) fn read_data<T: std::io::Read>(
+        dds_file: &mut T,
+        dds_header: &DirectDrawHeader,
+        allow_trailing_data: bool,
+    ) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        Self::read_data_into(dds_file, dds_header, allow_trailing_data, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    // Reads at most one byte past the payload size implied by the header, that's enough to detect trailing data
+    // without reading all of it
+    fn read_data_into<T: std::io::Read>(
+        dds_file: &mut T,
+        dds_header: &DirectDrawHeader,
+        allow_trailing_data: bool,
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        let data_size = layout::data_size(dds_header) as u64;
+        let read_size = if allow_trailing_data { data_size } else { data_size + 1 };
+
+        std::io::Read::read_to_end(&mut std::io::Read::take(dds_file, read_size), buffer)?;
+        Ok(())
+    }
+
+    pub(crate) fn apply_parse_options(dds_header: &mut DirectDrawHeader, options: &ParseOptions) -> Result<()> {
+        if try_bits_per_pixel(dds_header.dxt10.dxgi_format).is_none() {
+            return Err(Error::UnsupportedFormat(dds_header.dxt10.dxgi_format));
+        }
+
+        options.limits.check_header(dds_header)?;
+        if is_partial_cubemap(dds_header) {
+            return Err(Error::BadImageDescription(
+                "Cubemap doesn't store all faces, it can be read with from_partial_cubemap_reader",
+            ));
+        }
+
+        // Writers that don't set DDSD_MIPMAPCOUNT often leave the count zero, that means a single mip
+        dds_header.mipmap_count = dds_header.mipmap_count.max(1);
+
+        if !options.strict_pitch {
+            dds_header.pitch_or_linear_size =
+                layout::pitch_or_linear_size(dds_header.width, dds_header.height, dds_header.dxt10.dxgi_format);
+        }
+
+        if options.strict_caps {
+            if let Some(diagnostic) = validation::flag_diagnostics(dds_header).into_iter().next() {
+                return Err(Error::BadHeaderFlags(diagnostic.message));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn read_header<T: std::io::Read>(dds_file: &mut T) -> Result<DirectDrawHeader> {
+        // The DXT10 header is only read after the FourCC says it is there, so files without it can be detected
+        // even if they are shorter than the full header
+        let mut header: DirectDrawHeader = bytemuck::Zeroable::zeroed();
+        let header_bytes = bytemuck::bytes_of_mut(&mut header);
+        dds_file.read_exact(&mut header_bytes[..BASE_HEADER_SIZE])?;
+        Self::validate_base_header(&header)?;
+
+        dds_file.read_exact(&mut bytemuck::bytes_of_mut(&mut header)[BASE_HEADER_SIZE..])?;
+        Ok(header)
+    }
+
+    // Magic and structure sizes, and the DX10 FourCC
+    pub(crate) fn validate_base_header(header: &DirectDrawHeader) -> Result<()> {
+        validate_eq!(&header.magic, b"DDS ", Error::BadFileMagic { actual: header.magic });
+        validate_eq!(
+            header.size,
+            124,
+            Error::BadFileHeader {
+                expected: 124,
+                actual: header.size as u64,
+                offset: HEADER_SIZE_OFFSET,
+            }
+        );
+        validate_eq!(
+            header.pixel_format.size,
+            32,
+            Error::BadPixelFormat {
+                expected: 32,
+                actual: header.pixel_format.size as u64,
+                offset: PIXEL_FORMAT_SIZE_OFFSET,
+            }
+        );
+        validate_eq!(
+            &header.pixel_format.four_cc,
+            b"DX10",
+            Error::NotImplementedYet("File does not have DX10 headers, DX9 files are not implemented yet")
+        );
+
+        Ok(())
+    }
+
+    // Image from a header and payload that were read from another container, validated like a file would be
+    pub fn from_parts(dds_header: DirectDrawHeader, dds_data: Vec<u8>) -> Result<ScratchImage> {
+        Self::from_storage(dds_header, dds_data)
+    }
+
+    pub(crate) fn from_header_and_data(dds_header: DirectDrawHeader, dds_data: Vec<u8>) -> Result<ScratchImage> {
+        Self::validate_header(&dds_header)?;
+        let data_size = layout::data_size(&dds_header);
+        validate_eq!(
+            data_size,
+            dds_data.len(),
+            Error::BadDataSize {
+                expected: data_size as u64,
+                actual: dds_data.len() as u64,
+                offset: DATA_OFFSET,
+            }
+        );
+
+        Ok(ScratchImage { dds_header, dds_data })
+    }
+
+    // Payload size implied by the header, without loading the payload itself.
+    // None if the format is unknown or the size overflows.
+    pub fn expected_data_size_for(header: &DirectDrawHeader) -> Option<u64> {
+        layout::checked_data_size(header)
+    }
+
+    pub(crate) fn validate_header(dds_header: &DirectDrawHeader) -> Result<()> {
+        if try_bits_per_pixel(dds_header.dxt10.dxgi_format).is_none() {
+            return Err(Error::UnsupportedFormat(dds_header.dxt10.dxgi_format));
+        }
+        if layout::checked_data_size(dds_header).is_none() {
+            return Err(Error::SizeOverflow);
+        }
 
         let is_compressed = is_block_compressed(dds_header.dxt10.dxgi_format);
         let (row_pitch, linear_size) =
             pitch_and_linear_size(dds_header.width, dds_header.height, dds_header.dxt10.dxgi_format);
         if is_compressed {
-            validate_eq!(linear_size, dds_header.pitch_or_linear_size, Error::BadLinearSize);
+            validate_eq!(
+                linear_size,
+                dds_header.pitch_or_linear_size,
+                Error::BadLinearSize {
+                    expected: linear_size as u64,
+                    actual: dds_header.pitch_or_linear_size as u64,
+                    offset: PITCH_OR_LINEAR_SIZE_OFFSET,
+                }
+            );
         } else {
-            validate_eq!(row_pitch, dds_header.pitch_or_linear_size, Error::BadPitch);
-        }
-
-        let mut image_data_size = linear_size;
-        for mip in 1..dds_header.mipmap_count {
-            let (_, mip_linear_size) = pitch_and_linear_size(
-                dds_header.width >> mip,
-                dds_header.height >> mip,
-                dds_header.dxt10.dxgi_format,
+            validate_eq!(
+                row_pitch,
+                dds_header.pitch_or_linear_size,
+                Error::BadPitch {
+                    expected: row_pitch as u64,
+                    actual: dds_header.pitch_or_linear_size as u64,
+                    offset: PITCH_OR_LINEAR_SIZE_OFFSET,
+                }
             );
-            image_data_size += mip_linear_size;
         }
-        if dds_header.dxt10.misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE == DDS_RESOURCE_MISC_TEXTURECUBE {
-            image_data_size *= 6;
+
+        // 1D textures are a single row, some writers store a height of 0
+        if dds_header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D {
+            if dds_header.height > 1 || dds_header.depth > 1 {
+                return Err(Error::BadImageDescription(
+                    "1D textures must have height and depth of 1",
+                ));
+            }
+            if layout::face_count(dds_header) == 6 {
+                return Err(Error::BadImageDescription("1D textures can't be cubemaps"));
+            }
         }
-        validate_eq!(image_data_size, dds_data.len() as _, Error::BadDataSize);
 
-        Ok(ScratchImage { dds_header, dds_data })
+        Ok(())
     }
 
     pub fn new(
@@ -104,26 +551,43 @@ impl ScratchImage {
         dxgi_format: u32,
         is_cubemap: bool,
     ) -> ScratchImage {
-        let mut flags = DDSD_CAPS | DDSD_PIXELFORMAT;
-        let mut resource_dimension = D3D10_RESOURCE_DIMENSION_UNKNOWN;
+        let desc = ImageDesc {
+            width,
+            height,
+            depth,
+            mipmap_count,
+            array_size,
+            dxgi_format,
+            is_cubemap,
+            dimension: None,
+        };
+        let dds_header = Self::new_header(&desc);
+        let dds_data = vec![0u8; layout::data_size(&dds_header)];
+
+        ScratchImage { dds_header, dds_data }
+    }
+
+    pub(crate) fn new_header(desc: &ImageDesc) -> DirectDrawHeader {
+        let width = desc.width;
+        let height = desc.height;
+        let depth = desc.depth.max(1);
+        let mipmap_count = desc.mipmap_count.max(1);
+        let array_size = desc.array_size.max(1);
+        let dxgi_format = desc.dxgi_format;
+        let dimension = desc.inferred_dimension();
+
+        let mut flags = DDSD_CAPS | DDSD_WIDTH | DDSD_HEIGHT | DDSD_PIXELFORMAT;
         let mut caps = DDSCAPS_TEXTURE;
         let mut caps2 = 0;
 
         if is_block_compressed(dxgi_format) {
             flags |= DDSD_LINEARSIZE;
+        } else {
+            flags |= DDSD_PITCH;
         }
 
-        if width > 1 {
-            flags |= DDSD_WIDTH;
-            resource_dimension = D3D10_RESOURCE_DIMENSION_TEXTURE1D;
-        }
-        if height > 1 {
-            flags |= DDSD_HEIGHT;
-            resource_dimension = D3D10_RESOURCE_DIMENSION_TEXTURE2D;
-        }
-        if depth > 1 {
+        if dimension == ResourceDimension::Texture3D {
             flags |= DDSD_DEPTH;
-            resource_dimension = D3D10_RESOURCE_DIMENSION_TEXTURE3D;
             caps |= DDSCAPS_COMPLEX;
             caps2 |= DDSCAPS2_VOLUME;
         }
@@ -134,7 +598,7 @@ impl ScratchImage {
         }
 
         let mut misc_flag = 0;
-        if is_cubemap {
+        if desc.is_cubemap {
             caps |= DDSCAPS_COMPLEX;
             caps2 |= DDSCAPS2_CUBEMAP;
             caps2 |= DDSCAPS2_CUBEMAP_POSITIVEX;
@@ -145,19 +609,14 @@ impl ScratchImage {
             caps2 |= DDSCAPS2_CUBEMAP_NEGATIVEZ;
             misc_flag |= DDS_RESOURCE_MISC_TEXTURECUBE
         }
-        let (row_pitch, linear_size) = pitch_and_linear_size(width, height, dxgi_format);
 
-        let dds_header = DirectDrawHeader {
+        DirectDrawHeader {
             magic: *b"DDS ",
             size: 124,
             flags,
             height,
             width,
-            pitch_or_linear_size: if is_block_compressed(dxgi_format) {
-                linear_size
-            } else {
-                row_pitch
-            },
+            pitch_or_linear_size: layout::pitch_or_linear_size(width, height, dxgi_format),
             depth,
             mipmap_count,
             reserved: [0; 11],
@@ -178,43 +637,112 @@ impl ScratchImage {
             reserved2: 0,
             dxt10: DirectDrawHeader10 {
                 dxgi_format,
-                resource_dimension,
+                resource_dimension: dimension.to_d3d10(),
                 misc_flag,
                 array_size,
                 misc_flags2: 0,
             },
-        };
-
-        let mut image_data_size = linear_size;
-        for mip in 1..mipmap_count {
-            let (_, mip_linear_size) = pitch_and_linear_size(width >> mip, height >> mip, dxgi_format);
-            image_data_size += mip_linear_size;
-        }
-        if is_cubemap {
-            image_data_size *= 6;
         }
-        image_data_size *= array_size;
+    }
+}
 
-        let mut dds_data = Vec::new();
-        dds_data.resize(image_data_size as _, 0u8);
+// The header and the first chunk of the payload are usually written with a single vectored write
+pub(crate) fn write_header_and_data<T, F>(
+    file: &mut T,
+    dds_header: &DirectDrawHeader,
+    data: &[u8],
+    mut progress: F,
+) -> Result<()>
+where
+    T: std::io::Write,
+    F: FnMut(u64, u64),
+{
+    let mut header = bytemuck::bytes_of(dds_header);
+    let mut data = data;
+    let total = (header.len() + data.len()) as u64;
+    let mut written = 0;
+
+    while !header.is_empty() {
+        let chunk = &data[..data.len().min(WRITE_CHUNK_SIZE)];
+        let size = match file.write_vectored(&[std::io::IoSlice::new(header), std::io::IoSlice::new(chunk)]) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+            Ok(size) => size,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
 
-        ScratchImage { dds_header, dds_data }
+        let header_size = size.min(header.len());
+        header = &header[header_size..];
+        data = &data[size - header_size..];
+        written += size as u64;
+        progress(written, total);
     }
 
+    for chunk in data.chunks(WRITE_CHUNK_SIZE) {
+        file.write_all(chunk)?;
+        written += chunk.len() as u64;
+        progress(written, total);
+    }
+    Ok(())
+}
+
+impl<S: Storage> ScratchImage<S> {
     pub fn write_to_file(&self, path: &std::path::Path) -> Result<()> {
         let mut file = std::fs::OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
             .open(path)?;
-        self.write_to(&mut file)
+
+        self.write_to_file_as(&mut file, path)
+    }
+
+    // Writes to a temporary file next to `path` and renames it, so `path` is either left as it was or completely
+    // written, even if the process is interrupted
+    pub fn write_to_file_atomic(&self, path: &std::path::Path) -> Result<()> {
+        write_file_atomic(path, |file| self.write_to_file_as(file, path))
+    }
+
+    // The format is picked from the extension of `path`
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    fn write_to_file_as(&self, file: &mut std::fs::File, path: &std::path::Path) -> Result<()> {
+        #[cfg(feature = "zstd")]
+        if crate::supercompression::is_zstd_path(path) {
+            return self.write_to_zstd(file, crate::supercompression::ZSTD_DEFAULT_COMPRESSION_LEVEL);
+        }
+
+        self.write_to(file)
     }
 
     pub fn write_to<T: std::io::Write>(&self, file: &mut T) -> Result<()> {
-        let header = bytemuck::bytes_of(&self.dds_header);
-        file.write_all(header)?;
-        file.write_all(&self.dds_data[..])?;
-        Ok(())
+        self.write_to_with_progress(file, |_, _| {})
+    }
+
+    // `progress(written, total)` is called after every write, sizes are in bytes and include the header
+    pub fn write_to_with_progress<T, F>(&self, file: &mut T, progress: F) -> Result<()>
+    where
+        T: std::io::Write,
+        F: FnMut(u64, u64),
+    {
+        write_header_and_data(file, &self.dds_header, self.as_slice(), progress)
+    }
+
+    pub fn header(&self) -> &DirectDrawHeader {
+        &self.dds_header
+    }
+
+    pub fn dxt10_header(&self) -> &DirectDrawHeader10 {
+        &self.dds_header.dxt10
+    }
+
+    // Inverse of `from_parts` and `from_storage`
+    pub fn into_raw(self) -> (DirectDrawHeader, S) {
+        (self.dds_header, self.dds_data)
+    }
+
+    // Compares headers only, ignoring the pixel data
+    pub fn same_metadata<T: Storage>(&self, other: &ScratchImage<T>) -> bool {
+        bytemuck::bytes_of(&self.dds_header) == bytemuck::bytes_of(&other.dds_header)
     }
 
     pub fn image_size(&self) -> (u32, u32, u32) {
@@ -241,10 +769,34 @@ impl ScratchImage {
         self.dds_header.dxt10.array_size
     }
 
+    pub fn subresource_count(&self) -> u32 {
+        layout::subresource_count(&self.dds_header)
+    }
+
+    pub fn data_size(&self) -> usize {
+        self.as_slice().len()
+    }
+
     pub fn block_size(&self) -> u32 {
         block_size(self.dds_header.dxt10.dxgi_format)
     }
 
+    // None if the header has an unknown resource dimension
+    pub fn resource_dimension(&self) -> Option<ResourceDimension> {
+        ResourceDimension::from_d3d10(self.dds_header.dxt10.resource_dimension)
+    }
+
+    // None if the header has an invalid alpha mode
+    pub fn alpha_mode(&self) -> Option<AlphaMode> {
+        AlphaMode::from_misc_flags2(self.dds_header.dxt10.misc_flags2)
+    }
+
+    // Only changes the metadata, the texels are not premultiplied or unpremultiplied
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        let misc_flags2 = &mut self.dds_header.dxt10.misc_flags2;
+        *misc_flags2 = (*misc_flags2 & !DDS_MISC_FLAGS2_ALPHA_MODE_MASK) | alpha_mode.to_misc_flags2();
+    }
+
     pub fn is_texture1d(&self) -> bool {
         self.dds_header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D
     }
@@ -265,37 +817,169 @@ impl ScratchImage {
         self.dds_header.dxt10.dxgi_format
     }
 
+    // Changes the format without touching the data, the formats must be the same or in the same typeless family,
+    // e.g. UNORM and UNORM_SRGB, or UNORM and TYPELESS
+    pub fn reinterpret_format(&mut self, dxgi_format: u32) -> Result<()> {
+        let current_format = self.dxgi_format();
+        let same_family =
+            typeless_format(dxgi_format).is_some() && typeless_format(dxgi_format) == typeless_format(current_format);
+        if dxgi_format != current_format && !same_family {
+            return Err(Error::IncompatibleImages("Formats are not in the same typeless family"));
+        }
+        // The endpoints of signed and unsigned BC6H blocks are encoded differently
+        let is_typed_bc6h = |dxgi_format| is_bc6h(dxgi_format) && !is_typeless(dxgi_format);
+        if is_typed_bc6h(dxgi_format)
+            && is_typed_bc6h(current_format)
+            && is_signed(dxgi_format) != is_signed(current_format)
+        {
+            return Err(Error::IncompatibleImages(
+                "BC6H_UF16 and BC6H_SF16 can't be reinterpreted",
+            ));
+        }
+
+        self.dds_header.dxt10.dxgi_format = dxgi_format;
+        Ok(())
+    }
+
     pub fn as_slice(&self) -> &[u8] {
-        &self.dds_data
+        self.dds_data.as_ref()
     }
 
-    pub fn as_slice_mut(&mut self) -> &mut [u8] {
-        &mut self.dds_data
+    pub fn subresource_layout(&self, mip: u32) -> Result<SubresourceLayout> {
+        layout::subresource_layout(&self.dds_header, mip).ok_or(Error::BadSubresource)
     }
 
-    pub fn as_typed_slice<T>(&self) -> Option<&[T]> {
-        let source_size = (bits_per_pixel(self.dds_header.dxt10.dxgi_format) / 8) as usize;
-        let target_size = std::mem::size_of::<T>();
+    // Payload of a single depth slice of a volume texture mip. Other images have a single slice per mip.
+    pub fn slice(&self, mip: u32, slice: u32) -> Result<&[u8]> {
+        let range = self.slice_range(mip, slice)?;
+        Ok(&self.as_slice()[range])
+    }
 
-        if source_size == target_size {
-            Some(unsafe {
-                std::slice::from_raw_parts(self.dds_data.as_ptr() as *const T, self.dds_data.len() * target_size)
-            })
-        } else {
-            None
+    fn slice_range(&self, mip: u32, slice: u32) -> Result<std::ops::Range<usize>> {
+        let range = layout::subresource_range(&self.dds_header, mip, 0).ok_or(Error::BadSubresource)?;
+        if slice >= layout::mip_depth(&self.dds_header, mip) {
+            return Err(Error::BadSubresource);
         }
+
+        let slice_size = layout::slice_size(&self.dds_header, mip);
+        let offset = range.start + slice as usize * slice_size;
+        Ok(offset..offset + slice_size)
     }
 
-    pub fn as_typed_slice_mut<T>(&mut self) -> Option<&mut [T]> {
-        let source_size = (bits_per_pixel(self.dds_header.dxt10.dxgi_format) / 8) as usize;
-        let target_size = std::mem::size_of::<T>();
+    // Size of a single texel, or of a single block for block compressed formats
+    fn element_size(&self) -> Option<usize> {
+        let dxgi_format = self.dds_header.dxt10.dxgi_format;
+        if is_block_compressed(dxgi_format) {
+            return Some(block_size(dxgi_format) as usize);
+        }
 
-        if source_size == target_size {
-            Some(unsafe {
-                std::slice::from_raw_parts_mut(self.dds_data.as_mut_ptr() as *mut T, self.dds_data.len() * target_size)
-            })
+        let bits_per_pixel = try_bits_per_pixel(dxgi_format)?;
+        if bits_per_pixel % 8 == 0 {
+            Some((bits_per_pixel / 8) as usize)
         } else {
             None
         }
     }
+
+    // Returns None if the size of T doesn't match the texel (or block) size, or if the data is not aligned for T.
+    // Block compressed data can be viewed as blocks, e.g. as [u8; 8] for BC1 or [u8; 16] for BC7.
+    pub fn as_typed_slice<T: bytemuck::Pod>(&self) -> Option<&[T]> {
+        if self.element_size()? != std::mem::size_of::<T>() {
+            return None;
+        }
+        bytemuck::try_cast_slice(self.as_slice()).ok()
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        self.dds_data.as_mut()
+    }
+
+    pub fn slice_mut(&mut self, mip: u32, slice: u32) -> Result<&mut [u8]> {
+        let range = self.slice_range(mip, slice)?;
+        Ok(&mut self.as_slice_mut()[range])
+    }
+
+    pub fn as_typed_slice_mut<T: bytemuck::Pod>(&mut self) -> Option<&mut [T]> {
+        if self.element_size()? != std::mem::size_of::<T>() {
+            return None;
+        }
+        bytemuck::try_cast_slice_mut(self.as_slice_mut()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(width: u32, height: u32, dxgi_format: u32) -> ImageDesc {
+        ImageDesc {
+            width,
+            height,
+            depth: 1,
+            mipmap_count: 1,
+            array_size: 1,
+            dxgi_format,
+            is_cubemap: false,
+            dimension: None,
+        }
+    }
+
+    fn exceeded_limit(result: Result<()>) -> Option<&'static str> {
+        match result {
+            Err(Error::LimitExceeded { limit, .. }) => Some(limit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn limits_check_header() {
+        let limits = Limits::default();
+        assert!(limits
+            .check_header(&desc(4096, 4096, DXGI_FORMAT_R8G8B8A8_UNORM).to_header())
+            .is_ok());
+
+        let mut header = desc(4, 4, DXGI_FORMAT_R8G8B8A8_UNORM).to_header();
+        header.width = 65537;
+        assert_eq!(exceeded_limit(limits.check_header(&header)), Some("width"));
+
+        let mut header = desc(4, 4, DXGI_FORMAT_R8G8B8A8_UNORM).to_header();
+        header.mipmap_count = 18;
+        assert_eq!(exceeded_limit(limits.check_header(&header)), Some("mipmap count"));
+
+        let mut header = desc(4, 4, DXGI_FORMAT_R8G8B8A8_UNORM).to_header();
+        header.dxt10.array_size = 2049;
+        header.dxt10.misc_flag |= DDS_RESOURCE_MISC_TEXTURECUBE;
+        assert_eq!(exceeded_limit(limits.check_header(&header)), Some("array size"));
+
+        let header = desc(65536, 65536, DXGI_FORMAT_R32G32B32A32_FLOAT).to_header();
+        assert_eq!(exceeded_limit(limits.check_header(&header)), Some("data size"));
+        assert!(Limits::unlimited().check_header(&header).is_ok());
+    }
+
+    #[test]
+    fn crafted_header_is_rejected_before_reading_data() {
+        // 64 GiB of texels claimed by a file that only has the header
+        let mut file = Vec::new();
+        ScratchImage::with_desc(&desc(4, 4, DXGI_FORMAT_R32G32B32A32_FLOAT))
+            .unwrap()
+            .write_to(&mut file)
+            .unwrap();
+        file.truncate(DATA_OFFSET as usize);
+        for offset in [
+            std::mem::offset_of!(DirectDrawHeader, width),
+            std::mem::offset_of!(DirectDrawHeader, height),
+        ] {
+            file[offset..offset + 4].copy_from_slice(&65536u32.to_le_bytes());
+        }
+
+        let result = ScratchImage::from_reader(&mut file.as_slice()).map(|_| ());
+        assert_eq!(exceeded_limit(result), Some("data size"));
+
+        let mut options = ParseOptions::default();
+        options.limits.max_dimension = 1024;
+        let result = ScratchImage::from_reader_with(&mut file.as_slice(), &options).map(|_| ());
+        assert_eq!(exceeded_limit(result), Some("width"));
+    }
 }
@@ -0,0 +1,56 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Signature of the tool that wrote a file, stored in reserved header fields 9 and 10 the way NVTT does: a tag of
+// 4 printable ASCII characters and a version number, e.g. "NVTT" and 0x00020109 for NVTT 2.1.9.
+
+use crate::dds::DirectDrawHeader;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ToolSignature {
+    pub tag: [u8; 4],
+    // Encoding is up to the tool
+    pub version: u32,
+}
+
+fn is_valid_tag(tag: &[u8; 4]) -> bool {
+    tag.iter().all(|byte| byte.is_ascii_graphic())
+}
+
+pub(crate) fn read_tool_signature(header: &DirectDrawHeader) -> Option<ToolSignature> {
+    let tag = header.reserved[9].to_le_bytes();
+    if !is_valid_tag(&tag) {
+        return None;
+    }
+    Some(ToolSignature {
+        tag,
+        version: header.reserved[10],
+    })
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // None if the image has no signature
+    pub fn tool_signature(&self) -> Option<ToolSignature> {
+        read_tool_signature(&self.dds_header)
+    }
+
+    // None removes the signature
+    pub fn set_tool_signature(&mut self, signature: Option<ToolSignature>) -> Result<()> {
+        let fields = match signature {
+            Some(signature) if !is_valid_tag(&signature.tag) => {
+                return Err(Error::BadImageDescription(
+                    "Tool signature tag must be 4 printable ASCII characters",
+                ));
+            }
+            Some(signature) => [u32::from_le_bytes(signature.tag), signature.version],
+            None => [0; 2],
+        };
+
+        self.dds_header.reserved[9..].copy_from_slice(&fields);
+        Ok(())
+    }
+}
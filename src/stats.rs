@@ -0,0 +1,152 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::bc;
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+// Histograms cover [0, 1], values outside of that range are counted in the first or the last bin
+pub const HISTOGRAM_BIN_COUNT: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f64,
+    pub histogram: [u32; HISTOGRAM_BIN_COUNT],
+}
+
+impl Default for ChannelStats {
+    fn default() -> Self {
+        Self {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            mean: 0.0,
+            histogram: [0; HISTOGRAM_BIN_COUNT],
+        }
+    }
+}
+
+impl ChannelStats {
+    // All values are the same
+    pub fn is_constant(&self) -> bool {
+        self.min == self.max
+    }
+}
+
+// Statistics of decoded texels, see `ScratchImage::decode_subresource`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageStats {
+    // RGBA
+    pub channels: [ChannelStats; 4],
+    pub texel_count: usize,
+}
+
+pub fn texel_stats(texels: &[[f32; 4]]) -> ImageStats {
+    let mut stats = ImageStats {
+        texel_count: texels.len(),
+        ..Default::default()
+    };
+
+    let mut sums = [0.0f64; 4];
+    for texel in texels {
+        for (channel, stats) in stats.channels.iter_mut().enumerate() {
+            let value = texel[channel];
+            stats.min = stats.min.min(value);
+            stats.max = stats.max.max(value);
+            sums[channel] += value as f64;
+
+            let bin = (value.clamp(0.0, 1.0) * (HISTOGRAM_BIN_COUNT - 1) as f32).round() as usize;
+            stats.histogram[bin] += 1;
+        }
+    }
+
+    for (stats, sum) in stats.channels.iter_mut().zip(sums.iter()) {
+        stats.mean = sum / texels.len().max(1) as f64;
+    }
+
+    stats
+}
+
+// Fraction of texels that pass an alpha test with the given threshold (alpha > threshold)
+pub fn alpha_coverage(texels: &[[f32; 4]], threshold: f32) -> f32 {
+    let covered = texels.iter().filter(|texel| texel[3] > threshold).count();
+    covered as f32 / texels.len().max(1) as f32
+}
+
+// Number of BC6H or BC7 blocks using every mode, and every partition of the modes that have partitions.
+// Modes are indexed as in the specification, except that BC6H modes 1 to 14 are stored at 0 to 13.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockModeStats {
+    pub block_count: usize,
+    // Blocks with a reserved mode, they decode to zeroes
+    pub reserved_count: usize,
+    pub mode_counts: Vec<usize>,
+    pub partition_counts: Vec<[usize; 64]>,
+}
+
+// None if the format is not BC6H or BC7
+pub fn block_mode_stats(data: &[u8], dxgi_format: u32) -> Option<BlockModeStats> {
+    let is_bc7 = match dxgi_format {
+        DXGI_FORMAT_BC7_TYPELESS | DXGI_FORMAT_BC7_UNORM | DXGI_FORMAT_BC7_UNORM_SRGB => true,
+        DXGI_FORMAT_BC6H_TYPELESS | DXGI_FORMAT_BC6H_UF16 | DXGI_FORMAT_BC6H_SF16 => false,
+        _ => return None,
+    };
+
+    let mode_count = if is_bc7 { 8 } else { 14 };
+    let mut stats = BlockModeStats {
+        mode_counts: vec![0; mode_count],
+        partition_counts: vec![[0; 64]; mode_count],
+        ..Default::default()
+    };
+
+    for block in data.chunks_exact(16) {
+        stats.block_count += 1;
+
+        let mode_and_partition = if is_bc7 {
+            bc::bc7_block_mode(block).map(|mode| (mode as usize, bc::bc7_block_partition(block, mode)))
+        } else {
+            bc::bc6h_block_mode(block).map(|mode| (mode, bc::bc6h_block_partition(block, mode)))
+        };
+
+        match mode_and_partition {
+            Some((mode, partition)) => {
+                stats.mode_counts[mode] += 1;
+                if let Some(partition) = partition {
+                    stats.partition_counts[mode][partition as usize] += 1;
+                }
+            }
+            None => stats.reserved_count += 1,
+        }
+    }
+
+    Some(stats)
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn stats(&self, mip: u32, layer: u32) -> Result<ImageStats> {
+        Ok(texel_stats(&self.decode_subresource(mip, layer)?))
+    }
+
+    // Alpha coverage of every mip level of a layer, see `alpha_coverage`
+    pub fn alpha_coverage(&self, layer: u32, threshold: f32) -> Result<Vec<f32>> {
+        (0..layout::mip_count(&self.dds_header))
+            .map(|mip| Ok(alpha_coverage(&self.decode_subresource(mip, layer)?, threshold)))
+            .collect()
+    }
+
+    // Block modes of all subresources
+    pub fn block_mode_stats(&self) -> Result<BlockModeStats> {
+        block_mode_stats(self.as_slice(), self.dxgi_format()).ok_or(Error::UnsupportedFormat(self.dxgi_format()))
+    }
+
+    pub fn subresource_block_mode_stats(&self, mip: u32, layer: u32) -> Result<BlockModeStats> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        block_mode_stats(&self.as_slice()[range], self.dxgi_format())
+            .ok_or(Error::UnsupportedFormat(self.dxgi_format()))
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Payload storage of an image. Anything that can be viewed as bytes can be used: owned vectors and boxes, shared
+// `Arc<[u8]>`, borrowed slices or memory mapped files, so images can be processed without copying the payload.
+// Everything that reads the payload works with any storage, writing texels needs mutable storage, and changing the
+// layout of an image (e.g. the mip count) needs an owned `Vec<u8>`. `SharedStorage` makes clones cheap, for caches
+// holding many images.
+
+use crate::dds::DirectDrawHeader;
+use crate::scratch_image::*;
+
+pub trait Storage: AsRef<[u8]> {}
+
+impl<T: AsRef<[u8]> + ?Sized> Storage for T {}
+
+// The size is still fixed, only the bytes can change
+pub trait StorageMut: Storage + AsMut<[u8]> {}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> StorageMut for T {}
+
+impl<S: Storage> ScratchImage<S> {
+    // Image over an existing payload, validated like `from_parts`
+    pub fn from_storage(dds_header: DirectDrawHeader, dds_data: S) -> Result<ScratchImage<S>> {
+        ScratchImage::validate_base_header(&dds_header)?;
+        ScratchImage::validate_header(&dds_header)?;
+        let data_size = crate::layout::data_size(&dds_header);
+        if data_size != dds_data.as_ref().len() {
+            return Err(Error::BadDataSize {
+                expected: data_size as u64,
+                actual: dds_data.as_ref().len() as u64,
+                offset: DATA_OFFSET,
+            });
+        }
+
+        Ok(ScratchImage { dds_header, dds_data })
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.dds_data
+    }
+
+    // Image that borrows the payload of this one
+    pub fn view(&self) -> ScratchImage<&[u8]> {
+        ScratchImage {
+            dds_header: self.dds_header,
+            dds_data: self.dds_data.as_ref(),
+        }
+    }
+
+    // Copy of the image with an owned payload
+    pub fn to_owned_image(&self) -> ScratchImage {
+        ScratchImage {
+            dds_header: self.dds_header,
+            dds_data: self.dds_data.as_ref().to_vec(),
+        }
+    }
+
+    // Moves the payload into another storage, e.g. from `Vec<u8>` to `Arc<[u8]>`
+    pub fn map_storage<T: Storage>(self, map: impl FnOnce(S) -> T) -> Result<ScratchImage<T>> {
+        ScratchImage::from_storage(self.dds_header, map(self.dds_data))
+    }
+}
+
+// Copy-on-write storage: clones share the payload, a clone copies it the first time its bytes are changed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SharedStorage(std::sync::Arc<Vec<u8>>);
+
+impl SharedStorage {
+    // True if no other clone shares the payload, so changing it doesn't make a copy
+    pub fn is_unique(&self) -> bool {
+        std::sync::Arc::strong_count(&self.0) == 1
+    }
+
+    // Only copies the payload if it is shared
+    pub fn into_vec(self) -> Vec<u8> {
+        std::sync::Arc::try_unwrap(self.0).unwrap_or_else(|shared| (*shared).clone())
+    }
+}
+
+impl From<Vec<u8>> for SharedStorage {
+    fn from(data: Vec<u8>) -> Self {
+        Self(std::sync::Arc::new(data))
+    }
+}
+
+impl AsRef<[u8]> for SharedStorage {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for SharedStorage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        std::sync::Arc::make_mut(&mut self.0).as_mut_slice()
+    }
+}
+
+pub type SharedScratchImage = ScratchImage<SharedStorage>;
+
+impl ScratchImage {
+    // Cheap to clone, the payload is not copied
+    pub fn into_shared(self) -> SharedScratchImage {
+        ScratchImage {
+            dds_header: self.dds_header,
+            dds_data: self.dds_data.into(),
+        }
+    }
+}
+
+impl SharedScratchImage {
+    // Needed to change the layout, e.g. the mip count. Only copies the payload if it is shared.
+    pub fn into_owned(self) -> ScratchImage {
+        ScratchImage {
+            dds_header: self.dds_header,
+            dds_data: self.dds_data.into_vec(),
+        }
+    }
+
+    // True if both images share the same payload
+    pub fn shares_data(&self, other: &SharedScratchImage) -> bool {
+        std::sync::Arc::ptr_eq(&self.dds_data.0, &other.dds_data.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageDesc;
+    use crate::dds::*;
+
+    fn image() -> ScratchImage {
+        let desc = ImageDesc {
+            width: 4,
+            height: 2,
+            depth: 1,
+            mipmap_count: 2,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            is_cubemap: false,
+            dimension: None,
+        };
+        let mut image = ScratchImage::with_desc(&desc).unwrap();
+        for (index, byte) in image.as_slice_mut().iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        image
+    }
+
+    #[test]
+    fn expected_data_size_without_storage_type() {
+        let image = image();
+        assert_eq!(
+            ScratchImage::expected_data_size_for(image.header()),
+            Some(image.data_size() as u64)
+        );
+    }
+
+    #[test]
+    fn borrowed_storage() {
+        let image = image();
+        let borrowed = ScratchImage::from_storage(*image.header(), image.as_slice()).unwrap();
+        assert_eq!(borrowed, image);
+        assert_eq!(
+            borrowed.decode_subresource(1, 0).unwrap(),
+            image.decode_subresource(1, 0).unwrap()
+        );
+        assert_eq!(image.view().to_owned_image(), image);
+
+        let short = &image.as_slice()[1..];
+        assert!(matches!(
+            ScratchImage::from_storage(*image.header(), short),
+            Err(Error::BadDataSize { .. })
+        ));
+    }
+
+    #[test]
+    fn shared_storage_copies_on_write() {
+        let shared = image().into_shared();
+        let mut changed = shared.clone();
+        assert!(changed.shares_data(&shared));
+        assert!(!changed.storage().is_unique());
+
+        changed.as_slice_mut()[0] = 255;
+        assert!(!changed.shares_data(&shared));
+        assert_eq!(shared.as_slice()[0], 0);
+        assert_eq!(changed.into_owned().as_slice()[0], 255);
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::dds::DirectDrawHeader;
+use crate::layout;
+use crate::scratch_image::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum LoadBudget {
+    // Load the largest mip chain tail that fits into this many bytes, the smallest mip is always loaded
+    MaxBytes(usize),
+    // Skip all mips larger than this one
+    MinMip(u32),
+}
+
+impl ScratchImage {
+    pub fn from_reader_with_budget<T: std::io::Read + std::io::Seek>(
+        dds_file: &mut T,
+        budget: LoadBudget,
+    ) -> Result<ScratchImage> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, &ParseOptions::default())?;
+        Self::validate_header(&dds_header)?;
+
+        let mip_count = layout::mip_count(&dds_header);
+        let first_mip = match budget {
+            LoadBudget::MaxBytes(max_bytes) => (0..mip_count)
+                .find(|&first_mip| {
+                    let header = layout::mip_chain_header(&dds_header, first_mip, mip_count - first_mip);
+                    layout::data_size(&header) <= max_bytes
+                })
+                .unwrap_or(mip_count - 1),
+            LoadBudget::MinMip(min_mip) => min_mip.min(mip_count - 1),
+        };
+
+        Self::read_mip_range(dds_file, &dds_header, first_mip, mip_count - first_mip)
+    }
+
+    // Loads the mips in `mips` of every layer, skipping the other mips with `Seek`. The image has the mip range
+    // as its mip chain, so its first mip is `mips.start()`.
+    pub fn from_reader_mip_range<T: std::io::Read + std::io::Seek>(
+        dds_file: &mut T,
+        mips: std::ops::RangeInclusive<u32>,
+    ) -> Result<ScratchImage> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, &ParseOptions::default())?;
+        Self::validate_header(&dds_header)?;
+
+        let (first_mip, last_mip) = mips.into_inner();
+        if first_mip > last_mip || last_mip >= layout::mip_count(&dds_header) {
+            return Err(Error::BadSubresource);
+        }
+        Self::read_mip_range(dds_file, &dds_header, first_mip, last_mip - first_mip + 1)
+    }
+
+    // The reader must be right after the header
+    fn read_mip_range<T: std::io::Read + std::io::Seek>(
+        dds_file: &mut T,
+        dds_header: &DirectDrawHeader,
+        first_mip: u32,
+        mip_count: u32,
+    ) -> Result<ScratchImage> {
+        let mip_size = |mip| layout::mip_size(dds_header, mip);
+        let skipped_size: usize = (0..first_mip).map(mip_size).sum();
+        let skipped_tail_size: usize = (first_mip + mip_count..layout::mip_count(dds_header))
+            .map(mip_size)
+            .sum();
+        let loaded_header = layout::mip_chain_header(dds_header, first_mip, mip_count);
+        let loaded_layer_size = layout::layer_size(&loaded_header);
+
+        let mut dds_data = vec![0u8; layout::data_size(&loaded_header)];
+        for (layer, layer_data) in dds_data.chunks_exact_mut(loaded_layer_size).enumerate() {
+            // The tail of the last layer is not read at all
+            let skip = skipped_size + if layer > 0 { skipped_tail_size } else { 0 };
+            dds_file.seek(std::io::SeekFrom::Current(skip as i64))?;
+            dds_file.read_exact(layer_data)?;
+        }
+
+        Self::from_header_and_data(loaded_header, dds_data)
+    }
+
+    // Reads a single subresource by seeking to it, the rest of the payload is not read. The reader must be at the
+    // start of the file, like for `from_reader`.
+    pub fn load_subresource<T: std::io::Read + std::io::Seek>(
+        dds_file: &mut T,
+        mip: u32,
+        layer: u32,
+    ) -> Result<Vec<u8>> {
+        let file_start = dds_file.stream_position()?;
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, &ParseOptions::default())?;
+        Self::validate_header(&dds_header)?;
+
+        let range = layout::subresource_range(&dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        dds_file.seek(std::io::SeekFrom::Start(file_start + DATA_OFFSET + range.start as u64))?;
+        let mut data = vec![0u8; range.len()];
+        dds_file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Zstd supercompressed DDS files (".dds.zst"): the header is stored as is, so the file can still be probed by
+// regular DDS tools, and everything after it is a single zstd frame containing the regular DDS payload.
+
+use crate::metadata::read_metadata_chunk;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+pub const ZSTD_DEFAULT_COMPRESSION_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+impl ScratchImage {
+    pub fn from_zstd_reader<T: std::io::Read>(dds_file: &mut T) -> Result<ScratchImage> {
+        Self::from_zstd_reader_with(dds_file, &ParseOptions::default())
+    }
+
+    pub fn from_zstd_reader_with<T: std::io::Read>(dds_file: &mut T, options: &ParseOptions) -> Result<ScratchImage> {
+        let mut dds_header = Self::read_header(dds_file)?;
+        Self::apply_parse_options(&mut dds_header, options)?;
+        let mut decoder = zstd::stream::Decoder::new(dds_file)?;
+        let dds_data = Self::read_data(&mut decoder, &dds_header, true)?;
+        let image = Self::from_header_and_data(dds_header, dds_data)?;
+
+        // Metadata chunks are the only trailing data that is accepted by default
+        if !options.allow_trailing_data {
+            read_metadata_chunk(&mut decoder, &image.dds_header, options)?;
+        }
+        Ok(image)
+    }
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn write_to_zstd<T: std::io::Write>(&self, file: &mut T, compression_level: i32) -> Result<()> {
+        file.write_all(bytemuck::bytes_of(&self.dds_header))?;
+        zstd::stream::copy_encode(self.as_slice(), file, compression_level)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn is_zstd_path(path: &std::path::Path) -> bool {
+    path.extension() == Some("zst".as_ref())
+}
@@ -0,0 +1,191 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Tiled memory layouts of a single 2D surface. The surface is split into tiles stored in row-major order, elements
+// inside of a tile are stored in Morton (Z) order. Elements are texels, or blocks for block compressed formats.
+// A tile covering the whole surface gives plain Morton order, 1xN tiles give row-major order within each tile.
+// Surfaces are padded with zeroes to a whole number of tiles.
+
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+use crate::video::packed_pair_size;
+
+// Tile size in elements, both must be powers of two
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileShape {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileShape {
+    pub fn is_valid(&self) -> bool {
+        self.width.is_power_of_two() && self.height.is_power_of_two()
+    }
+}
+
+// Index of an element inside of a tile. Bits of x and y are interleaved (x first) until the smaller dimension runs
+// out, the remaining bits of the larger dimension are on top.
+pub fn morton_index(x: u32, y: u32, tile: TileShape) -> u64 {
+    let (width_bits, height_bits) = (tile.width.trailing_zeros(), tile.height.trailing_zeros());
+    let mut index = 0u64;
+    let mut shift = 0;
+    for bit in 0..width_bits.max(height_bits) {
+        if bit < width_bits {
+            index |= (((x >> bit) & 1) as u64) << shift;
+            shift += 1;
+        }
+        if bit < height_bits {
+            index |= (((y >> bit) & 1) as u64) << shift;
+            shift += 1;
+        }
+    }
+    index
+}
+
+// Element size in bytes and the surface size in elements, None for formats that have elements smaller than a byte
+pub(crate) fn element_layout(width: u32, height: u32, dxgi_format: u32) -> Option<(usize, usize, usize)> {
+    let (width, height) = (width as usize, height as usize);
+    if is_block_compressed(dxgi_format) {
+        let (block_width, block_height) = block_dimensions(dxgi_format);
+        return Some((
+            block_size(dxgi_format) as usize,
+            width.div_ceil(block_width as usize).max(1),
+            height.div_ceil(block_height as usize).max(1),
+        ));
+    }
+
+    // Pairs of texels that share chroma
+    if let Some(pair_size) = packed_pair_size(dxgi_format) {
+        return Some((pair_size, width.div_ceil(2), height));
+    }
+    if is_planar(dxgi_format) {
+        return None;
+    }
+
+    match try_bits_per_pixel(dxgi_format)? {
+        bits_per_pixel if bits_per_pixel % 8 == 0 => Some((bits_per_pixel as usize / 8, width, height)),
+        _ => None,
+    }
+}
+
+// Element size in texels
+pub(crate) fn element_extent(dxgi_format: u32) -> (u32, u32) {
+    if is_block_compressed(dxgi_format) {
+        block_dimensions(dxgi_format)
+    } else if packed_pair_size(dxgi_format).is_some() {
+        (2, 1)
+    } else {
+        (1, 1)
+    }
+}
+
+fn linear_size(element_size: usize, columns: usize, rows: usize) -> Option<usize> {
+    columns.checked_mul(rows)?.checked_mul(element_size)
+}
+
+// Size of the swizzled surface in bytes, including the padding
+pub fn swizzled_size(width: u32, height: u32, dxgi_format: u32, tile: TileShape) -> Option<usize> {
+    let (element_size, columns, rows) = element_layout(width, height, dxgi_format)?;
+    if !tile.is_valid() {
+        return None;
+    }
+    let tile_columns = columns.div_ceil(tile.width as usize);
+    let tile_rows = rows.div_ceil(tile.height as usize);
+    tile_columns
+        .checked_mul(tile_rows)?
+        .checked_mul(tile.width as usize)?
+        .checked_mul(tile.height as usize)?
+        .checked_mul(element_size)
+}
+
+// Calls `copy(linear offset, swizzled offset)` for every element of the surface
+fn for_each_element(
+    columns: usize,
+    rows: usize,
+    element_size: usize,
+    tile: TileShape,
+    mut copy: impl FnMut(usize, usize),
+) {
+    let (tile_width, tile_height) = (tile.width as usize, tile.height as usize);
+    let tile_size = tile_width * tile_height;
+    let tile_columns = columns.div_ceil(tile_width);
+
+    for y in 0..rows {
+        for x in 0..columns {
+            let tile_index = (y / tile_height) * tile_columns + x / tile_width;
+            let element = morton_index((x % tile_width) as u32, (y % tile_height) as u32, tile) as usize;
+            copy(
+                (y * columns + x) * element_size,
+                (tile_index * tile_size + element) * element_size,
+            );
+        }
+    }
+}
+
+// None if the format is not supported, the tile shape is not valid or `data` is not a complete linear surface
+pub fn swizzle_surface(data: &[u8], width: u32, height: u32, dxgi_format: u32, tile: TileShape) -> Option<Vec<u8>> {
+    let (element_size, columns, rows) = element_layout(width, height, dxgi_format)?;
+    if Some(data.len()) != linear_size(element_size, columns, rows) {
+        return None;
+    }
+
+    let mut swizzled = vec![0u8; swizzled_size(width, height, dxgi_format, tile)?];
+    for_each_element(columns, rows, element_size, tile, |linear, tiled| {
+        swizzled[tiled..tiled + element_size].copy_from_slice(&data[linear..linear + element_size]);
+    });
+    Some(swizzled)
+}
+
+// Inverse of `swizzle_surface`, the padding is dropped
+pub fn unswizzle_surface(data: &[u8], width: u32, height: u32, dxgi_format: u32, tile: TileShape) -> Option<Vec<u8>> {
+    let (element_size, columns, rows) = element_layout(width, height, dxgi_format)?;
+    if data.len() != swizzled_size(width, height, dxgi_format, tile)? {
+        return None;
+    }
+
+    let mut linear = vec![0u8; linear_size(element_size, columns, rows)?];
+    for_each_element(columns, rows, element_size, tile, |offset, tiled| {
+        linear[offset..offset + element_size].copy_from_slice(&data[tiled..tiled + element_size]);
+    });
+    Some(linear)
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn swizzle_subresource(&self, mip: u32, layer: u32, tile: TileShape) -> Result<Vec<u8>> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        self.check_swizzle(width, height, tile)?;
+
+        swizzle_surface(&self.as_slice()[range], width, height, self.dxgi_format(), tile)
+            .ok_or(Error::BadImageDescription("Subresource can't be swizzled"))
+    }
+
+    fn check_swizzle(&self, width: u32, height: u32, tile: TileShape) -> Result<()> {
+        if !tile.is_valid() {
+            return Err(Error::BadImageDescription("Tile size must be a power of two"));
+        }
+        if element_layout(width, height, self.dxgi_format()).is_none() {
+            return Err(Error::UnsupportedFormat(self.dxgi_format()));
+        }
+        Ok(())
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    // Replaces a subresource with linear data restored from `swizzled`
+    pub fn unswizzle_subresource(&mut self, mip: u32, layer: u32, swizzled: &[u8], tile: TileShape) -> Result<()> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        self.check_swizzle(width, height, tile)?;
+
+        let linear = unswizzle_surface(swizzled, width, height, self.dxgi_format(), tile).ok_or(
+            Error::BadImageDescription("Swizzled data size does not match the subresource"),
+        )?;
+        self.as_slice_mut()[range].copy_from_slice(&linear);
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Tiled (sparse) resources: 2D subresources split into 64KB tiles with the standard tile shapes, which are the same
+// for D3D12 and the Vulkan standard sparse image block shapes of single sampled images.
+// Tile data is row-major, as used by CopyTiles and buffer to image copies, edge tiles are padded with zeroes.
+// Mips smaller than a tile in either dimension are not tiled, they form the packed mip tail of every layer.
+
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+use crate::swizzle::element_layout;
+
+pub const TILE_SIZE_IN_BYTES: usize = 65536;
+
+// Tile size in texels, None for formats that can't be used with tiled resources
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d11/texture2d-and-texture2darray-subresource-tiling"]
+pub fn standard_tile_shape(dxgi_format: u32) -> Option<(u32, u32)> {
+    if is_legacy_yuv(dxgi_format) || is_astc(dxgi_format) {
+        return None;
+    }
+    if is_block_compressed(dxgi_format) {
+        return match block_size(dxgi_format) {
+            8 => Some((512, 256)),
+            _ => Some((256, 256)),
+        };
+    }
+
+    match try_bits_per_pixel(dxgi_format)? {
+        8 => Some((256, 256)),
+        16 => Some((256, 128)),
+        32 => Some((128, 128)),
+        64 => Some((128, 64)),
+        128 => Some((64, 64)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceTile {
+    pub mip: u32,
+    pub layer: u32,
+    // Tile coordinates inside of the subresource
+    pub x: u32,
+    pub y: u32,
+    // TILE_SIZE_IN_BYTES bytes
+    pub data: Vec<u8>,
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // First mip of the packed mip tail, equal to the mip count if every mip is tiled
+    pub fn first_packed_mip(&self) -> Result<u32> {
+        let (tile_width, tile_height) = self.tile_shape()?;
+        Ok((0..layout::mip_count(&self.dds_header))
+            .find(|&mip| {
+                let (width, height) = layout::mip_extent(&self.dds_header, mip);
+                width < tile_width || height < tile_height
+            })
+            .unwrap_or_else(|| layout::mip_count(&self.dds_header)))
+    }
+
+    // Tiles of every layer and tiled mip, in subresource order, tiles of a subresource are in row-major order
+    pub fn tiles(&self) -> Result<impl Iterator<Item = ResourceTile> + '_> {
+        let (tile_width, tile_height) = self.tile_shape()?;
+        let first_packed_mip = self.first_packed_mip()?;
+
+        let subresources = (0..layout::layer_count(&self.dds_header))
+            .flat_map(move |layer| (0..first_packed_mip).map(move |mip| (mip, layer)));
+        Ok(subresources.flat_map(move |(mip, layer)| {
+            let (width, height) = layout::mip_extent(&self.dds_header, mip);
+            let (columns, rows) = (width.div_ceil(tile_width), height.div_ceil(tile_height));
+            (0..rows).flat_map(move |y| (0..columns).map(move |x| self.tile(mip, layer, x, y)))
+        }))
+    }
+
+    // Payload of the mips that are not tiled, as laid out in the DDS file
+    pub fn packed_mip_tail(&self, layer: u32) -> Result<&[u8]> {
+        let first_packed_mip = self.first_packed_mip()?;
+        let mip_count = layout::mip_count(&self.dds_header);
+        let last = layout::subresource_range(&self.dds_header, mip_count - 1, layer).ok_or(Error::BadSubresource)?;
+        if first_packed_mip == mip_count {
+            return Ok(&[]);
+        }
+
+        let first = layout::subresource_range(&self.dds_header, first_packed_mip, layer).unwrap();
+        Ok(&self.as_slice()[first.start..last.end])
+    }
+
+    fn tile_shape(&self) -> Result<(u32, u32)> {
+        if !self.is_texture2d() || self.image_depth() > 1 {
+            return Err(Error::NotImplementedYet("Tiling of 1D and volume textures"));
+        }
+        standard_tile_shape(self.dxgi_format()).ok_or(Error::UnsupportedFormat(self.dxgi_format()))
+    }
+
+    fn tile(&self, mip: u32, layer: u32, x: u32, y: u32) -> ResourceTile {
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        let (tile_width, tile_height) = standard_tile_shape(self.dxgi_format()).unwrap();
+        let (element_size, columns, rows) = element_layout(width, height, self.dxgi_format()).unwrap();
+        let (tile_columns, tile_rows) = element_layout(tile_width, tile_height, self.dxgi_format())
+            .map(|(_, columns, rows)| (columns, rows))
+            .unwrap();
+
+        let range = layout::subresource_range(&self.dds_header, mip, layer).unwrap();
+        let surface = &self.as_slice()[range];
+        let mut data = vec![0u8; TILE_SIZE_IN_BYTES];
+
+        let (first_column, first_row) = (x as usize * tile_columns, y as usize * tile_rows);
+        let row_size = tile_columns.min(columns - first_column) * element_size;
+        for row in 0..tile_rows.min(rows - first_row) {
+            let offset = ((first_row + row) * columns + first_column) * element_size;
+            let tile_offset = row * tile_columns * element_size;
+            data[tile_offset..tile_offset + row_size].copy_from_slice(&surface[offset..offset + row_size]);
+        }
+
+        ResourceTile { mip, layer, x, y, data }
+    }
+}
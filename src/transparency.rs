@@ -0,0 +1,105 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Operations on transparent texels of images with straight alpha.
+
+use crate::encode::can_encode;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::{Storage, StorageMut};
+
+impl<S: Storage> ScratchImage<S> {
+    // Makes texels that differ from `key` by at most `tolerance` in every RGB channel transparent, other texels become
+    // opaque. Colors are kept, so filtering can still blend the key color into edges, see `dilate_edges`.
+    // `dxgi_format` must have alpha, keyed images usually don't.
+    pub fn color_key_to_alpha(&self, key: [f32; 3], tolerance: f32, dxgi_format: u32) -> Result<ScratchImage> {
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+        if !has_alpha(dxgi_format) {
+            return Err(Error::BadImageDescription("Color key target format must have alpha"));
+        }
+
+        let mut desc = self.desc();
+        desc.dxgi_format = dxgi_format;
+        let mut image = ScratchImage::with_desc(&desc)?;
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let mut texels = self.decode_subresource(mip, layer)?;
+                for texel in texels.iter_mut() {
+                    let is_key = (0..3).all(|channel| (texel[channel] - key[channel]).abs() <= tolerance);
+                    texel[3] = if is_key { 0.0 } else { 1.0 };
+                }
+                image.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(image)
+    }
+}
+
+impl<S: StorageMut> ScratchImage<S> {
+    // Fills the color of transparent texels from their opaque neighbours, growing opaque regions by one texel per
+    // iteration in every mip, so filtering and mips don't blend in the color of transparent texels. Alpha is kept.
+    // A texel is transparent if its alpha is 0.
+    pub fn dilate_edges(&mut self, iterations: u32) -> Result<()> {
+        let dxgi_format = self.dxgi_format();
+        if !has_alpha(dxgi_format) {
+            return Err(Error::BadImageDescription("Edge dilation needs a format with alpha"));
+        }
+        if !can_encode(dxgi_format) {
+            return Err(Error::UnsupportedFormat(dxgi_format));
+        }
+
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let (width, height) = layout::mip_extent(&self.dds_header, mip);
+                let mut texels = self.decode_subresource(mip, layer)?;
+                for slice in texels.chunks_exact_mut(width as usize * height as usize) {
+                    dilate(slice, width as usize, height as usize, iterations);
+                }
+                self.encode_subresource(mip, layer, &texels)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn dilate(texels: &mut [[f32; 4]], width: usize, height: usize, iterations: u32) {
+    let mut filled: Vec<bool> = texels.iter().map(|texel| texel[3] > 0.0).collect();
+    for _ in 0..iterations {
+        let mut grown = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if filled[y * width + x] {
+                    continue;
+                }
+
+                let mut sum = [0.0f32; 3];
+                let mut count = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if filled[ny * width + nx] {
+                            let neighbour = texels[ny * width + nx];
+                            (0..3).for_each(|channel| sum[channel] += neighbour[channel]);
+                            count += 1;
+                        }
+                    }
+                }
+                if count > 0 {
+                    grown.push((y * width + x, sum.map(|channel| channel / count as f32)));
+                }
+            }
+        }
+
+        if grown.is_empty() {
+            break;
+        }
+        for (index, color) in grown {
+            texels[index][..3].copy_from_slice(&color);
+            filled[index] = true;
+        }
+    }
+}
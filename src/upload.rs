@@ -0,0 +1,99 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Layout of a staging buffer for uploading the payload to a GPU texture, the analogue of D3D12
+// GetCopyableFootprints. Rows of the DDS payload are tightly packed, staging rows and subresources are padded to
+// the alignments required by the graphics API (256 and 512 bytes for D3D12, optimalBufferCopy* limits for Vulkan).
+
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UploadCopy {
+    // D3D subresource index: mip + layer * mip count, cubemap faces are layers
+    pub subresource: u32,
+    pub mip: u32,
+    pub layer: u32,
+    // Offset of the subresource in the DDS payload
+    pub source_offset: u64,
+    pub destination_offset: u64,
+    // Size of the subresource in texels
+    pub width: u32,
+    pub height: u32,
+    // Depth slices of volume textures, 1 for other images
+    pub depth: u32,
+    // Rows of texels, or rows of blocks for block compressed formats, in a single depth slice. `row_size` bytes of
+    // every row are copied. Slices of the staging buffer follow each other without padding.
+    pub row_count: u32,
+    pub row_size: u64,
+    pub destination_row_pitch: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UploadPlan {
+    // In subresource order
+    pub copies: Vec<UploadCopy>,
+    pub staging_size: u64,
+}
+
+impl UploadPlan {
+    // Copies the payload of `image` into a staging buffer of at least `staging_size` bytes
+    pub fn fill_staging<S: Storage>(&self, image: &ScratchImage<S>, staging: &mut [u8]) {
+        let data = image.as_slice();
+        for copy in self.copies.iter() {
+            for row in 0..copy.row_count as u64 * copy.depth as u64 {
+                let source = (copy.source_offset + row * copy.row_size) as usize;
+                let destination = (copy.destination_offset + row * copy.destination_row_pitch) as usize;
+                let row_size = copy.row_size as usize;
+                staging[destination..destination + row_size].copy_from_slice(&data[source..source + row_size]);
+            }
+        }
+    }
+}
+
+fn align(size: u64, alignment: u64) -> u64 {
+    size.div_ceil(alignment) * alignment
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // Alignments are in bytes and must not be zero
+    pub fn upload_plan(&self, row_alignment: u64, placement_alignment: u64) -> Result<UploadPlan> {
+        if row_alignment == 0 || placement_alignment == 0 {
+            return Err(Error::BadImageDescription("Upload alignments must not be zero"));
+        }
+
+        let mut plan = UploadPlan::default();
+        for layer in 0..layout::layer_count(&self.dds_header) {
+            for mip in 0..layout::mip_count(&self.dds_header) {
+                let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+                let (width, height) = layout::mip_extent(&self.dds_header, mip);
+                let (row_size, linear_size) = checked_pitch_and_linear_size(width, height, self.dxgi_format())
+                    .ok_or(Error::UnsupportedFormat(self.dxgi_format()))?;
+                let row_count = linear_size / row_size;
+                let depth = layout::mip_depth(&self.dds_header, mip);
+                let destination_row_pitch = align(row_size, row_alignment);
+
+                let destination_offset = align(plan.staging_size, placement_alignment);
+                plan.staging_size = destination_offset + destination_row_pitch * row_count * depth as u64;
+                plan.copies.push(UploadCopy {
+                    subresource: layout::subresource_index(&self.dds_header, mip, layer).unwrap(),
+                    mip,
+                    layer,
+                    source_offset: range.start as u64,
+                    destination_offset,
+                    width,
+                    height,
+                    depth,
+                    row_count: row_count as u32,
+                    row_size,
+                    destination_row_pitch,
+                });
+            }
+        }
+        Ok(plan)
+    }
+}
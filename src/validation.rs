@@ -0,0 +1,678 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::builder::max_mipmap_count;
+use crate::dds::*;
+use crate::flipbook::FLIPBOOK_TAG;
+use crate::format::*;
+use crate::layout;
+use crate::scratch_image::{ScratchImage, DATA_OFFSET};
+use crate::signature::read_tool_signature;
+use crate::storage::Storage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationLevel {
+    // Problems that make the image unusable or ambiguous
+    Basic,
+    // Also report header flags and caps that are inconsistent with the image description
+    Strict,
+    // Also report images that can't be created as D3D11 or D3D12 resources
+    Hardware,
+}
+
+impl ValidationLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            ValidationLevel::Basic => "basic",
+            ValidationLevel::Strict => "strict",
+            ValidationLevel::Hardware => "hardware",
+        }
+    }
+}
+
+// D3D11 and D3D12 resource limits, as in d3d12.h
+const D3D12_REQ_TEXTURE1D_U_DIMENSION: u32 = 16384;
+const D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION: u32 = 16384;
+const D3D12_REQ_TEXTURE3D_U_V_OR_W_DIMENSION: u32 = 2048;
+const D3D12_REQ_TEXTURECUBE_DIMENSION: u32 = 16384;
+const D3D12_REQ_TEXTURE1D_ARRAY_AXIS_DIMENSION: u32 = 2048;
+const D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION: u32 = 2048;
+
+// File offset of a header field, in the layout with the DXT10 header that images are written with
+macro_rules! field_offset {
+    ($($field: tt)+) => {
+        Some(std::mem::offset_of!(DirectDrawHeader, $($field)+) as u64)
+    };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    // File offset of the header field with the problem, or of the data for data size problems. None if the problem
+    // is not in a single field.
+    pub offset: Option<u64>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity.name(), self.message)?;
+        match self.offset {
+            Some(offset) => write!(f, " at offset {}", offset),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn error(&mut self, offset: Option<u64>, message: String) {
+        self.0.push(Diagnostic {
+            severity: Severity::Error,
+            message,
+            offset,
+        });
+    }
+
+    fn warning(&mut self, offset: Option<u64>, message: String) {
+        self.0.push(Diagnostic {
+            severity: Severity::Warning,
+            message,
+            offset,
+        });
+    }
+}
+
+// Names of the checks in validation reports
+pub const CHECK_DESCRIPTION: &str = "description";
+pub const CHECK_FLAGS: &str = "flags";
+pub const CHECK_MIP_CHAIN: &str = "mip_chain";
+pub const CHECK_HARDWARE_LIMITS: &str = "hardware_limits";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+    pub check: &'static str,
+    // Empty if the check passed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckResult {
+    // Warnings don't fail a check
+    pub fn passed(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity != Severity::Error)
+    }
+}
+
+// Results of every check that runs at the validation level, in the order they ran
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub level: ValidationLevel,
+    pub checks: Vec<CheckResult>,
+}
+
+impl ValidationReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(CheckResult::passed)
+    }
+
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.checks.iter().flat_map(|check| check.diagnostics.iter())
+    }
+
+    // None if there are no diagnostics
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.diagnostics().map(|diagnostic| diagnostic.severity).max()
+    }
+
+    // JSON object with the level, the overall result and the checks, e.g.
+    // {"level":"basic","passed":false,"checks":[{"check":"description","passed":false,"diagnostics":
+    // [{"severity":"error","message":"pitch is 0, expected 16","offset":20}]}]}
+    pub fn to_json(&self) -> String {
+        let checks: Vec<String> = self
+            .checks
+            .iter()
+            .map(|check| {
+                let diagnostics: Vec<String> = check
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| {
+                        format!(
+                            "{{\"severity\":\"{}\",\"message\":{},\"offset\":{}}}",
+                            diagnostic.severity.name(),
+                            json_string(&diagnostic.message),
+                            diagnostic
+                                .offset
+                                .map_or_else(|| "null".to_string(), |offset| offset.to_string())
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"check\":{},\"passed\":{},\"diagnostics\":[{}]}}",
+                    json_string(check.check),
+                    check.passed(),
+                    diagnostics.join(",")
+                )
+            })
+            .collect();
+        format!(
+            "{{\"level\":\"{}\",\"passed\":{},\"checks\":[{}]}}",
+            self.level.name(),
+            self.passed(),
+            checks.join(",")
+        )
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            character if character.is_control() => json.push_str(&format!("\\u{:04x}", character as u32)),
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl<S: Storage> ScratchImage<S> {
+    pub fn validate(&self, level: ValidationLevel) -> Vec<Diagnostic> {
+        self.validation_report(level)
+            .checks
+            .into_iter()
+            .flat_map(|check| check.diagnostics)
+            .collect()
+    }
+
+    pub fn validation_report(&self, level: ValidationLevel) -> ValidationReport {
+        validation_report(&self.dds_header, Some(self.data_size()), level)
+    }
+}
+
+// `data_size` is None if the payload is not available
+pub(crate) fn validation_report(
+    header: &DirectDrawHeader,
+    data_size: Option<usize>,
+    level: ValidationLevel,
+) -> ValidationReport {
+    let run = |check: &'static str, validate: &dyn Fn(&mut Diagnostics)| {
+        let mut diagnostics = Diagnostics(Vec::new());
+        validate(&mut diagnostics);
+        CheckResult {
+            check,
+            diagnostics: diagnostics.0,
+        }
+    };
+
+    let mut checks = vec![run(CHECK_DESCRIPTION, &|diagnostics| {
+        validate_description(header, data_size, diagnostics)
+    })];
+    if level != ValidationLevel::Basic {
+        checks.push(run(CHECK_FLAGS, &|diagnostics| validate_flags(header, diagnostics)));
+        checks.push(run(CHECK_MIP_CHAIN, &|diagnostics| {
+            validate_mip_chain(header, diagnostics)
+        }));
+    }
+    if level == ValidationLevel::Hardware {
+        checks.push(run(CHECK_HARDWARE_LIMITS, &|diagnostics| {
+            validate_hardware_limits(header, diagnostics)
+        }));
+    }
+    ValidationReport { level, checks }
+}
+
+// Header flags and caps that are inconsistent with the image description
+pub(crate) fn flag_diagnostics(header: &DirectDrawHeader) -> Vec<Diagnostic> {
+    let mut diagnostics = Diagnostics(Vec::new());
+    validate_flags(header, &mut diagnostics);
+    diagnostics.0
+}
+
+// Problems that don't prevent the image from being read, in a header as it is stored in the file
+pub(crate) fn parse_warnings(header: &DirectDrawHeader) -> Vec<Diagnostic> {
+    let mut diagnostics = Diagnostics(Vec::new());
+    if header.mipmap_count == 0 {
+        diagnostics.warning(
+            field_offset!(mipmap_count),
+            "mipmap count is zero, read as a single mip".to_string(),
+        );
+    }
+
+    let pitch_or_linear_size = layout::pitch_or_linear_size(header.width, header.height, header.dxt10.dxgi_format);
+    if header.pitch_or_linear_size != pitch_or_linear_size {
+        diagnostics.warning(
+            field_offset!(pitch_or_linear_size),
+            format!(
+                "pitch or linear size is {}, expected {}",
+                header.pitch_or_linear_size, pitch_or_linear_size
+            ),
+        );
+    }
+
+    validate_flags(header, &mut diagnostics);
+    validate_reserved(header, &mut diagnostics);
+    diagnostics.0
+}
+
+fn validate_description(header: &DirectDrawHeader, data_size: Option<usize>, diagnostics: &mut Diagnostics) {
+    let dxgi_format = header.dxt10.dxgi_format;
+    if try_bits_per_pixel(dxgi_format).is_none() {
+        diagnostics.error(
+            field_offset!(dxt10.dxgi_format),
+            format!("unknown or unsupported DXGI format {}", dxgi_format),
+        );
+        return;
+    }
+
+    let is_texture1d = header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D;
+    if header.width == 0 || (header.height == 0 && !is_texture1d) {
+        diagnostics.error(
+            field_offset!(width),
+            format!("zero image size {}x{}", header.width, header.height),
+        );
+        return;
+    }
+
+    if layout::checked_data_size(header).is_none() {
+        diagnostics.error(
+            None,
+            format!(
+                "image size overflows: {}x{}x{}, {} mips, array size {}",
+                header.width, header.height, header.depth, header.mipmap_count, header.dxt10.array_size
+            ),
+        );
+        return;
+    }
+
+    let mip_count = layout::mip_count(header);
+    let max_mip_count = full_mip_count(header);
+    if mip_count > max_mip_count {
+        diagnostics.error(
+            field_offset!(mipmap_count),
+            format!(
+                "mipmap count {} exceeds the full mip chain length {}",
+                mip_count, max_mip_count
+            ),
+        );
+    }
+
+    let (row_pitch, linear_size) = pitch_and_linear_size(header.width, header.height, dxgi_format);
+    if is_block_compressed(dxgi_format) {
+        if header.pitch_or_linear_size != linear_size {
+            diagnostics.error(
+                field_offset!(pitch_or_linear_size),
+                format!(
+                    "linear size is {}, expected {}",
+                    header.pitch_or_linear_size, linear_size
+                ),
+            );
+        }
+    } else if header.pitch_or_linear_size != row_pitch {
+        diagnostics.error(
+            field_offset!(pitch_or_linear_size),
+            format!("pitch is {}, expected {}", header.pitch_or_linear_size, row_pitch),
+        );
+    }
+
+    let is_cubemap = layout::face_count(header) == 6;
+    match header.dxt10.resource_dimension {
+        D3D10_RESOURCE_DIMENSION_TEXTURE1D => {
+            if header.height > 1 {
+                diagnostics.error(
+                    field_offset!(height),
+                    format!("1D texture has height {}", header.height),
+                );
+            }
+            if is_cubemap {
+                diagnostics.error(
+                    field_offset!(dxt10.misc_flag),
+                    "1D texture is marked as a cubemap".to_string(),
+                );
+            }
+        }
+        D3D10_RESOURCE_DIMENSION_TEXTURE2D => {
+            if header.depth > 1 {
+                diagnostics.warning(
+                    field_offset!(depth),
+                    format!("2D texture has depth {}, which is ignored", header.depth),
+                );
+            }
+        }
+        D3D10_RESOURCE_DIMENSION_TEXTURE3D => {
+            if header.dxt10.array_size > 1 {
+                diagnostics.error(
+                    field_offset!(dxt10.array_size),
+                    format!("3D texture has array size {}", header.dxt10.array_size),
+                );
+            }
+            if is_cubemap {
+                diagnostics.error(
+                    field_offset!(dxt10.misc_flag),
+                    "3D texture is marked as a cubemap".to_string(),
+                );
+            }
+        }
+        resource_dimension => {
+            diagnostics.error(
+                field_offset!(dxt10.resource_dimension),
+                format!("invalid resource dimension {}", resource_dimension),
+            );
+        }
+    }
+
+    if is_cubemap && header.width != header.height {
+        diagnostics.error(
+            None,
+            format!("cubemap faces are not square: {}x{}", header.width, header.height),
+        );
+    }
+
+    if header.dxt10.misc_flags2 > DDS_ALPHA_MODE_CUSTOM {
+        diagnostics.warning(
+            field_offset!(dxt10.misc_flags2),
+            format!("invalid alpha mode {}", header.dxt10.misc_flags2),
+        );
+    }
+
+    if let Some(data_size) = data_size {
+        let expected_data_size = layout::data_size(header);
+        if data_size != expected_data_size {
+            diagnostics.error(
+                Some(DATA_OFFSET),
+                format!("data size is {} bytes, expected {}", data_size, expected_data_size),
+            );
+        }
+    }
+}
+
+fn validate_flags(header: &DirectDrawHeader, diagnostics: &mut Diagnostics) {
+    let required_flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+    if header.flags & required_flags != required_flags {
+        diagnostics.warning(
+            field_offset!(flags),
+            format!(
+                "required header flags are missing: {:#x}",
+                required_flags & !header.flags
+            ),
+        );
+    }
+
+    if header.pixel_format.flags & DDPF_FOURCC == 0 {
+        diagnostics.warning(
+            field_offset!(pixel_format.flags),
+            "DDPF_FOURCC is not set for a DX10 header".to_string(),
+        );
+    }
+
+    if header.caps & DDSCAPS_TEXTURE == 0 {
+        diagnostics.warning(field_offset!(caps), "DDSCAPS_TEXTURE is not set".to_string());
+    }
+
+    let is_compressed = is_block_compressed(header.dxt10.dxgi_format);
+    if is_compressed && header.flags & DDSD_LINEARSIZE == 0 {
+        diagnostics.warning(
+            field_offset!(flags),
+            "DDSD_LINEARSIZE is not set for a block compressed format".to_string(),
+        );
+    }
+    if !is_compressed && header.flags & DDSD_PITCH == 0 {
+        diagnostics.warning(
+            field_offset!(flags),
+            "DDSD_PITCH is not set for an uncompressed format".to_string(),
+        );
+    }
+
+    let has_mips = header.mipmap_count > 1;
+    if has_mips && header.flags & DDSD_MIPMAPCOUNT == 0 {
+        diagnostics.warning(
+            field_offset!(flags),
+            "DDSD_MIPMAPCOUNT is not set for an image with mipmaps".to_string(),
+        );
+    }
+    if has_mips && header.caps & DDSCAPS_MIPMAP == 0 {
+        diagnostics.warning(
+            field_offset!(caps),
+            "DDSCAPS_MIPMAP is not set for an image with mipmaps".to_string(),
+        );
+    }
+
+    let is_cubemap = layout::face_count(header) == 6;
+    let cubemap_caps2 = DDSCAPS2_CUBEMAP
+        | DDSCAPS2_CUBEMAP_POSITIVEX
+        | DDSCAPS2_CUBEMAP_NEGATIVEX
+        | DDSCAPS2_CUBEMAP_POSITIVEY
+        | DDSCAPS2_CUBEMAP_NEGATIVEY
+        | DDSCAPS2_CUBEMAP_POSITIVEZ
+        | DDSCAPS2_CUBEMAP_NEGATIVEZ;
+    if is_cubemap && header.caps2 & cubemap_caps2 != cubemap_caps2 {
+        diagnostics.warning(
+            field_offset!(caps2),
+            "cubemap is missing DDSCAPS2_CUBEMAP face bits".to_string(),
+        );
+    }
+    if !is_cubemap && header.caps2 & cubemap_caps2 != 0 {
+        diagnostics.warning(
+            field_offset!(caps2),
+            "DDSCAPS2_CUBEMAP bits are set, but DX10 header is not a cubemap".to_string(),
+        );
+    }
+
+    let is_volume = header.dxt10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D;
+    if is_volume && header.caps2 & DDSCAPS2_VOLUME == 0 {
+        diagnostics.warning(
+            field_offset!(caps2),
+            "DDSCAPS2_VOLUME is not set for a 3D texture".to_string(),
+        );
+    }
+    if is_volume && header.flags & DDSD_DEPTH == 0 {
+        diagnostics.warning(
+            field_offset!(flags),
+            "DDSD_DEPTH is not set for a 3D texture".to_string(),
+        );
+    }
+    if !is_volume && header.caps2 & DDSCAPS2_VOLUME != 0 {
+        diagnostics.warning(
+            field_offset!(caps2),
+            "DDSCAPS2_VOLUME is set, but DX10 header is not a 3D texture".to_string(),
+        );
+    }
+
+    if (has_mips || is_cubemap || is_volume) && header.caps & DDSCAPS_COMPLEX == 0 {
+        diagnostics.warning(
+            field_offset!(caps),
+            "DDSCAPS_COMPLEX is not set for a complex surface".to_string(),
+        );
+    }
+}
+
+// Known uses of the reserved fields are flipbook metadata in the first 4 and a writer tag and version in the last 2
+fn validate_reserved(header: &DirectDrawHeader, diagnostics: &mut Diagnostics) {
+    let reserved = &header.reserved;
+    let first_unknown = if reserved[0] == FLIPBOOK_TAG { 4 } else { 0 };
+    let last_unknown = match read_tool_signature(header) {
+        Some(_) => 9,
+        None => reserved.len(),
+    };
+
+    for (index, &value) in reserved.iter().enumerate().take(last_unknown).skip(first_unknown) {
+        if value != 0 {
+            diagnostics.warning(
+                field_offset!(reserved).map(|offset| offset + index as u64 * 4),
+                format!("unknown data {:#x} in reserved header field {}", value, index),
+            );
+        }
+    }
+    if header.reserved2 != 0 {
+        diagnostics.warning(
+            field_offset!(reserved2),
+            format!("unknown data {:#x} in reserved2 header field", header.reserved2),
+        );
+    }
+}
+
+// Height and depth only count for the dimensions that have them, other writers can leave any value there
+fn full_mip_count(header: &DirectDrawHeader) -> u32 {
+    let (width, height) = layout::mip_extent(header, 0);
+    max_mipmap_count(width, height, layout::mip_depth(header, 0))
+}
+
+fn validate_mip_chain(header: &DirectDrawHeader, diagnostics: &mut Diagnostics) {
+    let mip_count = layout::mip_count(header);
+    let max_mip_count = full_mip_count(header);
+    if mip_count > 1 && mip_count < max_mip_count {
+        diagnostics.warning(
+            field_offset!(mipmap_count),
+            format!("incomplete mip chain: {} of {} mips", mip_count, max_mip_count),
+        );
+    }
+}
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d11/overviews-direct3d-11-resources-limits"]
+fn validate_hardware_limits(header: &DirectDrawHeader, diagnostics: &mut Diagnostics) {
+    let dxgi_format = header.dxt10.dxgi_format;
+    let is_cubemap = layout::face_count(header) == 6;
+    let (max_extent, max_layer_count, dimension_name) = match header.dxt10.resource_dimension {
+        D3D10_RESOURCE_DIMENSION_TEXTURE1D => (
+            D3D12_REQ_TEXTURE1D_U_DIMENSION,
+            D3D12_REQ_TEXTURE1D_ARRAY_AXIS_DIMENSION,
+            "1D texture",
+        ),
+        D3D10_RESOURCE_DIMENSION_TEXTURE3D => (D3D12_REQ_TEXTURE3D_U_V_OR_W_DIMENSION, 1, "3D texture"),
+        _ if is_cubemap => (
+            D3D12_REQ_TEXTURECUBE_DIMENSION,
+            D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION,
+            "cubemap",
+        ),
+        _ => (
+            D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+            D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION,
+            "2D texture",
+        ),
+    };
+
+    let depth = match header.dxt10.resource_dimension {
+        D3D10_RESOURCE_DIMENSION_TEXTURE3D => header.depth,
+        _ => 1,
+    };
+    if header.width > max_extent || header.height > max_extent || depth > max_extent {
+        diagnostics.error(
+            field_offset!(width),
+            format!(
+                "{} size {}x{}x{} exceeds the D3D limit of {}",
+                dimension_name, header.width, header.height, depth, max_extent
+            ),
+        );
+    }
+
+    // Every cubemap face is an array slice
+    let slice_count = layout::layer_count(header);
+    if max_layer_count > 1 && slice_count > max_layer_count {
+        diagnostics.error(
+            field_offset!(dxt10.array_size),
+            format!(
+                "{} has {} array slices, exceeds the D3D limit of {}",
+                dimension_name, slice_count, max_layer_count
+            ),
+        );
+    }
+
+    if is_astc(dxgi_format) {
+        diagnostics.error(
+            field_offset!(dxt10.dxgi_format),
+            format!("ASTC format {} is not supported by D3D", dxgi_format),
+        );
+    } else if is_block_compressed(dxgi_format) {
+        // Only the top mip, smaller mips are padded to whole blocks
+        let (block_width, block_height) = block_dimensions(dxgi_format);
+        if !header.width.is_multiple_of(block_width) || !header.height.is_multiple_of(block_height) {
+            diagnostics.error(
+                field_offset!(width),
+                format!(
+                    "block compressed image size {}x{} is not a multiple of {}x{}",
+                    header.width, header.height, block_width, block_height
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageDesc;
+
+    fn header(width: u32, height: u32, depth: u32, mipmap_count: u32) -> DirectDrawHeader {
+        ScratchImage::new_header(&ImageDesc {
+            width,
+            height,
+            depth,
+            mipmap_count,
+            array_size: 1,
+            dxgi_format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            is_cubemap: false,
+            dimension: None,
+        })
+    }
+
+    fn messages(header: &DirectDrawHeader) -> Vec<String> {
+        validation_report(header, None, ValidationLevel::Strict)
+            .checks
+            .into_iter()
+            .flat_map(|check| check.diagnostics)
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn mip_chain_of_2d_textures_ignores_depth() {
+        let mut full_chain = header(4, 4, 1, 3);
+        full_chain.depth = 64;
+        assert!(!messages(&full_chain).iter().any(|message| message.contains("mip")));
+
+        let mut long_chain = header(4, 4, 1, 3);
+        long_chain.depth = 64;
+        long_chain.mipmap_count = 7;
+        assert!(messages(&long_chain)
+            .iter()
+            .any(|message| message.contains("exceeds the full mip chain")));
+    }
+
+    #[test]
+    fn mip_chain_of_1d_and_3d_textures() {
+        let mut texture1d = header(8, 1, 1, 4);
+        texture1d.height = 64;
+        assert!(!messages(&texture1d).iter().any(|message| message.contains("mip")));
+
+        let volume = header(4, 4, 16, 3);
+        assert!(messages(&volume)
+            .iter()
+            .any(|message| message.contains("incomplete mip chain")));
+        assert!(!messages(&header(4, 4, 16, 5))
+            .iter()
+            .any(|message| message.contains("mip")));
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright (c) 2021 Kyrylo Bazhenov
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Video formats. YUV values are studio range BT.601 like in Media Foundation, 10 bit formats that are stored in
+// 16 bit channels use the upper bits, so they are treated as 16 bit values.
+// The 4:2:2 formats store pairs of texels that share chroma, an odd width repeats the last texel.
+// Planar formats store a subresource as a luma plane followed by the chroma planes, see `plane_layouts`.
+
+use crate::dds::*;
+use crate::layout;
+use crate::scratch_image::*;
+use crate::storage::Storage;
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/medfound/recommended-8-bit-yuv-formats-for-video-rendering"]
+// Code values of the given bit depth to RGB in [0, 1], not clamped
+pub fn ycbcr_to_rgb(y: u32, cb: u32, cr: u32, bit_depth: u32) -> [f32; 3] {
+    let scale = (1u32 << (bit_depth - 8)) as f32;
+    let y = (y as f32 - 16.0 * scale) / (219.0 * scale);
+    let cb = (cb as f32 - 128.0 * scale) / (224.0 * scale);
+    let cr = (cr as f32 - 128.0 * scale) / (224.0 * scale);
+
+    [y + 1.402 * cr, y - 0.344136 * cb - 0.714136 * cr, y + 1.772 * cb]
+}
+
+// Inverse of `ycbcr_to_rgb`, code values are rounded and clamped to the range of the bit depth
+pub fn rgb_to_ycbcr(rgb: [f32; 3], bit_depth: u32) -> [u32; 3] {
+    let [r, g, b] = rgb;
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let cb = (b - y) / 1.772;
+    let cr = (r - y) / 1.402;
+
+    let scale = (1u32 << (bit_depth - 8)) as f32;
+    let max = ((1u64 << bit_depth) - 1) as f32;
+    let code = |value: f32| value.round().clamp(0.0, max) as u32;
+    [
+        code((16.0 + 219.0 * y) * scale),
+        code((128.0 + 224.0 * cb) * scale),
+        code((128.0 + 224.0 * cr) * scale),
+    ]
+}
+
+// Size of a pair of texels of the 4:2:2 formats
+pub(crate) fn packed_pair_size(dxgi_format: u32) -> Option<usize> {
+    match dxgi_format {
+        DXGI_FORMAT_R8G8_B8G8_UNORM | DXGI_FORMAT_G8R8_G8B8_UNORM | DXGI_FORMAT_YUY2 => Some(4),
+        DXGI_FORMAT_Y210 | DXGI_FORMAT_Y216 => Some(8),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_packed_video(dxgi_format: u32) -> bool {
+    packed_pair_size(dxgi_format).is_some()
+        || matches!(dxgi_format, DXGI_FORMAT_AYUV | DXGI_FORMAT_Y410 | DXGI_FORMAT_Y416)
+}
+
+// Size of a 4:4:4 pixel
+fn pixel_size(dxgi_format: u32) -> usize {
+    if dxgi_format == DXGI_FORMAT_Y416 {
+        8
+    } else {
+        4
+    }
+}
+
+fn read_u16(bytes: &[u8], index: usize) -> u32 {
+    u16::from_le_bytes([bytes[index * 2], bytes[index * 2 + 1]]) as u32
+}
+
+fn write_u16(bytes: &mut [u8], index: usize, value: u32) {
+    bytes[index * 2..index * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes());
+}
+
+fn rgba(rgb: [f32; 3], alpha: f32) -> [f32; 4] {
+    [rgb[0], rgb[1], rgb[2], alpha]
+}
+
+// Texels of a 4:4:4 pixel
+fn decode_444(pixel: &[u8], dxgi_format: u32) -> [f32; 4] {
+    match dxgi_format {
+        DXGI_FORMAT_AYUV => {
+            let [v, u, y, a] = [pixel[0], pixel[1], pixel[2], pixel[3]].map(|value| value as u32);
+            rgba(ycbcr_to_rgb(y, u, v, 8), a as f32 / 255.0)
+        }
+        DXGI_FORMAT_Y410 => {
+            let bits = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            let [u, y, v] = [bits & 0x3ff, (bits >> 10) & 0x3ff, (bits >> 20) & 0x3ff];
+            rgba(ycbcr_to_rgb(y, u, v, 10), (bits >> 30) as f32 / 3.0)
+        }
+        _ => {
+            let [u, y, v, a] = [0, 1, 2, 3].map(|index| read_u16(pixel, index));
+            rgba(ycbcr_to_rgb(y, u, v, 16), a as f32 / 65535.0)
+        }
+    }
+}
+
+fn encode_444(texel: &[f32; 4], dxgi_format: u32, pixel: &mut [u8]) {
+    let rgb = [texel[0], texel[1], texel[2]];
+    let alpha = |max: f32| (texel[3].clamp(0.0, 1.0) * max).round() as u32;
+    match dxgi_format {
+        DXGI_FORMAT_AYUV => {
+            let [y, u, v] = rgb_to_ycbcr(rgb, 8);
+            pixel.copy_from_slice(&[v as u8, u as u8, y as u8, alpha(255.0) as u8]);
+        }
+        DXGI_FORMAT_Y410 => {
+            let [y, u, v] = rgb_to_ycbcr(rgb, 10);
+            let bits = u | (y << 10) | (v << 20) | (alpha(3.0) << 30);
+            pixel.copy_from_slice(&bits.to_le_bytes());
+        }
+        _ => {
+            let [y, u, v] = rgb_to_ycbcr(rgb, 16);
+            for (index, value) in [u, y, v, alpha(65535.0)].iter().enumerate() {
+                write_u16(pixel, index, *value);
+            }
+        }
+    }
+}
+
+// Both texels of a 4:2:2 pair
+fn decode_422(pair: &[u8], dxgi_format: u32) -> [[f32; 4]; 2] {
+    let unorm = |value: u8| value as f32 / 255.0;
+    match dxgi_format {
+        DXGI_FORMAT_R8G8_B8G8_UNORM => {
+            let (r, b) = (unorm(pair[0]), unorm(pair[2]));
+            [[r, unorm(pair[1]), b, 1.0], [r, unorm(pair[3]), b, 1.0]]
+        }
+        DXGI_FORMAT_G8R8_G8B8_UNORM => {
+            let (r, b) = (unorm(pair[1]), unorm(pair[3]));
+            [[r, unorm(pair[0]), b, 1.0], [r, unorm(pair[2]), b, 1.0]]
+        }
+        DXGI_FORMAT_YUY2 => {
+            let [y0, u, y1, v] = [pair[0], pair[1], pair[2], pair[3]].map(|value| value as u32);
+            [
+                rgba(ycbcr_to_rgb(y0, u, v, 8), 1.0),
+                rgba(ycbcr_to_rgb(y1, u, v, 8), 1.0),
+            ]
+        }
+        _ => {
+            let [y0, u, y1, v] = [0, 1, 2, 3].map(|index| read_u16(pair, index));
+            [
+                rgba(ycbcr_to_rgb(y0, u, v, 16), 1.0),
+                rgba(ycbcr_to_rgb(y1, u, v, 16), 1.0),
+            ]
+        }
+    }
+}
+
+// Chroma is the average of the two texels
+fn encode_422(texels: [&[f32; 4]; 2], dxgi_format: u32, pair: &mut [u8]) {
+    let unorm = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let average = |channel: usize| (texels[0][channel] + texels[1][channel]) / 2.0;
+    match dxgi_format {
+        DXGI_FORMAT_R8G8_B8G8_UNORM => {
+            pair.copy_from_slice(&[
+                unorm(average(0)),
+                unorm(texels[0][1]),
+                unorm(average(2)),
+                unorm(texels[1][1]),
+            ]);
+        }
+        DXGI_FORMAT_G8R8_G8B8_UNORM => {
+            pair.copy_from_slice(&[
+                unorm(texels[0][1]),
+                unorm(average(0)),
+                unorm(texels[1][1]),
+                unorm(average(2)),
+            ]);
+        }
+        _ => {
+            let bit_depth = if dxgi_format == DXGI_FORMAT_YUY2 { 8 } else { 16 };
+            let [y0, _, _] = rgb_to_ycbcr([texels[0][0], texels[0][1], texels[0][2]], bit_depth);
+            let [y1, _, _] = rgb_to_ycbcr([texels[1][0], texels[1][1], texels[1][2]], bit_depth);
+            let [_, u, v] = rgb_to_ycbcr([average(0), average(1), average(2)], bit_depth);
+            if bit_depth == 8 {
+                pair.copy_from_slice(&[y0 as u8, u as u8, y1 as u8, v as u8]);
+            } else {
+                for (index, value) in [y0, u, y1, v].iter().enumerate() {
+                    write_u16(pair, index, *value);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn decode_packed(data: &[u8], width: usize, row_pitch: usize, dxgi_format: u32, texels: &mut [[f32; 4]]) {
+    for (row, texel_row) in data.chunks(row_pitch).zip(texels.chunks_mut(width)) {
+        match packed_pair_size(dxgi_format) {
+            Some(pair_size) => {
+                for (pair, texel_pair) in row.chunks(pair_size).zip(texel_row.chunks_mut(2)) {
+                    let decoded = decode_422(pair, dxgi_format);
+                    texel_pair.copy_from_slice(&decoded[..texel_pair.len()]);
+                }
+            }
+            None => {
+                for (pixel, texel) in row.chunks(pixel_size(dxgi_format)).zip(texel_row.iter_mut()) {
+                    *texel = decode_444(pixel, dxgi_format);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn encode_packed(texels: &[[f32; 4]], width: usize, row_pitch: usize, dxgi_format: u32, data: &mut [u8]) {
+    for (row, texel_row) in data.chunks_mut(row_pitch).zip(texels.chunks(width)) {
+        match packed_pair_size(dxgi_format) {
+            Some(pair_size) => {
+                for (pair, texel_pair) in row.chunks_mut(pair_size).zip(texel_row.chunks(2)) {
+                    encode_422([&texel_pair[0], texel_pair.last().unwrap()], dxgi_format, pair);
+                }
+            }
+            None => {
+                for (pixel, texel) in row.chunks_mut(pixel_size(dxgi_format)).zip(texel_row.iter()) {
+                    encode_444(texel, dxgi_format, pixel);
+                }
+            }
+        }
+    }
+}
+
+// Plane of a planar surface, offsets are from the start of the surface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlaneLayout {
+    pub offset: u64,
+    pub row_pitch: u64,
+    pub row_count: u64,
+}
+
+#[doc = "https://docs.microsoft.com/en-us/windows/win32/direct3d12/subresources#plane-slice"]
+// Luma plane first, then the chroma planes. All planes use the row pitch of the luma plane, as in D3D.
+// None if the format is not planar or the size overflows.
+pub fn plane_layouts(width: u32, height: u32, dxgi_format: u32) -> Option<Vec<PlaneLayout>> {
+    let (width, height) = (width as u64, height as u64);
+    let (row_pitch, chroma_rows): (u64, &[u64]) = match dxgi_format {
+        DXGI_FORMAT_NV12 | DXGI_FORMAT_420_OPAQUE => (width.div_ceil(2) * 2, &[height.div_ceil(2)]),
+        DXGI_FORMAT_P010 | DXGI_FORMAT_P016 => (width.div_ceil(2) * 4, &[height.div_ceil(2)]),
+        // D3D reserves a whole luma plane for the 4:1:1 chroma
+        DXGI_FORMAT_NV11 => (width.div_ceil(4) * 4, &[height]),
+        DXGI_FORMAT_P208 => (width.div_ceil(2) * 2, &[height]),
+        DXGI_FORMAT_V208 => (width, &[height.div_ceil(2), height.div_ceil(2)]),
+        DXGI_FORMAT_V408 => (width, &[height, height]),
+        _ => return None,
+    };
+
+    let mut planes = vec![PlaneLayout {
+        offset: 0,
+        row_pitch,
+        row_count: height,
+    }];
+    for &row_count in chroma_rows {
+        let last = planes.last()?;
+        planes.push(PlaneLayout {
+            offset: last.offset.checked_add(row_pitch.checked_mul(last.row_count)?)?,
+            row_pitch,
+            row_count,
+        });
+    }
+    Some(planes)
+}
+
+// 4:2:0 formats with an interleaved chroma plane that can be converted to RGB
+pub(crate) fn is_convertible_planar(dxgi_format: u32) -> bool {
+    matches!(dxgi_format, DXGI_FORMAT_NV12 | DXGI_FORMAT_P010 | DXGI_FORMAT_P016)
+}
+
+// Size of a luma value, chroma pairs are twice as large
+fn sample_size(dxgi_format: u32) -> usize {
+    if dxgi_format == DXGI_FORMAT_NV12 {
+        1
+    } else {
+        2
+    }
+}
+
+fn read_sample(bytes: &[u8], index: usize, sample_size: usize) -> u32 {
+    if sample_size == 1 {
+        bytes[index] as u32
+    } else {
+        read_u16(bytes, index)
+    }
+}
+
+fn write_sample(bytes: &mut [u8], index: usize, sample_size: usize, value: u32) {
+    if sample_size == 1 {
+        bytes[index] = value as u8;
+    } else {
+        write_u16(bytes, index, value);
+    }
+}
+
+pub(crate) fn decode_planar(data: &[u8], width: usize, height: usize, dxgi_format: u32, texels: &mut [[f32; 4]]) {
+    let planes = plane_layouts(width as u32, height as u32, dxgi_format).unwrap();
+    let (luma, chroma) = (&planes[0], &planes[1]);
+    let (sample_size, row_pitch) = (sample_size(dxgi_format), luma.row_pitch as usize);
+    let bit_depth = sample_size as u32 * 8;
+
+    for y in 0..height {
+        let luma_row = &data[y * row_pitch..];
+        let chroma_row = &data[chroma.offset as usize + (y / 2) * row_pitch..];
+        for x in 0..width {
+            let luma = read_sample(luma_row, x, sample_size);
+            let (cb, cr) = (
+                read_sample(chroma_row, x / 2 * 2, sample_size),
+                read_sample(chroma_row, x / 2 * 2 + 1, sample_size),
+            );
+            texels[y * width + x] = rgba(ycbcr_to_rgb(luma, cb, cr, bit_depth), 1.0);
+        }
+    }
+}
+
+// Chroma is the average of every 2x2 quad, edges of odd sizes are repeated
+pub(crate) fn encode_planar(texels: &[[f32; 4]], width: usize, height: usize, dxgi_format: u32, data: &mut [u8]) {
+    let planes = plane_layouts(width as u32, height as u32, dxgi_format).unwrap();
+    let (sample_size, row_pitch) = (sample_size(dxgi_format), planes[0].row_pitch as usize);
+    let chroma_offset = planes[1].offset as usize;
+    let bit_depth = sample_size as u32 * 8;
+    let rgb = |x: usize, y: usize| {
+        let texel = &texels[y.min(height - 1) * width + x.min(width - 1)];
+        [texel[0], texel[1], texel[2]]
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let [luma, _, _] = rgb_to_ycbcr(rgb(x, y), bit_depth);
+            write_sample(&mut data[y * row_pitch..], x, sample_size, luma);
+        }
+    }
+
+    for y in 0..height.div_ceil(2) {
+        for x in 0..width.div_ceil(2) {
+            let quad = [
+                rgb(x * 2, y * 2),
+                rgb(x * 2 + 1, y * 2),
+                rgb(x * 2, y * 2 + 1),
+                rgb(x * 2 + 1, y * 2 + 1),
+            ];
+            let average = |channel: usize| quad.iter().map(|texel| texel[channel]).sum::<f32>() / 4.0;
+            let [_, cb, cr] = rgb_to_ycbcr([average(0), average(1), average(2)], bit_depth);
+
+            let chroma_row = &mut data[chroma_offset + y * row_pitch..];
+            write_sample(chroma_row, x * 2, sample_size, cb);
+            write_sample(chroma_row, x * 2 + 1, sample_size, cr);
+        }
+    }
+}
+
+// A plane of a subresource
+#[derive(Clone, Copy, Debug)]
+pub struct Plane<'a> {
+    pub data: &'a [u8],
+    pub row_pitch: usize,
+    pub row_count: usize,
+}
+
+impl<S: Storage> ScratchImage<S> {
+    // 1 for formats that are not planar
+    pub fn plane_count(&self) -> u32 {
+        plane_layouts(self.image_width(), self.image_height(), self.dxgi_format())
+            .map_or(1, |planes| planes.len() as u32)
+    }
+
+    // Plane of the first subresource, 0 is luma and 1 is chroma (U and then V for 3 planes)
+    pub fn plane(&self, index: u32) -> Result<Plane<'_>> {
+        self.subresource_plane(0, 0, index)
+    }
+
+    pub fn subresource_plane(&self, mip: u32, layer: u32, index: u32) -> Result<Plane<'_>> {
+        let range = layout::subresource_range(&self.dds_header, mip, layer).ok_or(Error::BadSubresource)?;
+        let (width, height) = layout::mip_extent(&self.dds_header, mip);
+        let planes =
+            plane_layouts(width, height, self.dxgi_format()).ok_or(Error::UnsupportedFormat(self.dxgi_format()))?;
+        let plane = planes.get(index as usize).ok_or(Error::BadSubresource)?;
+
+        let start = range.start + plane.offset as usize;
+        let size = (plane.row_pitch * plane.row_count) as usize;
+        Ok(Plane {
+            data: &self.as_slice()[start..start + size],
+            row_pitch: plane.row_pitch as usize,
+            row_count: plane.row_count as usize,
+        })
+    }
+}